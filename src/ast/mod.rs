@@ -2,16 +2,28 @@
 pub mod parser;
 
 /// An Abstract syntax tree for single regular expression.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum AST {
     /// An empty regex that matches everything.
     Empty(Box<Span>),
     /// A single character literal.
     Literal(Box<Literal>),
+    /// The "any character" class, matching everything except `\n`.
+    Dot(Box<Span>),
+    /// A character class, e.g. `[a-z]` or `\d`.
+    Class(Box<Class>),
+    /// A repetition operator applied to an arbitrary regular expression.
+    Repetition(Box<Repetition>),
+    /// A grouped regular expression.
+    Group(Box<Group>),
     /// An alternation of regular expressions.
     Alternation(Box<Alternation>),
     /// A concatenation of regular expressions.
     Concat(Box<Concat>),
+    /// A backreference to a capturing group, e.g. `\1` or `\k<name>`.
+    Backreference(Box<Backreference>),
+    /// An assertion on the position, e.g. `^` or `$`.
+    Assertion(Box<Assertion>),
 }
 
 impl AST {
@@ -25,6 +37,36 @@ impl AST {
         AST::Literal(Box::new(e))
     }
 
+    /// Create a "dot" AST item.
+    pub fn dot(e: Span) -> AST {
+        AST::Dot(Box::new(e))
+    }
+
+    /// Create a "class" AST item.
+    pub fn class(e: Class) -> AST {
+        AST::Class(Box::new(e))
+    }
+
+    /// Create a "repetition" AST item.
+    pub fn repetition(e: Repetition) -> AST {
+        AST::Repetition(Box::new(e))
+    }
+
+    /// Create a "group" AST item.
+    pub fn group(e: Group) -> AST {
+        AST::Group(Box::new(e))
+    }
+
+    /// Create a "backreference" AST item.
+    pub fn backreference(e: Backreference) -> AST {
+        AST::Backreference(Box::new(e))
+    }
+
+    /// Create an "assertion" AST item.
+    pub fn assertion(e: Assertion) -> AST {
+        AST::Assertion(Box::new(e))
+    }
+
     /// Create a "concat" AST item.
     pub fn concat(e: Concat) -> AST {
         AST::Concat(Box::new(e))
@@ -34,10 +76,90 @@ impl AST {
     pub fn alternation(e: Alternation) -> AST {
         AST::Alternation(Box::new(e))
     }
+
+    /// Return the span of this AST item.
+    pub fn span(&self) -> &Span {
+        match *self {
+            AST::Empty(ref span) => span,
+            AST::Literal(ref x) => &x.span,
+            AST::Dot(ref span) => span,
+            AST::Class(ref x) => &x.span,
+            AST::Repetition(ref x) => &x.span,
+            AST::Group(ref x) => &x.span,
+            AST::Alternation(ref x) => &x.span,
+            AST::Concat(ref x) => &x.span,
+            AST::Backreference(ref x) => &x.span,
+            AST::Assertion(ref x) => &x.span,
+        }
+    }
+
+    /// Return the minimum and maximum (if bounded) number of characters that
+    /// this AST can match.
+    pub fn char_len_bounds(&self) -> (usize, Option<usize>) {
+        match *self {
+            AST::Empty(_) | AST::Assertion(_) => (0, Some(0)),
+            AST::Literal(_) | AST::Dot(_) | AST::Class(_) => (1, Some(1)),
+            // What a backreference matches depends on the text.
+            AST::Backreference(_) => (0, None),
+            AST::Group(ref x) => match x.kind {
+                GroupKind::Lookaround(_) => (0, Some(0)),
+                _ => x.ast.char_len_bounds(),
+            },
+            AST::Repetition(ref x) => {
+                let (min, max) = x.op.kind.bounds();
+                let (sub_min, sub_max) = x.ast.char_len_bounds();
+                let len_min = sub_min.saturating_mul(min as usize);
+                let len_max = match (max, sub_max) {
+                    (_, Some(0)) => Some(0),
+                    (Some(max), Some(sub_max)) => sub_max.checked_mul(max as usize),
+                    _ => None,
+                };
+                (len_min, len_max)
+            }
+            AST::Alternation(ref x) => {
+                let bounds = x.asts.iter().map(|a| a.char_len_bounds());
+                bounds.fold((usize::MAX, Some(0)), |(min, max), (a_min, a_max)| {
+                    (
+                        min.min(a_min),
+                        max.zip(a_max).map(|(max, a_max)| max.max(a_max)),
+                    )
+                })
+            }
+            AST::Concat(ref x) => {
+                let bounds = x.asts.iter().map(|a| a.char_len_bounds());
+                bounds.fold((0, Some(0)), |(min, max), (a_min, a_max)| {
+                    (
+                        min.saturating_add(a_min),
+                        max.zip(a_max)
+                            .and_then(|(max, a_max)| max.checked_add(a_max)),
+                    )
+                })
+            }
+        }
+    }
+
+    /// Return the total number of capturing groups in this AST.
+    pub fn captures_len(&self) -> usize {
+        match *self {
+            AST::Empty(_)
+            | AST::Literal(_)
+            | AST::Dot(_)
+            | AST::Class(_)
+            | AST::Backreference(_)
+            | AST::Assertion(_) => 0,
+            AST::Repetition(ref x) => x.ast.captures_len(),
+            AST::Group(ref x) => {
+                let own = if x.capture_index().is_some() { 1 } else { 0 };
+                own + x.ast.captures_len()
+            }
+            AST::Alternation(ref x) => x.asts.iter().map(|a| a.captures_len()).sum(),
+            AST::Concat(ref x) => x.asts.iter().map(|a| a.captures_len()).sum(),
+        }
+    }
 }
 
 /// Represents the position information of a single AST item.
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Span {
     pub start: Position,
     pub end: Position,
@@ -62,13 +184,13 @@ pub struct Position {
 }
 
 impl Position {
-    fn new(offset: usize) -> Position {
+    pub fn new(offset: usize) -> Position {
         Position { offset }
     }
 }
 
 /// A single literal expression.
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Literal {
     pub span: Span,
     pub kind: LiteralKind,
@@ -76,14 +198,241 @@ pub struct Literal {
 }
 
 /// The kind of a single literal expression.
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum LiteralKind {
     /// The literal is written verbatim.
     Verbatim,
+    /// The literal is written as an escape because it is otherwise a special
+    /// regex meta character, e.g. `\*` or `\[`.
+    Punctuation,
+    /// The literal is written as a special escape, e.g. `\n` or `\t`.
+    Special,
+}
+
+/// A character class.
+///
+/// Both bracketed classes (`[a-z]`) and the Perl shorthand classes
+/// (`\d`, `\w`, `\s`) are represented by a flat list of ranges.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Class {
+    /// The span of this class.
+    pub span: Span,
+    /// Whether this class is negated or not. e.g., `[a]` is not negated but
+    /// `[^a]` is.
+    pub negated: bool,
+    /// The ranges in this class. A single character is a range where the
+    /// start and end are equal.
+    pub ranges: Vec<ClassRange>,
+}
+
+impl Class {
+    /// Return true if the given character is matched by this class.
+    pub fn matches(&self, c: char) -> bool {
+        self.ranges.iter().any(|r| r.start <= c && c <= r.end) != self.negated
+    }
+
+    /// Return the sorted and disjoint ranges of the characters matched by this
+    /// class, with negation applied.
+    pub fn to_ranges(&self) -> Vec<ClassRange> {
+        let negated = parser::negate_ranges(&self.ranges);
+        if self.negated {
+            negated
+        } else {
+            parser::negate_ranges(&negated)
+        }
+    }
+}
+
+/// A single inclusive range in a character class.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ClassRange {
+    pub start: char,
+    pub end: char,
+}
+
+impl ClassRange {
+    /// Create a new range with the given inclusive bounds.
+    pub fn new(start: char, end: char) -> ClassRange {
+        ClassRange { start, end }
+    }
+}
+
+/// A repetition operation applied to a regular expression.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Repetition {
+    /// The span of this operation.
+    pub span: Span,
+    /// The actual operation.
+    pub op: RepetitionOp,
+    /// Whether this operation was applied greedily or not. A lazy operation,
+    /// e.g. `*?`, prefers to match as few times as possible.
+    pub greedy: bool,
+    /// The regular expression under repetition.
+    pub ast: Box<AST>,
+}
+
+/// The repetition operator itself.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RepetitionOp {
+    /// The span of this operator. This includes things like `+`, `*?` and
+    /// `{m,n}`.
+    pub span: Span,
+    /// The type of operation.
+    pub kind: RepetitionKind,
+}
+
+/// The kind of a repetition operator.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RepetitionKind {
+    /// `?`
+    ZeroOrOne,
+    /// `*`
+    ZeroOrMore,
+    /// `+`
+    OneOrMore,
+    /// `{m,n}`
+    Range(RepetitionRange),
+}
+
+impl RepetitionKind {
+    /// Return the minimum and maximum (if bounded) number of repetitions.
+    pub fn bounds(&self) -> (u32, Option<u32>) {
+        match *self {
+            RepetitionKind::ZeroOrOne => (0, Some(1)),
+            RepetitionKind::ZeroOrMore => (0, None),
+            RepetitionKind::OneOrMore => (1, None),
+            RepetitionKind::Range(RepetitionRange::Exactly(n)) => (n, Some(n)),
+            RepetitionKind::Range(RepetitionRange::AtLeast(n)) => (n, None),
+            RepetitionKind::Range(RepetitionRange::Bounded(m, n)) => (m, Some(n)),
+        }
+    }
+}
+
+/// A range repetition operator.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RepetitionRange {
+    /// `{m}`
+    Exactly(u32),
+    /// `{m,}`
+    AtLeast(u32),
+    /// `{m,n}`
+    Bounded(u32, u32),
+}
+
+/// A grouped regular expression.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Group {
+    /// The span of this group.
+    pub span: Span,
+    /// The kind of this group.
+    pub kind: GroupKind,
+    /// The regular expression in this group.
+    pub ast: Box<AST>,
+}
+
+impl Group {
+    /// Returns the capture index of this group, if this is a capturing group.
+    pub fn capture_index(&self) -> Option<u32> {
+        match self.kind {
+            GroupKind::CaptureIndex(i) => Some(i),
+            GroupKind::CaptureName { index, .. } => Some(index),
+            GroupKind::NonCapturing | GroupKind::Lookaround(_) => None,
+        }
+    }
+}
+
+/// The kind of a group.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum GroupKind {
+    /// `(a)`
+    CaptureIndex(u32),
+    /// `(?<name>a)` or `(?P<name>a)`
+    CaptureName { name: String, index: u32 },
+    /// `(?:a)`
+    NonCapturing,
+    /// `(?=a)`, `(?!a)`, `(?<=a)` or `(?<!a)`
+    Lookaround(LookaroundKind),
+}
+
+/// The kind of a lookaround assertion.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LookaroundKind {
+    /// `(?=a)`
+    PositiveLookahead,
+    /// `(?!a)`
+    NegativeLookahead,
+    /// `(?<=a)`
+    PositiveLookbehind,
+    /// `(?<!a)`
+    NegativeLookbehind,
+}
+
+impl LookaroundKind {
+    /// Returns true if the assertion succeeds when its expression matches.
+    pub fn is_positive(&self) -> bool {
+        matches!(
+            self,
+            LookaroundKind::PositiveLookahead | LookaroundKind::PositiveLookbehind
+        )
+    }
+
+    /// Returns true if the assertion looks at the text before the current
+    /// position.
+    pub fn is_lookbehind(&self) -> bool {
+        matches!(
+            self,
+            LookaroundKind::PositiveLookbehind | LookaroundKind::NegativeLookbehind
+        )
+    }
+}
+
+/// A backreference to a capturing group.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Backreference {
+    /// The span of this backreference.
+    pub span: Span,
+    /// How the group was referred to in the pattern.
+    pub kind: BackreferenceKind,
+    /// The capture index of the referenced group, resolved once the whole
+    /// pattern has been parsed.
+    pub index: u32,
+    /// Whether the text of the group is compared ignoring case.
+    pub case_insensitive: bool,
+}
+
+/// The kind of a backreference.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BackreferenceKind {
+    /// `\1`
+    Index,
+    /// `\k<name>`
+    Name(String),
+}
+
+/// An assertion on the position in the text, which matches no character.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Assertion {
+    /// The span of this assertion.
+    pub span: Span,
+    /// The assertion kind, e.g. `^` or `$`.
+    pub kind: AssertionKind,
+}
+
+/// The kind of an assertion.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AssertionKind {
+    /// `^` in multi-line mode, matching at the start of the text or of a line.
+    StartLine,
+    /// `$` in multi-line mode, matching at the end of the text or of a line.
+    EndLine,
+    /// `^`, matching at the start of the text.
+    StartText,
+    /// `$`, matching at the end of the text.
+    EndText,
 }
 
 /// A concatenation of regular expressions.
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Concat {
     /// The span of the concatenation.
     pub span: Span,
@@ -103,7 +452,7 @@ impl Concat {
 }
 
 /// An alternation of regular expressions.
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Alternation {
     /// The span of the alternation.
     pub span: Span,
@@ -160,10 +509,100 @@ impl core::fmt::Display for Error {
 
 /// The type of an error that occurred while building an AST.
 #[derive(Debug, Eq, PartialEq)]
-pub enum ErrorKind {}
+pub enum ErrorKind {
+    /// A backreference refers to a capture index that was never defined.
+    BackreferenceUndefined(u32),
+    /// A backreference refers to a capture name that was never defined.
+    BackreferenceNameUndefined(String),
+    /// An invalid escape sequence was found in a character class set.
+    ClassEscapeInvalid,
+    /// An invalid character class range was found. An invalid range is any
+    /// range where the start is greater than the end.
+    ClassRangeInvalid,
+    /// An opening `[` was found with no corresponding closing `]`.
+    ClassUnclosed,
+    /// A decimal number was too big to fit in a `u32`.
+    DecimalInvalid,
+    /// An escape sequence was found with no character following it.
+    EscapeUnexpectedEof,
+    /// An unrecognized escape sequence.
+    EscapeUnrecognized,
+    /// A duplicate capture name was found.
+    GroupNameDuplicate,
+    /// A capture group name is empty, e.g., `(?<>abc)`.
+    GroupNameEmpty,
+    /// An invalid character was seen for a capture group name.
+    GroupNameInvalid,
+    /// A closing `>` could not be found for a capture group name.
+    GroupNameUnexpectedEof,
+    /// A group was opened with `(?` followed by something that is not
+    /// understood.
+    GroupUnrecognized,
+    /// An unclosed group, e.g., `(ab`.
+    GroupUnclosed,
+    /// An unopened group, e.g., `ab)`.
+    GroupUnopened,
+    /// The range provided in a counted repetition operator is invalid. The
+    /// range is invalid if the start is greater than the end.
+    RepetitionCountInvalid,
+    /// An opening `{` was not followed by a valid decimal value.
+    RepetitionCountDecimalEmpty,
+    /// An opening `{` was found with no corresponding closing `}`.
+    RepetitionCountUnclosed,
+    /// A repetition operator was applied to a missing sub-expression.
+    RepetitionMissing,
+    /// The pattern nests groups and repetitions deeper than the nest limit
+    /// of the parser.
+    NestLimitExceeded(u32),
+}
 
 impl core::fmt::Display for ErrorKind {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        Ok(())
+        use self::ErrorKind::*;
+        match *self {
+            BackreferenceUndefined(index) => {
+                write!(f, "backreference to undefined group {}", index)
+            }
+            BackreferenceNameUndefined(ref name) => {
+                write!(f, "backreference to undefined group name '{}'", name)
+            }
+            ClassEscapeInvalid => write!(f, "invalid escape sequence found in character class"),
+            ClassRangeInvalid => write!(
+                f,
+                "invalid character class range, \
+                 the start must be <= the end"
+            ),
+            ClassUnclosed => write!(f, "unclosed character class"),
+            DecimalInvalid => write!(f, "decimal literal invalid"),
+            EscapeUnexpectedEof => write!(
+                f,
+                "incomplete escape sequence, \
+                 reached end of pattern prematurely"
+            ),
+            EscapeUnrecognized => write!(f, "unrecognized escape sequence"),
+            GroupNameDuplicate => write!(f, "duplicate capture group name"),
+            GroupNameEmpty => write!(f, "empty capture group name"),
+            GroupNameInvalid => write!(f, "invalid capture group character"),
+            GroupNameUnexpectedEof => write!(f, "unclosed capture group name"),
+            GroupUnrecognized => write!(f, "unrecognized group syntax"),
+            GroupUnclosed => write!(f, "unclosed group"),
+            GroupUnopened => write!(f, "unopened group"),
+            RepetitionCountInvalid => write!(
+                f,
+                "invalid repetition count range, \
+                 the start must be <= the end"
+            ),
+            RepetitionCountDecimalEmpty => {
+                write!(f, "repetition quantifier expects a valid decimal")
+            }
+            RepetitionCountUnclosed => write!(f, "unclosed counted repetition"),
+            RepetitionMissing => write!(f, "repetition operator missing expression"),
+            NestLimitExceeded(limit) => write!(
+                f,
+                "exceed the maximum number of \
+                 nested parentheses/repetitions ({})",
+                limit
+            ),
+        }
     }
 }
@@ -9,12 +9,18 @@ type Result<T> = core::result::Result<T, ast::Error>;
 /// Primitive is an expression with no sub-expressions.
 enum Primitive {
     Literal(ast::Literal),
+    Dot(ast::Span),
+    Class(ast::Class),
+    Backreference(ast::Backreference),
 }
 
 impl Primitive {
     fn into_ast(self) -> ast::AST {
         match self {
             Primitive::Literal(lit) => ast::AST::literal(lit),
+            Primitive::Dot(span) => ast::AST::dot(span),
+            Primitive::Class(cls) => ast::AST::class(cls),
+            Primitive::Backreference(br) => ast::AST::backreference(br),
         }
     }
 }
@@ -24,19 +30,126 @@ impl Primitive {
 /// or an alternating `|`.
 enum GroupState {
     /// This state is pushed whenever an opening group is found.
-    // Group {
-    //     /// The concatenation immediately precending the opening group.
-    //     concat: ast::Concat,
-    // },
+    Group {
+        /// The concatenation immediately precending the opening group.
+        concat: ast::Concat,
+        /// The group that has been opened. Its sub-AST is always empty.
+        group: ast::Group,
+    },
+    /// This state is pushed whenever a new alternation branch is found. If
+    /// an alternation branch is found and this state is at the top of the
+    /// stack, then this state should be modified to include the new
+    /// alternation.
     Alternation(ast::Alternation),
 }
 
+/// The default nest limit of a parser.
+pub const DEFAULT_NEST_LIMIT: u32 = 250;
+
+/// A builder for a regular expression parser.
+///
+/// This builder permits modifying configuration options for the parser.
+#[derive(Clone, Debug)]
+pub struct ParserBuilder {
+    nest_limit: u32,
+    case_insensitive: bool,
+    multi_line: bool,
+    dot_all: bool,
+    ignore_whitespace: bool,
+}
+
+impl Default for ParserBuilder {
+    fn default() -> Self {
+        ParserBuilder::new()
+    }
+}
+
+impl ParserBuilder {
+    /// Create a new parser builder with a default configuration.
+    pub fn new() -> ParserBuilder {
+        ParserBuilder {
+            nest_limit: DEFAULT_NEST_LIMIT,
+            case_insensitive: false,
+            multi_line: false,
+            dot_all: false,
+            ignore_whitespace: false,
+        }
+    }
+
+    /// Build a parser from this configuration with the given pattern.
+    pub fn build(&self) -> Parser {
+        Parser {
+            pos: Cell::new(ast::Position { offset: 0 }),
+            capture_index: Cell::new(0),
+            capture_names: RefCell::new(vec![]),
+            stack_group: RefCell::new(vec![]),
+            nest_limit: self.nest_limit,
+            case_insensitive: self.case_insensitive,
+            multi_line: self.multi_line,
+            dot_all: self.dot_all,
+            ignore_whitespace: self.ignore_whitespace,
+        }
+    }
+
+    /// Set the nesting limit for this parser.
+    ///
+    /// The nesting limit bounds how deep groups and repetitions may be nested,
+    /// e.g. `((a))` and `a**` both have a depth of 2. Exceeding it fails with
+    /// `ErrorKind::NestLimitExceeded`. Translating and dropping the AST recurse
+    /// through its nested nodes, so this keeps a hostile pattern from
+    /// overflowing the stack.
+    pub fn nest_limit(&mut self, limit: u32) -> &mut ParserBuilder {
+        self.nest_limit = limit;
+        self
+    }
+
+    /// Match letters regardless of their case, e.g. `a` matches both `a` and
+    /// `A`, and `[a-c]` also matches `B`.
+    pub fn case_insensitive(&mut self, yes: bool) -> &mut ParserBuilder {
+        self.case_insensitive = yes;
+        self
+    }
+
+    /// Make `^` and `$` match at the start and end of every line, rather
+    /// than only at the start and end of the text.
+    pub fn multi_line(&mut self, yes: bool) -> &mut ParserBuilder {
+        self.multi_line = yes;
+        self
+    }
+
+    /// Make `.` match any character, including `\n`.
+    pub fn dot_all(&mut self, yes: bool) -> &mut ParserBuilder {
+        self.dot_all = yes;
+        self
+    }
+
+    /// Enable verbose mode in the regular expression.
+    ///
+    /// When enabled, whitespace is ignored everywhere outside of character
+    /// classes, and a `#` starts a comment running up to the end of the line.
+    /// A literal space or `#` is written with an escape, e.g. `\ ` or `\#`.
+    pub fn ignore_whitespace(&mut self, yes: bool) -> &mut ParserBuilder {
+        self.ignore_whitespace = yes;
+        self
+    }
+}
+
 /// A regular expression parser.
 pub struct Parser {
     /// The current position of the parser.
     pos: Cell<ast::Position>,
+    /// The current capture index.
+    capture_index: Cell<u32>,
+    /// The names of all capture groups seen so far, with their capture index.
+    capture_names: RefCell<Vec<(String, u32)>>,
     /// A stack of grouped sub-expressions, including alternations.
     stack_group: RefCell<Vec<GroupState>>,
+    /// The maximum depth of nested groups and repetitions.
+    nest_limit: u32,
+    case_insensitive: bool,
+    multi_line: bool,
+    dot_all: bool,
+    ignore_whitespace: bool,
 }
 
 /// ParserI is the internal parser implementation.
@@ -47,13 +160,18 @@ struct ParserI<'s, P> {
     pattern: &'s str,
 }
 
+impl Default for Parser {
+    fn default() -> Self {
+        Parser::new()
+    }
+}
+
 impl Parser {
     /// Create a new parser with default configuration.
+    ///
+    /// To configure the parser, use `ParserBuilder`.
     pub fn new() -> Parser {
-        Parser {
-            pos: Cell::new(ast::Position { offset: 0 }),
-            stack_group: RefCell::new(vec![]),
-        }
+        ParserBuilder::new().build()
     }
 
     pub fn parse(&mut self, pattern: &str) -> Result<ast::AST> {
@@ -77,6 +195,15 @@ impl<'s, P: Borrow<Parser>> ParserI<'s, P> {
         self.pattern
     }
 
+    /// Create a new error with the given span and error type.
+    fn error(&self, span: ast::Span, kind: ast::ErrorKind) -> ast::Error {
+        ast::Error {
+            kind,
+            pattern: self.pattern().to_string(),
+            span,
+        }
+    }
+
     /// Return true if the next call to bump would return false.
     fn is_eof(&self) -> bool {
         self.offset() == self.pattern().len()
@@ -101,15 +228,8 @@ impl<'s, P: Borrow<Parser>> ParserI<'s, P> {
     fn span_char(&self) -> ast::Span {
         let next = ast::Position {
             offset: self.offset().checked_add(self.char().len_utf8()).unwrap(),
-            // line: self.line(),
-            // column: self.column().checked_add(1).unwrap(),
         };
 
-        // if self.char() == '\n' {
-        //     next.line += 1;
-        //     next.column = 1;
-        // }
-
         ast::Span::new(self.pos(), next)
     }
 
@@ -126,6 +246,26 @@ impl<'s, P: Borrow<Parser>> ParserI<'s, P> {
             .unwrap_or_else(|| panic!("expected char at offset {}", i))
     }
 
+    /// Peek at the next character in the input without advancing the parser.
+    fn peek(&self) -> Option<char> {
+        if self.is_eof() {
+            return None;
+        }
+        self.pattern()[self.offset() + self.char().len_utf8()..]
+            .chars()
+            .next()
+    }
+
+    /// Assign the next capture index, failing with `err` on overflow.
+    fn next_capture_index(&self, span: &ast::Span) -> Result<u32> {
+        let current = self.parser().capture_index.get();
+        let i = current
+            .checked_add(1)
+            .ok_or_else(|| self.error(span.clone(), ast::ErrorKind::DecimalInvalid))?;
+        self.parser().capture_index.set(i);
+        Ok(i)
+    }
+
     // Parse the regular expression into an abstract syntax tree.
     fn parse(&self) -> Result<ast::AST> {
         assert_eq!(self.offset(), 0, "parser can only be used once");
@@ -136,18 +276,31 @@ impl<'s, P: Borrow<Parser>> ParserI<'s, P> {
         };
 
         loop {
-            // self.bump_space();
+            self.bump_space();
             if self.is_eof() {
                 break;
             }
 
             match self.char() {
+                '(' => concat = self.push_group(concat)?,
+                ')' => concat = self.pop_group(concat)?,
                 '|' => concat = self.push_alternate(concat)?,
-                _ => concat.asts.push(self.parse_primitive()?.into_ast()),
+                '[' => {
+                    let class = self.parse_set_class()?;
+                    concat.asts.push(ast::AST::class(self.fold_case(class)));
+                }
+                '^' | '$' => concat.asts.push(self.parse_assertion()),
+                '?' | '*' | '+' => concat = self.parse_uncounted_repetition(concat)?,
+                '{' => concat = self.parse_counted_repetition(concat)?,
+                _ => {
+                    let primitive = self.fold_case_primitive(self.parse_primitive()?);
+                    concat.asts.push(primitive.into_ast());
+                }
             }
         }
 
-        let ast = self.pop_group_end(concat)?;
+        let mut ast = self.pop_group_end(concat)?;
+        self.resolve_backreferences(&mut ast)?;
         Ok(ast)
     }
 
@@ -157,24 +310,112 @@ impl<'s, P: Borrow<Parser>> ParserI<'s, P> {
             return false;
         }
 
-        let ast::Position {
-            mut offset, /*, mut line, mut column*/
-        } = self.pos();
-        // if self.char() == '\n' {
-        //     line = line.checked_add(1).unwrap();
-        //     column = 1;
-        // } else {
-        //     column = column.checked_add(1).unwrap();
-        // }
-
+        let ast::Position { mut offset } = self.pos();
         offset += self.char().len_utf8();
         self.parser().pos.set(ast::Position { offset });
         self.pattern()[self.offset()..].chars().next().is_some()
     }
 
+    /// If the substring starting at the current position of the parser has
+    /// the given prefix, then bump the parser to the character immediately
+    /// following the prefix and return true. Otherwise, don't bump the parser
+    /// and return false.
+    fn bump_if(&self, prefix: &str) -> bool {
+        if self.pattern()[self.offset()..].starts_with(prefix) {
+            for _ in 0..prefix.chars().count() {
+                self.bump();
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    /// If the parser ignores whitespace, skip the whitespace and comments
+    /// starting at the current position.
+    fn bump_space(&self) {
+        if !self.parser().ignore_whitespace {
+            return;
+        }
+        while !self.is_eof() {
+            if self.char().is_whitespace() {
+                self.bump();
+            } else if self.char() == '#' {
+                while !self.is_eof() && self.char() != '\n' {
+                    self.bump();
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Parse a `^` or `$` assertion.
+    fn parse_assertion(&self) -> ast::AST {
+        let multi_line = self.parser().multi_line;
+        let kind = match self.char() {
+            '^' if multi_line => ast::AssertionKind::StartLine,
+            '^' => ast::AssertionKind::StartText,
+            '$' if multi_line => ast::AssertionKind::EndLine,
+            '$' => ast::AssertionKind::EndText,
+            _ => unreachable!(),
+        };
+        let span = self.span_char();
+        self.bump();
+        ast::AST::assertion(ast::Assertion { span, kind })
+    }
+
+    /// If the parser is case insensitive, turn a literal with other cases
+    /// into a class of all its cases, and add the other cases to a class.
+    fn fold_case_primitive(&self, primitive: Primitive) -> Primitive {
+        if !self.parser().case_insensitive {
+            return primitive;
+        }
+        match primitive {
+            Primitive::Literal(lit) => {
+                let ranges = case_fold(&[ast::ClassRange::new(lit.c, lit.c)]);
+                if ranges.len() == 1 {
+                    return Primitive::Literal(lit);
+                }
+                Primitive::Class(ast::Class {
+                    span: lit.span,
+                    negated: false,
+                    ranges,
+                })
+            }
+            Primitive::Class(cls) => Primitive::Class(self.fold_case(cls)),
+            primitive => primitive,
+        }
+    }
+
+    /// If the parser is case insensitive, add the other cases of the
+    /// characters to the class.
+    fn fold_case(&self, mut class: ast::Class) -> ast::Class {
+        if self.parser().case_insensitive {
+            class.ranges = case_fold(&class.ranges);
+        }
+        class
+    }
+
     /// Parse a primitive AST. e.g., a literal, non-set character class, or assertion.
     fn parse_primitive(&self) -> Result<Primitive> {
         match self.char() {
+            '\\' => self.parse_escape(),
+            '.' if self.parser().dot_all => {
+                // A negated empty class matches every character.
+                let ast = Primitive::Class(ast::Class {
+                    span: self.span_char(),
+                    negated: true,
+                    ranges: vec![],
+                });
+                self.bump();
+                Ok(ast)
+            }
+            '.' => {
+                let ast = Primitive::Dot(self.span_char());
+                self.bump();
+                Ok(ast)
+            }
             c => {
                 let ast = Primitive::Literal(ast::Literal {
                     span: self.span_char(),
@@ -187,6 +428,485 @@ impl<'s, P: Borrow<Parser>> ParserI<'s, P> {
         }
     }
 
+    /// Parse an escape sequence as a primitive AST.
+    ///
+    /// This assumes the parser is positioned at the start of the escape
+    /// sequence, i.e., `\`.
+    fn parse_escape(&self) -> Result<Primitive> {
+        assert_eq!(self.char(), '\\');
+        let start = self.pos();
+        if !self.bump() {
+            return Err(self.error(
+                ast::Span::new(start, self.pos()),
+                ast::ErrorKind::EscapeUnexpectedEof,
+            ));
+        }
+
+        let c = self.char();
+        if is_meta_character(c) || (self.parser().ignore_whitespace && is_space_character(c)) {
+            self.bump();
+            return Ok(Primitive::Literal(ast::Literal {
+                span: ast::Span::new(start, self.pos()),
+                kind: ast::LiteralKind::Punctuation,
+                c,
+            }));
+        }
+
+        let special = match c {
+            'n' => Some('\n'),
+            't' => Some('\t'),
+            'r' => Some('\r'),
+            'f' => Some('\x0C'),
+            'v' => Some('\x0B'),
+            _ => None,
+        };
+        if let Some(special) = special {
+            self.bump();
+            return Ok(Primitive::Literal(ast::Literal {
+                span: ast::Span::new(start, self.pos()),
+                kind: ast::LiteralKind::Special,
+                c: special,
+            }));
+        }
+
+        if let Some((negated, ranges)) = perl_class(c) {
+            self.bump();
+            return Ok(Primitive::Class(ast::Class {
+                span: ast::Span::new(start, self.pos()),
+                negated,
+                ranges,
+            }));
+        }
+
+        match c {
+            '1'..='9' => {
+                let index = self.parse_decimal()?;
+                Ok(Primitive::Backreference(ast::Backreference {
+                    span: ast::Span::new(start, self.pos()),
+                    kind: ast::BackreferenceKind::Index,
+                    index,
+                    case_insensitive: self.parser().case_insensitive,
+                }))
+            }
+            'k' if self.peek() == Some('<') => {
+                self.bump();
+                let name = self.parse_capture_name()?;
+                Ok(Primitive::Backreference(ast::Backreference {
+                    span: ast::Span::new(start, self.pos()),
+                    kind: ast::BackreferenceKind::Name(name),
+                    index: 0,
+                    case_insensitive: self.parser().case_insensitive,
+                }))
+            }
+            _ => Err(self.error(
+                ast::Span::new(start, self.span_char().end),
+                ast::ErrorKind::EscapeUnrecognized,
+            )),
+        }
+    }
+
+    /// Parse a decimal number into a u32.
+    ///
+    /// The number must be made of ASCII digits and must fit into a `u32`,
+    /// otherwise an error is returned.
+    fn parse_decimal(&self) -> Result<u32> {
+        let start = self.pos();
+        let mut digits = String::new();
+        while !self.is_eof() && self.char().is_ascii_digit() {
+            digits.push(self.char());
+            self.bump();
+        }
+        let span = ast::Span::new(start, self.pos());
+        if digits.is_empty() {
+            return Err(self.error(span, ast::ErrorKind::RepetitionCountDecimalEmpty));
+        }
+        digits
+            .parse::<u32>()
+            .map_err(|_| self.error(span, ast::ErrorKind::DecimalInvalid))
+    }
+
+    /// Parse a capture name, e.g. `<name>`, returning the name.
+    ///
+    /// This assumes the parser is positioned at the opening `<`. On success,
+    /// the parser is positioned immediately after the closing `>`.
+    fn parse_capture_name(&self) -> Result<String> {
+        assert_eq!(self.char(), '<');
+        let open = self.span_char();
+        self.bump();
+        let start = self.pos();
+        let mut name = String::new();
+        loop {
+            if self.is_eof() {
+                return Err(self.error(
+                    ast::Span::new(open.start, self.pos()),
+                    ast::ErrorKind::GroupNameUnexpectedEof,
+                ));
+            }
+            let c = self.char();
+            if c == '>' {
+                break;
+            }
+            if !is_capture_char(c, name.is_empty()) {
+                return Err(self.error(self.span_char(), ast::ErrorKind::GroupNameInvalid));
+            }
+            name.push(c);
+            self.bump();
+        }
+        if name.is_empty() {
+            return Err(self.error(
+                ast::Span::new(start, self.pos()),
+                ast::ErrorKind::GroupNameEmpty,
+            ));
+        }
+        self.bump();
+        Ok(name)
+    }
+
+    /// Parse a standard character class consisting primarily of characters or
+    /// character ranges, e.g. `[a-z]` or `[^\d]`.
+    ///
+    /// This assumes the parser is positioned at the opening `[`.
+    fn parse_set_class(&self) -> Result<ast::Class> {
+        assert_eq!(self.char(), '[');
+        let open = self.span_char();
+        self.bump();
+
+        let negated = self.bump_if("^");
+        let mut ranges = vec![];
+        let mut first = true;
+
+        loop {
+            if self.is_eof() {
+                return Err(self.error(open, ast::ErrorKind::ClassUnclosed));
+            }
+            if self.char() == ']' && !first {
+                self.bump();
+                break;
+            }
+            first = false;
+
+            let item_start = self.pos();
+            match self.parse_set_class_item()? {
+                SetItem::Ranges(class_ranges) => ranges.extend(class_ranges),
+                SetItem::Char(c) => {
+                    if self.char_is('-') && !matches!(self.peek(), None | Some(']')) {
+                        self.bump();
+                        let end = match self.parse_set_class_item()? {
+                            SetItem::Char(end) => end,
+                            SetItem::Ranges(_) => {
+                                return Err(self.error(
+                                    ast::Span::new(item_start, self.pos()),
+                                    ast::ErrorKind::ClassRangeInvalid,
+                                ))
+                            }
+                        };
+                        if c > end {
+                            return Err(self.error(
+                                ast::Span::new(item_start, self.pos()),
+                                ast::ErrorKind::ClassRangeInvalid,
+                            ));
+                        }
+                        ranges.push(ast::ClassRange::new(c, end));
+                    } else {
+                        ranges.push(ast::ClassRange::new(c, c));
+                    }
+                }
+            }
+        }
+
+        Ok(ast::Class {
+            span: ast::Span::new(open.start, self.pos()),
+            negated,
+            ranges,
+        })
+    }
+
+    /// Parse a single item of a bracketed class: either a character (possibly
+    /// escaped) or a Perl class escape like `\d`.
+    fn parse_set_class_item(&self) -> Result<SetItem> {
+        if self.is_eof() {
+            return Err(self.error(self.span(), ast::ErrorKind::ClassUnclosed));
+        }
+        if self.char() != '\\' {
+            let c = self.char();
+            self.bump();
+            return Ok(SetItem::Char(c));
+        }
+
+        let start = self.pos();
+        match self.parse_escape()? {
+            Primitive::Literal(lit) => Ok(SetItem::Char(lit.c)),
+            Primitive::Class(cls) if cls.negated => Ok(SetItem::Ranges(negate_ranges(&cls.ranges))),
+            Primitive::Class(cls) => Ok(SetItem::Ranges(cls.ranges)),
+            Primitive::Dot(_) | Primitive::Backreference(_) => Err(self.error(
+                ast::Span::new(start, self.pos()),
+                ast::ErrorKind::ClassEscapeInvalid,
+            )),
+        }
+    }
+
+    /// Return true if the parser is positioned at the given character.
+    fn char_is(&self, c: char) -> bool {
+        !self.is_eof() && self.char() == c
+    }
+
+    /// Parse a single repetition operator (`?`, `*` or `+`) and apply it to
+    /// the last expression of the given concatenation.
+    fn parse_uncounted_repetition(&self, mut concat: ast::Concat) -> Result<ast::Concat> {
+        let op_start = self.pos();
+        let kind = match self.char() {
+            '?' => ast::RepetitionKind::ZeroOrOne,
+            '*' => ast::RepetitionKind::ZeroOrMore,
+            '+' => ast::RepetitionKind::OneOrMore,
+            _ => unreachable!(),
+        };
+
+        let ast = match concat.asts.pop() {
+            Some(ast) => ast,
+            None => return Err(self.error(self.span_char(), ast::ErrorKind::RepetitionMissing)),
+        };
+        self.bump();
+        let greedy = !self.bump_if("?");
+
+        let op_span = ast::Span::new(op_start, self.pos());
+        self.check_nest_limit(self.group_depth() + nest_depth(&ast) + 1, &op_span)?;
+        concat.asts.push(ast::AST::repetition(ast::Repetition {
+            span: ast::Span::new(ast.span().start, self.pos()),
+            op: ast::RepetitionOp {
+                span: op_span,
+                kind,
+            },
+            greedy,
+            ast: Box::new(ast),
+        }));
+
+        Ok(concat)
+    }
+
+    /// Parse a counted repetition operator (`{m}`, `{m,}` or `{m,n}`) and
+    /// apply it to the last expression of the given concatenation.
+    fn parse_counted_repetition(&self, mut concat: ast::Concat) -> Result<ast::Concat> {
+        assert_eq!(self.char(), '{');
+        let start = self.pos();
+
+        let ast = match concat.asts.pop() {
+            Some(ast) => ast,
+            None => return Err(self.error(self.span_char(), ast::ErrorKind::RepetitionMissing)),
+        };
+        self.bump();
+
+        let unclosed = |p: &Self| {
+            p.error(
+                ast::Span::new(start, p.pos()),
+                ast::ErrorKind::RepetitionCountUnclosed,
+            )
+        };
+
+        if self.is_eof() {
+            return Err(unclosed(self));
+        }
+
+        let count_start = self.parse_decimal()?;
+        let mut range = ast::RepetitionRange::Exactly(count_start);
+
+        if self.is_eof() {
+            return Err(unclosed(self));
+        }
+
+        if self.char() == ',' {
+            self.bump();
+            if self.is_eof() {
+                return Err(unclosed(self));
+            }
+            if self.char() == '}' {
+                range = ast::RepetitionRange::AtLeast(count_start);
+            } else {
+                let count_end = self.parse_decimal()?;
+                range = ast::RepetitionRange::Bounded(count_start, count_end);
+            }
+        }
+
+        if self.is_eof() || self.char() != '}' {
+            return Err(unclosed(self));
+        }
+        self.bump();
+        let greedy = !self.bump_if("?");
+
+        let op_span = ast::Span::new(start, self.pos());
+        if let ast::RepetitionRange::Bounded(m, n) = range {
+            if m > n {
+                return Err(self.error(op_span, ast::ErrorKind::RepetitionCountInvalid));
+            }
+        }
+        self.check_nest_limit(self.group_depth() + nest_depth(&ast) + 1, &op_span)?;
+
+        concat.asts.push(ast::AST::repetition(ast::Repetition {
+            span: ast::Span::new(ast.span().start, self.pos()),
+            op: ast::RepetitionOp {
+                span: op_span,
+                kind: ast::RepetitionKind::Range(range),
+            },
+            greedy,
+            ast: Box::new(ast),
+        }));
+
+        Ok(concat)
+    }
+
+    /// Parse the opening of a group and push it on the parser's internal
+    /// stack. Return a fresh concatenation for the group's contents.
+    ///
+    /// This assumes the parser is positioned at the opening `(`.
+    fn push_group(&self, concat: ast::Concat) -> Result<ast::Concat> {
+        assert_eq!(self.char(), '(');
+        let open = self.span_char();
+        self.bump();
+
+        let kind = if self.bump_if("?") {
+            if self.is_eof() {
+                return Err(self.error(open, ast::ErrorKind::GroupUnclosed));
+            }
+            match self.char() {
+                ':' => {
+                    self.bump();
+                    ast::GroupKind::NonCapturing
+                }
+                '=' => {
+                    self.bump();
+                    ast::GroupKind::Lookaround(ast::LookaroundKind::PositiveLookahead)
+                }
+                '!' => {
+                    self.bump();
+                    ast::GroupKind::Lookaround(ast::LookaroundKind::NegativeLookahead)
+                }
+                '<' if self.bump_if("<=") => {
+                    ast::GroupKind::Lookaround(ast::LookaroundKind::PositiveLookbehind)
+                }
+                '<' if self.bump_if("<!") => {
+                    ast::GroupKind::Lookaround(ast::LookaroundKind::NegativeLookbehind)
+                }
+                'P' if self.peek() == Some('<') => {
+                    self.bump();
+                    self.parse_named_group_kind()?
+                }
+                '<' => self.parse_named_group_kind()?,
+                _ => {
+                    return Err(self.error(
+                        ast::Span::new(open.start, self.span_char().end),
+                        ast::ErrorKind::GroupUnrecognized,
+                    ))
+                }
+            }
+        } else {
+            ast::GroupKind::CaptureIndex(self.next_capture_index(&open)?)
+        };
+
+        let depth = self.group_depth() + 1;
+        self.check_nest_limit(depth, &open)?;
+
+        self.parser()
+            .stack_group
+            .borrow_mut()
+            .push(GroupState::Group {
+                concat,
+                group: ast::Group {
+                    span: open,
+                    kind,
+                    ast: Box::new(ast::AST::empty(self.span())),
+                },
+            });
+
+        Ok(ast::Concat {
+            span: self.span(),
+            asts: vec![],
+        })
+    }
+
+    /// Return the number of groups currently open.
+    fn group_depth(&self) -> u32 {
+        let stack = self.parser().stack_group.borrow();
+        stack
+            .iter()
+            .filter(|state| matches!(state, GroupState::Group { .. }))
+            .count() as u32
+    }
+
+    /// Fail if an expression nested `depth` levels deep exceeds the nest limit.
+    fn check_nest_limit(&self, depth: u32, span: &ast::Span) -> Result<()> {
+        let limit = self.parser().nest_limit;
+        if depth > limit {
+            return Err(self.error(span.clone(), ast::ErrorKind::NestLimitExceeded(limit)));
+        }
+        Ok(())
+    }
+
+    /// Parse the `<name>` part of a named group and register the name.
+    fn parse_named_group_kind(&self) -> Result<ast::GroupKind> {
+        let start = self.pos();
+        let name = self.parse_capture_name()?;
+        let span = ast::Span::new(start, self.pos());
+
+        if self
+            .parser()
+            .capture_names
+            .borrow()
+            .iter()
+            .any(|(n, _)| *n == name)
+        {
+            return Err(self.error(span, ast::ErrorKind::GroupNameDuplicate));
+        }
+
+        let index = self.next_capture_index(&span)?;
+        self.parser()
+            .capture_names
+            .borrow_mut()
+            .push((name.clone(), index));
+
+        Ok(ast::GroupKind::CaptureName { name, index })
+    }
+
+    /// Pop a group AST from the parser's internal stack and set the group's
+    /// AST to the given concatenation. Return the concatenation containing
+    /// the group.
+    ///
+    /// This assumes that the parser is currently positioned on the closing
+    /// parenthesis.
+    fn pop_group(&self, mut group_concat: ast::Concat) -> Result<ast::Concat> {
+        assert_eq!(self.char(), ')');
+
+        let mut stack = self.parser().stack_group.borrow_mut();
+        let (mut prior_concat, mut group, alt) = match stack.pop() {
+            Some(GroupState::Group { concat, group }) => (concat, group, None),
+            Some(GroupState::Alternation(alt)) => match stack.pop() {
+                Some(GroupState::Group { concat, group }) => (concat, group, Some(alt)),
+                None | Some(GroupState::Alternation(_)) => {
+                    return Err(self.error(self.span_char(), ast::ErrorKind::GroupUnopened));
+                }
+            },
+            None => {
+                return Err(self.error(self.span_char(), ast::ErrorKind::GroupUnopened));
+            }
+        };
+
+        group_concat.span.end = self.pos();
+        self.bump();
+        group.span.end = self.pos();
+
+        match alt {
+            Some(mut alt) => {
+                alt.span.end = group_concat.span.end;
+                alt.asts.push(group_concat.into_ast());
+                group.ast = Box::new(alt.into_ast());
+            }
+            None => {
+                group.ast = Box::new(group_concat.into_ast());
+            }
+        }
+
+        prior_concat.asts.push(ast::AST::group(group));
+        Ok(prior_concat)
+    }
+
     /// Parse and push a single alternation on the parser's internal stack.
     /// If the top of the stack already has an alternation, then add to that
     /// instead of pushing a new one.
@@ -230,6 +950,9 @@ impl<'s, P: Borrow<Parser>> ParserI<'s, P> {
                 alt.asts.push(concat.into_ast());
                 Ok(ast::AST::alternation(alt))
             }
+            Some(GroupState::Group { group, .. }) => {
+                return Err(self.error(group.span, ast::ErrorKind::GroupUnclosed));
+            }
         };
 
         // If we try to pop again, there should be nothing.
@@ -244,8 +967,199 @@ impl<'s, P: Borrow<Parser>> ParserI<'s, P> {
                 // the stack.
                 unreachable!()
             }
+            Some(GroupState::Group { group, .. }) => {
+                Err(self.error(group.span, ast::ErrorKind::GroupUnclosed))
+            }
+        }
+    }
+
+    /// Check every backreference against the capturing groups defined in the
+    /// whole pattern, resolving named references to their capture index.
+    fn resolve_backreferences(&self, ast: &mut ast::AST) -> Result<()> {
+        match *ast {
+            ast::AST::Backreference(ref mut br) => match br.kind {
+                ast::BackreferenceKind::Index => {
+                    if br.index > self.parser().capture_index.get() {
+                        return Err(self.error(
+                            br.span.clone(),
+                            ast::ErrorKind::BackreferenceUndefined(br.index),
+                        ));
+                    }
+                    Ok(())
+                }
+                ast::BackreferenceKind::Name(ref name) => {
+                    let names = self.parser().capture_names.borrow();
+                    match names.iter().find(|(n, _)| n == name) {
+                        Some(&(_, index)) => {
+                            br.index = index;
+                            Ok(())
+                        }
+                        None => Err(self.error(
+                            br.span.clone(),
+                            ast::ErrorKind::BackreferenceNameUndefined(name.clone()),
+                        )),
+                    }
+                }
+            },
+            ast::AST::Repetition(ref mut rep) => self.resolve_backreferences(&mut rep.ast),
+            ast::AST::Group(ref mut group) => self.resolve_backreferences(&mut group.ast),
+            ast::AST::Alternation(ref mut alt) => alt
+                .asts
+                .iter_mut()
+                .try_for_each(|a| self.resolve_backreferences(a)),
+            ast::AST::Concat(ref mut con) => con
+                .asts
+                .iter_mut()
+                .try_for_each(|a| self.resolve_backreferences(a)),
+            ast::AST::Empty(_)
+            | ast::AST::Literal(_)
+            | ast::AST::Dot(_)
+            | ast::AST::Class(_)
+            | ast::AST::Assertion(_) => Ok(()),
+        }
+    }
+}
+
+/// A single parsed item of a bracketed character class.
+enum SetItem {
+    Char(char),
+    Ranges(Vec<ast::ClassRange>),
+}
+
+/// Returns true if the given character has significance in a regex.
+fn is_meta_character(c: char) -> bool {
+    matches!(
+        c,
+        '\\' | '.'
+            | '+'
+            | '*'
+            | '?'
+            | '('
+            | ')'
+            | '|'
+            | '['
+            | ']'
+            | '{'
+            | '}'
+            | '^'
+            | '$'
+            | '-'
+            | '/'
+    )
+}
+
+/// Returns true if the given character must be escaped to be matched
+/// literally when whitespace is ignored.
+fn is_space_character(c: char) -> bool {
+    c.is_whitespace() || c == '#'
+}
+
+/// Returns the depth of the groups and repetitions directly wrapping each
+/// other at the top of the AST, e.g. 3 for `((a)*)`. Other nested
+/// expressions were already checked against the nest limit when their group
+/// was opened.
+fn nest_depth(mut ast: &ast::AST) -> u32 {
+    let mut depth = 0;
+    loop {
+        ast = match ast {
+            ast::AST::Group(group) => &group.ast,
+            ast::AST::Repetition(rep) => &rep.ast,
+            _ => return depth,
+        };
+        depth += 1;
+    }
+}
+
+/// Return the given ranges along with the other cases of their characters.
+fn case_fold(ranges: &[ast::ClassRange]) -> Vec<ast::ClassRange> {
+    let mut folded = ranges.to_vec();
+    for r in ranges {
+        for c in r.start..=r.end {
+            let mut lower = c.to_lowercase();
+            let mut upper = c.to_uppercase();
+            for other in [lower.next(), upper.next()].into_iter().flatten() {
+                if other != c && lower.len() == 0 && upper.len() == 0 {
+                    folded.push(ast::ClassRange::new(other, other));
+                }
+            }
         }
     }
+    folded
+}
+
+/// Returns true if the given character is allowed in a capture name. The
+/// first character of a name can't be a digit.
+fn is_capture_char(c: char, first: bool) -> bool {
+    c == '_' || c.is_ascii_alphabetic() || (!first && c.is_ascii_digit())
+}
+
+/// Returns the negation flag and ranges of a Perl class escape like `\d`.
+fn perl_class(c: char) -> Option<(bool, Vec<ast::ClassRange>)> {
+    let digit = || vec![ast::ClassRange::new('0', '9')];
+    let word = || {
+        vec![
+            ast::ClassRange::new('0', '9'),
+            ast::ClassRange::new('A', 'Z'),
+            ast::ClassRange::new('_', '_'),
+            ast::ClassRange::new('a', 'z'),
+        ]
+    };
+    let space = || {
+        vec![
+            ast::ClassRange::new('\t', '\r'),
+            ast::ClassRange::new(' ', ' '),
+        ]
+    };
+    match c {
+        'd' => Some((false, digit())),
+        'D' => Some((true, digit())),
+        'w' => Some((false, word())),
+        'W' => Some((true, word())),
+        's' => Some((false, space())),
+        'S' => Some((true, space())),
+        _ => None,
+    }
+}
+
+/// Return the complement of the given ranges over all Unicode scalar values.
+pub(super) fn negate_ranges(ranges: &[ast::ClassRange]) -> Vec<ast::ClassRange> {
+    let mut sorted = ranges.to_vec();
+    sorted.sort_by_key(|r| r.start);
+
+    let mut negated = vec![];
+    let mut next = Some('\0');
+    for r in sorted {
+        if let Some(start) = next {
+            if start < r.start {
+                negated.push(ast::ClassRange::new(start, decrement(r.start)));
+            }
+        }
+        next = match next {
+            Some(n) if n > r.end => Some(n),
+            _ => increment(r.end),
+        };
+    }
+    if let Some(start) = next {
+        negated.push(ast::ClassRange::new(start, char::MAX));
+    }
+    negated
+}
+
+/// Return the next Unicode scalar value, skipping the surrogate range.
+fn increment(c: char) -> Option<char> {
+    match c {
+        '\u{D7FF}' => Some('\u{E000}'),
+        char::MAX => None,
+        c => char::from_u32(c as u32 + 1),
+    }
+}
+
+/// Return the previous Unicode scalar value, skipping the surrogate range.
+fn decrement(c: char) -> char {
+    match c {
+        '\u{E000}' => '\u{D7FF}',
+        c => char::from_u32(c as u32 - 1).unwrap(),
+    }
 }
 
 #[cfg(test)]
@@ -366,4 +1280,310 @@ mod tests {
             ))
         );
     }
+
+    /// Create a capturing group with the given range.
+    fn group(range: Range<usize>, index: u32, ast: ast::AST) -> ast::AST {
+        ast::AST::group(ast::Group {
+            span: span(range),
+            kind: ast::GroupKind::CaptureIndex(index),
+            ast: Box::new(ast),
+        })
+    }
+
+    /// Create a numeric backreference with the given range.
+    fn backref(range: Range<usize>, index: u32) -> ast::AST {
+        ast::AST::backreference(ast::Backreference {
+            span: span(range),
+            kind: ast::BackreferenceKind::Index,
+            index,
+            case_insensitive: false,
+        })
+    }
+
+    fn parse_error(pattern: &str) -> (ast::ErrorKind, Range<usize>) {
+        let err = Parser::new().parse(pattern).unwrap_err();
+        (err.kind, err.span.start.offset..err.span.end.offset)
+    }
+
+    #[test]
+    fn group_pattern() {
+        assert_eq!(
+            Parser::new().parse("(a|b)c"),
+            Ok(concat(
+                0..6,
+                vec![
+                    group(0..5, 1, alt(1..4, vec![lit('a', 1), lit('b', 3)])),
+                    lit('c', 5),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn repetition_pattern() {
+        assert_eq!(
+            Parser::new().parse("a{2,3}"),
+            Ok(ast::AST::repetition(ast::Repetition {
+                span: span(0..6),
+                op: ast::RepetitionOp {
+                    span: span(1..6),
+                    kind: ast::RepetitionKind::Range(ast::RepetitionRange::Bounded(2, 3)),
+                },
+                greedy: true,
+                ast: Box::new(lit('a', 0)),
+            }))
+        );
+        assert_eq!(
+            Parser::new().parse("a+?b{2,}?"),
+            Ok(concat(
+                0..9,
+                vec![
+                    ast::AST::repetition(ast::Repetition {
+                        span: span(0..3),
+                        op: ast::RepetitionOp {
+                            span: span(1..3),
+                            kind: ast::RepetitionKind::OneOrMore,
+                        },
+                        greedy: false,
+                        ast: Box::new(lit('a', 0)),
+                    }),
+                    ast::AST::repetition(ast::Repetition {
+                        span: span(3..9),
+                        op: ast::RepetitionOp {
+                            span: span(4..9),
+                            kind: ast::RepetitionKind::Range(ast::RepetitionRange::AtLeast(2)),
+                        },
+                        greedy: false,
+                        ast: Box::new(lit('b', 3)),
+                    }),
+                ]
+            ))
+        );
+        assert_eq!(
+            parse_error("a{3,2}"),
+            (ast::ErrorKind::RepetitionCountInvalid, 1..6)
+        );
+        assert_eq!(parse_error("*"), (ast::ErrorKind::RepetitionMissing, 0..1));
+    }
+
+    #[test]
+    fn class_pattern() {
+        assert_eq!(
+            Parser::new().parse("[^a-c_]"),
+            Ok(ast::AST::class(ast::Class {
+                span: span(0..7),
+                negated: true,
+                ranges: vec![
+                    ast::ClassRange::new('a', 'c'),
+                    ast::ClassRange::new('_', '_'),
+                ],
+            }))
+        );
+        assert_eq!(
+            parse_error("[z-a]"),
+            (ast::ErrorKind::ClassRangeInvalid, 1..4)
+        );
+        assert_eq!(parse_error("[ab"), (ast::ErrorKind::ClassUnclosed, 0..1));
+    }
+
+    #[test]
+    fn numeric_backreference_pattern() {
+        assert_eq!(
+            Parser::new().parse(r"(a)\1"),
+            Ok(concat(
+                0..5,
+                vec![group(0..3, 1, lit('a', 1)), backref(3..5, 1)]
+            ))
+        );
+    }
+
+    #[test]
+    fn named_backreference_pattern() {
+        assert_eq!(
+            Parser::new().parse(r"(?<q>a)\k<q>"),
+            Ok(concat(
+                0..12,
+                vec![
+                    ast::AST::group(ast::Group {
+                        span: span(0..7),
+                        kind: ast::GroupKind::CaptureName {
+                            name: "q".to_string(),
+                            index: 1,
+                        },
+                        ast: Box::new(lit('a', 5)),
+                    }),
+                    ast::AST::backreference(ast::Backreference {
+                        span: span(7..12),
+                        kind: ast::BackreferenceKind::Name("q".to_string()),
+                        index: 1,
+                        case_insensitive: false,
+                    }),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn undefined_backreference_pattern() {
+        assert_eq!(
+            parse_error(r"(a)(?:b)\2"),
+            (ast::ErrorKind::BackreferenceUndefined(2), 8..10)
+        );
+        assert_eq!(
+            parse_error(r"(?P<x>a)\k<y>"),
+            (
+                ast::ErrorKind::BackreferenceNameUndefined("y".to_string()),
+                8..13
+            )
+        );
+    }
+
+    #[test]
+    fn lookaround_pattern() {
+        let lookaround = |range: Range<usize>, kind, ast| {
+            ast::AST::group(ast::Group {
+                span: span(range),
+                kind: ast::GroupKind::Lookaround(kind),
+                ast: Box::new(ast),
+            })
+        };
+        assert_eq!(
+            Parser::new().parse("(?<!a)(?=b)"),
+            Ok(concat(
+                0..11,
+                vec![
+                    lookaround(0..6, ast::LookaroundKind::NegativeLookbehind, lit('a', 4)),
+                    lookaround(6..11, ast::LookaroundKind::PositiveLookahead, lit('b', 9)),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn group_errors() {
+        assert_eq!(parse_error("(a"), (ast::ErrorKind::GroupUnclosed, 0..1));
+        assert_eq!(parse_error("a)"), (ast::ErrorKind::GroupUnopened, 1..2));
+        assert_eq!(
+            parse_error("(?<a>x)(?<a>y)"),
+            (ast::ErrorKind::GroupNameDuplicate, 9..12)
+        );
+    }
+
+    #[test]
+    fn assertion_pattern() {
+        let assertion = |start, kind| {
+            ast::AST::assertion(ast::Assertion {
+                span: span(start..start + 1),
+                kind,
+            })
+        };
+        assert_eq!(
+            Parser::new().parse("^a$"),
+            Ok(concat(
+                0..3,
+                vec![
+                    assertion(0, ast::AssertionKind::StartText),
+                    lit('a', 1),
+                    assertion(2, ast::AssertionKind::EndText),
+                ]
+            ))
+        );
+        assert_eq!(
+            ParserBuilder::new().multi_line(true).build().parse("^$"),
+            Ok(concat(
+                0..2,
+                vec![
+                    assertion(0, ast::AssertionKind::StartLine),
+                    assertion(1, ast::AssertionKind::EndLine),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn verbose_pattern() {
+        let parse = |p| {
+            ParserBuilder::new()
+                .ignore_whitespace(true)
+                .build()
+                .parse(p)
+        };
+        let escaped = |c, start| {
+            ast::AST::literal(ast::Literal {
+                span: span(start..start + 2),
+                kind: ast::LiteralKind::Punctuation,
+                c,
+            })
+        };
+        assert_eq!(
+            parse("a # comment\n b\\ \\#"),
+            Ok(concat(
+                0..18,
+                vec![
+                    lit('a', 0),
+                    lit('b', 13),
+                    escaped(' ', 14),
+                    escaped('#', 16),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn case_insensitive_pattern() {
+        let parse = |p| ParserBuilder::new().case_insensitive(true).build().parse(p);
+        let class = |range, ranges: &[(char, char)]| {
+            ast::AST::class(ast::Class {
+                span: span(range),
+                negated: false,
+                ranges: ranges
+                    .iter()
+                    .map(|&(s, e)| ast::ClassRange::new(s, e))
+                    .collect(),
+            })
+        };
+        assert_eq!(
+            parse("a1[x-y]"),
+            Ok(concat(
+                0..7,
+                vec![
+                    class(0..1, &[('a', 'a'), ('A', 'A')]),
+                    lit('1', 1),
+                    class(2..7, &[('x', 'y'), ('X', 'X'), ('Y', 'Y')]),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn dot_all_pattern() {
+        let parse = |p| ParserBuilder::new().dot_all(true).build().parse(p);
+        assert_eq!(
+            parse("."),
+            Ok(ast::AST::class(ast::Class {
+                span: span(0..1),
+                negated: true,
+                ranges: vec![],
+            }))
+        );
+    }
+
+    #[test]
+    fn nest_limit() {
+        let parse = |p| ParserBuilder::new().nest_limit(2).build().parse(p);
+        assert!(parse("((a))b*").is_ok());
+        assert!(parse("(a*)").is_ok());
+
+        let err = parse("(((a)))").unwrap_err();
+        assert_eq!(err.kind, ast::ErrorKind::NestLimitExceeded(2));
+        assert_eq!(err.span, span(2..3));
+
+        let err = parse("(a)**").unwrap_err();
+        assert_eq!(err.kind, ast::ErrorKind::NestLimitExceeded(2));
+        assert_eq!(err.span, span(4..5));
+
+        let err = parse("((a){2})").unwrap_err();
+        assert_eq!(err.kind, ast::ErrorKind::NestLimitExceeded(2));
+        assert_eq!(err.span, span(4..7));
+    }
 }
@@ -0,0 +1,389 @@
+use std::{char::REPLACEMENT_CHARACTER, collections::HashMap};
+
+use crate::automata::{
+    dense::{DenseDFA, StateID, DEAD},
+    utf8,
+};
+
+/// The transition out of a partial char on a byte that can't continue it.
+const INVALID: StateID = StateID::MAX;
+
+/// A byte that never appears in UTF-8, which transitions like U+FFFD.
+const INVALID_BYTE: u8 = 0xFF;
+
+/// A DFA transitioning on the bytes of UTF-8 encoded text, compiled from a dense
+/// DFA transitioning on chars.
+///
+/// The transitions on each class of chars become transitions on the sequences of
+/// byte ranges encoding them, see `utf8::sequences`, going through intermediate
+/// states for the chars encoded in several bytes. The states of the dense DFA keep
+/// their IDs, and the partial char states come after them. Bytes are matched as
+/// they are, without decoding nor validating them first.
+///
+/// Like `utf8::decode`, a byte that isn't part of the encoding of a char matches as
+/// U+FFFD on its own. A byte that can't start a char transitions like U+FFFD, while
+/// a byte that can't continue a partial char leads to `INVALID`: the search then
+/// reads each byte of the partial char as U+FFFD, and that byte again after them.
+///
+/// A byte DFA built by `latin1` reads Latin-1 encoded text instead, where each byte
+/// is the char with the same code point, so it has no partial char states.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ByteDFA {
+    transitions: Vec<StateID>,
+    matches: Vec<bool>,
+    /// Whether each state matches at the end of the text, see `DenseDFA`.
+    end_matches: Vec<bool>,
+    /// For each partial char state, the char state it started from and the number
+    /// of bytes read since.
+    partials: Vec<(StateID, usize)>,
+    start: StateID,
+    /// The starting state for bytes after the start of the text.
+    inner_start: StateID,
+}
+
+/// What's left to read of the encodings of chars, each with the state it leads to.
+type Suffixes<'s> = Vec<(&'s [(u8, u8)], StateID)>;
+
+impl ByteDFA {
+    pub fn from(dense: &DenseDFA) -> ByteDFA {
+        let classes = dense.classes();
+        let sequences: Vec<Vec<Vec<(u8, u8)>>> = (0..classes.len())
+            .map(|class| {
+                let ranges = classes.ranges(class).into_iter();
+                ranges
+                    .flat_map(|(start, end)| utf8::sequences(start, end))
+                    .collect()
+            })
+            .collect();
+
+        let states_len = dense.states_len();
+        let mut dfa = ByteDFA {
+            transitions: vec![DEAD; states_len * 256],
+            matches: vec![],
+            end_matches: vec![],
+            partials: vec![],
+            start: dense.start_state(),
+            inner_start: dense.inner_start_state(),
+        };
+        dfa.add_matches(dense);
+
+        // The dead state already loops back to itself.
+        for state in 1..states_len as StateID {
+            let mut suffixes: Suffixes = vec![];
+            for (class, sequences) in sequences.iter().enumerate() {
+                let target = dense.next_state(state, classes.representative(class));
+                suffixes.extend(sequences.iter().map(|seq| (&seq[..], target)));
+            }
+            let invalid = dense.next_state(state, REPLACEMENT_CHARACTER);
+            dfa.add_transitions(state, state, 0, suffixes, invalid, &mut HashMap::new());
+        }
+        dfa
+    }
+
+    /// Compiles the dense DFA for Latin-1 encoded text, see `ByteDFA`.
+    pub fn latin1(dense: &DenseDFA) -> ByteDFA {
+        let states_len = dense.states_len();
+        let mut transitions = vec![DEAD; states_len * 256];
+        for state in 1..states_len as StateID {
+            for byte in 0..=255 {
+                let target = dense.next_state(state, char::from(byte));
+                transitions[state as usize * 256 + byte as usize] = target;
+            }
+        }
+        let mut dfa = ByteDFA {
+            transitions,
+            matches: vec![],
+            end_matches: vec![],
+            partials: vec![],
+            start: dense.start_state(),
+            inner_start: dense.inner_start_state(),
+        };
+        dfa.add_matches(dense);
+        dfa
+    }
+
+    // Sets whether the states of the dense DFA match, before any partial char state
+    // is added.
+    fn add_matches(&mut self, dense: &DenseDFA) {
+        let states = 0..dense.states_len() as StateID;
+        self.matches = states.clone().map(|s| dense.is_match_state(s)).collect();
+        self.end_matches = states.map(|s| dense.is_end_match_state(s)).collect();
+    }
+
+    // Sets the transitions of `from`, a state `read` bytes after the char state
+    // `origin`, on the next byte of the suffixes, building the partial char states
+    // reading the rest of them. A byte starting no suffix leads to `invalid` from a
+    // char state, and to `INVALID` from a partial char state.
+    fn add_transitions<'s>(
+        &mut self,
+        from: StateID,
+        origin: StateID,
+        read: usize,
+        suffixes: Suffixes<'s>,
+        invalid: StateID,
+        partials: &mut HashMap<(usize, Suffixes<'s>), StateID>,
+    ) {
+        for byte in 0..=255 {
+            let mut next: Suffixes = suffixes
+                .iter()
+                .filter(|(seq, _)| seq[0].0 <= byte && byte <= seq[0].1)
+                .map(|&(seq, target)| (&seq[1..], target))
+                .collect();
+
+            let target = match next.first() {
+                None if read == 0 => invalid,
+                None => INVALID,
+                // The encodings are prefix-free, so a whole char was read.
+                Some(&([], target)) => target,
+                Some(_) => {
+                    next.sort_unstable();
+                    match partials.get(&(read + 1, next.clone())) {
+                        Some(&partial) => partial,
+                        None => {
+                            let partial = self.matches.len() as StateID;
+                            self.transitions.extend([DEAD; 256]);
+                            self.matches.push(false);
+                            self.end_matches.push(false);
+                            self.partials.push((origin, read + 1));
+                            partials.insert((read + 1, next.clone()), partial);
+                            self.add_transitions(
+                                partial,
+                                origin,
+                                read + 1,
+                                next,
+                                invalid,
+                                partials,
+                            );
+                            partial
+                        }
+                    }
+                }
+            };
+            self.transitions[from as usize * 256 + byte as usize] = target;
+        }
+    }
+
+    pub fn start_state(&self) -> StateID {
+        self.start
+    }
+
+    /// The number of states, counting the partial char states.
+    pub fn states_len(&self) -> usize {
+        self.matches.len()
+    }
+
+    pub fn next_state(&self, state: StateID, byte: u8) -> StateID {
+        self.transitions[state as usize * 256 + byte as usize]
+    }
+
+    pub fn is_match_state(&self, state: StateID) -> bool {
+        self.matches[state as usize]
+    }
+
+    /// Whether the state matches at the end of the text, see
+    /// `DenseDFA::is_end_match_state`.
+    pub fn is_end_match_state(&self, state: StateID) -> bool {
+        self.end_matches[state as usize]
+    }
+
+    // Tests whether this DFA accepts the bytes.
+    pub fn test(&self, bytes: &[u8]) -> bool {
+        let mut last_match = None;
+        self.for_each_match(bytes, 0, |at| {
+            last_match = Some(at);
+            true
+        });
+        last_match == Some(bytes.len())
+    }
+
+    /// Runs the DFA over `bytes` from the offset `start` until it dies, returning the
+    /// last position where it accepted, like `DenseDFA::find_end`.
+    pub fn find_end(&self, bytes: &[u8], start: usize) -> Option<usize> {
+        let mut last_match = None;
+        self.for_each_match(bytes, start, |at| {
+            last_match = Some(at);
+            true
+        });
+        last_match
+    }
+
+    /// Runs the DFA over `bytes` from the offset `start`, returning the first
+    /// position where it accepts, like `DenseDFA::find_earliest_end`.
+    pub fn find_earliest_end(&self, bytes: &[u8], start: usize) -> Option<usize> {
+        let mut first_match = None;
+        self.for_each_match(bytes, start, |at| {
+            first_match = Some(at);
+            false
+        });
+        first_match
+    }
+
+    /// Runs the DFA over the whole `bytes` until it dies, calling `f` with every
+    /// position where it accepts, like `DenseDFA::for_each_match_end`.
+    pub fn for_each_match_end(&self, bytes: &[u8], mut f: impl FnMut(usize)) {
+        self.for_each_match(bytes, 0, |at| {
+            f(at);
+            true
+        });
+    }
+
+    // Runs the DFA over the bytes from `start`, calling `f` with every position where
+    // the DFA accepts until it dies or `f` returns false.
+    fn for_each_match(&self, bytes: &[u8], start: usize, mut f: impl FnMut(usize) -> bool) {
+        let mut state = if start == 0 {
+            self.start
+        } else {
+            self.inner_start
+        };
+        for (i, &byte) in bytes[start..].iter().enumerate() {
+            let at = start + i;
+            if self.is_match_state(state) && !f(at) {
+                return;
+            }
+            let mut next = self.next_state(state, byte);
+            if next == INVALID {
+                state = match self.read_partial(state, at, &mut f) {
+                    Some(state) => state,
+                    None => return,
+                };
+                if self.is_match_state(state) && !f(at) {
+                    return;
+                }
+                next = self.next_state(state, byte);
+            }
+            if next == DEAD {
+                return;
+            }
+            state = next;
+        }
+
+        if self.is_partial(state) {
+            state = match self.read_partial(state, bytes.len(), &mut f) {
+                Some(state) => state,
+                None => return,
+            };
+        }
+        if self.is_end_match_state(state) {
+            f(bytes.len());
+        }
+    }
+
+    fn is_partial(&self, state: StateID) -> bool {
+        state as usize >= self.states_len() - self.partials.len()
+    }
+
+    // Reads the bytes of the partial char state ending at `at` as U+FFFD, one for
+    // each, calling `f` with the positions between them where the DFA accepts.
+    // Returns the char state reached, or `None` once the DFA dies or `f` returns
+    // false.
+    fn read_partial(
+        &self,
+        state: StateID,
+        at: usize,
+        f: &mut impl FnMut(usize) -> bool,
+    ) -> Option<StateID> {
+        let chars_len = self.states_len() - self.partials.len();
+        let (mut state, read) = self.partials[state as usize - chars_len];
+        for i in 0..read {
+            // The position before the first byte was checked when reading it.
+            if i > 0 && self.is_match_state(state) && !f(at - read + i) {
+                return None;
+            }
+            state = self.next_state(state, INVALID_BYTE);
+            if state == DEAD {
+                return None;
+            }
+        }
+        Some(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        automata::{dfa_table::DFATable, limits::SizeLimits},
+        match_kind::MatchKind,
+        regex,
+    };
+
+    fn search(pattern: &str) -> (DenseDFA, ByteDFA) {
+        let table = regex::compile_nfa(pattern).unwrap().get_transition_table();
+        let table = DFATable::determinize_search(&table, MatchKind::All, &SizeLimits::default());
+        let dense = DenseDFA::from(&table.unwrap());
+        let bytes = ByteDFA::from(&dense);
+        (dense, bytes)
+    }
+
+    #[test]
+    fn matches_dense_dfa() {
+        let patterns = ["[a-z]+", "é|€+", ".", "[^a]x", "\u{FFFD}", "(😀|ab)c"];
+        let texts: [&[u8]; 8] = [
+            b"abc",
+            "xéy€€".as_bytes(),
+            "😀c".as_bytes(),
+            b"a\xffx",
+            b"\xe2\x82",
+            b"\xe2\x82x\xf0\x9f\x98",
+            b"\xed\xa0\x80\xc3",
+            b"",
+        ];
+
+        for pattern in patterns {
+            let (dense, bytes) = search(pattern);
+            for text in texts {
+                // The decoded text, with the offset in the bytes of each char.
+                let mut decoded = String::new();
+                let mut offsets = HashMap::new();
+                for (i, c) in utf8::char_indices(text) {
+                    offsets.insert(decoded.len(), i);
+                    decoded.push(c);
+                }
+                offsets.insert(decoded.len(), text.len());
+
+                let mut expected = vec![];
+                dense.for_each_match_end(&decoded, |end| expected.push(offsets[&end]));
+                let mut ends = vec![];
+                bytes.for_each_match_end(text, |end| ends.push(end));
+                assert_eq!(ends, expected, "/{pattern}/ on {text:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_bytes_match_replacement_char() {
+        let (_, bytes) = search(".");
+        // Each byte of a cut short char matches on its own.
+        let mut ends = vec![];
+        bytes.for_each_match_end(b"\xf0\x9f\x98a", |end| ends.push(end));
+        assert_eq!(ends, [1, 2, 3, 4]);
+
+        let (_, bytes) = search("a\u{FFFD}b");
+        assert_eq!(bytes.find_end(b"xa\xe2b", 0), Some(4));
+        assert_eq!(bytes.find_end(b"xa\xe2\x82b", 0), None);
+        assert_eq!(bytes.find_earliest_end("a\u{FFFD}b".as_bytes(), 0), Some(5));
+    }
+
+    #[test]
+    fn latin1() {
+        let nfa = regex::compile_nfa("[à-ÿ]+x|é").unwrap();
+        let dense = DenseDFA::from(&DFATable::determinize(&nfa.get_transition_table()));
+        let bytes = ByteDFA::latin1(&dense);
+        assert_eq!(bytes.states_len(), dense.states_len());
+        assert!(bytes.test(b"\xe0\xffx"));
+        assert!(bytes.test(b"\xe9"));
+        assert!(!bytes.test("é".as_bytes()));
+        assert!(!bytes.test(b"\xdfx"));
+    }
+
+    #[test]
+    fn test_whole_bytes() {
+        let nfa = regex::compile_nfa("[à-ÿ]+x").unwrap();
+        let dense = DenseDFA::from(&DFATable::determinize(&nfa.get_transition_table()));
+        let bytes = ByteDFA::from(&dense);
+        assert!(bytes.test("àÿx".as_bytes()));
+        assert!(!bytes.test("àÿ".as_bytes()));
+        assert!(!bytes.test(b"\xc3x"));
+        assert!(!bytes.test(b"\xc3\xa0x\xc3"));
+    }
+}
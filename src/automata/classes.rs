@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+
+/// A partition of all chars into equivalence classes.
+///
+/// Two chars are in the same class when no transition of the automaton tells them
+/// apart: every transition either matches both or none of them. An automaton only
+/// needs one column per class instead of one per char, which keeps a DFA with large
+/// Unicode classes small. The chars matched by no transition at all form a class too.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CharClasses {
+    /// Sorted and disjoint ranges covering every char, with their class.
+    ranges: Vec<(char, char, usize)>,
+    len: usize,
+}
+
+impl Default for CharClasses {
+    fn default() -> Self {
+        CharClasses::new(Vec::<Vec<(char, char)>>::new())
+    }
+}
+
+impl CharClasses {
+    /// Computes the classes from the sets of char ranges each transition matches.
+    ///
+    /// Classes are numbered in the order of their first char.
+    pub fn new<S: AsRef<[(char, char)]>>(sets: impl IntoIterator<Item = S>) -> CharClasses {
+        let sets: Vec<S> = sets.into_iter().collect();
+
+        // Chars between two consecutive boundaries are in the same ranges. The
+        // surrogate range isn't made of chars, so it's bounded too and left out.
+        let mut boundaries = vec![0, 0xD800, 0xE000, char::MAX as u32 + 1];
+        for set in &sets {
+            for &(start, end) in set.as_ref() {
+                boundaries.push(start as u32);
+                boundaries.push(end as u32 + 1);
+            }
+        }
+        boundaries.sort_unstable();
+        boundaries.dedup();
+
+        let mut classes: HashMap<Vec<usize>, usize> = HashMap::new();
+        let mut ranges: Vec<(char, char, usize)> = vec![];
+        for bounds in boundaries.windows(2) {
+            let (Some(start), Some(end)) =
+                (char::from_u32(bounds[0]), char::from_u32(bounds[1] - 1))
+            else {
+                continue;
+            };
+
+            // The sets containing the interval tell its class apart.
+            let signature: Vec<usize> = sets
+                .iter()
+                .enumerate()
+                .filter(|(_, set)| set.as_ref().iter().any(|&(s, e)| s <= start && start <= e))
+                .map(|(i, _)| i)
+                .collect();
+            let next_class = classes.len();
+            let class = *classes.entry(signature).or_insert(next_class);
+
+            match ranges.last_mut() {
+                Some(last) if last.2 == class && next_char(last.1) == Some(start) => last.1 = end,
+                _ => ranges.push((start, end, class)),
+            }
+        }
+
+        CharClasses {
+            ranges,
+            len: classes.len(),
+        }
+    }
+
+    /// One class for each of the given chars, and one for all the other chars.
+    pub fn singletons(chars: &str) -> CharClasses {
+        CharClasses::new(chars.chars().map(|c| [(c, c)]))
+    }
+
+    /// The number of classes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the class of the char.
+    pub fn class(&self, c: char) -> usize {
+        let i = self.ranges.partition_point(|&(start, _, _)| start <= c) - 1;
+        self.ranges[i].2
+    }
+
+    /// Returns the ranges of chars in the class.
+    pub fn ranges(&self, class: usize) -> Vec<(char, char)> {
+        self.ranges
+            .iter()
+            .filter(|r| r.2 == class)
+            .map(|&(start, end, _)| (start, end))
+            .collect()
+    }
+
+    /// Returns a char of the class, which behaves as any other char of it.
+    pub fn representative(&self, class: usize) -> char {
+        self.ranges.iter().find(|r| r.2 == class).unwrap().0
+    }
+
+    /// Returns the label of the class for display, e.g. "a" or "0-9,A-F".
+    pub fn label(&self, class: usize) -> String {
+        self.ranges(class)
+            .into_iter()
+            .map(|(start, end)| range_label(start, end))
+            .collect::<Vec<String>>()
+            .join(",")
+    }
+}
+
+/// Returns the label of a range of chars, e.g. "a" or "a-z".
+pub fn range_label(start: char, end: char) -> String {
+    if start == end {
+        start.escape_debug().to_string()
+    } else {
+        format!("{}-{}", start.escape_debug(), end.escape_debug())
+    }
+}
+
+fn next_char(c: char) -> Option<char> {
+    match c {
+        '\u{D7FF}' => Some('\u{E000}'),
+        c => char::from_u32(c as u32 + 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn singletons() {
+        let classes = CharClasses::singletons("ba");
+        assert_eq!(classes.len(), 3);
+
+        // The chars before 'a' come first, and take the class of every other char.
+        assert_eq!(classes.class('\0'), 0);
+        assert_eq!(classes.class('a'), 1);
+        assert_eq!(classes.class('b'), 2);
+        assert_eq!(classes.class('c'), 0);
+        assert_eq!(classes.class(char::MAX), 0);
+        assert_eq!(classes.label(1), "a");
+        assert_eq!(classes.representative(0), '\0');
+        assert_eq!(classes.ranges(0), vec![('\0', '`'), ('c', char::MAX)]);
+    }
+
+    #[test]
+    fn overlapping_ranges() {
+        // [a-z] and [0-9a-f]: the chars a-f are in both, g-z and 0-9 only in one.
+        let classes = CharClasses::new([vec![('a', 'z')], vec![('0', '9'), ('a', 'f')]]);
+        assert_eq!(classes.len(), 4);
+
+        let digits = classes.class('5');
+        let hex = classes.class('c');
+        let letters = classes.class('x');
+        assert_eq!(classes.label(digits), "0-9");
+        assert_eq!(classes.label(hex), "a-f");
+        assert_eq!(classes.label(letters), "g-z");
+        assert_eq!(classes.class('A'), classes.class('\n'));
+    }
+
+    #[test]
+    fn indistinguishable_ranges_share_a_class() {
+        // Both ranges are always matched by the same transitions.
+        let classes =
+            CharClasses::new([vec![('a', 'c'), ('x', 'z')], vec![('b', 'b'), ('y', 'y')]]);
+        assert_eq!(classes.len(), 3);
+        assert_eq!(classes.class('a'), classes.class('z'));
+        assert_eq!(classes.class('b'), classes.class('y'));
+        assert_eq!(classes.label(classes.class('a')), "a,c,x,z");
+    }
+
+    #[test]
+    fn no_transitions() {
+        let classes = CharClasses::default();
+        assert_eq!(classes.len(), 1);
+        assert_eq!(classes.class('a'), 0);
+    }
+}
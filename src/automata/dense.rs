@@ -0,0 +1,595 @@
+use std::{collections::HashMap, mem};
+
+use crate::automata::{
+    classes::CharClasses,
+    dfa_table::DFATable,
+    state::Edges,
+    utf8::{self, Decoder},
+};
+
+/// The identifier of a state in a dense DFA.
+pub type StateID = u32;
+
+/// The dead state, which never leads to a match. Every dense DFA has it at ID `0`,
+/// with all its transitions looping back to itself.
+pub const DEAD: StateID = 0;
+
+/// A DFA stored as a flat table of integer state IDs.
+///
+/// Chars are first mapped to their equivalence class, the index of their column in
+/// the table. The transition of `state` for a class is at `state * stride + class`, where
+/// the stride is the number of classes. Missing transitions of the string-labeled
+/// table go to the dead state, so the dense DFA is complete.
+///
+/// Whether a state is a match state is stored in a bitset, along with whether it
+/// matches at the end of the text, where `$` holds. Following transitions and
+/// checking for a match never allocates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DenseDFA {
+    classes: CharClasses,
+    stride: usize,
+    transitions: Vec<StateID>,
+    matches: Vec<u64>,
+    end_matches: Vec<u64>,
+    /// The patterns each state matches, for a DFA of several patterns. Empty for a
+    /// single pattern.
+    patterns: Vec<Vec<usize>>,
+    /// The patterns each state matches at the end of the text, when they differ.
+    end_patterns: Vec<Vec<usize>>,
+    start: StateID,
+    /// The starting state for chars after the start of the text.
+    inner_start: StateID,
+}
+
+impl DenseDFA {
+    pub fn from(table: &DFATable) -> DenseDFA {
+        let classes: HashMap<String, usize> = (0..table.classes.len())
+            .map(|class| (table.classes.label(class), class))
+            .collect();
+
+        // The dead state comes first, then the labeled states in table order.
+        let ids: HashMap<&String, StateID> = table
+            .table
+            .keys()
+            .enumerate()
+            .map(|(i, label)| (label, i as StateID + 1))
+            .collect();
+        let states_len = table.table.len() + 1;
+        let stride = table.classes.len();
+
+        let mut dfa = DenseDFA {
+            classes: table.classes.clone(),
+            stride,
+            transitions: vec![DEAD; states_len * stride],
+            matches: vec![0; states_len.div_ceil(64)],
+            end_matches: vec![0; states_len.div_ceil(64)],
+            patterns: vec![],
+            end_patterns: vec![],
+            start: ids.get(&table.starting_state).copied().unwrap_or(DEAD),
+            inner_start: ids
+                .get(table.inner_starting_state())
+                .copied()
+                .unwrap_or(DEAD),
+        };
+
+        for (label, row) in table.table.iter() {
+            let id = ids[label];
+            for (symbol, target) in row.iter() {
+                let i = id as usize * stride + classes[symbol];
+                dfa.transitions[i] = ids[target];
+            }
+            if table.accepting_states.contains(label) {
+                dfa.matches[id as usize / 64] |= 1 << (id % 64);
+            }
+            if table.accepting_states.contains(label) || table.end_accepting_states.contains(label)
+            {
+                dfa.end_matches[id as usize / 64] |= 1 << (id % 64);
+            }
+            if let Some(patterns) = table.patterns.get(label) {
+                dfa.patterns.resize(states_len, vec![]);
+                dfa.patterns[id as usize] = patterns.iter().copied().collect();
+            }
+            if let Some(patterns) = table.end_patterns.get(label) {
+                dfa.end_patterns.resize(states_len, vec![]);
+                dfa.end_patterns[id as usize] = patterns.iter().copied().collect();
+            }
+        }
+
+        dfa
+    }
+
+    /// The equivalence classes of chars the transitions are labeled with.
+    pub fn classes(&self) -> &CharClasses {
+        &self.classes
+    }
+
+    pub fn start_state(&self) -> StateID {
+        self.start
+    }
+
+    /// The starting state for chars after the start of the text, which differs from
+    /// `start_state` when the DFA has a `^`.
+    pub fn inner_start_state(&self) -> StateID {
+        self.inner_start
+    }
+
+    /// The starting state for chars with the given edges.
+    pub fn start_state_at(&self, edges: Edges) -> StateID {
+        if edges.start {
+            self.start
+        } else {
+            self.inner_start
+        }
+    }
+
+    /// The number of states, including the dead state.
+    pub fn states_len(&self) -> usize {
+        self.transitions.len() / self.stride
+    }
+
+    /// The number of classes, which is the length of each row of the table.
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// Returns the class of the char, which is its column in the table.
+    pub fn class(&self, c: char) -> usize {
+        self.classes.class(c)
+    }
+
+    pub fn next_state(&self, state: StateID, c: char) -> StateID {
+        self.transitions[state as usize * self.stride + self.class(c)]
+    }
+
+    pub fn is_match_state(&self, state: StateID) -> bool {
+        self.matches[state as usize / 64] & (1 << (state % 64)) != 0
+    }
+
+    /// Whether the state is a match state at the end of the text. Match states
+    /// always are, and so are the states waiting for a `$`.
+    pub fn is_end_match_state(&self, state: StateID) -> bool {
+        self.end_matches[state as usize / 64] & (1 << (state % 64)) != 0
+    }
+
+    /// The IDs of the patterns the state matches, in order, for a DFA built from a
+    /// table combining several patterns, see `NFATable::union`. Always empty for a
+    /// DFA of a single pattern.
+    pub fn match_patterns(&self, state: StateID) -> &[usize] {
+        self.patterns
+            .get(state as usize)
+            .map_or(&[], |patterns| patterns)
+    }
+
+    /// The IDs of the patterns the state matches at the end of the text, like
+    /// `match_patterns`.
+    pub fn end_match_patterns(&self, state: StateID) -> &[usize] {
+        match self.end_patterns.get(state as usize) {
+            Some(patterns) if !patterns.is_empty() => patterns,
+            _ => self.match_patterns(state),
+        }
+    }
+
+    pub fn is_dead_state(&self, state: StateID) -> bool {
+        state == DEAD
+    }
+
+    // Tests whether this DFA accepts the string.
+    pub fn test(&self, string: &str) -> bool {
+        let mut state = self.start;
+        for c in string.chars() {
+            state = self.next_state(state, c);
+            if self.is_dead_state(state) {
+                return false;
+            }
+        }
+        self.is_end_match_state(state)
+    }
+
+    /// Runs the DFA over `text` from the byte offset `start` until it dies, returning
+    /// the last position where it accepted. For a DFA built by
+    /// `DFATable::determinize_search`, this is the end of the leftmost-first match.
+    pub fn find_end(&self, text: &str, start: usize) -> Option<usize> {
+        let chars = text[start..].char_indices().map(|(i, c)| (start + i, c));
+        self.find_match(chars, Edges::from_offset(start), text.len(), false)
+    }
+
+    /// Runs the DFA over `text` from the byte offset `start`, returning the first
+    /// position where it accepts. For a DFA searching for all matches, this is the
+    /// end of the match ending first.
+    pub fn find_earliest_end(&self, text: &str, start: usize) -> Option<usize> {
+        let chars = text[start..].char_indices().map(|(i, c)| (start + i, c));
+        self.find_match(chars, Edges::from_offset(start), text.len(), true)
+    }
+
+    /// Runs the DFA backwards over `text[start..end]` from `end` until it dies,
+    /// returning the last position where it accepted. For a DFA of the reversed
+    /// NFA, this is the leftmost start of a match ending at `end`.
+    pub fn rfind_start(&self, text: &str, start: usize, end: usize) -> Option<usize> {
+        let chars = text[start..end]
+            .char_indices()
+            .rev()
+            .map(|(i, c)| (start + i + c.len_utf8(), c));
+        self.find_match(
+            chars,
+            Edges::backwards(start, end, text.len()),
+            start,
+            false,
+        )
+    }
+
+    /// Runs the DFA backwards over `bytes[start..end]` like `rfind_start`, decoding
+    /// the bytes like `utf8::decode`, so that invalid UTF-8 reads as U+FFFD.
+    pub fn rfind_start_bytes(&self, bytes: &[u8], start: usize, end: usize) -> Option<usize> {
+        // A U+FFFD may stand for a single invalid byte, so the position after a
+        // char is the one before the char read previously.
+        let mut after = end;
+        let chars = utf8::char_indices(&bytes[start..end])
+            .rev()
+            .map(move |(i, c)| (mem::replace(&mut after, start + i), c));
+        self.find_match(
+            chars,
+            Edges::backwards(start, end, bytes.len()),
+            start,
+            false,
+        )
+    }
+
+    /// Runs the DFA over the whole `text` until it dies, calling `f` with every
+    /// position where it accepts. For a DFA searching for all matches, these are
+    /// the ends of every match, overlapping ones included.
+    pub fn for_each_match_end(&self, text: &str, mut f: impl FnMut(usize)) {
+        self.for_each_match(text.char_indices(), Edges::WHOLE, text.len(), |at| {
+            f(at);
+            true
+        });
+    }
+
+    /// Starts running the DFA over a text given in chunks, see `Stream`.
+    pub fn stream(&self) -> Stream<'_> {
+        Stream {
+            dfa: self,
+            state: self.start,
+            decoder: Decoder::default(),
+            offset: 0,
+            started: false,
+            ended: false,
+            matches: vec![],
+        }
+    }
+
+    /// Runs the DFA over the chars, each given with the position before it, returning
+    /// the last position where the DFA accepted, the one after the last char being
+    /// `end`. The earliest search returns the first position instead. The edges tell
+    /// whether the chars begin at the start of the text and end at its end.
+    ///
+    /// The chars may come from any encoding, and be given in reverse for a backward
+    /// search, each with the position after it.
+    pub fn find_match(
+        &self,
+        chars: impl Iterator<Item = (usize, char)>,
+        edges: Edges,
+        end: usize,
+        earliest: bool,
+    ) -> Option<usize> {
+        let mut last_match = None;
+        self.for_each_match(chars, edges, end, |at| {
+            last_match = Some(at);
+            !earliest
+        });
+        last_match
+    }
+
+    // Runs the DFA over the chars like `find_match`, calling `f` with every position
+    // where the DFA accepts until it dies or `f` returns false.
+    fn for_each_match(
+        &self,
+        chars: impl Iterator<Item = (usize, char)>,
+        edges: Edges,
+        end: usize,
+        mut f: impl FnMut(usize) -> bool,
+    ) {
+        let mut state = self.start_state_at(edges);
+        for (at, c) in chars {
+            if self.is_match_state(state) && !f(at) {
+                return;
+            }
+            state = self.next_state(state, c);
+            if self.is_dead_state(state) {
+                return;
+            }
+        }
+        let matched = if edges.end {
+            self.is_end_match_state(state)
+        } else {
+            self.is_match_state(state)
+        };
+        if matched {
+            f(end);
+        }
+    }
+}
+
+/// A DFA running over a text given in chunks, e.g. read from a file or the
+/// network, without ever holding the whole text.
+///
+/// Like `DenseDFA::for_each_match_end`, the stream reports every position where
+/// the DFA accepts, as soon as a chunk reveals it. Positions are byte offsets
+/// from the start of the whole text, so a match may span any number of chunks.
+/// The chunks are UTF-8 encoded: a char split between two chunks is decoded once
+/// the next one completes it, and invalid sequences decode to U+FFFD.
+#[derive(Debug)]
+pub struct Stream<'d> {
+    dfa: &'d DenseDFA,
+    state: StateID,
+    decoder: Decoder,
+    /// The number of bytes decoded so far.
+    offset: usize,
+    /// Whether the starting state was checked for a match.
+    started: bool,
+    /// Whether the text ended, where `$` holds.
+    ended: bool,
+    /// The positions where the DFA accepted during the last call.
+    matches: Vec<usize>,
+}
+
+impl Stream<'_> {
+    /// Feeds the next chunk of the text, returning the positions where the DFA
+    /// accepts that it revealed.
+    pub fn feed(&mut self, chunk: impl AsRef<[u8]>) -> &[usize] {
+        self.start();
+        let mut decoder = mem::take(&mut self.decoder);
+        decoder.decode(chunk.as_ref(), |c, len| self.step(c, len));
+        self.decoder = decoder;
+        &self.matches
+    }
+
+    /// Ends the text, decoding the bytes of a char it cut short as U+FFFD, and
+    /// returns the positions where the DFA accepts that it revealed.
+    pub fn end(&mut self) -> &[usize] {
+        self.start();
+        let mut decoder = mem::take(&mut self.decoder);
+        decoder.finish(|c, len| self.step(c, len));
+        self.decoder = decoder;
+        if !self.is_match() && self.dfa.is_end_match_state(self.state) {
+            self.matches.push(self.offset);
+        }
+        self.ended = true;
+        &self.matches
+    }
+
+    /// Whether the DFA accepts the text decoded so far. Once the stream ended,
+    /// this tells whether a DFA built by `DFA::from` matches the whole text.
+    pub fn is_match(&self) -> bool {
+        if self.ended {
+            self.dfa.is_end_match_state(self.state)
+        } else {
+            self.dfa.is_match_state(self.state)
+        }
+    }
+
+    /// Whether the DFA died, so that no chunk can make it accept anymore.
+    pub fn is_dead(&self) -> bool {
+        self.dfa.is_dead_state(self.state)
+    }
+
+    /// The number of bytes decoded so far.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    // Clears the positions reported by the last call, reporting the start of the
+    // text on the first one if the DFA accepts it.
+    fn start(&mut self) {
+        self.matches.clear();
+        if !self.started && self.is_match() {
+            self.matches.push(0);
+        }
+        self.started = true;
+    }
+
+    // Moves the DFA past a char encoded in `len` bytes.
+    fn step(&mut self, c: char, len: usize) {
+        self.offset += len;
+        if self.is_dead() {
+            return;
+        }
+        self.state = self.dfa.next_state(self.state, c);
+        if self.is_match() {
+            self.matches.push(self.offset);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashSet};
+
+    use super::*;
+    use crate::{
+        automata::{limits::SizeLimits, nfa::NFA},
+        match_kind::MatchKind,
+        regex,
+    };
+
+    #[test]
+    fn from_table() {
+        // Given the DFA table of /a|bc/ with simplified notations:
+        //
+        // ┌─────┬───┬───┬───┐
+        // │     │ a │ b │ c │
+        // ├─────┼───┼───┼───┤
+        // │ 1 > │ 2 │ 3 │   │
+        // ├─────┼───┼───┼───┤
+        // │ 2 ✓ │   │   │   │
+        // ├─────┼───┼───┼───┤
+        // │ 3   │   │   │ 4 │
+        // ├─────┼───┼───┼───┤
+        // │ 4 ✓ │   │   │   │
+        // └─────┴───┴───┴───┘
+        //
+        // The dense DFA adds the dead state 0. Its first class holds the chars other
+        // than a, b and c, as they come first:
+        //
+        // ┌─────┬───┬───┬───┬───┐
+        // │     │ * │ a │ b │ c │
+        // ├─────┼───┼───┼───┼───┤
+        // │ 0   │ 0 │ 0 │ 0 │ 0 │
+        // ├─────┼───┼───┼───┼───┤
+        // │ 1 > │ 0 │ 2 │ 3 │ 0 │
+        // ├─────┼───┼───┼───┼───┤
+        // │ 2 ✓ │ 0 │ 0 │ 0 │ 0 │
+        // ├─────┼───┼───┼───┼───┤
+        // │ 3   │ 0 │ 0 │ 0 │ 4 │
+        // ├─────┼───┼───┼───┼───┤
+        // │ 4 ✓ │ 0 │ 0 │ 0 │ 0 │
+        // └─────┴───┴───┴───┴───┘
+        let mut table = DFATable::from(&NFA::or(vec![
+            NFA::char('a'),
+            NFA::concat(vec![NFA::char('b'), NFA::char('c')]),
+        ]));
+        table.simplify_notations();
+        assert_eq!(table.starting_state, "1");
+        assert_eq!(
+            table.accepting_states,
+            HashSet::from(["2".to_string(), "4".to_string()])
+        );
+        assert_eq!(
+            table.table.get("3"),
+            Some(&BTreeMap::from([("c".to_string(), "4".to_string())]))
+        );
+
+        let dfa = DenseDFA::from(&table);
+        assert_eq!(dfa.states_len(), 5);
+        assert_eq!(dfa.stride(), 4);
+        assert_eq!(dfa.start_state(), 1);
+        #[rustfmt::skip]
+        assert_eq!(
+            dfa.transitions,
+            vec![
+                0, 0, 0, 0,
+                0, 2, 3, 0,
+                0, 0, 0, 0,
+                0, 0, 0, 4,
+                0, 0, 0, 0,
+            ]
+        );
+        let matches: Vec<StateID> = (0..5).filter(|&s| dfa.is_match_state(s)).collect();
+        assert_eq!(matches, vec![2, 4]);
+
+        assert_eq!(dfa.next_state(1, 'b'), 3);
+        assert_eq!(dfa.next_state(1, 'z'), DEAD);
+    }
+
+    #[test]
+    fn test_strings() {
+        let nfa = regex::compile_nfa("(a|b)*abb|c").unwrap();
+        let dfa = DenseDFA::from(&DFATable::from(&nfa));
+
+        assert!(dfa.test("abb"));
+        assert!(dfa.test("babaabb"));
+        assert!(dfa.test("c"));
+        assert!(!dfa.test("ab"));
+        assert!(!dfa.test("abbc"));
+        assert!(!dfa.test("xabb"));
+    }
+
+    #[test]
+    fn large_classes() {
+        // Chars no transition tells apart share a column, however many there are.
+        let nfa = regex::compile_nfa("[^a]+a|[α-ω]").unwrap();
+        let dfa = DenseDFA::from(&DFATable::from(&nfa));
+
+        assert_eq!(dfa.stride(), 3);
+        assert_eq!(dfa.class('b'), dfa.class('€'));
+        assert!(dfa.test("€€a"));
+        assert!(dfa.test("β"));
+        assert!(!dfa.test("a"));
+    }
+
+    #[test]
+    fn many_states() {
+        // More than 64 states, so the match bitset takes several words.
+        let nfa = NFA::concat((0..100).map(|_| NFA::char('a')).collect());
+        let dfa = DenseDFA::from(&DFATable::from(&nfa));
+
+        assert!(dfa.states_len() > 64);
+        assert!(dfa.test(&"a".repeat(100)));
+        assert!(!dfa.test(&"a".repeat(99)));
+        assert!(!dfa.test(&"a".repeat(101)));
+    }
+
+    #[test]
+    fn find_end_and_start() {
+        let nfa = regex::compile_nfa("ab|a(b|c)*").unwrap();
+        let table = nfa.get_transition_table();
+        let search = DenseDFA::from(
+            &DFATable::determinize_search(&table, MatchKind::LeftmostFirst, &SizeLimits::default())
+                .unwrap(),
+        );
+        let reverse = DenseDFA::from(&DFATable::determinize(&table.reverse()));
+
+        // The first alternative wins over the longer match of the second one.
+        assert_eq!(search.find_end("xabcb", 0), Some(3));
+        assert_eq!(reverse.rfind_start("xabcb", 0, 3), Some(1));
+        assert_eq!(search.find_end("xacbc", 0), Some(5));
+        assert_eq!(search.find_end("xyz", 0), None);
+    }
+
+    #[test]
+    fn find_end_match_kinds() {
+        let table = regex::compile_nfa("a|ab").unwrap().get_transition_table();
+        let search = |kind| {
+            DenseDFA::from(
+                &DFATable::determinize_search(&table, kind, &SizeLimits::default()).unwrap(),
+            )
+        };
+
+        assert_eq!(search(MatchKind::LeftmostFirst).find_end("xab", 0), Some(2));
+        assert_eq!(
+            search(MatchKind::LeftmostLongest).find_end("xab", 0),
+            Some(3)
+        );
+        assert_eq!(search(MatchKind::All).find_earliest_end("xab", 0), Some(2));
+        // Searching for all matches, the DFA goes on after the first one.
+        assert_eq!(search(MatchKind::All).find_end("xab", 0), Some(3));
+    }
+
+    #[test]
+    fn stream_chunks() {
+        let table = regex::compile_nfa("abc|€").unwrap().get_transition_table();
+        let search = DenseDFA::from(
+            &DFATable::determinize_search(&table, MatchKind::All, &SizeLimits::default()).unwrap(),
+        );
+
+        // Matches spanning chunks are reported by the chunk they end in.
+        let mut stream = search.stream();
+        assert_eq!(stream.feed("xa"), []);
+        assert_eq!(stream.feed(b"b"), []);
+        assert_eq!(stream.feed("cab"), [4]);
+        assert_eq!(stream.feed(b"c\xe2\x82"), [7]);
+        assert_eq!(stream.feed(b"\xac"), [10]);
+        assert_eq!(stream.end(), []);
+        assert_eq!(stream.offset(), 10);
+
+        let test = DenseDFA::from(&DFATable::determinize(&table));
+        let mut stream = test.stream();
+        stream.feed("ab");
+        assert!(!stream.is_match());
+        stream.feed("c");
+        assert!(stream.is_match());
+        stream.feed("c");
+        assert!(stream.is_dead());
+        assert_eq!(stream.end(), []);
+
+        // A match ending with `$` is only reported once the stream ends.
+        let table = regex::compile_nfa("^a|b$").unwrap().get_transition_table();
+        let search = DenseDFA::from(
+            &DFATable::determinize_search(&table, MatchKind::All, &SizeLimits::default()).unwrap(),
+        );
+        let mut stream = search.stream();
+        assert_eq!(stream.feed("ab"), [1]);
+        assert_eq!(stream.feed("ab"), []);
+        assert_eq!(stream.end(), [4]);
+        assert_eq!(stream.offset(), 4);
+    }
+}
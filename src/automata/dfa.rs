@@ -1,20 +1,60 @@
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
-use crate::automata::{dfa_table::DFATable, nfa::NFA};
+use crate::{
+    automata::{
+        classes::CharClasses,
+        dense::{DenseDFA, Stream},
+        dfa_table::DFATable,
+        limits::SizeLimits,
+        nfa::NFA,
+    },
+    error::Error,
+    match_kind::MatchKind,
+};
 
+// The string-labeled table shows how the DFA was built, while matching runs on the
+// dense DFA compiled from it, which is rebuilt whenever the table changes.
 #[derive(Debug, Clone)]
 pub struct DFA {
     table: DFATable,
+    dense: DenseDFA,
 }
 
 impl DFA {
     pub fn from(nfa: &NFA) -> Self {
-        dbg!(&nfa);
-        let d = DFA {
-            table: DFATable::from(nfa),
-        };
-        dbg!(&d);
-        d
+        DFA::from_table(DFATable::from(nfa))
+    }
+
+    pub fn from_with_limits(nfa: &NFA, limits: &SizeLimits) -> Result<Self, Error> {
+        Ok(DFA::from_table(DFATable::from_with_limits(nfa, limits)?))
+    }
+
+    // Builds the DFA searching for matches of the NFA anywhere in a text, see
+    // `find_end` and `find_earliest_end`.
+    pub fn search(nfa: &NFA, kind: MatchKind, limits: &SizeLimits) -> Result<Self, Error> {
+        let table = DFATable::determinize_search(&nfa.get_transition_table(), kind, limits)?;
+        Ok(DFA::from_table(table))
+    }
+
+    // Builds the DFA matching the reversed strings of the NFA, see `rfind_start`.
+    pub fn reversed(nfa: &NFA, limits: &SizeLimits) -> Result<Self, Error> {
+        let reversed = nfa.get_transition_table().reverse();
+        let table = DFATable::determinize_with_limits(&reversed, limits)?;
+        Ok(DFA::from_table(table))
+    }
+
+    pub fn from_table(table: DFATable) -> Self {
+        let dense = DenseDFA::from(&table);
+        DFA { table, dense }
+    }
+
+    pub fn dense(&self) -> &DenseDFA {
+        &self.dense
+    }
+
+    // The equivalence classes of chars the transitions are labeled with.
+    pub fn classes(&self) -> &CharClasses {
+        &self.table.classes
     }
 
     // DFA alphabet. Same as in NFA, except ε.
@@ -43,126 +83,279 @@ impl DFA {
 
     pub fn simplify_notations(&mut self) {
         self.table.simplify_notations();
+        self.dense = DenseDFA::from(&self.table);
     }
 
-    // Minimize this DFA.
-    // To check whether they are equivalent we should see where we go from each character from these states,
-    // if we go to the states which belong to the same group, the original states are equivalent.
-    // Even if the actual states are different, the important is whether the outgoing state belongs to the same group.
+    // Minimizes the DFA with Hopcroft's partition refinement algorithm, merging
+    // every group of equivalent states into a single state labeled by the group,
+    // e.g. "1,3". States that can never reach an accepting state are dropped along
+    // with the transitions going into them. Returns whether any state was merged or
+    // dropped.
     pub fn minimize(&mut self) -> bool {
-        let mut groups = vec![];
-        let mut minimizing = true;
-        let mut minimized = false;
-
-        while minimizing {
-            minimizing = self.minimize_once(&mut groups);
-            if minimizing {
-                minimized = true;
-            }
+        let states: Vec<String> = self.table.table.keys().cloned().collect();
+        let mut groups = self.partition(&states);
+
+        // The group of the dead state holds all the states that can't accept. Unless the
+        // starting state is one of them, they are dropped like the dead state itself.
+        let dead = states.len();
+        let dead_group = groups.iter().position(|g| g.contains(&dead)).unwrap();
+        let mut dead_group = groups.swap_remove(dead_group);
+        dead_group.retain(|&s| s != dead);
+        if dead_group
+            .iter()
+            .any(|&s| states[s] == self.table.starting_state)
+        {
+            groups.push(dead_group);
+        } else {
+            let dead_states: HashSet<String> =
+                dead_group.iter().map(|&s| states[s].to_owned()).collect();
+            self.table.remove_states(&dead_states);
         }
 
+        let groups: Vec<Vec<String>> = groups
+            .into_iter()
+            .filter(|g| !g.is_empty())
+            .map(|g| g.into_iter().map(|s| states[s].to_owned()).collect())
+            .collect();
+
+        let minimized = groups.len() < states.len();
         if minimized {
             self.table.apply_minimization(&groups);
+            self.dense = DenseDFA::from(&self.table);
         }
 
         minimized
     }
 
-    fn minimize_once(&mut self, groups: &mut Vec<Vec<String>>) -> bool {
-        let groups_len = groups.len();
-        if groups_len == 0 {
-            let mut non_accepting_states = Vec::new();
-            let mut accepting_states = Vec::new();
+    // Minimizes the DFA with Brzozowski's algorithm: reversing and determinizing the
+    // automaton twice. Determinizing the reverse of a DFA whose states are all reachable
+    // yields a minimal DFA for the reversed language, so doing it twice yields the
+    // minimal DFA for the original one, without the dead state.
+    //
+    // The subset construction can take exponential time on the reversed automaton,
+    // while it's often faster than Hopcroft's algorithm when it doesn't. Returns whether
+    // the number of states went down. The reversed automaton only has one accepting
+    // state, so a DFA of several patterns loses them, see `minimize` instead. Neither
+    // can it tell apart the two starting states of a DFA with a `^`, which may accept
+    // unrelated texts, e.g. when a search DFA stops at its first match, so such a DFA
+    // is minimized by `minimize`.
+    pub fn minimize_brzozowski(&mut self) -> bool {
+        if self.table.inner_starting_state.is_some() {
+            return self.minimize();
+        }
+        let states_len = self.table.table.len();
 
-            for (state, _) in self.table.table.iter() {
-                if self.table.accepting_states.contains(state) {
-                    accepting_states.push(state.to_owned());
-                } else {
-                    non_accepting_states.push(state.to_owned());
-                }
-            }
+        let reversed = self.table.determinize_reversed();
+        self.table = reversed.determinize_reversed();
+        self.dense = DenseDFA::from(&self.table);
 
-            groups.push(non_accepting_states);
-            groups.push(accepting_states);
+        self.table.table.len() < states_len
+    }
+
+    // Splits the states into groups of equivalent states, identifying each state by its
+    // index in `states`.
+    //
+    // The DFA is completed first with a dead state, with index `states.len()`, which every
+    // missing transition goes to. The states start split by whether they accept, and
+    // whether they accept at the end of the text, and every group is used in turn as a
+    // splitter: for each symbol, a group whose states move into the splitter only
+    // partially is split in two. Only the smaller half of a split is queued as a new
+    // splitter, which bounds the work to O(n log n). Once no splitter is left, states in
+    // the same group are equivalent.
+    fn partition(&self, states: &[String]) -> Vec<Vec<usize>> {
+        let dead = states.len();
+        let ids: HashMap<&str, usize> = states
+            .iter()
+            .enumerate()
+            .map(|(i, label)| (label.as_str(), i))
+            .collect();
+        let alphabet: BTreeSet<&String> = self
+            .table
+            .table
+            .values()
+            .flat_map(|row| row.keys())
+            .collect();
 
-            return true;
+        // For each symbol, the states moving into each state by that symbol.
+        let mut inverse = vec![vec![vec![]; dead + 1]; alphabet.len()];
+        for (s, label) in states.iter().enumerate() {
+            let row = &self.table.table[label];
+            for (a, &symbol) in alphabet.iter().enumerate() {
+                let t = row.get(symbol).map_or(dead, |t| ids[t.as_str()]);
+                inverse[a][t].push(s);
+            }
+        }
+        for transitions in inverse.iter_mut() {
+            transitions[dead].push(dead);
         }
 
-        let mut base_group_iter = groups[0].to_owned().into_iter();
-        let mut state_a = base_group_iter.next().unwrap();
-        let mut new_base_group = vec![state_a.to_owned()];
+        // The accepting states of a DFA of several patterns are only equivalent when
+        // they match the same patterns, and so are the states matching at the end of
+        // the text. The rejecting states come last.
+        let table = &self.table;
+        let mut matching: BTreeMap<_, Vec<usize>> = BTreeMap::new();
+        for s in 0..=dead {
+            let label = states.get(s);
+            let key = label.map(|label| {
+                (
+                    !table.accepting_states.contains(label),
+                    table.patterns.get(label),
+                    table.end_accepting_states.contains(label),
+                    table.end_patterns.get(label),
+                )
+            });
+            matching
+                .entry(key.unwrap_or((true, None, false, None)))
+                .or_default()
+                .push(s);
+        }
+        // The states, listed group by group, with the range of each group. Splitting a
+        // group reorders its states in place, so it only costs the states moving.
+        let mut states_by_group: Vec<usize> = vec![];
+        let mut bounds: Vec<(usize, usize)> = vec![];
+        for group in matching.into_values() {
+            let start = states_by_group.len();
+            states_by_group.extend(group);
+            bounds.push((start, states_by_group.len()));
+        }
+        let mut position = vec![0; dead + 1];
+        let mut group_of = vec![0; dead + 1];
+        for (g, &(start, end)) in bounds.iter().enumerate() {
+            for i in start..end {
+                position[states_by_group[i]] = i;
+                group_of[states_by_group[i]] = g;
+            }
+        }
 
-        while let Some(state_b) = base_group_iter.next() {
-            println!("-----------------");
-            println!("state_a {:?}", state_a);
-            println!("state_b {:?}", state_b);
+        // Splitting by all the groups but one splits by the last one too.
+        let groups_len = bounds.len();
+        let largest = (0..groups_len)
+            .max_by_key(|&g| bounds[g].1 - bounds[g].0)
+            .unwrap();
+        let mut splitters: Vec<(usize, usize)> = (0..groups_len)
+            .filter(|&g| g != largest)
+            .flat_map(|g| (0..alphabet.len()).map(move |a| (g, a)))
+            .collect();
+        let mut queued: HashSet<(usize, usize)> = splitters.iter().copied().collect();
 
-            let state_a_transitions = self.table.table.get(&state_a).unwrap();
-            let state_b_transitions = self.table.table.get(&state_b).unwrap();
-            println!("state_a_transitions {:#?}", state_a_transitions);
-            println!("state_b_transitions {:#?}", state_b_transitions);
+        // The number of states of each group moving into the splitter, which are moved
+        // to the front of the group.
+        let mut moved = vec![0; groups_len];
+        while let Some((splitter, a)) = splitters.pop() {
+            queued.remove(&(splitter, a));
 
-            let mut moved = false;
+            let (start, end) = bounds[splitter];
+            let moving: Vec<usize> = states_by_group[start..end]
+                .iter()
+                .flat_map(|&t| inverse[a][t].iter().copied())
+                .collect();
+            let mut touched = vec![];
+            for s in moving {
+                let g = group_of[s];
+                if moved[g] == 0 {
+                    touched.push(g);
+                }
+                // Swap the state with the first state of its group not moved yet.
+                let first = bounds[g].0 + moved[g];
+                let at = position[s];
+                states_by_group.swap(at, first);
+                position[states_by_group[at]] = at;
+                position[s] = first;
+                moved[g] += 1;
+            }
 
-            for (transition, transition_state_a) in state_a_transitions {
-                let transition_state_b = state_b_transitions.get(transition).unwrap();
+            for g in touched {
+                let (start, end) = bounds[g];
+                let middle = start + std::mem::take(&mut moved[g]);
+                if middle == end {
+                    continue;
+                }
 
-                let group = &groups[0];
-                if transition_state_a != transition_state_b
-                    && (!group.contains(transition_state_a) || !group.contains(transition_state_b))
-                {
-                    groups.push(vec![state_b.to_owned()]);
-                    moved = true;
+                // The group keeps its larger half, and the smaller one becomes a new
+                // group, whose states are the only ones relabeled.
+                let split = if middle - start <= end - middle {
+                    bounds[g] = (middle, end);
+                    (start, middle)
+                } else {
+                    bounds[g] = (start, middle);
+                    (middle, end)
+                };
+                let new_group = bounds.len();
+                for &s in &states_by_group[split.0..split.1] {
+                    group_of[s] = new_group;
                 }
-            }
+                bounds.push(split);
+                moved.push(0);
 
-            if !moved {
-                new_base_group.push(state_b.to_owned());
-                state_a = state_b;
+                // Whether or not the split group was still queued as a splitter for a
+                // symbol, queuing its smaller half is enough.
+                for c in 0..alphabet.len() {
+                    if queued.insert((new_group, c)) {
+                        splitters.push((new_group, c));
+                    }
+                }
             }
         }
-        // println!("END-----------------");
 
-        if groups[0].len() != new_base_group.len() {
-            groups[0] = new_base_group;
-            // println!("groups {:#?}", groups);
-            return true;
-        }
-
-        // println!("groups {:#?}", groups);
-        false
+        bounds
+            .into_iter()
+            .map(|(start, end)| {
+                let mut group = states_by_group[start..end].to_vec();
+                group.sort_unstable();
+                group
+            })
+            .collect()
     }
 
     // Tests whether this DFA accepts the string.
     pub fn test(&self, string: &str) -> bool {
-        dbg!(&self);
-        let table = self.get_transition_table();
-        // println!("test table={:#?}", &table);
-        dbg!(&table);
-
-        let mut state = self.get_starting_state();
-        // println!("test starting_state={:#?}", &state);
-        dbg!(&state);
-
-        for c in string.chars() {
-            // println!("test c={c} state={:?}", state);
-            dbg!(&c);
-            let looking_state = table.get(&state).unwrap().get(&c.to_string());
-
-            if let Some(s) = looking_state {
-                state = s.to_string();
-            } else {
-                return false;
-            }
-        }
+        self.dense.test(string)
+    }
+
+    // Returns the last position where this DFA accepts, running from `start`.
+    pub fn find_end(&self, text: &str, start: usize) -> Option<usize> {
+        self.dense.find_end(text, start)
+    }
+
+    // Returns the first position where this DFA accepts, running from `start`.
+    pub fn find_earliest_end(&self, text: &str, start: usize) -> Option<usize> {
+        self.dense.find_earliest_end(text, start)
+    }
+
+    // Returns the last position where this DFA accepts, running backwards over
+    // `text[start..end]`.
+    pub fn rfind_start(&self, text: &str, start: usize, end: usize) -> Option<usize> {
+        self.dense.rfind_start(text, start, end)
+    }
+
+    // Returns the last position where this DFA accepts, running backwards over
+    // `bytes[start..end]` decoded like `utf8::decode`.
+    pub fn rfind_start_bytes(&self, bytes: &[u8], start: usize, end: usize) -> Option<usize> {
+        self.dense.rfind_start_bytes(bytes, start, end)
+    }
+
+    // Calls `f` with every position where this DFA accepts, running over the whole
+    // text until it dies. Built by `search` with `MatchKind::All`, the DFA reports
+    // the end of every match, overlapping ones included, e.g. 2, 3 and 4 for `aa`
+    // in "aaaa".
+    pub fn for_each_match_end(&self, text: &str, f: impl FnMut(usize)) {
+        self.dense.for_each_match_end(text, f)
+    }
 
-        self.get_accepting_states().contains(&state)
+    // Starts running this DFA over a text given in chunks, reporting the positions
+    // where it accepts as `for_each_match_end` does, see `Stream`.
+    pub fn stream(&self) -> Stream<'_> {
+        self.dense.stream()
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison, clippy::unnecessary_to_owned)]
 mod tests {
-    use crate::automata::dfa::DFA;
+    use crate::{
+        automata::{dfa::DFA, nfa_table::NFATable},
+        regex,
+    };
 
     use super::*;
 
@@ -288,19 +481,19 @@ mod tests {
         // ┌─────────┬─────┬─────┐
         // │         │ a   │ b   │
         // ├─────────┼─────┼─────┤
-        // │ 1,2,5 > │ 3,4 │ 6,4 │
+        // │ 1,2,5 > │ 3,4 │ 4,6 │
         // ├─────────┼─────┼─────┤
         // │ 3,4 ✓   │     │     │
         // ├─────────┼─────┼─────┤
-        // │ 6,4 ✓   │     │     │
+        // │ 4,6 ✓   │     │     │
         // └─────────┴─────┴─────┘
         //
         // And the data representation as JSON is:
         //
         // {
-        //   "1,2,5": { a: "3,4", 'ε*': "6,4" },
+        //   "1,2,5": { a: "3,4", 'ε*': "4,6" },
         //   "3,4": {},
-        //   "6,4": {},
+        //   "4,6": {},
         // }
         let nfa = NFA::or(vec![NFA::char('a'), NFA::char('b')]);
         let re = DFA::from(&nfa);
@@ -371,7 +564,7 @@ mod tests {
     // }
 
     #[test]
-    fn minimize_once_table() {
+    fn partition_table() {
         // Given a DFA graph like this:
         //
         //                           a           a
@@ -410,6 +603,7 @@ mod tests {
         let mut dfa_table = DFATable::new();
         dfa_table.starting_state = "1".to_string();
         dfa_table.accepting_states = HashSet::from([("5".to_string())]);
+        dfa_table.classes = CharClasses::singletons("ab");
         dfa_table.table = BTreeMap::from([
             (
                 "1".to_string(),
@@ -447,68 +641,14 @@ mod tests {
                 ]),
             ),
         ]);
-        // println!("test minimize_table table {:#?}", dfa_table);
-
-        let mut dfa = DFA {
-            table: dfa_table.to_owned(),
-        };
-
-        let mut groups = vec![];
-
-        // First step: 0-equivalence
-        // println!("------ First step");
-        let minimized = dfa.minimize_once(&mut groups);
-        assert_eq!(minimized, true);
-        assert_eq!(groups.len(), 2);
-
-        assert_eq!(
-            groups[0],
-            [
-                "1".to_string(),
-                "2".to_string(),
-                "3".to_string(),
-                "4".to_string()
-            ]
-        );
-
-        assert_eq!(groups[1], ["5".to_string()]);
-
-        // Second step: 1-equivalence
-        // println!("------ Second step");
-        let minimized = dfa.minimize_once(&mut groups);
-        assert_eq!(minimized, true);
-        assert_eq!(groups.len(), 3);
-
-        assert_eq!(
-            groups[0],
-            ["1".to_string(), "2".to_string(), "3".to_string()]
-        );
-
-        assert_eq!(groups[1], ["5".to_string()]);
-        assert_eq!(groups[2], ["4".to_string()]);
-
-        // Third step: 2-equivalence
-        // println!("------ Third step");
-        let minimized = dfa.minimize_once(&mut groups);
-        assert_eq!(minimized, true);
-        assert_eq!(groups.len(), 4);
 
-        assert_eq!(groups[0], ["1".to_string(), "3".to_string()]);
+        let dfa = DFA::from_table(dfa_table);
+        let states: Vec<String> = dfa.table.table.keys().cloned().collect();
 
-        assert_eq!(groups[1], ["5".to_string()]);
-        assert_eq!(groups[2], ["4".to_string()]);
-        assert_eq!(groups[3], ["2".to_string()]);
-
-        // Forth step: 3-equivalence
-        // println!("------ Forth step");
-        let minimized = dfa.minimize_once(&mut groups);
-        assert_eq!(minimized, false);
-        assert_eq!(groups.len(), 4);
-
-        assert_eq!(groups[0], ["1".to_string(), "3".to_string()]);
-        assert_eq!(groups[1], ["5".to_string()]);
-        assert_eq!(groups[2], ["4".to_string()]);
-        assert_eq!(groups[3], ["2".to_string()]);
+        // 1 and 3 are equivalent, and the dead state (index 5) is on its own.
+        let mut groups = dfa.partition(&states);
+        groups.sort();
+        assert_eq!(groups, vec![vec![0, 2], vec![1], vec![3], vec![4], vec![5]]);
     }
 
     #[test]
@@ -551,6 +691,7 @@ mod tests {
         let mut dfa_table = DFATable::new();
         dfa_table.starting_state = "1".to_string();
         dfa_table.accepting_states = HashSet::from([("5".to_string())]);
+        dfa_table.classes = CharClasses::singletons("ab");
         dfa_table.table = BTreeMap::from([
             (
                 "1".to_string(),
@@ -590,9 +731,7 @@ mod tests {
         ]);
         // println!("test minimize_table table {:#?}", dfa_table);
 
-        let mut dfa = DFA {
-            table: dfa_table.to_owned(),
-        };
+        let mut dfa = DFA::from_table(dfa_table.to_owned());
 
         let minimized = dfa.minimize();
         assert_eq!(minimized, true);
@@ -665,4 +804,341 @@ mod tests {
             ])
         );
     }
+
+    // All the strings over the alphabet, from the empty one up to `max_len` chars.
+    fn enumerate_strings(alphabet: &[char], max_len: usize) -> Vec<String> {
+        let mut strings = vec![String::new()];
+        let mut last = vec![String::new()];
+        for _ in 0..max_len {
+            last = last
+                .iter()
+                .flat_map(|s| alphabet.iter().map(move |&c| format!("{s}{c}")))
+                .collect();
+            strings.extend(last.iter().cloned());
+        }
+        strings
+    }
+
+    #[test]
+    fn test_accepting_states_keep_transitions() {
+        let nfa = NFA::or(vec![
+            NFA::concat(vec![NFA::char('a'), NFA::char('b')]),
+            NFA::concat(vec![NFA::char('a'), NFA::char('b'), NFA::char('c')]),
+        ]);
+        let re = DFA::from(&nfa);
+
+        assert!(re.test("ab"));
+        assert!(re.test("abc"));
+        assert!(!re.test("abcc"));
+    }
+
+    #[test]
+    fn test_nested_epsilon_closures() {
+        // Reaching the accepting state of /(a*)*b?/ from the start takes a
+        // chain of ε-transitions through both repetitions and the optional b.
+        let nfa = regex::compile_nfa("(a*)*b?").unwrap();
+        let re = DFA::from(&nfa);
+
+        assert!(re.test(""));
+        assert!(re.test("aaa"));
+        assert!(re.test("aab"));
+        assert!(!re.test("ba"));
+    }
+
+    #[test]
+    fn test_matches_nfa_simulation() {
+        let patterns = [
+            "",
+            "a",
+            "ab|abc",
+            "a|b|c",
+            "a*",
+            "a*?b",
+            "(ab)*",
+            "(a*b)*",
+            "(a|b)*abb",
+            "(a|b)+?c",
+            "a?b?c?",
+            "(a*)*",
+            "a{2,3}b{1,}",
+            "((a|b)c?)*?",
+            "(ab|a)(bc|c)",
+        ];
+        let strings = enumerate_strings(&['a', 'b', 'c'], 6);
+
+        for pattern in patterns {
+            let nfa = regex::compile_nfa(pattern).unwrap();
+            let dfa = DFA::from(&nfa);
+
+            for string in &strings {
+                assert_eq!(
+                    dfa.test(string),
+                    nfa.test(&mut string.clone()),
+                    "pattern /{}/ on {:?}",
+                    pattern,
+                    string
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn minimize_drops_dead_states() {
+        // The state reached by "ac" can't accept anymore.
+        let mut dfa_table = DFATable::new();
+        dfa_table.starting_state = "1".to_string();
+        dfa_table.accepting_states = HashSet::from(["3".to_string()]);
+        dfa_table.classes = CharClasses::singletons("abc");
+        dfa_table.table = BTreeMap::from([
+            (
+                "1".to_string(),
+                BTreeMap::from([("a".to_string(), "2".to_string())]),
+            ),
+            (
+                "2".to_string(),
+                BTreeMap::from([
+                    ("b".to_string(), "3".to_string()),
+                    ("c".to_string(), "4".to_string()),
+                ]),
+            ),
+            ("3".to_string(), BTreeMap::new()),
+            ("4".to_string(), BTreeMap::new()),
+        ]);
+        let mut dfa = DFA::from_table(dfa_table);
+
+        assert!(dfa.minimize());
+        assert_eq!(
+            dfa.table.table,
+            BTreeMap::from([
+                (
+                    "1".to_string(),
+                    BTreeMap::from([("a".to_string(), "2".to_string())]),
+                ),
+                (
+                    "2".to_string(),
+                    BTreeMap::from([("b".to_string(), "3".to_string())]),
+                ),
+                ("3".to_string(), BTreeMap::new()),
+            ])
+        );
+        assert!(!dfa.minimize());
+    }
+
+    #[test]
+    fn minimize_to_minimal_size() {
+        let cases = [
+            ("a|b", 2),
+            ("ab|ac", 3),
+            ("ab|abc", 4),
+            ("(a|b)*abb", 4),
+            ("(a*)*", 1),
+            ("a*b*", 2),
+            ("(ab|a)(bc|c)", 5),
+        ];
+
+        for (pattern, len) in cases {
+            let nfa = regex::compile_nfa(pattern).unwrap();
+            let mut dfa = DFA::from(&nfa);
+            dfa.minimize();
+            assert_eq!(dfa.table.table.len(), len, "pattern /{}/", pattern);
+        }
+    }
+
+    #[test]
+    fn minimize_preserves_language() {
+        let patterns = [
+            "ab|abc",
+            "(a|b)*abb",
+            "(a*b)*",
+            "a{2,3}b{1,}",
+            "((a|b)c?)*?",
+        ];
+        let strings = enumerate_strings(&['a', 'b', 'c'], 6);
+
+        for pattern in patterns {
+            let nfa = regex::compile_nfa(pattern).unwrap();
+            let dfa = DFA::from(&nfa);
+            let mut minimal = dfa.clone();
+            minimal.minimize();
+
+            for string in &strings {
+                assert_eq!(
+                    minimal.test(string),
+                    dfa.test(string),
+                    "pattern /{}/ on {:?}",
+                    pattern,
+                    string
+                );
+            }
+        }
+    }
+
+    // Relabels the states by the order a breadth-first walk from the starting state
+    // reaches them, following the transitions in symbol order. Two DFAs are
+    // isomorphic if and only if their canonical forms are equal.
+    fn canonical(dfa: &DFA) -> (BTreeMap<usize, BTreeMap<String, usize>>, BTreeSet<usize>) {
+        let mut ids = HashMap::from([(dfa.get_starting_state(), 0)]);
+        let mut queue = std::collections::VecDeque::from([dfa.get_starting_state()]);
+        let mut table = BTreeMap::new();
+        let mut accepting = BTreeSet::new();
+
+        while let Some(label) = queue.pop_front() {
+            let id = ids[&label];
+            if dfa.get_accepting_states().contains(&label) {
+                accepting.insert(id);
+            }
+            let mut row = BTreeMap::new();
+            for (symbol, target) in &dfa.table.table[&label] {
+                let next_id = ids.len();
+                let target_id = *ids.entry(target.to_owned()).or_insert_with(|| {
+                    queue.push_back(target.to_owned());
+                    next_id
+                });
+                row.insert(symbol.to_owned(), target_id);
+            }
+            table.insert(id, row);
+        }
+
+        (table, accepting)
+    }
+
+    #[test]
+    fn minimize_brzozowski_table() {
+        // The same DFA as in `partition_table`, where 1 and 3 are equivalent.
+        let mut dfa_table = DFATable::new();
+        dfa_table.starting_state = "1".to_string();
+        dfa_table.accepting_states = HashSet::from(["5".to_string()]);
+        dfa_table.classes = CharClasses::singletons("ab");
+        dfa_table.table = BTreeMap::from([
+            (
+                "1".to_string(),
+                BTreeMap::from([
+                    ("a".to_string(), "2".to_string()),
+                    ("b".to_string(), "3".to_string()),
+                ]),
+            ),
+            (
+                "2".to_string(),
+                BTreeMap::from([
+                    ("a".to_string(), "2".to_string()),
+                    ("b".to_string(), "4".to_string()),
+                ]),
+            ),
+            (
+                "3".to_string(),
+                BTreeMap::from([
+                    ("a".to_string(), "2".to_string()),
+                    ("b".to_string(), "3".to_string()),
+                ]),
+            ),
+            (
+                "4".to_string(),
+                BTreeMap::from([
+                    ("a".to_string(), "2".to_string()),
+                    ("b".to_string(), "5".to_string()),
+                ]),
+            ),
+            (
+                "5".to_string(),
+                BTreeMap::from([
+                    ("a".to_string(), "2".to_string()),
+                    ("b".to_string(), "3".to_string()),
+                ]),
+            ),
+        ]);
+        let mut hopcroft = DFA::from_table(dfa_table);
+        let mut brzozowski = hopcroft.clone();
+
+        assert!(hopcroft.minimize());
+        assert!(brzozowski.minimize_brzozowski());
+        assert_eq!(brzozowski.table.table.len(), 4);
+        assert_eq!(canonical(&brzozowski), canonical(&hopcroft));
+    }
+
+    #[test]
+    fn minimize_keeps_patterns_apart() {
+        // The states reached by `a` and `b` both accept without going anywhere, but
+        // match different patterns, while the ones reached by `c` and `cc` match the
+        // same pattern but only one of them goes on.
+        let tables: Vec<NFATable> = ["a", "b", "c|cc"]
+            .into_iter()
+            .map(|pattern| regex::compile_nfa(pattern).unwrap().get_transition_table())
+            .collect();
+        let mut dfa = DFA::from_table(DFATable::determinize(&NFATable::union(&tables)));
+        dfa.minimize();
+
+        let dense = dfa.dense();
+        let start = dense.start_state();
+        for (c, pattern) in [('a', 0), ('b', 1), ('c', 2)] {
+            let state = dense.next_state(start, c);
+            assert!(dense.is_match_state(state));
+            assert_eq!(dense.match_patterns(state), [pattern]);
+        }
+        assert_eq!(dense.match_patterns(start), []);
+        let state = dense.next_state(dense.next_state(start, 'c'), 'c');
+        assert_eq!(dense.match_patterns(state), [2]);
+    }
+
+    #[test]
+    fn minimize_brzozowski_matches_hopcroft() {
+        let patterns = [
+            "a|b",
+            "ab|abc",
+            "(a|b)*abb",
+            "(a*b)*",
+            "(a*)*",
+            "a{2,3}b{1,}",
+            "((a|b)c?)*?",
+            "(ab|a)(bc|c)",
+            "(a|b)*a(a|b)(a|b)",
+        ];
+
+        for pattern in patterns {
+            let nfa = regex::compile_nfa(pattern).unwrap();
+            let mut hopcroft = DFA::from(&nfa);
+            let mut brzozowski = hopcroft.clone();
+            hopcroft.minimize();
+            brzozowski.minimize_brzozowski();
+
+            assert_eq!(
+                canonical(&brzozowski),
+                canonical(&hopcroft),
+                "pattern /{}/",
+                pattern
+            );
+        }
+    }
+
+    #[test]
+    fn minimize_preserves_anchors() {
+        let patterns = ["^ab", "a$|b", "^a*$", "(^|b)a($|b)", "^$|a"];
+        let strings = enumerate_strings(&['a', 'b'], 4);
+
+        for pattern in patterns {
+            let nfa = regex::compile_nfa(pattern).unwrap();
+            let dfas = [
+                DFA::from(&nfa),
+                DFA::search(&nfa, MatchKind::LeftmostFirst, &SizeLimits::default()).unwrap(),
+            ];
+            for dfa in dfas {
+                let mut hopcroft = dfa.clone();
+                let mut brzozowski = dfa.clone();
+                hopcroft.minimize();
+                brzozowski.minimize_brzozowski();
+
+                for string in &strings {
+                    for minimal in [&hopcroft, &brzozowski] {
+                        assert_eq!(minimal.test(string), dfa.test(string), "/{pattern}/");
+                        for start in 0..=string.len() {
+                            assert_eq!(
+                                minimal.find_end(string, start),
+                                dfa.find_end(string, start),
+                                "/{pattern}/ on {string:?} from {start}"
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
 }
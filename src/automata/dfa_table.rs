@@ -1,22 +1,217 @@
-use std::collections::{BTreeMap, HashMap, HashSet};
-
-use crate::automata::nfa::NFA;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use crate::{
+    automata::{
+        classes::CharClasses,
+        dense::StateID,
+        limits::SizeLimits,
+        nfa::NFA,
+        state::{self, Edges, Look},
+    },
+    error::Error,
+    match_kind::MatchKind,
+};
 
 use super::nfa_table::NFATable;
 
+/// The NFA state standing for the unanchored prefix `(?s:.)*?` of a search DFA,
+/// which starts the NFA over at every position. NFA tables number their states
+/// from `1`, so this never is one of them.
+pub(crate) const UNANCHORED: usize = 0;
+
+/// Ends each group of NFA states of a leftmost-longest search DFA. The states of
+/// a group belong to the matches starting at the same position, and the groups
+/// are ordered by that position.
+pub(crate) const MARK: usize = usize::MAX;
+
+/// Marks the starting DFA state of a text, when `^` can match there. No transition
+/// leads back to it, so at the end of an empty text it's the only state where both
+/// `^` and `$` hold.
+pub(crate) const TEXT_START: usize = usize::MAX - 1;
+
+/// How the states of a DFA follow the states of its NFA.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Mode {
+    /// The DFA states are sets of NFA states, and the DFA only matches from
+    /// where it started.
+    Anchored,
+    /// The DFA searches for matches anywhere in the text. The match kind decides
+    /// which NFA states are dropped once a match is reached:
+    ///
+    /// - `LeftmostFirst`: the DFA states are NFA states in priority order, and
+    ///   the ones with a lower priority than the match are dropped.
+    /// - `LeftmostLongest`: the DFA states are groups of NFA states, and the
+    ///   groups of the matches starting after the match are dropped.
+    /// - `All`: no state is dropped, and the DFA accepts at every match end.
+    ///
+    /// Under the first two kinds the unanchored prefix is dropped along with
+    /// them, so the DFA dies right after the end of the match it searched for.
+    Search(MatchKind),
+}
+
+impl Mode {
+    /// The NFA states of the starting DFA state, for chars beginning at the start of
+    /// the text or after it. Both are the same unless the NFA has a `^`.
+    pub(crate) fn start(self, nfa_table: &NFATable, text_start: bool) -> Vec<usize> {
+        let edges = Edges {
+            start: text_start,
+            end: false,
+        };
+        let mut states = match self {
+            Mode::Anchored => {
+                DFATable::epsilon_closure([nfa_table.starting_state], edges, nfa_table)
+                    .into_iter()
+                    .collect()
+            }
+            Mode::Search(MatchKind::LeftmostFirst) => {
+                DFATable::search_closure([UNANCHORED], edges, nfa_table)
+            }
+            Mode::Search(MatchKind::LeftmostLongest) => {
+                DFATable::longest_closure(vec![], true, edges, nfa_table)
+            }
+            Mode::Search(MatchKind::All) => {
+                DFATable::all_closure(BTreeSet::new(), edges, nfa_table)
+            }
+        };
+        if text_start && nfa_table.has_look(Look::Start) {
+            states.push(TEXT_START);
+        }
+        states
+    }
+
+    /// The NFA states of the DFA state reached from `states` by the char.
+    pub(crate) fn next(self, states: &[usize], c: char, nfa_table: &NFATable) -> Vec<usize> {
+        match self {
+            Mode::Anchored => {
+                let moved = DFATable::move_states(states, c, nfa_table);
+                DFATable::epsilon_closure(moved, Edges::default(), nfa_table)
+                    .into_iter()
+                    .collect()
+            }
+            Mode::Search(MatchKind::LeftmostFirst) => {
+                let moved = states.iter().flat_map(|&state| {
+                    let targets: Vec<usize> = if state == UNANCHORED {
+                        vec![UNANCHORED]
+                    } else {
+                        DFATable::targets(state, c, nfa_table).collect()
+                    };
+                    targets
+                });
+                DFATable::search_closure(moved, Edges::default(), nfa_table)
+            }
+            Mode::Search(MatchKind::LeftmostLongest) => {
+                let mut groups = vec![];
+                let mut group = BTreeSet::new();
+                let mut unanchored = false;
+                for &state in states {
+                    match state {
+                        MARK => groups.push(std::mem::take(&mut group)),
+                        UNANCHORED => unanchored = true,
+                        _ => group.extend(DFATable::targets(state, c, nfa_table)),
+                    }
+                }
+                DFATable::longest_closure(groups, unanchored, Edges::default(), nfa_table)
+            }
+            Mode::Search(MatchKind::All) => {
+                let moved = DFATable::move_states(states, c, nfa_table);
+                DFATable::all_closure(moved, Edges::default(), nfa_table)
+            }
+        }
+    }
+
+    /// The NFA states the DFA state stands for at the end of the text, where `$`
+    /// holds. The DFA state accepts there if any of them is accepting. The unanchored
+    /// prefix starts the NFA over at the end too, and the match kind plays no part,
+    /// as no char follows.
+    pub(crate) fn end(states: &[usize], nfa_table: &NFATable) -> BTreeSet<usize> {
+        let edges = Edges {
+            start: states.contains(&TEXT_START),
+            end: true,
+        };
+        let states = states.iter().filter_map(|&state| match state {
+            UNANCHORED => Some(nfa_table.starting_state),
+            MARK | TEXT_START => None,
+            _ => Some(state),
+        });
+        DFATable::epsilon_closure(states, edges, nfa_table)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct DFATable {
     pub starting_state: String,
+    /// The starting state for a text searched from after its start, when it differs
+    /// from `starting_state` because of a `^`. A label missing from the table stands
+    /// for the dead state, e.g. for /^a/ which can't match there.
+    pub inner_starting_state: Option<String>,
     pub accepting_states: HashSet<String>,
+    /// The states that don't accept, except at the end of the text through a `$`.
+    pub end_accepting_states: HashSet<String>,
     pub table: BTreeMap<String, BTreeMap<String, String>>,
+    /// The equivalence classes of chars labeling the columns of the table.
+    pub classes: CharClasses,
+    /// The patterns each accepting state matches, for a DFA built from a table
+    /// combining several patterns, see `NFATable::union`. Empty for a single pattern.
+    pub patterns: HashMap<String, BTreeSet<usize>>,
+    /// The patterns each state matches at the end of the text, when some only match
+    /// there.
+    pub end_patterns: HashMap<String, BTreeSet<usize>>,
+}
+
+impl Default for DFATable {
+    fn default() -> Self {
+        DFATable::new()
+    }
 }
 
 impl DFATable {
     pub fn new() -> Self {
         DFATable {
             starting_state: "".to_string(),
+            inner_starting_state: None,
             accepting_states: HashSet::new(),
+            end_accepting_states: HashSet::new(),
             table: BTreeMap::new(),
+            classes: CharClasses::default(),
+            patterns: HashMap::new(),
+            end_patterns: HashMap::new(),
+        }
+    }
+
+    // The starting state for a text searched from after its start.
+    pub fn inner_starting_state(&self) -> &String {
+        self.inner_starting_state
+            .as_ref()
+            .unwrap_or(&self.starting_state)
+    }
+
+    // Relabels the states by `labels`, which maps every state of the table.
+    fn relabel(&mut self, labels: &HashMap<String, String>) {
+        self.accepting_states = self
+            .accepting_states
+            .iter()
+            .map(|id| labels[id].to_owned())
+            .collect();
+        self.end_accepting_states = self
+            .end_accepting_states
+            .iter()
+            .map(|id| labels[id].to_owned())
+            .collect();
+        // The states relabeled together match the same patterns.
+        self.patterns = std::mem::take(&mut self.patterns)
+            .into_iter()
+            .map(|(id, patterns)| (labels[&id].to_owned(), patterns))
+            .collect();
+        self.end_patterns = std::mem::take(&mut self.end_patterns)
+            .into_iter()
+            .map(|(id, patterns)| (labels[&id].to_owned(), patterns))
+            .collect();
+        self.starting_state = labels[&self.starting_state].to_owned();
+        // The dead inner starting state keeps a label missing from the table.
+        if let Some(inner) = self.inner_starting_state.as_mut() {
+            if let Some(label) = labels.get(inner) {
+                *inner = label.to_owned();
+            }
         }
     }
 
@@ -24,7 +219,6 @@ impl DFATable {
         let mut count = 0_usize;
         let mut hash: BTreeMap<String, String> = BTreeMap::new();
         let mut new_table = BTreeMap::new();
-        let mut new_accepting_states = HashSet::new();
 
         for (root_states_id, transitions) in self.table.iter() {
             let new_root_states_id = hash
@@ -52,19 +246,12 @@ impl DFATable {
             new_table.insert(new_root_states_id, new_transitions);
         }
 
-        for id in self.accepting_states.iter() {
-            let new_id = hash.get(id).unwrap().to_owned();
-            new_accepting_states.insert(new_id);
-        }
-
         self.table = new_table;
-        self.accepting_states = new_accepting_states;
-        self.starting_state = hash.get(&self.starting_state).unwrap().to_owned();
+        self.relabel(&hash.into_iter().collect());
     }
 
     pub fn apply_minimization(&mut self, groups: &Vec<Vec<String>>) {
         let mut new_table = BTreeMap::new();
-        let mut new_accepting_states = HashSet::new();
         let mut new_labels = HashMap::new();
 
         for group_states in groups {
@@ -75,7 +262,7 @@ impl DFATable {
                     .collect::<Vec<String>>()
                     .join(",");
 
-                new_labels.insert(old_label, new_label);
+                new_labels.insert(old_label.to_owned(), new_label);
             }
         }
 
@@ -86,7 +273,7 @@ impl DFATable {
 
             for (transition, transition_states_id) in transitions.iter() {
                 let new_transition_states_id =
-                    new_labels.get(&transition_states_id).unwrap().to_owned();
+                    new_labels.get(transition_states_id).unwrap().to_owned();
 
                 new_transitions.insert(transition.to_owned(), new_transition_states_id);
             }
@@ -94,157 +281,505 @@ impl DFATable {
             new_table.insert(new_root_states_id, new_transitions);
         }
 
-        for id in self.accepting_states.iter() {
-            let new_id = new_labels.get(id).unwrap().to_owned();
-            new_accepting_states.insert(new_id);
-        }
-
         self.table = new_table;
-        self.accepting_states = new_accepting_states;
-        self.starting_state = new_labels.get(&self.starting_state).unwrap().to_owned();
+        self.relabel(&new_labels);
+    }
+
+    // Removes the states along with every transition going into them.
+    pub fn remove_states(&mut self, states: &HashSet<String>) {
+        self.table.retain(|label, _| !states.contains(label));
+        for transitions in self.table.values_mut() {
+            transitions.retain(|_, target| !states.contains(target));
+        }
+        self.accepting_states
+            .retain(|label| !states.contains(label));
+        self.end_accepting_states
+            .retain(|label| !states.contains(label));
+        self.patterns.retain(|label, _| !states.contains(label));
+        self.end_patterns.retain(|label, _| !states.contains(label));
+        if states.contains(self.inner_starting_state()) {
+            self.inner_starting_state = Some(String::new());
+        }
     }
 
+    // Builds the DFA with the powerset (subset) construction: every DFA state is
+    // the set of NFA states the NFA could be in at the same time, labeled by
+    // their ids, e.g. "1,2,5".
+    //
+    // The starting state is the ε-closure of the NFA starting state, and the
+    // transition of a DFA state for a symbol is the ε-closure of the NFA states
+    // reached by moving each of its states by that symbol. Chars no transition of
+    // the NFA tells apart share a class, and the DFA has one symbol per class. A
+    // DFA state accepts if any of its NFA states does, and still keeps its
+    // outgoing transitions, so the DFA for /ab|abc/ accepts both "ab" and "abc".
+    //
+    // The number of states can grow exponentially with the size of the NFA, and
+    // nothing bounds it here. Use `from_with_limits` for patterns you don't trust.
     pub fn from(nfa: &NFA) -> Self {
+        DFATable::determinize(&nfa.get_transition_table())
+    }
+
+    // Builds the DFA like `from`, but fails with `Error::CompiledTooBig` as soon as it
+    // has more states or transitions than the limits allow, or its states hold more
+    // NFA states in total.
+    pub fn from_with_limits(nfa: &NFA, limits: &SizeLimits) -> Result<Self, Error> {
+        DFATable::determinize_with_limits(&nfa.get_transition_table(), limits)
+    }
+
+    // The subset construction itself, which works on any NFA table. Only the states
+    // reachable from the starting state end up in the DFA.
+    pub fn determinize(nfa_table: &NFATable) -> Self {
+        DFATable::determinize_with_limits(nfa_table, &SizeLimits::unlimited())
+            .expect("no limit to exceed")
+    }
+
+    pub fn determinize_with_limits(
+        nfa_table: &NFATable,
+        limits: &SizeLimits,
+    ) -> Result<Self, Error> {
+        let classes = DFATable::char_classes(nfa_table);
+        DFATable::subsets(nfa_table, Mode::Anchored, classes, limits, |start| start)
+    }
+
+    // Builds a DFA searching for matches of the NFA anywhere in a text. Under the
+    // leftmost kinds, running it from where the search starts until it dies, the last
+    // position where it accepted is the end of the match. Under `MatchKind::All`, it
+    // accepts at the end of every match.
+    //
+    // Its states are labeled by their NFA states in the order the match kind keeps
+    // them, with the unanchored prefix as "*" and the end of each group as "|".
+    pub fn determinize_search(
+        nfa_table: &NFATable,
+        kind: MatchKind,
+        limits: &SizeLimits,
+    ) -> Result<Self, Error> {
+        let classes = DFATable::char_classes(nfa_table);
+        DFATable::subsets(nfa_table, Mode::Search(kind), classes, limits, |start| {
+            start
+        })
+    }
+
+    // Determinizes the reverse of this DFA.
+    //
+    // The new starting state of the reversed automaton is left out of the DFA states.
+    // It has no incoming transitions, so all it could do is tell the starting DFA state
+    // apart from an otherwise equal state, and the result of reversing a DFA with only
+    // reachable states then determinizing it must stay minimal. The reversed DFA keeps
+    // the classes of this one.
+    pub fn determinize_reversed(&self) -> Self {
+        let reversed = self.reverse();
+        DFATable::subsets(
+            &reversed,
+            Mode::Anchored,
+            self.classes.clone(),
+            &SizeLimits::unlimited(),
+            |mut start| {
+                start.retain(|&s| s != reversed.starting_state);
+                start
+            },
+        )
+        .expect("no limit to exceed")
+    }
+
+    // The subset construction from the starting states of the mode, which `starts`
+    // may change.
+    fn subsets(
+        nfa_table: &NFATable,
+        mode: Mode,
+        classes: CharClasses,
+        limits: &SizeLimits,
+        starts: impl Fn(Vec<usize>) -> Vec<usize>,
+    ) -> Result<Self, Error> {
         let mut dfa_table = DFATable::new();
+        dfa_table.classes = classes;
+        let alphabet: Vec<(char, String)> = (0..dfa_table.classes.len())
+            .map(|class| {
+                let classes = &dfa_table.classes;
+                (classes.representative(class), classes.label(class))
+            })
+            .collect();
+        let start = starts(mode.start(nfa_table, true));
+        dfa_table.starting_state = DFATable::label(&start);
+        let mut new_states_bucket = vec![start];
+        let ends = nfa_table.has_look(Look::End);
+        // Without a `^`, the text starts like its inner parts.
+        if nfa_table.has_look(Look::Start) {
+            let inner = starts(mode.start(nfa_table, false));
+            dfa_table.inner_starting_state = Some(DFATable::label(&inner));
+            if !inner.is_empty() {
+                new_states_bucket.push(inner);
+            }
+        }
 
-        let epsilon_transitions_id = "ε*".to_string();
+        // The NFA states held by the DFA states built so far.
+        let mut nfa_states = 0;
+        while let Some(states) = new_states_bucket.pop() {
+            let label = DFATable::label(&states);
+            if dfa_table.table.contains_key(&label) {
+                continue;
+            }
+            nfa_states += states.len();
+            limits.check_determinization(nfa_states)?;
 
-        let nfa_table = nfa.get_transition_table();
-        println!("nfa_table {:#?}", nfa_table);
+            // The dense DFA stores a transition for every class of every state,
+            // including its dead state.
+            let states_len = dfa_table.table.len() + 1;
+            limits.check_dfa_states(states_len)?;
+            limits.check_transition_memory(
+                (states_len + 1) * alphabet.len() * std::mem::size_of::<StateID>(),
+            )?;
 
-        let alphabet = dfa_table.get_alphabet(&nfa_table);
+            let mut row: BTreeMap<String, String> = BTreeMap::new();
+            for (c, class) in alphabet.iter() {
+                let next_states = mode.next(&states, *c, nfa_table);
+                // No NFA state survives this symbol, which is left out of the
+                // row so the DFA rejects there.
+                if next_states.is_empty() {
+                    continue;
+                }
 
-        let nfa_row_starting_state_transitions = nfa_table
-            .table
-            .get(&1)
-            .expect("expected NFA table to have a state with key 1")
-            .get(&epsilon_transitions_id)
-            .expect("expected NFA table first row to have transition states for the epsilon closure column");
+                let next_label = DFATable::label(&next_states);
+                if !dfa_table.table.contains_key(&next_label) {
+                    new_states_bucket.push(next_states);
+                }
+                row.insert(class.to_owned(), next_label);
+            }
 
-        let mut row: BTreeMap<String, String> = BTreeMap::new();
-        let mut new_states_bucket: Vec<Vec<usize>> = vec![];
+            let accepting = DFATable::accepts(&states, nfa_table);
+            if let Some(patterns) = &accepting {
+                dfa_table.accepting_states.insert(label.to_owned());
+                if !patterns.is_empty() {
+                    dfa_table
+                        .patterns
+                        .insert(label.to_owned(), patterns.clone());
+                }
+            }
+            if ends {
+                let end = Mode::end(&states, nfa_table);
+                match DFATable::accepts(&end, nfa_table) {
+                    Some(patterns) if accepting.is_none() => {
+                        dfa_table.end_accepting_states.insert(label.to_owned());
+                        if !patterns.is_empty() {
+                            dfa_table.end_patterns.insert(label.to_owned(), patterns);
+                        }
+                    }
+                    Some(patterns) if Some(&patterns) != accepting.as_ref() => {
+                        dfa_table.end_patterns.insert(label.to_owned(), patterns);
+                    }
+                    _ => {}
+                }
+            }
+            dfa_table.table.insert(label, row);
+        }
 
-        for c in alphabet.iter() {
-            if let Some(ids) =
-                DFATable::find_epislon_closure(&c, &nfa_row_starting_state_transitions, &nfa_table)
-            {
-                let states = ids
-                    .iter()
-                    .map(|&n| n.to_string())
-                    .collect::<Vec<String>>()
-                    .join(",");
+        Ok(dfa_table)
+    }
+
+    // The patterns matching if any of the NFA states is accepting, empty unless the
+    // table combines several patterns.
+    fn accepts<'s>(
+        states: impl IntoIterator<Item = &'s usize>,
+        nfa_table: &NFATable,
+    ) -> Option<BTreeSet<usize>> {
+        let mut accepting = false;
+        let mut patterns = BTreeSet::new();
+        for s in states {
+            if nfa_table.accepting_states.contains(s) {
+                accepting = true;
+                patterns.extend(nfa_table.patterns.get(s));
+            }
+        }
+        accepting.then_some(patterns)
+    }
 
-                println!("found for char {c} new state={:?}", ids);
+    // Reverses the automaton: every transition is flipped, the starting state becomes
+    // the only accepting state, and a new starting state has ε-transitions into all
+    // the previously accepting states. The result accepts the reversed strings, but
+    // is generally not deterministic anymore.
+    //
+    // The end of the text is the start of the reversed one, where the new starting
+    // state also moves into the states accepting there by a `^`. When the DFA has an
+    // inner starting state, that one becomes accepting instead, and the starting state
+    // moves by a `$` into a new accepting state. The inner starting state must accept
+    // less than the starting state, as it does in a DFA determinized from a reversed
+    // one, since it also accepts at the end of the reversed text.
+    //
+    // The NFA states are numbered after the DFA labels in order, and the new
+    // starting state comes last, before the new accepting one.
+    pub fn reverse(&self) -> NFATable {
+        let ids: HashMap<&String, usize> = self
+            .table
+            .keys()
+            .enumerate()
+            .map(|(i, label)| (label, i + 1))
+            .collect();
+        let starting_state = ids.len() + 1;
+
+        let mut table: HashMap<usize, HashMap<String, Vec<usize>>> = HashMap::new();
+        for id in 1..=starting_state {
+            table.insert(
+                id,
+                HashMap::from([(state::EPSILON_TRANSITIONS.to_string(), vec![id])]),
+            );
+        }
 
-                row.insert(c.to_owned(), states);
-                new_states_bucket.push(ids);
+        for (label, transitions) in self.table.iter() {
+            for (symbol, target) in transitions.iter() {
+                table
+                    .get_mut(&ids[target])
+                    .unwrap()
+                    .entry(symbol.to_owned())
+                    .or_default()
+                    .push(ids[label]);
             }
         }
 
-        let start_state_label = nfa_row_starting_state_transitions
-            .iter()
-            .map(|&n| n.to_string())
-            .collect::<Vec<String>>()
-            .join(",");
+        let sorted = |states: &HashSet<String>| {
+            let mut states: Vec<usize> = states.iter().map(|s| ids[s]).collect();
+            states.sort_unstable();
+            states
+        };
+        let row = table.get_mut(&starting_state).unwrap();
+        row.get_mut(state::EPSILON_TRANSITIONS)
+            .unwrap()
+            .extend(sorted(&self.accepting_states));
+        if !self.end_accepting_states.is_empty() {
+            row.insert(
+                Look::Start.label().to_string(),
+                sorted(&self.end_accepting_states),
+            );
+        }
 
-        dfa_table.starting_state = start_state_label.to_owned();
-        dfa_table.table.insert(start_state_label, row);
-        println!("nfa_accepting {:?}", nfa_table.accepting_states);
-        println!("new_states_bucket {:?}", new_states_bucket);
+        let accepting_states = match self.inner_starting_state {
+            None => HashSet::from([ids[&self.starting_state]]),
+            Some(ref inner) => {
+                let accepting = starting_state + 1;
+                table.insert(
+                    accepting,
+                    HashMap::from([(state::EPSILON_TRANSITIONS.to_string(), vec![accepting])]),
+                );
+                table
+                    .get_mut(&ids[&self.starting_state])
+                    .unwrap()
+                    .insert(Look::End.label().to_string(), vec![accepting]);
+                ids.get(inner)
+                    .copied()
+                    .into_iter()
+                    .chain([accepting])
+                    .collect()
+            }
+        };
 
-        // Now that we found the starting state, we need to resolve any new states found during the states lookup.
-        while new_states_bucket.len() > 0 {
-            let new_states = new_states_bucket.pop().unwrap();
-            let mut row: BTreeMap<String, String> = BTreeMap::new();
+        // The columns of the table are the classes, which the reversed automaton keeps.
+        let symbols = (0..self.classes.len())
+            .map(|class| (self.classes.label(class), self.classes.ranges(class)))
+            .collect();
 
-            let label = new_states
-                .iter()
-                .map(|&n| n.to_string())
-                .collect::<Vec<String>>()
-                .join(",");
+        NFATable::new(starting_state, accepting_states, table, symbols)
+    }
 
-            let accepting = new_states
-                .iter()
-                .any(|&s| nfa_table.accepting_states.contains(&s));
+    // The equivalence classes of chars for the NFA. A state can move to the same target
+    // with several labels, e.g. the two ranges of /./, which together make up the set
+    // of chars of that transition.
+    pub(crate) fn char_classes(nfa_table: &NFATable) -> CharClasses {
+        let mut sets: BTreeMap<(usize, usize), Vec<(char, char)>> = BTreeMap::new();
+        for (&state_id, row) in nfa_table.table.iter() {
+            for (symbol, ids) in row.iter() {
+                let Some(ranges) = nfa_table.symbols.get(symbol) else {
+                    continue;
+                };
+                for &id in ids {
+                    sets.entry((state_id, id)).or_default().extend(ranges);
+                }
+            }
+        }
+        CharClasses::new(sets.into_values())
+    }
 
-            println!("new_states {:?}", new_states);
+    // Epsilon closure: the set of NFA states reachable from the given states following
+    // only ε-transitions, including the given states themselves. The "ε*" rows of the
+    // NFA table only hold the direct ε-transitions, so they are followed transitively.
+    // The assertions holding at the edges are followed like ε-transitions.
+    pub(crate) fn epsilon_closure(
+        states: impl IntoIterator<Item = usize>,
+        edges: Edges,
+        nfa_table: &NFATable,
+    ) -> BTreeSet<usize> {
+        let mut closure = BTreeSet::new();
+        let mut stack: Vec<usize> = states.into_iter().collect();
 
-            if accepting {
-                dfa_table.accepting_states.insert(label.to_owned());
-                dfa_table.table.insert(label, row);
+        while let Some(state_id) = stack.pop() {
+            if !closure.insert(state_id) {
                 continue;
             }
 
-            for c in alphabet.iter() {
-                if let Some(ids) = DFATable::find_epislon_closure(&c, &new_states, &nfa_table) {
-                    let states = ids
-                        .iter()
-                        .map(|&n| n.to_string())
-                        .collect::<Vec<String>>()
-                        .join(",");
+            stack.extend(
+                DFATable::epsilon_targets(state_id, edges, nfa_table)
+                    .filter(|id| !closure.contains(id)),
+            );
+        }
 
-                    if !dfa_table.table.contains_key(&states) {
-                        new_states_bucket.push(ids);
-                    }
+        closure
+    }
 
-                    row.insert(c.to_owned(), states);
+    // Epsilon closure in priority order: each state is followed by the states its
+    // ε-transitions reach, depth first, unless an earlier state already reached them.
+    // The unanchored prefix prefers starting the NFA over to consuming a char, so its
+    // closure is the one of the starting state followed by the prefix itself.
+    //
+    // Once a match is reached, the remaining states have a lower priority than the
+    // match and are dropped.
+    pub(crate) fn search_closure(
+        states: impl IntoIterator<Item = usize>,
+        edges: Edges,
+        nfa_table: &NFATable,
+    ) -> Vec<usize> {
+        let mut closure = vec![];
+        let mut seen = HashSet::new();
+
+        for state_id in states {
+            let first = match state_id {
+                UNANCHORED => nfa_table.starting_state,
+                _ => state_id,
+            };
+
+            let mut stack = vec![first];
+            while let Some(state_id) = stack.pop() {
+                if !seen.insert(state_id) {
+                    continue;
                 }
+                closure.push(state_id);
+                if nfa_table.accepting_states.contains(&state_id) {
+                    return closure;
+                }
+
+                // The first ε-transition has the highest priority, so it's walked
+                // first. The row starts with the state itself.
+                let targets: Vec<usize> =
+                    DFATable::epsilon_targets(state_id, edges, nfa_table).collect();
+                stack.extend(targets.into_iter().rev().filter(|&id| id != state_id));
             }
 
-            dfa_table.table.insert(label, row);
+            if state_id == UNANCHORED && seen.insert(UNANCHORED) {
+                closure.push(UNANCHORED);
+            }
         }
 
-        println!("dfa_table {:#?}", dfa_table);
-        dfa_table
+        closure
     }
 
-    fn find_epislon_closure(
-        c: &String,
-        states: &Vec<usize>,
+    // Epsilon closure of each group of a leftmost-longest search DFA, and of the
+    // starting state for a new group when the unanchored prefix is still there. A
+    // state already in an earlier group is left out of the later ones, as the match
+    // starting first wins. Once a group reaches a match, the later groups are dropped.
+    fn longest_closure(
+        groups: Vec<BTreeSet<usize>>,
+        unanchored: bool,
+        edges: Edges,
         nfa_table: &NFATable,
-    ) -> Option<Vec<usize>> {
-        let epsilon_transitions_id = "ε*".to_string();
-        let mut states = states.clone();
-        let mut active = false;
-
-        println!("find_epislon_closure char={}", c);
-        while states.len() > 0 {
-            println!("  states={:?}", states);
-            let state_id = states.pop().unwrap();
-            println!("  state_id={}", state_id);
-
-            if let Some(row) = nfa_table.table.get(&state_id) {
-                println!("    row={:?}", row);
-
-                if let Some(ids) = row.get(c) {
-                    active = true;
-                    println!("      deep lookup ids={:?}", ids);
-                    states.extend(ids);
-                } else if active {
-                    if let Some(ids) = row.get(&epsilon_transitions_id) {
-                        println!("      found={:?}", ids);
-                        return Some(ids.to_vec());
-                    }
-                }
+    ) -> Vec<usize> {
+        let mut states = vec![];
+        let mut seen = HashSet::new();
+        let restart = unanchored.then(|| BTreeSet::from([nfa_table.starting_state]));
+
+        for group in groups.into_iter().chain(restart) {
+            let len = states.len();
+            let closure = DFATable::epsilon_closure(group, edges, nfa_table);
+            states.extend(closure.into_iter().filter(|&s| seen.insert(s)));
+            if states.len() == len {
+                continue;
+            }
+            states.push(MARK);
+            if states[len..]
+                .iter()
+                .any(|s| nfa_table.accepting_states.contains(s))
+            {
+                return states;
             }
         }
 
-        None
+        if unanchored {
+            states.push(UNANCHORED);
+        }
+        states
     }
 
-    // DFA alphabet. Same as in NFA, except ε.
-    fn get_alphabet(&self, nfa_table: &NFATable) -> HashSet<String> {
-        nfa_table
-            .table
-            .values()
-            .map(|map| map.keys().cloned().collect::<Vec<String>>())
+    // Epsilon closure of the states of a search DFA reporting all matches, which
+    // starts the NFA over at every position.
+    fn all_closure(moved: BTreeSet<usize>, edges: Edges, nfa_table: &NFATable) -> Vec<usize> {
+        let mut closure = DFATable::epsilon_closure(
+            moved.into_iter().chain([nfa_table.starting_state]),
+            edges,
+            nfa_table,
+        );
+        closure.insert(UNANCHORED);
+        closure.into_iter().collect()
+    }
+
+    // The NFA states reached from the state without consuming a char, in priority
+    // order: by its ε-transitions, then by its assertions holding at the edges. No NFA
+    // state has both, see `NFA::look`.
+    fn epsilon_targets(
+        state_id: usize,
+        edges: Edges,
+        nfa_table: &NFATable,
+    ) -> impl Iterator<Item = usize> + '_ {
+        let row = nfa_table.table.get(&state_id);
+        let looks = [Look::Start, Look::End]
+            .into_iter()
+            .filter(move |&look| edges.holds(look))
+            .map(|look| look.label());
+        [state::EPSILON_TRANSITIONS]
+            .into_iter()
+            .chain(looks)
+            .filter_map(move |label| row?.get(label))
             .flatten()
-            .filter(|c| *c != "ε*".to_string())
+            .copied()
+    }
+
+    // Move: the set of NFA states reached from the given states by a single
+    // transition consuming the char.
+    pub(crate) fn move_states(states: &[usize], c: char, nfa_table: &NFATable) -> BTreeSet<usize> {
+        states
+            .iter()
+            .flat_map(|&state_id| DFATable::targets(state_id, c, nfa_table))
             .collect()
     }
+
+    // The NFA states reached from the state by a single transition consuming the char.
+    fn targets(state_id: usize, c: char, nfa_table: &NFATable) -> impl Iterator<Item = usize> + '_ {
+        nfa_table
+            .table
+            .get(&state_id)
+            .into_iter()
+            .flat_map(|row| row.iter())
+            .filter(move |(symbol, _)| {
+                nfa_table
+                    .symbols
+                    .get(*symbol)
+                    .is_some_and(|ranges| ranges.iter().any(|&(s, e)| s <= c && c <= e))
+            })
+            .flat_map(|(_, ids)| ids)
+            .copied()
+    }
+
+    // The label of the DFA state for a set of NFA states, e.g. "1,2,5".
+    fn label(states: &[usize]) -> String {
+        states
+            .iter()
+            .map(|&n| match n {
+                UNANCHORED => "*".to_string(),
+                MARK => "|".to_string(),
+                TEXT_START => "^".to_string(),
+                _ => n.to_string(),
+            })
+            .collect::<Vec<String>>()
+            .join(",")
+    }
 }
 
 #[cfg(test)]
+#[allow(clippy::unnecessary_to_owned)]
 mod tests {
     use super::*;
 
@@ -441,11 +976,11 @@ mod tests {
         // ┌─────────┬─────┬─────┐
         // │         │ a   │ b   │
         // ├─────────┼─────┼─────┤
-        // │ 1,2,5 > │ 3,4 │ 6,4 │
+        // │ 1,2,5 > │ 3,4 │ 4,6 │
         // ├─────────┼─────┼─────┤
         // │ 3,4 ✓   │     │     │
         // ├─────────┼─────┼─────┤
-        // │ 6,4 ✓   │     │     │
+        // │ 4,6 ✓   │     │     │
         // └─────────┴─────┴─────┘
         //
         let nfa = NFA::or(vec![NFA::char('a'), NFA::char('b')]);
@@ -457,7 +992,7 @@ mod tests {
         assert_eq!(dfa_table.accepting_states.len(), 2);
         assert_eq!(
             dfa_table.accepting_states,
-            HashSet::from(["3,4".to_string(), "6,4".to_string()])
+            HashSet::from(["3,4".to_string(), "4,6".to_string()])
         );
 
         assert_eq!(dfa_table.table.len(), 3);
@@ -465,7 +1000,7 @@ mod tests {
         assert_eq!(
             dfa_table.table.get(&"1,2,5".to_string()),
             Some(&BTreeMap::from([
-                ("b".to_string(), "6,4".to_string()),
+                ("b".to_string(), "4,6".to_string()),
                 ("a".to_string(), "3,4".to_string()),
             ]))
         );
@@ -476,7 +1011,7 @@ mod tests {
         );
 
         assert_eq!(
-            dfa_table.table.get(&"6,4".to_string()),
+            dfa_table.table.get(&"4,6".to_string()),
             Some(&BTreeMap::new())
         );
 
@@ -567,8 +1102,8 @@ mod tests {
         // This is how the DFA table is built from the NFA table:
         //
         // 1,2,5 starting       | a: 2->3->3,4 | b: 5->6->6,7 | c:
-        // 6,7                  | a:           | b:           | c: 7->8->8,4
-        // 8,4   accepting (bc) | b:           | b:           | c:
+        // 6,7                  | a:           | b:           | c: 7->8->4,8
+        // 4,8   accepting (bc) | b:           | b:           | c:
         // 3,4   accepting (a)  | a:           | b:           | c:
         //
         let nfa = NFA::or(vec![
@@ -583,7 +1118,7 @@ mod tests {
         assert_eq!(dfa_table.accepting_states.len(), 2);
         assert_eq!(
             dfa_table.accepting_states,
-            HashSet::from(["3,4".to_string(), "8,4".to_string()])
+            HashSet::from(["3,4".to_string(), "4,8".to_string()])
         );
 
         assert_eq!(dfa_table.table.len(), 4);
@@ -603,11 +1138,11 @@ mod tests {
 
         assert_eq!(
             dfa_table.table.get(&"6,7".to_string()),
-            Some(&BTreeMap::from([("c".to_string(), "8,4".to_string())]))
+            Some(&BTreeMap::from([("c".to_string(), "4,8".to_string())]))
         );
 
         assert_eq!(
-            dfa_table.table.get(&"8,4".to_string()),
+            dfa_table.table.get(&"4,8".to_string()),
             Some(&BTreeMap::new())
         );
 
@@ -650,6 +1185,87 @@ mod tests {
         );
     }
 
+    #[test]
+    fn from_with_limits() {
+        use crate::{error::LimitKind, regex};
+
+        // The DFA needs a state for each of the 2^9 possible last 9 chars.
+        let nfa = regex::compile_nfa("(a|b)*a(a|b){8}").unwrap();
+        assert_eq!(
+            DFATable::from_with_limits(&nfa, &SizeLimits::default()).err(),
+            None
+        );
+
+        let limits = SizeLimits {
+            dfa_states: 100,
+            ..SizeLimits::default()
+        };
+        assert_eq!(
+            DFATable::from_with_limits(&nfa, &limits).err(),
+            Some(Error::CompiledTooBig {
+                limit: 100,
+                kind: LimitKind::DfaStates
+            })
+        );
+
+        // Each state takes 4 bytes for each of its 3 classes.
+        let limits = SizeLimits {
+            transition_memory: 600,
+            ..SizeLimits::default()
+        };
+        assert_eq!(
+            DFATable::from_with_limits(&nfa, &limits).err(),
+            Some(Error::CompiledTooBig {
+                limit: 600,
+                kind: LimitKind::TransitionMemory
+            })
+        );
+
+        // The DFA of /[a-z]{20}/ has a state per position, but only a handful of
+        // NFA states in each of them, while searching for it anywhere in a text
+        // makes each DFA state hold the NFA states of every position before it.
+        let nfa = regex::compile_nfa("[a-z]{20}").unwrap();
+        let table = nfa.get_transition_table();
+        let limits = SizeLimits {
+            determinization: 200,
+            ..SizeLimits::default()
+        };
+        assert_eq!(
+            DFATable::determinize_with_limits(&table, &limits).err(),
+            None
+        );
+        assert_eq!(
+            DFATable::determinize_search(&table, MatchKind::LeftmostFirst, &limits).err(),
+            Some(Error::CompiledTooBig {
+                limit: 200,
+                kind: LimitKind::Determinization
+            })
+        );
+    }
+
+    #[test]
+    fn search_closure_priority() {
+        use crate::regex;
+
+        // /b|a*/: the states of the first alternative come before the ones of the
+        // second, then the unanchored prefix. Once the empty match of /a*/ is
+        // reached, the prefix has a lower priority and is dropped.
+        let nfa = regex::compile_nfa("b|a*").unwrap().get_transition_table();
+        let start = Mode::Search(MatchKind::LeftmostFirst).start(&nfa, true);
+        assert!(!start.contains(&UNANCHORED));
+        assert!(nfa.accepting_states.contains(start.last().unwrap()));
+
+        // Without any match at the start, the prefix comes last.
+        let nfa = regex::compile_nfa("ab").unwrap().get_transition_table();
+        let start = Mode::Search(MatchKind::LeftmostFirst).start(&nfa, true);
+        assert_eq!(start.last(), Some(&UNANCHORED));
+        assert_eq!(start[0], nfa.starting_state);
+        assert_eq!(
+            Mode::Search(MatchKind::LeftmostFirst).next(&start, 'x', &nfa),
+            start
+        );
+    }
+
     // #[test]
     // fn get_transition_table_rep() {
     //     // Given regex /a*/
@@ -0,0 +1,277 @@
+use crate::automata::{bytes::ByteDFA, dense::DenseDFA};
+
+/// A DFA compiled into native x86-64 code, testing whether a text contains a match.
+///
+/// The dense DFA is first compiled into a `ByteDFA`, and each of its states becomes
+/// a block of machine code: a matching state returns true at once, and any other
+/// state returns whether it matches at the end of the text, e.g. before a `$`, or
+/// reads the next byte and compares it with the ranges of bytes leading to other
+/// states, jumping to the block of the state it leads to. A byte leading to the
+/// dead state returns false. The code is written into a page mapped with `mmap`,
+/// which is then made executable but not writable.
+///
+/// The code runs the DFA like `DenseDFA::find_earliest_end` from the start of the
+/// text, so for a search DFA, e.g. from `codegen::search_dfa`, it tests whether
+/// the text contains a match. On other architectures, or if the page can't be
+/// mapped, the byte DFA runs instead.
+#[derive(Debug)]
+pub struct JitDfa {
+    dfa: ByteDFA,
+    #[cfg(all(unix, target_arch = "x86_64"))]
+    code: Option<x86_64::Code>,
+}
+
+impl JitDfa {
+    pub fn new(dense: &DenseDFA) -> JitDfa {
+        let dfa = ByteDFA::from(dense);
+        JitDfa {
+            #[cfg(all(unix, target_arch = "x86_64"))]
+            code: x86_64::Code::new(&x86_64::assemble(&dfa)),
+            dfa,
+        }
+    }
+
+    /// Whether the DFA runs as native code, rather than falling back to the byte
+    /// DFA.
+    pub fn is_compiled(&self) -> bool {
+        #[cfg(all(unix, target_arch = "x86_64"))]
+        return self.code.is_some();
+        #[cfg(not(all(unix, target_arch = "x86_64")))]
+        return false;
+    }
+
+    /// Runs the DFA over the text, returning whether it accepted anywhere.
+    pub fn is_match(&self, text: &str) -> bool {
+        #[cfg(all(unix, target_arch = "x86_64"))]
+        if let Some(code) = &self.code {
+            return code.run(text.as_bytes());
+        }
+        self.dfa.find_earliest_end(text.as_bytes(), 0).is_some()
+    }
+}
+
+#[cfg(all(unix, target_arch = "x86_64"))]
+mod x86_64 {
+    use std::{mem, ptr};
+
+    use crate::automata::{
+        bytes::ByteDFA,
+        dense::{StateID, DEAD},
+    };
+
+    /// Machine code in an executable page, called with the System V calling
+    /// convention as `fn(bytes: *const u8, len: usize) -> bool`.
+    #[derive(Debug)]
+    pub struct Code {
+        page: *mut libc::c_void,
+        len: usize,
+    }
+
+    // The page is never written after it's made executable, and running the code
+    // only reads the bytes it's given.
+    unsafe impl Send for Code {}
+    unsafe impl Sync for Code {}
+
+    impl Code {
+        /// Copies the machine code into a new executable page, returning `None` if
+        /// the page can't be mapped.
+        pub fn new(machine_code: &[u8]) -> Option<Code> {
+            let len = machine_code.len();
+            // SAFETY: the mapping is new, so nothing else refers to it while the
+            // code is copied into it, and it's unmapped if it can't be made
+            // executable.
+            unsafe {
+                let page = libc::mmap(
+                    ptr::null_mut(),
+                    len,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                    -1,
+                    0,
+                );
+                if page == libc::MAP_FAILED {
+                    return None;
+                }
+                ptr::copy_nonoverlapping(machine_code.as_ptr(), page.cast::<u8>(), len);
+                if libc::mprotect(page, len, libc::PROT_READ | libc::PROT_EXEC) != 0 {
+                    libc::munmap(page, len);
+                    return None;
+                }
+                Some(Code { page, len })
+            }
+        }
+
+        pub fn run(&self, bytes: &[u8]) -> bool {
+            // SAFETY: the page holds the code of `assemble`, which only reads the
+            // `len` bytes from `bytes` and returns 0 or 1.
+            unsafe {
+                let f: extern "sysv64" fn(*const u8, usize) -> bool = mem::transmute(self.page);
+                f(bytes.as_ptr(), bytes.len())
+            }
+        }
+    }
+
+    impl Drop for Code {
+        fn drop(&mut self) {
+            // SAFETY: the page was mapped by `new` and nothing refers to it anymore.
+            unsafe {
+                libc::munmap(self.page, self.len);
+            }
+        }
+    }
+
+    /// Assembles the machine code running the DFA over bytes, with the pointer to
+    /// the bytes in `rdi` and their number in `rsi`, returning the result in `al`.
+    ///
+    /// The pointer moves forward in `rdi` as each byte is read into `eax`, while
+    /// `rsi` holds the end of the bytes.
+    pub fn assemble(dfa: &ByteDFA) -> Vec<u8> {
+        let states_len = dfa.states_len();
+        let (matched, failed) = (states_len, states_len + 1);
+        let mut asm = Assembler::new(states_len + 2);
+
+        asm.emit(&[0x48, 0x01, 0xFE]); // add rsi, rdi
+        asm.jump(&[0xE9], dfa.start_state() as usize); // jmp start
+        for state in 0..states_len {
+            asm.bind(state);
+            if dfa.is_match_state(state as StateID) {
+                asm.jump(&[0xE9], matched); // jmp matched
+                continue;
+            }
+            if state != DEAD as usize {
+                // At the end of the text, a state matching there returns true.
+                let end = if dfa.is_end_match_state(state as StateID) {
+                    matched
+                } else {
+                    failed
+                };
+                asm.emit(&[0x48, 0x39, 0xF7]); // cmp rdi, rsi
+                asm.jump(&[0x0F, 0x83], end); // jae end
+                asm.emit(&[0x0F, 0xB6, 0x07]); // movzx eax, byte [rdi]
+                asm.emit(&[0x48, 0xFF, 0xC7]); // inc rdi
+                for (low, high, target) in byte_ranges(dfa, state as StateID) {
+                    if low == high {
+                        asm.emit(&[0x3C, low]); // cmp al, low
+                        asm.jump(&[0x0F, 0x84], target as usize); // je target
+                    } else {
+                        asm.emit(&[0x89, 0xC1]); // mov ecx, eax
+                        asm.emit(&[0x81, 0xE9]); // sub ecx, low
+                        asm.emit(&u32::from(low).to_le_bytes());
+                        asm.emit(&[0x81, 0xF9]); // cmp ecx, high - low
+                        asm.emit(&u32::from(high - low).to_le_bytes());
+                        asm.jump(&[0x0F, 0x86], target as usize); // jbe target
+                    }
+                }
+            }
+            asm.jump(&[0xE9], failed); // jmp failed
+        }
+        asm.bind(matched);
+        asm.emit(&[0xB8, 0x01, 0x00, 0x00, 0x00, 0xC3]); // mov eax, 1; ret
+        asm.bind(failed);
+        asm.emit(&[0x31, 0xC0, 0xC3]); // xor eax, eax; ret
+        asm.finish()
+    }
+
+    // Returns the maximal ranges of bytes leading from the state to the same state,
+    // leaving out the ones leading to the dead state, or to an invalid transition
+    // that no valid UTF-8 can take.
+    fn byte_ranges(dfa: &ByteDFA, state: StateID) -> Vec<(u8, u8, StateID)> {
+        let mut ranges: Vec<(u8, u8, StateID)> = vec![];
+        for byte in 0..=u8::MAX {
+            let target = dfa.next_state(state, byte);
+            match ranges.last_mut() {
+                Some((_, high, last)) if *last == target && *high + 1 == byte => *high = byte,
+                _ => ranges.push((byte, byte, target)),
+            }
+        }
+        ranges.retain(|&(_, _, target)| target != DEAD && (target as usize) < dfa.states_len());
+        ranges
+    }
+
+    /// Machine code with jumps to labels, whose 32-bit offsets are patched once all
+    /// the labels are bound.
+    struct Assembler {
+        code: Vec<u8>,
+        /// The offset in the code of each label, once bound.
+        labels: Vec<Option<usize>>,
+        /// The offset of each jump offset to patch, with the label it jumps to.
+        jumps: Vec<(usize, usize)>,
+    }
+
+    impl Assembler {
+        fn new(labels_len: usize) -> Assembler {
+            Assembler {
+                code: vec![],
+                labels: vec![None; labels_len],
+                jumps: vec![],
+            }
+        }
+
+        fn emit(&mut self, bytes: &[u8]) {
+            self.code.extend_from_slice(bytes);
+        }
+
+        // Emits the jump instruction, followed by the offset to the label.
+        fn jump(&mut self, opcode: &[u8], label: usize) {
+            self.emit(opcode);
+            self.jumps.push((self.code.len(), label));
+            self.emit(&[0; 4]);
+        }
+
+        fn bind(&mut self, label: usize) {
+            self.labels[label] = Some(self.code.len());
+        }
+
+        fn finish(mut self) -> Vec<u8> {
+            for &(at, label) in &self.jumps {
+                // Offsets are relative to the end of the jump instruction.
+                let target = self.labels[label].unwrap() as i64;
+                let offset = (target - (at as i64 + 4)) as i32;
+                self.code[at..at + 4].copy_from_slice(&offset.to_le_bytes());
+            }
+            self.code
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{automata::limits::SizeLimits, codegen::search_dfa, regex::Regex};
+
+    #[test]
+    fn matches_like_regex() {
+        let patterns = [
+            "[a-z]+@example\\.com",
+            "é|😀+",
+            "a.c",
+            "[^a]x",
+            "",
+            "(ab)*c",
+            "\\d{3}",
+            "^ab|c$",
+            "^$",
+        ];
+        let texts = [
+            "",
+            "bob@example.com",
+            "x😀y",
+            "abc",
+            "a\nc",
+            "zx",
+            "ababc",
+            "٣42",
+        ];
+        for pattern in patterns {
+            let dfa = search_dfa(pattern, &SizeLimits::default()).unwrap();
+            let jit = JitDfa::new(dfa.dense());
+            // The machine code, rather than the byte DFA, runs where it can.
+            assert_eq!(jit.is_compiled(), cfg!(all(unix, target_arch = "x86_64")));
+            let regex = Regex::new(pattern).unwrap();
+            for text in texts {
+                let expected = regex.is_match_bytes(text.as_bytes()).unwrap();
+                assert_eq!(jit.is_match(text), expected, "/{pattern}/ on {text:?}");
+            }
+        }
+    }
+}
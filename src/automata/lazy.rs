@@ -0,0 +1,688 @@
+use std::{cell::RefCell, collections::HashMap, mem};
+
+use crate::automata::{
+    classes::CharClasses,
+    dense::{StateID, DEAD},
+    dfa_table::{DFATable, Mode},
+    nfa::NFA,
+    nfa_table::NFATable,
+    state::Edges,
+    utf8::{self, Decoder},
+};
+use crate::match_kind::MatchKind;
+
+/// The memory the cache of states may use, in bytes, unless configured otherwise.
+pub const DEFAULT_CACHE_CAPACITY: usize = 2 * (1 << 20);
+
+/// The number of times the cache can be cleared during a single search before the
+/// search may give up on it.
+const MIN_CACHE_CLEARS: usize = 3;
+
+/// Once the cache was cleared enough times, a search gives up on it if it built a
+/// state for fewer than this many chars on average: rebuilding states is then slower
+/// than simulating the NFA.
+const MIN_CHARS_PER_STATE: usize = 10;
+
+/// A transition that wasn't computed yet.
+const UNKNOWN: StateID = StateID::MAX;
+
+/// The starting state of a text, which is always the first state after the dead one.
+const START: StateID = 1;
+
+/// A DFA whose states are only determinized when the input reaches them.
+///
+/// The subset construction of `DFATable::from` can build exponentially many states,
+/// e.g. for `(a|b)*a(a|b){20}`. A search only ever visits one state per char, so this
+/// DFA builds the states and transitions it needs during the search, and caches them
+/// for the next ones. The cache is bounded: when it's full, it's cleared and the
+/// search goes on building states from scratch.
+///
+/// If the cache keeps being cleared, the search is spending its time building states
+/// that are thrown away, and it falls back to simulating the NFA, which never takes
+/// more than linear time either.
+///
+/// Besides matching whole strings, a lazy DFA can search for the end of the
+/// leftmost-first match, see `search`, or run backwards to find where the match
+/// starts, see `reverse`.
+#[derive(Debug)]
+pub struct LazyDFA {
+    nfa: NFATable,
+    mode: Mode,
+    classes: CharClasses,
+    cache_capacity: usize,
+    cache: RefCell<Cache>,
+}
+
+/// The states built so far. IDs index the states like in a dense DFA, with the dead
+/// state at `0` and the starting state at `1`.
+#[derive(Debug, Default)]
+struct Cache {
+    /// The NFA states of each DFA state.
+    states: Vec<Vec<usize>>,
+    ids: HashMap<Vec<usize>, StateID>,
+    /// The starting state for chars after the start of the text, see
+    /// `DFATable::inner_starting_state`.
+    inner_start: StateID,
+    /// The transitions of each state, `UNKNOWN` until the input reaches them.
+    transitions: Vec<StateID>,
+    matches: Vec<bool>,
+    /// Whether each state accepts at the end of the text.
+    end_matches: Vec<bool>,
+    /// The patterns each state matches, for a DFA of several patterns.
+    patterns: Vec<Vec<usize>>,
+    /// The patterns each state matches at the end of the text.
+    end_patterns: Vec<Vec<usize>>,
+    /// An estimate of the memory used by the cache, in bytes.
+    memory: usize,
+    clears: usize,
+}
+
+impl LazyDFA {
+    pub fn new(nfa: &NFA) -> LazyDFA {
+        LazyDFA::with_mode(nfa.get_transition_table(), Mode::Anchored)
+    }
+
+    /// A lazy DFA searching for matches anywhere in a text, for `find_end` and
+    /// `find_earliest_end`.
+    pub fn search(nfa: &NFA, kind: MatchKind) -> LazyDFA {
+        LazyDFA::with_mode(nfa.get_transition_table(), Mode::Search(kind))
+    }
+
+    /// A lazy DFA searching for all the matches of several patterns at once, for
+    /// `for_each_match_patterns`.
+    pub fn search_patterns(tables: &[NFATable]) -> LazyDFA {
+        LazyDFA::with_mode(NFATable::union(tables), Mode::Search(MatchKind::All))
+    }
+
+    /// A lazy DFA matching the reversed strings of the NFA, for `rfind_start`.
+    pub fn reverse(nfa: &NFA) -> LazyDFA {
+        LazyDFA::with_mode(nfa.get_transition_table().reverse(), Mode::Anchored)
+    }
+
+    fn with_mode(nfa: NFATable, mode: Mode) -> LazyDFA {
+        let classes = DFATable::char_classes(&nfa);
+        let dfa = LazyDFA {
+            nfa,
+            mode,
+            classes,
+            cache_capacity: DEFAULT_CACHE_CAPACITY,
+            cache: RefCell::new(Cache::default()),
+        };
+        dfa.clear_cache(&mut dfa.cache.borrow_mut());
+        dfa
+    }
+
+    /// Set the memory the cache of states may use, in bytes.
+    pub fn with_cache_capacity(mut self, capacity: usize) -> LazyDFA {
+        self.cache_capacity = capacity;
+        self
+    }
+
+    pub fn cache_capacity(&self) -> usize {
+        self.cache_capacity
+    }
+
+    /// The number of times the cache was cleared because it was full.
+    pub fn cache_clears(&self) -> usize {
+        self.cache.borrow().clears
+    }
+
+    // Tests whether this DFA accepts the string.
+    pub fn test(&self, string: &str) -> bool {
+        let chars = string.char_indices();
+        self.find_match(chars, Edges::WHOLE, string.len(), false) == Some(string.len())
+    }
+
+    /// Runs the DFA over `text` from the byte offset `start` until it dies, returning
+    /// the last position where it accepted. For a DFA built by `search`, this is the
+    /// end of the leftmost-first match.
+    pub fn find_end(&self, text: &str, start: usize) -> Option<usize> {
+        let chars = text[start..].char_indices().map(|(i, c)| (start + i, c));
+        self.find_match(chars, Edges::from_offset(start), text.len(), false)
+    }
+
+    /// Runs the DFA over `text` from the byte offset `start`, returning the first
+    /// position where it accepts. For a DFA searching for all matches, this is the
+    /// end of the match ending first.
+    pub fn find_earliest_end(&self, text: &str, start: usize) -> Option<usize> {
+        let chars = text[start..].char_indices().map(|(i, c)| (start + i, c));
+        self.find_match(chars, Edges::from_offset(start), text.len(), true)
+    }
+
+    /// Runs the DFA backwards over `text[start..end]` from `end` until it dies,
+    /// returning the last position where it accepted. For a DFA built by `reverse`,
+    /// this is the leftmost start of a match ending at `end`.
+    pub fn rfind_start(&self, text: &str, start: usize, end: usize) -> Option<usize> {
+        let chars = text[start..end]
+            .char_indices()
+            .rev()
+            .map(|(i, c)| (start + i + c.len_utf8(), c));
+        self.find_match(
+            chars,
+            Edges::backwards(start, end, text.len()),
+            start,
+            false,
+        )
+    }
+
+    /// Runs the DFA over `bytes` from the offset `start` like `find_end`, decoding the
+    /// bytes like `utf8::decode`, so that invalid UTF-8 reads as U+FFFD.
+    pub fn find_end_bytes(&self, bytes: &[u8], start: usize) -> Option<usize> {
+        let chars = utf8::char_indices(&bytes[start..]).map(|(i, c)| (start + i, c));
+        self.find_match(chars, Edges::from_offset(start), bytes.len(), false)
+    }
+
+    /// Runs the DFA over `bytes` from the offset `start` like `find_earliest_end`,
+    /// decoding the bytes like `find_end_bytes`.
+    pub fn find_earliest_end_bytes(&self, bytes: &[u8], start: usize) -> Option<usize> {
+        let chars = utf8::char_indices(&bytes[start..]).map(|(i, c)| (start + i, c));
+        self.find_match(chars, Edges::from_offset(start), bytes.len(), true)
+    }
+
+    /// Runs the DFA backwards over `bytes[start..end]` like `rfind_start`, decoding
+    /// the bytes like `find_end_bytes`.
+    pub fn rfind_start_bytes(&self, bytes: &[u8], start: usize, end: usize) -> Option<usize> {
+        // A U+FFFD may stand for a single invalid byte, so the position after a
+        // char is the one before the char read previously.
+        let mut after = end;
+        let chars = utf8::char_indices(&bytes[start..end])
+            .rev()
+            .map(move |(i, c)| (mem::replace(&mut after, start + i), c));
+        self.find_match(
+            chars,
+            Edges::backwards(start, end, bytes.len()),
+            start,
+            false,
+        )
+    }
+
+    /// Runs the DFA over the whole `text` until it dies, calling `f` with every
+    /// position where it accepts. For a DFA searching for all matches, these are
+    /// the ends of every match, overlapping ones included.
+    pub fn for_each_match_end(&self, text: &str, mut f: impl FnMut(usize)) {
+        self.for_each_match(text.char_indices(), Edges::WHOLE, text.len(), |at| {
+            f(at);
+            true
+        });
+    }
+
+    /// Runs the DFA over the whole `text` until it dies, calling `f` with the IDs of
+    /// the patterns matching at every position where it accepts, until `f` returns
+    /// false. For a DFA built by `search_patterns`, this finds every pattern matching
+    /// anywhere in the text, the IDs being the indices of their tables.
+    pub fn for_each_match_patterns(&self, text: &str, mut f: impl FnMut(&[usize]) -> bool) {
+        let chars = text.char_indices();
+        self.for_each_match_with(chars, Edges::WHOLE, text.len(), |_, patterns| f(patterns));
+    }
+
+    /// Starts running the DFA over a text given in chunks, see `Stream`.
+    pub fn stream(&self) -> Stream<'_> {
+        Stream {
+            dfa: self,
+            states: self.start_states(true),
+            decoder: Decoder::default(),
+            offset: 0,
+            started: false,
+            ended: false,
+            chars: vec![],
+            matches: vec![],
+        }
+    }
+
+    /// Runs the DFA over the chars, each given with the position before it, returning
+    /// the last position where the DFA accepted, the one after the last char being
+    /// `end`. The earliest search returns the first position instead. The edges tell
+    /// whether the chars begin at the start of the text and end at its end.
+    ///
+    /// The chars may come from any encoding, and be given in reverse for a backward
+    /// search, each with the position after it.
+    pub fn find_match(
+        &self,
+        chars: impl Iterator<Item = (usize, char)>,
+        edges: Edges,
+        end: usize,
+        earliest: bool,
+    ) -> Option<usize> {
+        let mut last_match = None;
+        self.for_each_match(chars, edges, end, |at| {
+            last_match = Some(at);
+            !earliest
+        });
+        last_match
+    }
+
+    // Runs the DFA over the chars like `find_match`, calling `f` with every position
+    // where the DFA accepts until it dies or `f` returns false.
+    fn for_each_match(
+        &self,
+        chars: impl Iterator<Item = (usize, char)>,
+        edges: Edges,
+        end: usize,
+        mut f: impl FnMut(usize) -> bool,
+    ) {
+        self.for_each_match_with(chars, edges, end, |at, _| f(at));
+    }
+
+    // Runs the DFA over the chars like `for_each_match`, also giving `f` the patterns
+    // matching where the DFA accepts.
+    fn for_each_match_with(
+        &self,
+        mut chars: impl Iterator<Item = (usize, char)>,
+        edges: Edges,
+        end: usize,
+        mut f: impl FnMut(usize, &[usize]) -> bool,
+    ) {
+        let mut cache = self.cache.borrow_mut();
+        let start = if edges.start {
+            START
+        } else {
+            cache.inner_start
+        };
+        match self.try_run(&mut cache, start, &mut chars, &mut f) {
+            Ok(Some(state)) => {
+                let state = state as usize;
+                let (matches, patterns) = if edges.end {
+                    (&cache.end_matches, &cache.end_patterns)
+                } else {
+                    (&cache.matches, &cache.patterns)
+                };
+                if matches[state] {
+                    f(end, &patterns[state]);
+                }
+            }
+            Ok(None) => {}
+            Err(states) => {
+                if let Some(mut states) = self.simulate(states, chars, &mut f) {
+                    if edges.end {
+                        states = Mode::end(&states, &self.nfa).into_iter().collect();
+                    }
+                    if self.is_match(&states) {
+                        f(end, &self.patterns(&states));
+                    }
+                }
+            }
+        }
+    }
+
+    // Runs the DFA from `state` over the chars, building the missing states as it
+    // goes, and calling `f` with every position before a char where it accepts, along
+    // with the patterns matching there.
+    // Returns the state reached after the last char, or `None` once the DFA dies or
+    // `f` returns false. When the cache thrashes, fails with the NFA states reached
+    // so far, for the simulation to go on from there with the chars left.
+    fn try_run(
+        &self,
+        cache: &mut Cache,
+        mut state: StateID,
+        chars: impl Iterator<Item = (usize, char)>,
+        f: &mut impl FnMut(usize, &[usize]) -> bool,
+    ) -> Result<Option<StateID>, Vec<usize>> {
+        let stride = self.classes.len();
+        let clears = cache.clears;
+        let mut built = 0;
+
+        for (searched, (at, c)) in chars.enumerate() {
+            if cache.matches[state as usize] && !f(at, &cache.patterns[state as usize]) {
+                return Ok(None);
+            }
+
+            let class = self.classes.class(c);
+            let mut next = cache.transitions[state as usize * stride + class];
+
+            if next == UNKNOWN {
+                let states = self.next_states(&cache.states[state as usize], c);
+                next = match cache.ids.get(&states) {
+                    Some(&id) => id,
+                    None => {
+                        if cache.memory + self.state_memory(&states) > self.cache_capacity {
+                            if cache.clears - clears >= MIN_CACHE_CLEARS
+                                && searched < MIN_CHARS_PER_STATE * built
+                            {
+                                return Err(states);
+                            }
+                            // The current state is built again right away, as its
+                            // transition is about to be set.
+                            let current = cache.states[state as usize].clone();
+                            cache.clears += 1;
+                            self.clear_cache(cache);
+                            state = self.add_state(cache, current);
+                        }
+                        built += 1;
+                        self.add_state(cache, states)
+                    }
+                };
+                cache.transitions[state as usize * stride + class] = next;
+            }
+
+            if next == DEAD {
+                return Ok(None);
+            }
+            state = next;
+        }
+        Ok(Some(state))
+    }
+
+    // Runs the chars through sets of NFA states from `states` like `try_run`, without
+    // building any DFA state.
+    fn simulate(
+        &self,
+        mut states: Vec<usize>,
+        chars: impl Iterator<Item = (usize, char)>,
+        f: &mut impl FnMut(usize, &[usize]) -> bool,
+    ) -> Option<Vec<usize>> {
+        for (at, c) in chars {
+            if self.is_match(&states) && !f(at, &self.patterns(&states)) {
+                return None;
+            }
+            states = self.next_states(&states, c);
+            if states.is_empty() {
+                return None;
+            }
+        }
+        Some(states)
+    }
+
+    fn start_states(&self, text_start: bool) -> Vec<usize> {
+        self.mode.start(&self.nfa, text_start)
+    }
+
+    fn next_states(&self, states: &[usize], c: char) -> Vec<usize> {
+        self.mode.next(states, c, &self.nfa)
+    }
+
+    fn is_match(&self, states: &[usize]) -> bool {
+        states.iter().any(|s| self.nfa.accepting_states.contains(s))
+    }
+
+    fn is_end_match(&self, states: &[usize]) -> bool {
+        Mode::end(states, &self.nfa)
+            .iter()
+            .any(|s| self.nfa.accepting_states.contains(s))
+    }
+
+    // The IDs of the patterns the NFA states match, in order, empty unless the table
+    // combines several patterns.
+    fn patterns<'s>(&self, states: impl IntoIterator<Item = &'s usize>) -> Vec<usize> {
+        let mut patterns: Vec<usize> = states
+            .into_iter()
+            .filter_map(|s| self.nfa.patterns.get(s).copied())
+            .collect();
+        patterns.sort_unstable();
+        patterns.dedup();
+        patterns
+    }
+
+    // Empties the cache, leaving only the dead and starting states.
+    fn clear_cache(&self, cache: &mut Cache) {
+        let clears = cache.clears;
+        *cache = Cache::default();
+        cache.clears = clears;
+
+        let dead = self.add_state(cache, vec![]);
+        let start = self.add_state(cache, self.start_states(true));
+        debug_assert_eq!((dead, start), (DEAD, START));
+        cache.inner_start = self.add_state(cache, self.start_states(false));
+
+        // The dead state never leads anywhere else.
+        let stride = self.classes.len();
+        cache.transitions[..stride].fill(DEAD);
+    }
+
+    fn add_state(&self, cache: &mut Cache, states: Vec<usize>) -> StateID {
+        if let Some(&id) = cache.ids.get(&states) {
+            return id;
+        }
+
+        let id = cache.states.len() as StateID;
+        cache.memory += self.state_memory(&states);
+        let end = Mode::end(&states, &self.nfa);
+        cache.matches.push(self.is_match(&states));
+        cache
+            .end_matches
+            .push(end.iter().any(|s| self.nfa.accepting_states.contains(s)));
+        cache.patterns.push(self.patterns(&states));
+        cache.end_patterns.push(self.patterns(&end));
+        let len = cache.transitions.len() + self.classes.len();
+        cache.transitions.resize(len, UNKNOWN);
+        cache.states.push(states.clone());
+        cache.ids.insert(states, id);
+        id
+    }
+
+    // An estimate of the memory taken by a state: its NFA states are stored twice,
+    // once for its ID and once in the map finding it.
+    fn state_memory(&self, states: &[usize]) -> usize {
+        2 * (mem::size_of::<Vec<usize>>() + mem::size_of_val(states))
+            + self.classes.len() * mem::size_of::<StateID>()
+            + mem::size_of::<StateID>()
+            + 2 * (mem::size_of::<bool>() + mem::size_of::<Vec<usize>>())
+    }
+}
+
+/// A lazy DFA running over a text given in chunks, like `dense::Stream`.
+#[derive(Debug)]
+pub struct Stream<'d> {
+    dfa: &'d LazyDFA,
+    /// The NFA states reached, empty once the DFA died. The ID of their DFA state
+    /// isn't kept, as other searches may clear the cache between two chunks.
+    states: Vec<usize>,
+    decoder: Decoder,
+    /// The number of bytes decoded so far.
+    offset: usize,
+    /// Whether the starting state was checked for a match.
+    started: bool,
+    /// Whether the text ended, where `$` holds.
+    ended: bool,
+    /// The chars decoded from the last chunk, each with the position before it.
+    chars: Vec<(usize, char)>,
+    /// The positions where the DFA accepted during the last call.
+    matches: Vec<usize>,
+}
+
+impl Stream<'_> {
+    /// Feeds the next chunk of the text, returning the positions where the DFA
+    /// accepts that it revealed.
+    pub fn feed(&mut self, chunk: impl AsRef<[u8]>) -> &[usize] {
+        let mut decoder = mem::take(&mut self.decoder);
+        decoder.decode(chunk.as_ref(), |c, len| self.push(c, len));
+        self.decoder = decoder;
+        self.run();
+        &self.matches
+    }
+
+    /// Ends the text, decoding the bytes of a char it cut short as U+FFFD, and
+    /// returns the positions where the DFA accepts that it revealed.
+    pub fn end(&mut self) -> &[usize] {
+        let mut decoder = mem::take(&mut self.decoder);
+        decoder.finish(|c, len| self.push(c, len));
+        self.decoder = decoder;
+        self.run();
+        if !self.is_match() && self.dfa.is_end_match(&self.states) {
+            self.matches.push(self.offset);
+        }
+        self.ended = true;
+        &self.matches
+    }
+
+    /// Whether the DFA accepts the text decoded so far, as a whole text once the
+    /// stream ended.
+    pub fn is_match(&self) -> bool {
+        if self.ended {
+            self.dfa.is_end_match(&self.states)
+        } else {
+            self.dfa.is_match(&self.states)
+        }
+    }
+
+    /// Whether the DFA died, so that no chunk can make it accept anymore.
+    pub fn is_dead(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    /// The number of bytes decoded so far.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    fn push(&mut self, c: char, len: usize) {
+        self.chars.push((self.offset, c));
+        self.offset += len;
+    }
+
+    // Runs the DFA over the chars decoded by the last call.
+    fn run(&mut self) {
+        self.matches.clear();
+        if !self.started && self.is_match() {
+            self.matches.push(0);
+        }
+        self.started = true;
+        if self.chars.is_empty() || self.is_dead() {
+            self.chars.clear();
+            return;
+        }
+
+        // The DFA accepting before the first char was reported by the last call,
+        // and the DFA accepting after the last one is checked below.
+        let dfa = self.dfa;
+        let first = self.chars[0].0;
+        let matches = &mut self.matches;
+        let mut f = |at, _: &[usize]| {
+            if at != first {
+                matches.push(at);
+            }
+            true
+        };
+        let mut chars = self.chars.drain(..);
+        let mut cache = dfa.cache.borrow_mut();
+        let start = dfa.add_state(&mut cache, mem::take(&mut self.states));
+        let reached = match dfa.try_run(&mut cache, start, &mut chars, &mut f) {
+            Ok(state) => state.map(|state| cache.states[state as usize].clone()),
+            Err(states) => dfa.simulate(states, &mut chars, &mut f),
+        };
+        drop(chars);
+
+        self.states = reached.unwrap_or_default();
+        if self.is_match() {
+            self.matches.push(self.offset);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{automata::dfa::DFA, regex};
+
+    #[test]
+    fn matches_dfa() {
+        let patterns = ["(a|b)*abb", "a*?b|c", "[a-c]x|.", "(ab|a)(bc|c)?"];
+        let strings = ["", "a", "ab", "abb", "babb", "abc", "ax", "c", "é", "abbc"];
+
+        for pattern in patterns {
+            let nfa = regex::compile_nfa(pattern).unwrap();
+            let lazy = LazyDFA::new(&nfa);
+            let dfa = DFA::from(&nfa);
+            for s in strings {
+                assert_eq!(lazy.test(s), dfa.test(s), "/{pattern}/ on {s:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn exponential_dfa() {
+        // The full DFA needs a state for each of the 2^21 possible last 21 chars.
+        let nfa = regex::compile_nfa("(a|b)*a(a|b){20}").unwrap();
+        let lazy = LazyDFA::new(&nfa);
+
+        let matching = format!("{}a{}", "ab".repeat(100), "b".repeat(20));
+        assert!(lazy.test(&matching));
+        assert!(!lazy.test(&format!("{matching}a")));
+        assert_eq!(lazy.cache_clears(), 0);
+    }
+
+    #[test]
+    fn cache_clears_when_full() {
+        let nfa = regex::compile_nfa("(a|b)*a(a|b){5}").unwrap();
+        let lazy = LazyDFA::new(&nfa).with_cache_capacity(4096);
+        let dfa = DFA::from(&nfa);
+
+        let strings = ["aaaaaaaa", "abbbbbbab", "babababababbbbba", "bbbbbabbbbb"];
+        for _ in 0..3 {
+            for s in strings {
+                assert_eq!(lazy.test(s), dfa.test(s), "{s:?}");
+            }
+        }
+        assert!(lazy.cache_clears() > 0);
+    }
+
+    #[test]
+    fn thrashing_cache_falls_back() {
+        // No room for any state besides the dead and starting ones.
+        let nfa = regex::compile_nfa("(a|b)*a(a|b){5}").unwrap();
+        let lazy = LazyDFA::new(&nfa).with_cache_capacity(0);
+
+        assert!(lazy.test("bbbbbbabbbbb"));
+        assert!(!lazy.test("bbbbbbbabbbb"));
+        assert!(lazy.cache_clears() >= MIN_CACHE_CLEARS);
+    }
+
+    #[test]
+    fn for_each_match_end() {
+        let nfa = regex::compile_nfa("a(a|b){3}").unwrap();
+        let dfa = DFA::search(&nfa, MatchKind::All, &Default::default()).unwrap();
+        let text = "abbaababbbaaabab".repeat(4);
+        let mut expected = vec![];
+        dfa.for_each_match_end(&text, |end| expected.push(end));
+        assert_eq!(&expected[..4], [4, 7, 8, 10]);
+
+        // Once the cache thrashes, the simulation of the NFA takes over without
+        // reporting any end twice.
+        for capacity in [DEFAULT_CACHE_CAPACITY, 0] {
+            let lazy = LazyDFA::search(&nfa, MatchKind::All).with_cache_capacity(capacity);
+            let mut ends = vec![];
+            lazy.for_each_match_end(&text, |end| ends.push(end));
+            assert_eq!(ends, expected, "{capacity}");
+            assert_eq!(lazy.cache_clears() >= MIN_CACHE_CLEARS, capacity == 0);
+        }
+    }
+
+    #[test]
+    fn stream_matches_dfa() {
+        let text = "abbaabéabbbaaabab".repeat(4);
+        for pattern in ["a(a|b){3}|é|", "^a(a|b)|b$|é$"] {
+            let nfa = regex::compile_nfa(pattern).unwrap();
+            let dfa = DFA::search(&nfa, MatchKind::All, &Default::default()).unwrap();
+
+            for capacity in [DEFAULT_CACHE_CAPACITY, 0] {
+                let lazy = LazyDFA::search(&nfa, MatchKind::All).with_cache_capacity(capacity);
+                for chunk_len in [1, 2, 5, 64] {
+                    let mut expected = dfa.stream();
+                    let mut stream = lazy.stream();
+                    for chunk in text.as_bytes().chunks(chunk_len) {
+                        let ends = expected.feed(chunk);
+                        assert_eq!(
+                            stream.feed(chunk),
+                            ends,
+                            "/{pattern}/ {capacity} {chunk_len}"
+                        );
+                    }
+                    assert_eq!(stream.end(), expected.end(), "/{pattern}/");
+                    assert_eq!(stream.offset(), text.len());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn find_end_and_start() {
+        let nfa = regex::compile_nfa("a+b|c").unwrap();
+        let search = LazyDFA::search(&nfa, MatchKind::LeftmostFirst);
+        let reverse = LazyDFA::reverse(&nfa);
+
+        assert_eq!(search.find_end("xaabab", 0), Some(4));
+        assert_eq!(reverse.rfind_start("xaabab", 0, 4), Some(1));
+        assert_eq!(search.find_end("xaabab", 4), Some(6));
+        assert_eq!(reverse.rfind_start("xaabab", 4, 6), Some(4));
+        assert_eq!(search.find_end("xaa", 0), None);
+    }
+}
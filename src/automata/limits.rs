@@ -0,0 +1,78 @@
+use crate::error::{Error, LimitKind};
+
+/// The number of NFA states a pattern may compile to, unless configured otherwise.
+pub const DEFAULT_NFA_STATES_LIMIT: usize = 100_000;
+
+/// The number of states a DFA may have, unless configured otherwise.
+pub const DEFAULT_DFA_STATES_LIMIT: usize = 10_000;
+
+/// The memory the transitions of a DFA may use, in bytes, unless configured otherwise.
+pub const DEFAULT_TRANSITION_MEMORY_LIMIT: usize = 10 * (1 << 20);
+
+/// The number of NFA states the states of a DFA may hold in total, unless configured
+/// otherwise.
+pub const DEFAULT_DETERMINIZATION_LIMIT: usize = 1_000_000;
+
+/// Bounds on the size of the automata compiled from a pattern.
+///
+/// A short pattern can compile to huge automata, e.g. `(a{100}){100}` has tens of
+/// thousands of NFA states, and the DFA of `(a|b)*a(a|b){20}` has millions of states.
+/// Building them is stopped with `Error::CompiledTooBig` once a limit is exceeded,
+/// instead of running for as long as it takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeLimits {
+    pub nfa_states: usize,
+    pub dfa_states: usize,
+    /// The memory of the transitions of a DFA, in bytes, as stored by the dense DFA.
+    pub transition_memory: usize,
+    /// The number of NFA states the states of a DFA hold in total, which bounds the
+    /// work of building it: a few DFA states can each hold thousands of NFA states.
+    pub determinization: usize,
+}
+
+impl Default for SizeLimits {
+    fn default() -> Self {
+        SizeLimits {
+            nfa_states: DEFAULT_NFA_STATES_LIMIT,
+            dfa_states: DEFAULT_DFA_STATES_LIMIT,
+            transition_memory: DEFAULT_TRANSITION_MEMORY_LIMIT,
+            determinization: DEFAULT_DETERMINIZATION_LIMIT,
+        }
+    }
+}
+
+impl SizeLimits {
+    /// No limits at all.
+    pub fn unlimited() -> SizeLimits {
+        SizeLimits {
+            nfa_states: usize::MAX,
+            dfa_states: usize::MAX,
+            transition_memory: usize::MAX,
+            determinization: usize::MAX,
+        }
+    }
+
+    pub fn check_nfa_states(&self, states: usize) -> Result<(), Error> {
+        check(states, self.nfa_states, LimitKind::NfaStates)
+    }
+
+    pub fn check_dfa_states(&self, states: usize) -> Result<(), Error> {
+        check(states, self.dfa_states, LimitKind::DfaStates)
+    }
+
+    pub fn check_transition_memory(&self, bytes: usize) -> Result<(), Error> {
+        check(bytes, self.transition_memory, LimitKind::TransitionMemory)
+    }
+
+    pub fn check_determinization(&self, nfa_states: usize) -> Result<(), Error> {
+        check(nfa_states, self.determinization, LimitKind::Determinization)
+    }
+}
+
+fn check(size: usize, limit: usize, kind: LimitKind) -> Result<(), Error> {
+    if size > limit {
+        Err(Error::CompiledTooBig { limit, kind })
+    } else {
+        Ok(())
+    }
+}
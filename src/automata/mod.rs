@@ -1,5 +1,15 @@
+pub mod bytes;
+pub mod classes;
+pub mod dense;
 pub mod dfa;
 pub mod dfa_table;
+pub mod jit;
+pub mod lazy;
+pub mod limits;
 pub mod nfa;
 pub mod nfa_table;
+pub mod pikevm;
 pub mod state;
+pub mod utf16;
+pub mod utf8;
+pub mod wide;
@@ -1,12 +1,8 @@
-use std::{
-    cell::RefCell,
-    collections::{HashMap, HashSet},
-    rc::Rc,
-};
+use std::{cell::RefCell, collections::HashSet, rc::Rc};
 
 use crate::{
     automata::nfa_table::NFATable,
-    state::{State, EPSILON},
+    automata::state::{Look, State, Symbol, EPSILON},
 };
 
 #[derive(Debug, Clone, PartialEq)]
@@ -17,34 +13,56 @@ pub struct NFA {
 
 impl NFA {
     // Tests whether this NFA matches the string.
-    fn test(&self, string: &mut String) -> bool {
+    pub fn test(&self, string: &mut String) -> bool {
         self.in_state.borrow().test(string, &mut HashSet::new())
     }
 
+    // Machine matching the empty string.
     pub fn empty() -> NFA {
+        NFA::epsilon()
+    }
+
+    // Single char machine.
+    pub fn char(c: char) -> NFA {
+        NFA::symbol(Symbol::Char(c))
+    }
+
+    // Char class machine, consuming any char in one of the inclusive ranges.
+    pub fn class(ranges: &[(char, char)]) -> NFA {
+        let in_state = Rc::new(RefCell::new(State::new(false)));
+        let out_state = Rc::new(RefCell::new(State::new(true)));
+
+        for &(start, end) in ranges {
+            let symbol = if start == end {
+                Symbol::Char(start)
+            } else {
+                Symbol::Range(start, end)
+            };
+            in_state
+                .borrow_mut()
+                .add_transition_for_symbol(symbol, out_state.clone());
+        }
+
         NFA {
-            in_state: Rc::new(RefCell::new(State {
-                accepting: false,
-                transitions: HashMap::new(),
-            })),
-            out_state: Rc::new(RefCell::new(State {
-                accepting: true,
-                transitions: HashMap::new(),
-            })),
+            in_state,
+            out_state,
         }
     }
 
-    // Single char machine.
-    pub fn char(symbol: char) -> NFA {
-        let in_state = Rc::new(RefCell::new(State {
-            accepting: false,
-            transitions: HashMap::new(),
-        }));
+    // Epsilon machine.
+    pub fn epsilon() -> NFA {
+        NFA::symbol(EPSILON)
+    }
 
-        let out_state = Rc::new(RefCell::new(State {
-            accepting: true,
-            transitions: HashMap::new(),
-        }));
+    // Assertion machine, matching the empty string where the assertion holds.
+    pub fn look(look: Look) -> NFA {
+        NFA::symbol(Symbol::Look(look))
+    }
+
+    // Single transition machine.
+    fn symbol(symbol: Symbol) -> NFA {
+        let in_state = Rc::new(RefCell::new(State::new(false)));
+        let out_state = Rc::new(RefCell::new(State::new(true)));
 
         in_state
             .borrow_mut()
@@ -56,11 +74,6 @@ impl NFA {
         }
     }
 
-    // Epsilon machine.
-    fn epsilon() -> NFA {
-        NFA::char(EPSILON)
-    }
-
     // Creates a concatenation NFA fragment from a single pair of fragments
     fn concat_pair(first: &mut NFA, second: &mut NFA) -> NFA {
         first.out_state.borrow_mut().accepting = false;
@@ -92,14 +105,8 @@ impl NFA {
     // Creates a union NFA fragment from a single pair of fragments
     pub fn or_pair(first: &mut NFA, second: &mut NFA) -> NFA {
         let union = NFA {
-            in_state: Rc::new(RefCell::new(State {
-                accepting: false,
-                transitions: HashMap::new(),
-            })),
-            out_state: Rc::new(RefCell::new(State {
-                accepting: true,
-                transitions: HashMap::new(),
-            })),
+            in_state: Rc::new(RefCell::new(State::new(false))),
+            out_state: Rc::new(RefCell::new(State::new(true))),
         };
 
         first.out_state.borrow_mut().accepting = false;
@@ -149,28 +156,57 @@ impl NFA {
             .unwrap()
     }
 
-    // Creates a repetition NFA frament (aka Kleene closure).
+    // Creates a repetition NFA frament (aka Kleene closure), which prefers to
+    // repeat the fragment as many times as possible.
     pub fn rep(fragment: NFA) -> NFA {
-        // Add the skip transition, repeating the machine 0 time.
-        fragment
-            .in_state
+        NFA::repeat(fragment, true)
+    }
+
+    // Creates a lazy repetition NFA fragment, which prefers to repeat the
+    // fragment as few times as possible.
+    pub fn rep_lazy(fragment: NFA) -> NFA {
+        NFA::repeat(fragment, false)
+    }
+
+    fn repeat(fragment: NFA, greedy: bool) -> NFA {
+        let split = Rc::new(RefCell::new(State::new(false)));
+        let out_state = Rc::new(RefCell::new(State::new(true)));
+        fragment.out_state.borrow_mut().accepting = false;
+
+        // The split state either enters the fragment, repeating it once more,
+        // or skips it. The transition added first is the preferred one.
+        let (enter, skip) = (fragment.in_state.clone(), out_state.clone());
+        let (first, second) = if greedy { (enter, skip) } else { (skip, enter) };
+        split.borrow_mut().add_transition_for_symbol(EPSILON, first);
+        split
             .borrow_mut()
-            .add_transition_for_symbol(EPSILON, fragment.out_state.clone());
+            .add_transition_for_symbol(EPSILON, second);
 
-        // Add the actual fragment repetition, repeating the machine 1 or more times.
+        // Loop back to the split state after each repetition.
         fragment
             .out_state
             .borrow_mut()
-            .add_transition_for_symbol(EPSILON, fragment.in_state.clone());
+            .add_transition_for_symbol(EPSILON, split.clone());
 
         NFA {
-            in_state: fragment.in_state.to_owned(),
-            out_state: fragment.out_state.to_owned(),
+            in_state: split,
+            out_state,
         }
     }
 
+    // Wraps a fragment into states recording where it starts and where it
+    // ends into the capture slots of the group `index`.
+    pub fn capture(fragment: NFA, index: usize) -> NFA {
+        let start = NFA::epsilon();
+        start.in_state.borrow_mut().slot = Some(index * 2);
+        let end = NFA::epsilon();
+        end.in_state.borrow_mut().slot = Some(index * 2 + 1);
+
+        NFA::concat(vec![start, fragment, end])
+    }
+
     pub fn get_transition_table(&self) -> NFATable {
-        NFATable::from(&self)
+        NFATable::from(self)
     }
 }
 
@@ -178,32 +214,27 @@ impl NFA {
 mod tests {
     use super::*;
 
-    fn assert_concatenation(state: &Rc<RefCell<State>>, char: &char) -> Rc<RefCell<State>> {
+    fn assert_concatenation(state: &Rc<RefCell<State>>, symbol: Symbol) -> Rc<RefCell<State>> {
         let transitions = &state.borrow().transitions;
         assert_eq!(transitions.len(), 1);
-        let frag = transitions.get(&char);
-        assert_eq!(frag.is_some(), true);
-        let states = frag.unwrap();
-        assert_eq!(states.len(), 1);
+        assert_eq!(transitions[0].0, symbol);
 
-        states[0].to_owned()
+        transitions[0].1.to_owned()
     }
 
     fn assert_union(
         state: &Rc<RefCell<State>>,
-        char1: &char,
-        char2: &char,
+        char1: char,
+        char2: char,
     ) -> (Rc<RefCell<State>>, Rc<RefCell<State>>) {
         let transitions = &state.borrow().transitions;
-        assert_eq!(transitions.len(), 1);
+        assert_eq!(transitions.len(), 2);
 
-        let frag = transitions.get(&EPSILON);
-        assert_eq!(frag.is_some(), true);
-        let states = frag.unwrap();
-        assert_eq!(states.len(), 2);
-
-        let state1 = assert_concatenation(&states[0], char1);
-        let state2 = assert_concatenation(&states[1], char2);
+        // The first alternative has priority over the second one.
+        let state1 = assert_concatenation(&transitions[0].1, Symbol::Char(char1));
+        let state2 = assert_concatenation(&transitions[1].1, Symbol::Char(char2));
+        assert_eq!(transitions[0].0, EPSILON);
+        assert_eq!(transitions[1].0, EPSILON);
 
         (state1.to_owned(), state2.to_owned())
     }
@@ -217,13 +248,13 @@ mod tests {
         let re = NFA::concat_pair(&mut NFA::char('a'), &mut NFA::char('b'));
 
         // <start> -> a
-        let state = assert_concatenation(&re.in_state, &'a');
+        let state = assert_concatenation(&re.in_state, Symbol::Char('a'));
 
         // a -> ε
-        let state = assert_concatenation(&state, &'ε');
+        let state = assert_concatenation(&state, EPSILON);
 
         // ε -> b
-        let state = assert_concatenation(&state, &'b');
+        let state = assert_concatenation(&state, Symbol::Char('b'));
 
         // b -> <end>
         let transitions = &state.borrow().transitions;
@@ -239,19 +270,19 @@ mod tests {
         let re = NFA::concat(vec![NFA::char('a'), NFA::char('b'), NFA::char('c')]);
 
         // <start> -> a
-        let state = assert_concatenation(&re.in_state, &'a');
+        let state = assert_concatenation(&re.in_state, Symbol::Char('a'));
 
         // a -> ε
-        let state = assert_concatenation(&state, &'ε');
+        let state = assert_concatenation(&state, EPSILON);
 
         // ε -> b
-        let state = assert_concatenation(&state, &'b');
+        let state = assert_concatenation(&state, Symbol::Char('b'));
 
         // b -> ε
-        let state = assert_concatenation(&state, &'ε');
+        let state = assert_concatenation(&state, EPSILON);
 
         // ε -> c
-        let state = assert_concatenation(&state, &'c');
+        let state = assert_concatenation(&state, Symbol::Char('c'));
 
         // c -> <end>
         let transitions = &state.borrow().transitions;
@@ -272,13 +303,13 @@ mod tests {
         let re = NFA::or_pair(&mut NFA::char('a'), &mut NFA::char('b'));
 
         // <start> -> fork into 2 ε framents
-        let (state1, state2) = assert_union(&re.in_state, &'a', &'b');
+        let (state1, state2) = assert_union(&re.in_state, 'a', 'b');
 
         // a -> ε
-        let state1 = assert_concatenation(&state1, &'ε');
+        let state1 = assert_concatenation(&state1, EPSILON);
 
         // b -> ε
-        let state2 = assert_concatenation(&state2, &'ε');
+        let state2 = assert_concatenation(&state2, EPSILON);
 
         // Check the both states merge into the same ε fragment end state.
         assert_eq!(&*state1.borrow(), &*state2.borrow());
@@ -303,22 +334,46 @@ mod tests {
         println!("test concat_pair re {:#?}", re);
 
         // // <start> -> a
-        // let state = assert_concatenation(&re.in_state, &'a');
+        // let state = assert_concatenation(&re.in_state, Symbol::Char('a'));
         //
         // // a -> ε
-        // let state = assert_concatenation(&state, &'ε');
+        // let state = assert_concatenation(&state, EPSILON);
         //
         // // ε -> b
-        // let state = assert_concatenation(&state, &'b');
+        // let state = assert_concatenation(&state, Symbol::Char('b'));
         //
         // // b -> ε
-        // let state = assert_concatenation(&state, &'ε');
+        // let state = assert_concatenation(&state, EPSILON);
         //
         // // ε -> c
-        // let state = assert_concatenation(&state, &'c');
+        // let state = assert_concatenation(&state, Symbol::Char('c'));
         //
         // // c -> <end>
         // let transitions = &state.borrow().transitions;
         // assert_eq!(transitions.len(), 0);
     }
+
+    #[test]
+    fn rep_priority() {
+        // A greedy repetition first tries to enter the fragment again, and a
+        // lazy one first tries to skip it.
+        //
+        //           ε          a          ε
+        //  (split) ---> (s:2) ---> (s:3) ---> (split)
+        //      \
+        //       ---> <end>
+        //        ε
+        //
+        let re = NFA::rep(NFA::char('a'));
+        let transitions = &re.in_state.borrow().transitions;
+        assert_eq!(transitions.len(), 2);
+        assert_concatenation(&transitions[0].1, Symbol::Char('a'));
+        assert!(Rc::ptr_eq(&transitions[1].1, &re.out_state));
+
+        let re = NFA::rep_lazy(NFA::char('a'));
+        let transitions = &re.in_state.borrow().transitions;
+        assert_eq!(transitions.len(), 2);
+        assert!(Rc::ptr_eq(&transitions[0].1, &re.out_state));
+        assert_concatenation(&transitions[1].1, Symbol::Char('a'));
+    }
 }
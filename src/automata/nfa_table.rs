@@ -4,8 +4,14 @@ use std::{
     rc::Rc,
 };
 
-use crate::state::State;
-use crate::{automata::nfa::NFA, state};
+use crate::automata::{
+    classes,
+    nfa::NFA,
+    state::{self, Look, State, Symbol},
+};
+
+/// A state being walked, with the index of its next transition and its row.
+type Walk = (Rc<RefCell<State>>, usize, HashMap<String, Vec<usize>>);
 
 #[derive(Debug)]
 pub struct NFATable {
@@ -16,6 +22,11 @@ pub struct NFATable {
     pub starting_state: usize,
     pub accepting_states: HashSet<usize>,
     pub table: HashMap<usize, HashMap<String, Vec<usize>>>,
+    /// The chars matched by the transitions of each label of the table.
+    pub symbols: HashMap<String, Vec<(char, char)>>,
+    /// The pattern each accepting state belongs to, for a table combining several
+    /// patterns, see `union`. Empty for a single pattern.
+    pub patterns: HashMap<usize, usize>,
 }
 
 impl NFATable {
@@ -38,6 +49,8 @@ impl NFATable {
             accepting_states: HashSet::new(),
             visited: HashSet::new(),
             table: HashMap::new(),
+            symbols: HashMap::new(),
+            patterns: HashMap::new(),
         };
 
         builder.walk_state(&nfa.in_state);
@@ -45,48 +58,191 @@ impl NFATable {
         builder
     }
 
-    fn walk_state(&mut self, ref_state: &Rc<RefCell<State>>) {
-        let ptr = ref_state.as_ptr();
-        let state = ref_state.borrow();
-        let state_id = self.get_state_id(ptr);
-        // println!(
-        //     "walk_state state_id={} table_len={}",
-        //     state_id,
-        //     self.table.len()
-        // );
-
-        if self.starting_state == 0 {
-            self.starting_state = state_id;
+    // Creates a table from its rows, e.g. for an NFA built from another automaton
+    // rather than from NFA states.
+    pub fn new(
+        starting_state: usize,
+        accepting_states: HashSet<usize>,
+        table: HashMap<usize, HashMap<String, Vec<usize>>>,
+        symbols: HashMap<String, Vec<(char, char)>>,
+    ) -> Self {
+        NFATable {
+            state_count: table.len(),
+            map_state_ids: HashMap::new(),
+            visited: HashSet::new(),
+            starting_state,
+            accepting_states,
+            table,
+            symbols,
+            patterns: HashMap::new(),
         }
+    }
 
-        if self.visited.contains(&ptr) {
-            return;
+    // Combines the tables of several patterns into one, whose new starting state has
+    // ε-transitions into the starting states of the tables, in order. The states of
+    // each table are numbered after the ones of the previous tables, and each
+    // accepting state is mapped to the index of its table in `patterns`.
+    pub fn union<'t>(tables: impl IntoIterator<Item = &'t NFATable>) -> NFATable {
+        let starting_state = 1;
+        let mut starting_row =
+            HashMap::from([(state::EPSILON_TRANSITIONS.to_string(), vec![starting_state])]);
+        let mut table = HashMap::new();
+        let mut accepting_states = HashSet::new();
+        let mut patterns = HashMap::new();
+        let mut symbols = HashMap::new();
+
+        let mut offset = starting_state;
+        for (pattern, nfa) in tables.into_iter().enumerate() {
+            for (&id, row) in nfa.table.iter() {
+                let row = row
+                    .iter()
+                    .map(|(symbol, ids)| {
+                        (symbol.clone(), ids.iter().map(|id| id + offset).collect())
+                    })
+                    .collect();
+                table.insert(id + offset, row);
+            }
+            starting_row
+                .get_mut(state::EPSILON_TRANSITIONS)
+                .unwrap()
+                .push(nfa.starting_state + offset);
+            for &id in nfa.accepting_states.iter() {
+                accepting_states.insert(id + offset);
+                patterns.insert(id + offset, pattern);
+            }
+            symbols.extend(nfa.symbols.clone());
+            offset += nfa.table.keys().max().copied().unwrap_or(0);
         }
-        self.visited.insert(ptr.to_owned());
-
-        let mut row: HashMap<String, Vec<usize>> = HashMap::new();
-        row.insert(state::EPSILON_TRANSITIONS.to_string(), vec![state_id]);
+        table.insert(starting_state, starting_row);
 
-        for (t, states) in &state.transitions {
-            let transition_label = match *t {
-                'ε' => state::EPSILON_TRANSITIONS.to_string(),
-                c => c.to_string(),
-            };
+        let mut union = NFATable::new(starting_state, accepting_states, table, symbols);
+        union.patterns = patterns;
+        union
+    }
 
-            let ids = row.entry(transition_label).or_insert(vec![]);
+    // Reverses the automaton: every transition is flipped, the starting state becomes
+    // the only accepting state, and a new starting state has ε-transitions into all
+    // the previously accepting states. The result accepts the reversed strings, and
+    // keeps the state numbers of this table, with the new starting state last. The
+    // start of the text is the end of the reversed one, so `^` and `$` swap.
+    pub fn reverse(&self) -> NFATable {
+        let starting_state = self.table.keys().max().copied().unwrap_or(0) + 1;
+
+        let mut table: HashMap<usize, HashMap<String, Vec<usize>>> = HashMap::new();
+        for &id in self.table.keys().chain([&starting_state]) {
+            table.insert(
+                id,
+                HashMap::from([(state::EPSILON_TRANSITIONS.to_string(), vec![id])]),
+            );
+        }
 
-            for child_state in states {
-                let child_state_id = self.get_state_id(child_state.as_ptr());
-                ids.push(child_state_id);
-                self.walk_state(&child_state);
+        // Sorting the states keeps the order of the reversed transitions stable.
+        let mut ids: Vec<&usize> = self.table.keys().collect();
+        ids.sort_unstable();
+        for &id in ids {
+            for (symbol, targets) in self.table[&id].iter() {
+                for target in targets {
+                    if symbol == state::EPSILON_TRANSITIONS && *target == id {
+                        continue;
+                    }
+                    let symbol = match NFATable::look(symbol) {
+                        Some(look) => look.reversed().label(),
+                        None => symbol,
+                    };
+                    table
+                        .get_mut(target)
+                        .unwrap()
+                        .entry(symbol.to_owned())
+                        .or_default()
+                        .push(id);
+                }
             }
         }
 
-        if state.accepting {
-            self.accepting_states.insert(state_id);
+        let mut accepting: Vec<usize> = self.accepting_states.iter().copied().collect();
+        accepting.sort_unstable();
+        table
+            .get_mut(&starting_state)
+            .unwrap()
+            .get_mut(state::EPSILON_TRANSITIONS)
+            .unwrap()
+            .extend(accepting);
+
+        NFATable::new(
+            starting_state,
+            HashSet::from([self.starting_state]),
+            table,
+            self.symbols.clone(),
+        )
+    }
+
+    // The assertion the transitions of a label check, if they do.
+    pub(crate) fn look(label: &str) -> Option<Look> {
+        [Look::Start, Look::End]
+            .into_iter()
+            .find(|look| look.label() == label)
+    }
+
+    // Whether a transition of the table checks the assertion.
+    pub(crate) fn has_look(&self, look: Look) -> bool {
+        self.table
+            .values()
+            .any(|row| row.contains_key(look.label()))
+    }
+
+    // Walks the states depth first, numbering them in the order they are found. The
+    // states left to walk are kept on a stack rather than recursing, as an NFA can
+    // chain far more states than there is room for stack frames.
+    fn walk_state(&mut self, ref_state: &Rc<RefCell<State>>) {
+        self.starting_state = self.get_state_id(ref_state.as_ptr());
+
+        let mut stack: Vec<Walk> = vec![];
+        self.visit_state(ref_state, &mut stack);
+
+        while let Some((ref_state, next, row)) = stack.last_mut() {
+            let state = ref_state.borrow();
+            let Some((symbol, child_state)) = state.transitions.get(*next) else {
+                let state_id = self.get_state_id(ref_state.as_ptr());
+                if state.accepting {
+                    self.accepting_states.insert(state_id);
+                }
+                self.table.insert(state_id, std::mem::take(row));
+                drop(state);
+                stack.pop();
+                continue;
+            };
+            *next += 1;
+
+            let transition_label = match (symbol, symbol.range()) {
+                (Symbol::Look(look), _) => look.label().to_string(),
+                (_, None) => state::EPSILON_TRANSITIONS.to_string(),
+                (_, Some((start, end))) => {
+                    let label = classes::range_label(start, end);
+                    self.symbols.insert(label.clone(), vec![(start, end)]);
+                    label
+                }
+            };
+
+            let child_state_id = self.get_state_id(child_state.as_ptr());
+            row.entry(transition_label)
+                .or_default()
+                .push(child_state_id);
+            let child_state = child_state.clone();
+            drop(state);
+            self.visit_state(&child_state, &mut stack);
+        }
+    }
+
+    // Starts walking the state, unless it was already.
+    fn visit_state(&mut self, ref_state: &Rc<RefCell<State>>, stack: &mut Vec<Walk>) {
+        let ptr = ref_state.as_ptr();
+        if !self.visited.insert(ptr) {
+            return;
         }
 
-        self.table.insert(state_id, row.to_owned());
+        let state_id = self.get_state_id(ptr);
+        let row = HashMap::from([(state::EPSILON_TRANSITIONS.to_string(), vec![state_id])]);
+        stack.push((ref_state.clone(), 0, row));
     }
 
     fn get_state_id(&mut self, ptr: *mut State) -> usize {
@@ -305,44 +461,39 @@ mod tests {
     #[test]
     fn get_transition_table_rep() {
         // Given regex /a*/
+        // Its graph looks like:
+        //                   .----------------------.
+        //                  \/                      |
+        //                        ε          a          ε
+        //  <start> (s:1 - split) ---> (s:2) ---> (s:3) --.
+        //                  |
+        //                  .-----> (s:4) <end>
+        //                     ε
         //
-        // The original zero or more NFA graph would look like this:
-        //                        .------------------.
-        //                       \/                  |
-        //                 ε          a          ε
-        //  <start> (s:1) ---> (s:2) ---> (s:3) ---> (s:4) <end>
-        //                                           /\
-        //                |                          |
-        //                .-------------------------.
-        //                             ε
-        //
-        // But we are using an optimized implementation,
-        // so the graph looks like this instead:
-        //
-        //            .-----------.
-        //           \/           |
-        //                 a
-        //  <start> (s:1) --->  (s:4) <end>
-        //                       /\
-        //           |           |
-        //           .-----------.
-        //                 ε
+        // The ε-transition entering the fragment is added before the one
+        // skipping it, which is what makes the repetition greedy.
         //
         // Its NFA table is:
         //
-        // ┌─────┬───┬───────┐
-        // │     │ a │ ε*    │
-        // ├─────┼───┼───────┤
-        // │ 1 > │ 2 │ {1,2} │
-        // ├─────┼───┼───────┤
-        // │ 2 ✓ │   │ {2,1} │
-        // └─────┴───┴───────┘
+        // ┌─────┬───┬─────────┐
+        // │     │ a │ ε*      │
+        // ├─────┼───┼─────────┤
+        // │ 1 > │   │ {1,2,4} │
+        // ├─────┼───┼─────────┤
+        // │ 2   │ 3 │ 2       │
+        // ├─────┼───┼─────────┤
+        // │ 3   │   │ {3,1}   │
+        // ├─────┼───┼─────────┤
+        // │ 4 ✓ │   │ 4       │
+        // └─────┴───┴─────────┘
         //
         // And the data representation as JSON is:
         //
         // {
-        //   '1': { a: [ 2 ], 'ε*': [ 1,2 ] },
-        //   '2': { 'ε*': [ 2, 1 ] },
+        //   '1': { 'ε*': [ 1, 2, 4 ] },
+        //   '2': { a: [ 3 ], 'ε*': [ 2 ] },
+        //   '3': { 'ε*': [ 3, 1 ] },
+        //   '4': { 'ε*': [ 4 ] },
         // }
         let re = NFA::rep(NFA::char('a'));
 
@@ -351,21 +502,101 @@ mod tests {
         assert_eq!(nfa_table.starting_state, 1);
 
         assert_eq!(nfa_table.accepting_states.len(), 1);
-        assert_eq!(nfa_table.accepting_states, HashSet::from([2]));
+        assert_eq!(nfa_table.accepting_states, HashSet::from([4]));
 
-        assert_eq!(nfa_table.table.len(), 2);
+        assert_eq!(nfa_table.table.len(), 4);
 
         assert_eq!(
             nfa_table.table.get(&1),
+            Some(&HashMap::from([("ε*".to_string(), vec![1, 2, 4])]))
+        );
+
+        assert_eq!(
+            nfa_table.table.get(&2),
             Some(&HashMap::from([
-                ("a".to_string(), vec![2]),
-                ("ε*".to_string(), vec![1, 2])
+                ("a".to_string(), vec![3]),
+                ("ε*".to_string(), vec![2])
             ]))
         );
 
         assert_eq!(
-            nfa_table.table.get(&2),
-            Some(&HashMap::from([("ε*".to_string(), vec![2, 1]),]))
+            nfa_table.table.get(&3),
+            Some(&HashMap::from([("ε*".to_string(), vec![3, 1])]))
+        );
+
+        assert_eq!(
+            nfa_table.table.get(&4),
+            Some(&HashMap::from([("ε*".to_string(), vec![4])]))
+        );
+    }
+
+    #[test]
+    fn union() {
+        // Given regexes /a/ and /b/, both tables are
+        //   '1': { x: [ 2 ], 'ε*': [ 1 ] },
+        //   '2': { 'ε*': [ 2 ] },
+        //
+        // and their union starts at the new state 1, going into both of them:
+        //   '1': { 'ε*': [ 1, 2, 4 ] },
+        //   '2': { a: [ 3 ], 'ε*': [ 2 ] },
+        //   '3': { 'ε*': [ 3 ] },
+        //   '4': { b: [ 5 ], 'ε*': [ 4 ] },
+        //   '5': { 'ε*': [ 5 ] },
+        let tables = [
+            NFA::char('a').get_transition_table(),
+            NFA::char('b').get_transition_table(),
+        ];
+        let union = NFATable::union(&tables);
+
+        assert_eq!(union.starting_state, 1);
+        assert_eq!(union.accepting_states, HashSet::from([3, 5]));
+        assert_eq!(union.patterns, HashMap::from([(3, 0), (5, 1)]));
+        assert_eq!(
+            union.table.get(&1),
+            Some(&HashMap::from([("ε*".to_string(), vec![1, 2, 4])]))
+        );
+        assert_eq!(
+            union.table.get(&4),
+            Some(&HashMap::from([
+                ("b".to_string(), vec![5]),
+                ("ε*".to_string(), vec![4])
+            ]))
+        );
+    }
+
+    #[test]
+    fn reverse() {
+        // Given regex /ab/, whose table is:
+        //   '1': { a: [ 2 ], 'ε*': [ 1 ] },
+        //   '2': { 'ε*': [ 2, 3 ] },
+        //   '3': { b: [ 4 ], 'ε*': [ 3 ] },
+        //   '4': { 'ε*': [ 4 ] },
+        //
+        // its reverse starts at the new state 5, going into the accepting state 4:
+        //   '1': { 'ε*': [ 1 ] },
+        //   '2': { a: [ 1 ], 'ε*': [ 2 ] },
+        //   '3': { 'ε*': [ 3, 2 ] },
+        //   '4': { b: [ 3 ], 'ε*': [ 4 ] },
+        //   '5': { 'ε*': [ 5, 4 ] },
+        let nfa = NFA::concat(vec![NFA::char('a'), NFA::char('b')]);
+        let reversed = nfa.get_transition_table().reverse();
+
+        assert_eq!(reversed.starting_state, 5);
+        assert_eq!(reversed.accepting_states, HashSet::from([1]));
+        assert_eq!(
+            reversed.table.get(&2),
+            Some(&HashMap::from([
+                ("a".to_string(), vec![1]),
+                ("ε*".to_string(), vec![2])
+            ]))
+        );
+        assert_eq!(
+            reversed.table.get(&3),
+            Some(&HashMap::from([("ε*".to_string(), vec![3, 2])]))
+        );
+        assert_eq!(
+            reversed.table.get(&5),
+            Some(&HashMap::from([("ε*".to_string(), vec![5, 4])]))
         );
     }
 }
@@ -0,0 +1,520 @@
+use std::{cell::RefCell, collections::HashSet, mem, rc::Rc};
+
+use crate::{
+    automata::{
+        nfa::NFA,
+        state::{Edges, Look, State, Symbol, EPSILON},
+        utf16,
+        utf8::{self, Decoder},
+    },
+    match_kind::MatchKind,
+};
+
+/// The capture positions of a match. Group `i` uses the slots `2 * i` and
+/// `2 * i + 1`, with group `0` being the whole match.
+pub type Slots = Vec<Option<usize>>;
+
+/// A text the VM searches, in any encoding, read one char at a time. Its
+/// positions are offsets in code units of the encoding.
+pub trait Text {
+    /// The number of code units of the text.
+    fn len(&self) -> usize;
+
+    /// Whether the text is empty.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The char at the offset, which is a char boundary, along with the number
+    /// of code units encoding it.
+    fn char_at(&self, at: usize) -> Option<(char, usize)>;
+}
+
+impl Text for str {
+    fn len(&self) -> usize {
+        str::len(self)
+    }
+
+    fn char_at(&self, at: usize) -> Option<(char, usize)> {
+        let c = self[at..].chars().next()?;
+        Some((c, c.len_utf8()))
+    }
+}
+
+/// UTF-8 encoded bytes, which needn't be valid: they are decoded like
+/// `utf8::decode`.
+impl Text for [u8] {
+    fn len(&self) -> usize {
+        <[u8]>::len(self)
+    }
+
+    fn char_at(&self, at: usize) -> Option<(char, usize)> {
+        utf8::decode(&self[at..])
+    }
+}
+
+/// UTF-16 encoded units, which needn't be valid: they are decoded like
+/// `utf16::char_indices`.
+impl Text for [u16] {
+    fn len(&self) -> usize {
+        <[u16]>::len(self)
+    }
+
+    fn char_at(&self, at: usize) -> Option<(char, usize)> {
+        let (_, c) = utf16::char_indices(&self[at..]).next()?;
+        Some((c, c.len_utf16()))
+    }
+}
+
+/// Latin-1 encoded bytes, each the char with the same code point.
+#[derive(Clone, Copy, Debug)]
+pub struct Latin1<'t>(pub &'t [u8]);
+
+impl Text for Latin1<'_> {
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn char_at(&self, at: usize) -> Option<(char, usize)> {
+        Some((char::from(*self.0.get(at)?), 1))
+    }
+}
+
+/// Searches the text by simulating the NFA, the way Pike's VM does.
+///
+/// Every NFA state reachable at the current position is a thread carrying
+/// its own copy of the capture slots. Threads are kept in priority order, so
+/// the first thread to reach the accepting state is the leftmost-first match,
+/// and all threads after it can be dropped. As each state is only added once
+/// per position, the search runs in time linear in the length of the text.
+///
+/// Threads started further to the left always have a higher priority, which
+/// the other match kinds rely on: a leftmost-longest search only drops the
+/// threads that started after a match, and a search for all matches stops at
+/// the first thread reaching the accepting state.
+#[derive(Debug)]
+pub struct PikeVM<'n> {
+    nfa: &'n NFA,
+    slots_len: usize,
+    kind: MatchKind,
+}
+
+impl<'n> PikeVM<'n> {
+    /// Creates a VM for the NFA of a pattern with `captures_len` groups,
+    /// not counting the implicit group of the whole match.
+    pub fn new(nfa: &'n NFA, captures_len: usize) -> PikeVM<'n> {
+        PikeVM {
+            nfa,
+            slots_len: (captures_len + 1) * 2,
+            kind: MatchKind::LeftmostFirst,
+        }
+    }
+
+    /// Set the match semantics of the searches.
+    pub fn with_match_kind(mut self, kind: MatchKind) -> PikeVM<'n> {
+        self.kind = kind;
+        self
+    }
+
+    /// Tests whether the whole text matches, moving all the threads started
+    /// at its beginning in lockstep.
+    pub fn test(&self, text: &str) -> bool {
+        let mut current = Threads::default();
+        let mut next = Threads::default();
+        current.add(&self.nfa.in_state, 0, text, vec![None; self.slots_len]);
+
+        for (i, c) in text.char_indices() {
+            for (state, slots) in current.list.drain(..) {
+                for target in state.borrow().get_transition_for_char(c) {
+                    next.add(target, i + c.len_utf8(), text, slots.clone());
+                }
+            }
+            if next.list.is_empty() {
+                return false;
+            }
+            std::mem::swap(&mut current, &mut next);
+            next.seen.clear();
+        }
+
+        current
+            .list
+            .iter()
+            .any(|(state, _)| state.borrow().accepting)
+    }
+
+    /// Returns an iterator over the start and end of a match for every position
+    /// where a match of the NFA ends, overlapping matches included. The start is
+    /// the leftmost one of the matches ending there, which is the start of the
+    /// first accepting thread. The match kind plays no part here.
+    pub fn overlapping_matches<'t>(self, text: &'t str) -> OverlappingMatches<'n, 't> {
+        OverlappingMatches {
+            vm: self,
+            text,
+            current: Threads::default(),
+            next: Threads::default(),
+            at: 0,
+        }
+    }
+
+    /// Starts running the VM over a text given in chunks, testing whether it
+    /// contains a match, see `Stream`.
+    pub fn stream(self) -> Stream<'n> {
+        Stream {
+            vm: self,
+            current: Threads::default(),
+            next: Threads::default(),
+            decoder: Decoder::default(),
+            offset: 0,
+            matched: false,
+        }
+    }
+
+    /// Finds the match in `text`, returning its capture slots.
+    pub fn captures(&self, text: &(impl Text + ?Sized)) -> Option<Slots> {
+        self.captures_at(text, 0)
+    }
+
+    /// Finds the match in `text` starting at or after the offset `start`, which
+    /// must be on a char boundary.
+    pub fn captures_at(&self, text: &(impl Text + ?Sized), start: usize) -> Option<Slots> {
+        let mut current = Threads::default();
+        let mut next = Threads::default();
+        let mut matched: Option<Slots> = None;
+        let mut at = start;
+
+        loop {
+            // A new thread starts at each position until a match is found.
+            // It has the lowest priority, since matches starting further to
+            // the left win.
+            if matched.is_none() {
+                let mut slots = vec![None; self.slots_len];
+                slots[0] = Some(at);
+                current.add(&self.nfa.in_state, at, text, slots);
+            }
+            if current.list.is_empty() {
+                break;
+            }
+
+            let c = text.char_at(at);
+            for (state, mut slots) in current.list.drain(..) {
+                let state = state.borrow();
+                if state.accepting {
+                    // The threads left after a leftmost-first match all have a
+                    // higher priority, so their matches win. A leftmost-longest
+                    // match only loses to an earlier start or a longer match.
+                    let better = match matched {
+                        Some(ref m) if self.kind == MatchKind::LeftmostLongest => {
+                            slots[0] < m[0] || (slots[0] == m[0] && Some(at) > m[1])
+                        }
+                        _ => true,
+                    };
+                    if better {
+                        slots[1] = Some(at);
+                        matched = Some(slots);
+                    }
+                    match self.kind {
+                        MatchKind::LeftmostFirst => break,
+                        MatchKind::LeftmostLongest => continue,
+                        MatchKind::All => return matched,
+                    }
+                }
+                // A leftmost-longest search keeps the threads that started before
+                // or with the match, whatever their priority.
+                if let Some(ref m) = matched {
+                    if slots[0] > m[0] {
+                        continue;
+                    }
+                }
+                if let Some((c, len)) = c {
+                    for target in state.get_transition_for_char(c) {
+                        next.add(target, at + len, text, slots.clone());
+                    }
+                }
+            }
+
+            match c {
+                Some((_, len)) => at += len,
+                None => break,
+            }
+            std::mem::swap(&mut current, &mut next);
+            next.seen.clear();
+        }
+
+        matched
+    }
+}
+
+/// The matches of `PikeVM::overlapping_matches`, found as the threads move
+/// forward.
+pub struct OverlappingMatches<'n, 't> {
+    vm: PikeVM<'n>,
+    text: &'t str,
+    current: Threads,
+    next: Threads,
+    /// The position of the current threads, past the end of the text once
+    /// they all moved through it.
+    at: usize,
+}
+
+impl Iterator for OverlappingMatches<'_, '_> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        while self.at <= self.text.len() {
+            let at = self.at;
+            // A thread starts at every position, whatever matched before.
+            let mut slots = vec![None; self.vm.slots_len];
+            slots[0] = Some(at);
+            self.current
+                .add(&self.vm.nfa.in_state, at, self.text, slots);
+
+            let accepting = self.current.list.iter().find(|(s, _)| s.borrow().accepting);
+            let matched = accepting.map(|(_, slots)| (slots[0].unwrap(), at));
+
+            match self.text[at..].chars().next() {
+                Some(c) => {
+                    for (state, slots) in self.current.list.drain(..) {
+                        for target in state.borrow().get_transition_for_char(c) {
+                            self.next
+                                .add(target, at + c.len_utf8(), self.text, slots.clone());
+                        }
+                    }
+                    self.at += c.len_utf8();
+                    std::mem::swap(&mut self.current, &mut self.next);
+                    self.next.seen.clear();
+                }
+                None => self.at = self.text.len() + 1,
+            }
+            if matched.is_some() {
+                return matched;
+            }
+        }
+        None
+    }
+}
+
+/// A PikeVM running over a text given in chunks, like `dense::Stream`, which
+/// tells whether the text contains a match as soon as a chunk reveals one.
+///
+/// A thread starts at every position, and only the threads alive at the end
+/// of the last chunk are kept, so the text is never held whole. The chunks are
+/// UTF-8 encoded, and invalid sequences decode to U+FFFD.
+pub struct Stream<'n> {
+    vm: PikeVM<'n>,
+    current: Threads,
+    next: Threads,
+    decoder: Decoder,
+    /// The number of bytes decoded so far.
+    offset: usize,
+    matched: bool,
+}
+
+impl Stream<'_> {
+    /// Feeds the next chunk of the text, returning whether the text fed so far
+    /// contains a match.
+    pub fn feed(&mut self, chunk: impl AsRef<[u8]>) -> bool {
+        let mut decoder = mem::take(&mut self.decoder);
+        decoder.decode(chunk.as_ref(), |c, len| self.step(c, len));
+        self.decoder = decoder;
+        self.matched
+    }
+
+    /// Ends the text, decoding the bytes of a char it cut short as U+FFFD, and
+    /// returns whether the text contains a match.
+    pub fn end(&mut self) -> bool {
+        let mut decoder = mem::take(&mut self.decoder);
+        decoder.finish(|c, len| self.step(c, len));
+        self.decoder = decoder;
+        if !self.matched {
+            // The threads are added again, now that `$` holds.
+            let edges = Edges {
+                start: self.offset == 0,
+                end: true,
+            };
+            let threads = mem::take(&mut self.current.list);
+            self.current.seen.clear();
+            for (state, slots) in threads {
+                self.current.add_at(&state, self.offset, edges, slots);
+            }
+            self.start_thread(edges);
+        }
+        self.matched
+    }
+
+    // Starts a thread at the current position, then checks the threads there
+    // for a match.
+    fn start_thread(&mut self, edges: Edges) {
+        let slots = vec![None; self.vm.slots_len];
+        let nfa = self.vm.nfa;
+        self.current
+            .add_at(&nfa.in_state, self.offset, edges, slots);
+        self.matched = self.current.list.iter().any(|(s, _)| s.borrow().accepting);
+    }
+
+    // Moves the threads past a char encoded in `len` bytes.
+    fn step(&mut self, c: char, len: usize) {
+        if self.matched {
+            return;
+        }
+        let edges = Edges {
+            start: self.offset == 0,
+            end: false,
+        };
+        self.start_thread(edges);
+        if self.matched {
+            return;
+        }
+        self.offset += len;
+        let next_edges = Edges::default();
+        for (state, slots) in self.current.list.drain(..) {
+            for target in state.borrow().get_transition_for_char(c) {
+                self.next
+                    .add_at(target, self.offset, next_edges, slots.clone());
+            }
+        }
+        mem::swap(&mut self.current, &mut self.next);
+        self.next.seen.clear();
+        self.matched = self.current.list.iter().any(|(s, _)| s.borrow().accepting);
+    }
+}
+
+/// The threads alive at a single position, in priority order.
+#[derive(Default)]
+struct Threads {
+    list: Vec<(Rc<RefCell<State>>, Slots)>,
+    seen: HashSet<*mut State>,
+}
+
+impl Threads {
+    /// Adds a thread for `state` and, following the ε-transitions in order,
+    /// for every state reachable from it without consuming input. The
+    /// assertions holding at the position `at` of the text are followed too.
+    fn add(
+        &mut self,
+        state: &Rc<RefCell<State>>,
+        at: usize,
+        text: &(impl Text + ?Sized),
+        slots: Slots,
+    ) {
+        let edges = Edges {
+            start: at == 0,
+            end: at == text.len(),
+        };
+        self.add_at(state, at, edges, slots);
+    }
+
+    /// Adds the threads like `add`, following the assertions holding at the
+    /// edges.
+    fn add_at(&mut self, state: &Rc<RefCell<State>>, at: usize, edges: Edges, mut slots: Slots) {
+        if !self.seen.insert(state.as_ptr()) {
+            return;
+        }
+
+        let s = state.borrow();
+        if let Some(slot) = s.slot {
+            slots[slot] = Some(at);
+        }
+        self.list.push((state.clone(), slots.clone()));
+
+        for target in s.get_transition_for_symbol(&EPSILON) {
+            self.add_at(target, at, edges, slots.clone());
+        }
+        for look in [Look::Start, Look::End] {
+            if edges.holds(look) {
+                for target in s.get_transition_for_symbol(&Symbol::Look(look)) {
+                    self.add_at(target, at, edges, slots.clone());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(nfa: &NFA, text: &str) -> Option<(usize, usize)> {
+        PikeVM::new(nfa, 0)
+            .captures(text)
+            .map(|slots| (slots[0].unwrap(), slots[1].unwrap()))
+    }
+
+    #[test]
+    fn leftmost_match() {
+        let nfa = NFA::concat(vec![NFA::char('a'), NFA::char('b')]);
+        assert_eq!(find(&nfa, "xxabab"), Some((2, 4)));
+        assert_eq!(find(&nfa, "xxa"), None);
+    }
+
+    #[test]
+    fn first_alternative_wins() {
+        let nfa = NFA::or(vec![
+            NFA::char('a'),
+            NFA::concat(vec![NFA::char('a'), NFA::char('b')]),
+        ]);
+        assert_eq!(find(&nfa, "ab"), Some((0, 1)));
+    }
+
+    #[test]
+    fn greedy_and_lazy_repetitions() {
+        assert_eq!(find(&NFA::rep(NFA::char('a')), "aaa"), Some((0, 3)));
+        assert_eq!(find(&NFA::rep_lazy(NFA::char('a')), "aaa"), Some((0, 0)));
+
+        let lazy = NFA::concat(vec![NFA::rep_lazy(NFA::char('a')), NFA::char('b')]);
+        assert_eq!(find(&lazy, "aab"), Some((0, 3)));
+    }
+
+    #[test]
+    fn test_whole_text() {
+        let nfa = NFA::concat(vec![NFA::rep(NFA::char('a')), NFA::char('b')]);
+        let vm = PikeVM::new(&nfa, 0);
+        assert!(vm.test("aab"));
+        assert!(vm.test("b"));
+        assert!(!vm.test("aabb"));
+        assert!(!vm.test("xaab"));
+        assert!(!vm.test(""));
+    }
+
+    #[test]
+    fn captures_at_offset() {
+        let nfa = NFA::concat(vec![NFA::char('a'), NFA::char('b')]);
+        let vm = PikeVM::new(&nfa, 0);
+        assert_eq!(vm.captures_at("abab", 1), Some(vec![Some(2), Some(4)]));
+        assert_eq!(vm.captures_at("abab", 3), None);
+    }
+
+    #[test]
+    fn overlapping_matches() {
+        let nfa = NFA::rep(NFA::char('a'));
+        let matches: Vec<(usize, usize)> =
+            PikeVM::new(&nfa, 0).overlapping_matches("aab").collect();
+        // A match of `a*` starts at 0 and ends at each of the first positions.
+        assert_eq!(matches, [(0, 0), (0, 1), (0, 2), (3, 3)]);
+    }
+
+    #[test]
+    fn stream_chunks() {
+        let nfa = NFA::concat(vec![NFA::char('a'), NFA::char('€')]);
+        let mut stream = PikeVM::new(&nfa, 0).stream();
+        assert!(!stream.feed("xa"));
+        assert!(!stream.feed(b"\xe2\x82"));
+        assert!(stream.feed(b"\xacx"));
+        assert!(stream.end());
+
+        let mut stream = PikeVM::new(&nfa, 0).stream();
+        assert!(!stream.feed(b"a\xe2\x82"));
+        assert!(!stream.end());
+    }
+
+    #[test]
+    fn capture_slots() {
+        let nfa = NFA::concat(vec![
+            NFA::char('x'),
+            NFA::capture(NFA::rep(NFA::char('a')), 1),
+        ]);
+        assert_eq!(
+            PikeVM::new(&nfa, 1).captures("-xaa"),
+            Some(vec![Some(1), Some(4), Some(2), Some(4)])
+        );
+    }
+}
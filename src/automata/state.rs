@@ -1,27 +1,159 @@
-use std::{
-    cell::RefCell,
-    collections::{HashMap, HashSet},
-    rc::Rc,
-};
+use std::{cell::RefCell, collections::HashSet, rc::Rc};
 
-pub static EPSILON: char = 'ε';
+pub static EPSILON: Symbol = Symbol::Epsilon;
 pub static EPSILON_TRANSITIONS: &str = "ε*";
 
+/// An assertion about the position in the text, which a transition checks without
+/// consuming any input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Look {
+    /// `^`, holding at the start of the text.
+    Start,
+    /// `$`, holding at the end of the text.
+    End,
+}
+
+impl Look {
+    /// The label of the transitions checking this assertion in an NFA table. Like
+    /// "ε*", it can't be mistaken for the label of a class.
+    pub fn label(self) -> &'static str {
+        match self {
+            Look::Start => "ε^",
+            Look::End => "ε$",
+        }
+    }
+
+    /// The assertion holding at the same position of the reversed text.
+    pub fn reversed(self) -> Look {
+        match self {
+            Look::Start => Look::End,
+            Look::End => Look::Start,
+        }
+    }
+}
+
+/// Whether the chars a search runs over begin at the start of the text and end at
+/// its end, which tells where `Look::Start` and `Look::End` hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Edges {
+    pub start: bool,
+    pub end: bool,
+}
+
+impl Edges {
+    /// The chars are the whole text.
+    pub const WHOLE: Edges = Edges {
+        start: true,
+        end: true,
+    };
+
+    /// The edges of the chars from the offset `start` to the end of a text.
+    pub fn from_offset(start: usize) -> Edges {
+        Edges {
+            start: start == 0,
+            end: true,
+        }
+    }
+
+    /// The edges of the chars between the offsets `start` and `end` of a text of
+    /// `len` bytes, read backwards from `end`.
+    pub fn backwards(start: usize, end: usize, len: usize) -> Edges {
+        Edges {
+            start: end == len,
+            end: start == 0,
+        }
+    }
+
+    /// Whether the assertion holds at a position lying at these edges.
+    pub fn holds(self, look: Look) -> bool {
+        match look {
+            Look::Start => self.start,
+            Look::End => self.end,
+        }
+    }
+}
+
+/// The symbol a transition is labeled with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Symbol {
+    /// A transition that doesn't consume any input.
+    Epsilon,
+    /// A transition consuming a single char.
+    Char(char),
+    /// A transition consuming any char in the inclusive range.
+    Range(char, char),
+    /// A transition that doesn't consume any input, taken only where the
+    /// assertion holds.
+    Look(Look),
+}
+
+impl Symbol {
+    /// Returns whether a transition for this symbol consumes the char.
+    pub fn matches(&self, c: char) -> bool {
+        match *self {
+            Symbol::Epsilon | Symbol::Look(_) => false,
+            Symbol::Char(x) => x == c,
+            Symbol::Range(start, end) => start <= c && c <= end,
+        }
+    }
+
+    /// Returns the range of chars consumed by a transition for this symbol.
+    pub fn range(&self) -> Option<(char, char)> {
+        match *self {
+            Symbol::Epsilon | Symbol::Look(_) => None,
+            Symbol::Char(c) => Some((c, c)),
+            Symbol::Range(start, end) => Some((start, end)),
+        }
+    }
+}
+
 // TODO: consider removing Rc trait
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct State {
     pub accepting: bool,
-    pub transitions: HashMap<char, Vec<Rc<RefCell<State>>>>,
+    /// The capture slot that records the current position when this state is
+    /// entered. Slots are only used when searching for match boundaries, the
+    /// automata themselves ignore them.
+    pub slot: Option<usize>,
+    /// The transitions out of this state, in the order they were added.
+    ///
+    /// The order is the priority of each transition: when several paths lead
+    /// to a match, a leftmost-first search prefers the one taking the earlier
+    /// transitions. This is what makes `a*` greedy and `a*?` lazy.
+    pub transitions: Vec<(Symbol, Rc<RefCell<State>>)>,
 }
 
 impl State {
-    pub fn add_transition_for_symbol(&mut self, symbol: char, state: Rc<RefCell<State>>) {
-        let t = self.transitions.entry(symbol).or_insert(vec![]);
-        t.push(state);
+    pub fn new(accepting: bool) -> State {
+        State {
+            accepting,
+            slot: None,
+            transitions: vec![],
+        }
+    }
+
+    pub fn add_transition_for_symbol(&mut self, symbol: Symbol, state: Rc<RefCell<State>>) {
+        self.transitions.push((symbol, state));
+    }
+
+    /// Returns the states reached by a transition for the given symbol, in
+    /// priority order.
+    pub fn get_transition_for_symbol<'s>(
+        &'s self,
+        symbol: &'s Symbol,
+    ) -> impl Iterator<Item = &'s Rc<RefCell<State>>> {
+        self.transitions
+            .iter()
+            .filter(move |(s, _)| s == symbol)
+            .map(|(_, state)| state)
     }
 
-    pub fn get_transition_for_symbol(&self, symbol: &char) -> Option<&Vec<Rc<RefCell<State>>>> {
-        self.transitions.get(&symbol)
+    /// Returns the states reached by consuming the char, in priority order.
+    pub fn get_transition_for_char(&self, c: char) -> impl Iterator<Item = &Rc<RefCell<State>>> {
+        self.transitions
+            .iter()
+            .filter(move |(s, _)| s.matches(c))
+            .map(|(_, state)| state)
     }
 
     // Tests whether this NFA matches the string.
@@ -39,11 +171,9 @@ impl State {
                 return true;
             }
 
-            if let Some(states) = self.get_transition_for_symbol(&EPSILON) {
-                for next_state in states.iter() {
-                    if next_state.borrow().test(&mut "".to_string(), visited) {
-                        return true;
-                    }
+            for next_state in self.get_transition_for_symbol(&EPSILON) {
+                if next_state.borrow().test(&mut "".to_string(), visited) {
+                    return true;
                 }
             }
 
@@ -51,24 +181,34 @@ impl State {
         }
 
         let mut rest = string.clone();
-        let symbol = rest.remove(0);
+        let c = rest.remove(0);
 
-        if let Some(symbol_transitions) = self.get_transition_for_symbol(&symbol) {
-            for next_state in symbol_transitions {
-                if next_state.borrow().test(&mut rest, &mut HashSet::new()) {
-                    return true;
-                }
+        for next_state in self.get_transition_for_char(c) {
+            if next_state.borrow().test(&mut rest, &mut HashSet::new()) {
+                return true;
             }
         }
 
-        if let Some(states) = self.get_transition_for_symbol(&EPSILON) {
-            for next_state in states {
-                if next_state.borrow().test(string, visited) {
-                    return true;
-                }
+        for next_state in self.get_transition_for_symbol(&EPSILON) {
+            if next_state.borrow().test(string, visited) {
+                return true;
             }
         }
 
-        return false;
+        false
+    }
+}
+
+// Dropping a state drops the states it transitions to, which would recurse once per
+// state of a long chain and overflow the stack. The states only this one holds are
+// emptied into a stack instead, so they have no transitions left when dropped.
+impl Drop for State {
+    fn drop(&mut self) {
+        let mut stack = std::mem::take(&mut self.transitions);
+        while let Some((_, state)) = stack.pop() {
+            if let Ok(state) = Rc::try_unwrap(state) {
+                stack.append(&mut state.borrow_mut().transitions);
+            }
+        }
     }
 }
@@ -0,0 +1,202 @@
+use std::char::REPLACEMENT_CHARACTER;
+
+/// The last char encoded in a single code unit, ending the Basic Multilingual Plane.
+const MAX_BMP: u32 = 0xFFFF;
+
+/// Returns the sequences of code unit ranges matching the UTF-16 encodings of the
+/// chars from `start` to `end`, e.g. `[[0xD83D], [0xDE00-0xDE4F]]` for the emoticons
+/// block `😀-🙏`.
+///
+/// Like `utf8::sequences`, every char of the range is matched by exactly one
+/// sequence, whose unit at each position is in the range at that position, and no
+/// other char is.
+pub fn sequences(start: char, end: char) -> Vec<Vec<(u16, u16)>> {
+    let mut sequences = vec![];
+    let mut ranges = vec![(start as u32, end as u32)];
+
+    while let Some((start, mut end)) = ranges.pop() {
+        'split: loop {
+            // The surrogates aren't chars, they have no encoding.
+            if start < 0xD800 && end > 0xDFFF {
+                ranges.push((0xE000, end));
+                end = 0xD7FF;
+            }
+            // The encodings of a range must all have the same length.
+            if start <= MAX_BMP && MAX_BMP < end {
+                ranges.push((MAX_BMP + 1, end));
+                end = MAX_BMP;
+            }
+            // A surrogate pair holds the 10 high and the 10 low bits of the char
+            // minus 0x10000, so all the pairs must share their high surrogate but
+            // the ones spanning whole ranges of low surrogates.
+            let mask = (1 << 10) - 1;
+            if start > MAX_BMP && start & !mask != end & !mask {
+                if start & mask != 0 {
+                    ranges.push(((start | mask) + 1, end));
+                    end = start | mask;
+                    continue 'split;
+                }
+                if end & mask != mask {
+                    ranges.push((end & !mask, end));
+                    end = (end & !mask) - 1;
+                    continue 'split;
+                }
+            }
+            break;
+        }
+
+        let (mut start_units, mut end_units) = ([0; 2], [0; 2]);
+        let start = char::from_u32(start)
+            .unwrap()
+            .encode_utf16(&mut start_units);
+        let end = char::from_u32(end).unwrap().encode_utf16(&mut end_units);
+        let sequence = start.iter().copied().zip(end.iter().copied()).collect();
+        sequences.push(sequence);
+    }
+
+    sequences
+}
+
+/// Whether the unit is a high surrogate, the first unit of a surrogate pair.
+pub fn is_high_surrogate(unit: u16) -> bool {
+    (0xD800..=0xDBFF).contains(&unit)
+}
+
+/// Whether the unit is a low surrogate, the second unit of a surrogate pair.
+pub fn is_low_surrogate(unit: u16) -> bool {
+    (0xDC00..=0xDFFF).contains(&unit)
+}
+
+/// Returns an iterator over the chars of the UTF-16 encoded units, along with
+/// their offsets in code units.
+///
+/// The units are decoded leniently, so that decoding never fails and reads the
+/// same forwards and backwards: a surrogate that isn't part of a pair decodes to
+/// U+FFFD on its own.
+pub fn char_indices(units: &[u16]) -> CharIndices<'_> {
+    CharIndices {
+        units,
+        front: 0,
+        back: units.len(),
+    }
+}
+
+/// An iterator over the chars of UTF-16 encoded units, returned by
+/// `char_indices`.
+#[derive(Clone, Debug)]
+pub struct CharIndices<'u> {
+    units: &'u [u16],
+    front: usize,
+    back: usize,
+}
+
+impl Iterator for CharIndices<'_> {
+    type Item = (usize, char);
+
+    fn next(&mut self) -> Option<(usize, char)> {
+        let units = &self.units[self.front..self.back];
+        let (c, len) = match *units {
+            [] => return None,
+            [high, low, ..] if is_high_surrogate(high) && is_low_surrogate(low) => {
+                (pair(high, low), 2)
+            }
+            [unit, ..] => (
+                char::from_u32(unit as u32).unwrap_or(REPLACEMENT_CHARACTER),
+                1,
+            ),
+        };
+        let at = self.front;
+        self.front += len;
+        Some((at, c))
+    }
+}
+
+impl DoubleEndedIterator for CharIndices<'_> {
+    fn next_back(&mut self) -> Option<(usize, char)> {
+        let units = &self.units[self.front..self.back];
+        let (c, len) = match *units {
+            [] => return None,
+            [.., high, low] if is_high_surrogate(high) && is_low_surrogate(low) => {
+                (pair(high, low), 2)
+            }
+            [.., unit] => (
+                char::from_u32(unit as u32).unwrap_or(REPLACEMENT_CHARACTER),
+                1,
+            ),
+        };
+        self.back -= len;
+        Some((self.back, c))
+    }
+}
+
+/// Decodes a surrogate pair.
+pub fn pair(high: u16, low: u16) -> char {
+    let c = 0x10000 + ((high as u32 - 0xD800) << 10) + (low as u32 - 0xDC00);
+    char::from_u32(c).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_both_ways() {
+        let texts: [Vec<u16>; 4] = [
+            "aé€😀".encode_utf16().collect(),
+            vec![0xD83D, 0xD83D, 0xDE00, 0xDE00],
+            vec![0xDE00, 0x61, 0xD83D],
+            vec![],
+        ];
+        for units in &texts {
+            let forwards: Vec<(usize, char)> = char_indices(units).collect();
+            let mut backwards: Vec<(usize, char)> = char_indices(units).rev().collect();
+            backwards.reverse();
+            assert_eq!(forwards, backwards, "{units:?}");
+
+            let lossy: String = forwards.iter().map(|&(_, c)| c).collect();
+            assert_eq!(lossy, String::from_utf16_lossy(units), "{units:?}");
+        }
+        let chars: Vec<(usize, char)> = char_indices(&texts[1]).collect();
+        let fffd = REPLACEMENT_CHARACTER;
+        assert_eq!(chars, [(0, fffd), (1, '😀'), (3, fffd)]);
+    }
+
+    #[test]
+    fn sequences_match_encodings() {
+        let ranges = [
+            ('\0', char::MAX),
+            ('a', 'z'),
+            ('\u{D7FF}', '\u{E000}'),
+            ('\u{FFFF}', '\u{10000}'),
+            ('\u{1F600}', '\u{1F64F}'),
+            ('\u{103FF}', '\u{10800}'),
+        ];
+        assert_eq!(
+            sequences('\u{1F600}', '\u{1F64F}'),
+            [vec![(0xD83D, 0xD83D), (0xDE00, 0xDE4F)]]
+        );
+        assert_eq!(sequences('\0', char::MAX).len(), 3);
+
+        let chars = (0..=char::MAX as u32)
+            .step_by(61)
+            .filter_map(char::from_u32);
+        for c in chars.chain(['\u{D7FF}', '\u{E000}', '\u{103FF}', char::MAX]) {
+            let mut units = [0; 2];
+            let units = c.encode_utf16(&mut units);
+            for (start, end) in ranges {
+                let matching = sequences(start, end)
+                    .into_iter()
+                    .filter(|seq| {
+                        seq.len() == units.len()
+                            && seq
+                                .iter()
+                                .zip(&*units)
+                                .all(|(&(s, e), &u)| s <= u && u <= e)
+                    })
+                    .count();
+                let expected = usize::from(start <= c && c <= end);
+                assert_eq!(matching, expected, "{c:?} in {start:?}-{end:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use std::char::REPLACEMENT_CHARACTER;
+
+/// Returns the sequences of byte ranges matching the UTF-8 encodings of the chars
+/// from `start` to `end`, e.g. `[[0xC3], [0xA0-0xBF]]` for `à-ÿ`.
+///
+/// Every char of the range is matched by exactly one sequence, whose byte at each
+/// position is in the range at that position, and no other char is. This turns a
+/// transition on a range of chars into transitions on bytes.
+pub fn sequences(start: char, end: char) -> Vec<Vec<(u8, u8)>> {
+    let mut sequences = vec![];
+    let mut ranges = vec![(start as u32, end as u32)];
+
+    while let Some((start, mut end)) = ranges.pop() {
+        'split: loop {
+            // The surrogates aren't chars, they have no encoding.
+            if start < 0xD800 && end > 0xDFFF {
+                ranges.push((0xE000, end));
+                end = 0xD7FF;
+            }
+            // The encodings of a range must all have the same length.
+            for max in [0x7F, 0x7FF, 0xFFFF] {
+                if start <= max && max < end {
+                    ranges.push((max + 1, end));
+                    end = max;
+                }
+            }
+            // All the encodings must share their bytes but the last ones, which
+            // may span any range of continuation bytes.
+            for i in 1..4 {
+                let mask = (1 << (6 * i)) - 1;
+                if start & !mask != end & !mask {
+                    if start & mask != 0 {
+                        ranges.push(((start | mask) + 1, end));
+                        end = start | mask;
+                        continue 'split;
+                    }
+                    if end & mask != mask {
+                        ranges.push((end & !mask, end));
+                        end = (end & !mask) - 1;
+                        continue 'split;
+                    }
+                }
+            }
+            break;
+        }
+
+        let (mut start_bytes, mut end_bytes) = ([0; 4], [0; 4]);
+        let start = char::from_u32(start).unwrap().encode_utf8(&mut start_bytes);
+        let end = char::from_u32(end).unwrap().encode_utf8(&mut end_bytes);
+        let sequence = start.bytes().zip(end.bytes()).collect();
+        sequences.push(sequence);
+    }
+
+    sequences
+}
+
+/// Returns the first char of the bytes and the number of bytes encoding it.
+///
+/// The bytes are decoded leniently, so that decoding never fails and reads the
+/// same forwards and backwards: a byte that isn't part of the encoding of a char
+/// decodes to U+FFFD on its own.
+pub fn decode(bytes: &[u8]) -> Option<(char, usize)> {
+    if bytes.is_empty() {
+        return None;
+    }
+    Some(decode_prefix(bytes).unwrap_or((REPLACEMENT_CHARACTER, 1)))
+}
+
+/// Returns the last char of the bytes and the number of bytes encoding it,
+/// decoding like `decode`.
+pub fn decode_last(bytes: &[u8]) -> Option<(char, usize)> {
+    let &last = bytes.last()?;
+    if last.is_ascii() {
+        return Some((last as char, 1));
+    }
+    // The char can only start at the last byte that isn't a continuation byte.
+    let from = bytes.len().saturating_sub(4);
+    let lead = bytes[from..].iter().rposition(|&b| b & 0xC0 != 0x80);
+    if let Some(lead) = lead.map(|i| from + i) {
+        if let Some((c, len)) = decode_prefix(&bytes[lead..]) {
+            if lead + len == bytes.len() {
+                return Some((c, len));
+            }
+        }
+    }
+    Some((REPLACEMENT_CHARACTER, 1))
+}
+
+/// Returns an iterator over the chars of the bytes decoded like `decode`, along
+/// with their byte offsets, like `str::char_indices`.
+pub fn char_indices(bytes: &[u8]) -> CharIndices<'_> {
+    CharIndices {
+        bytes,
+        front: 0,
+        back: bytes.len(),
+    }
+}
+
+/// An iterator over the chars of bytes decoded like `decode`, returned by
+/// `char_indices`.
+#[derive(Clone, Debug)]
+pub struct CharIndices<'b> {
+    bytes: &'b [u8],
+    front: usize,
+    back: usize,
+}
+
+impl Iterator for CharIndices<'_> {
+    type Item = (usize, char);
+
+    fn next(&mut self) -> Option<(usize, char)> {
+        let (c, len) = decode(&self.bytes[self.front..self.back])?;
+        let at = self.front;
+        self.front += len;
+        Some((at, c))
+    }
+}
+
+impl DoubleEndedIterator for CharIndices<'_> {
+    fn next_back(&mut self) -> Option<(usize, char)> {
+        let (c, len) = decode_last(&self.bytes[self.front..self.back])?;
+        self.back -= len;
+        Some((self.back, c))
+    }
+}
+
+/// Decodes UTF-8 text given in chunks, keeping the bytes of a char split
+/// between two chunks until the next one completes it.
+///
+/// The text is decoded like `decode`, so decoding never fails.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Decoder {
+    /// The first bytes of a char cut short by the end of the last chunk.
+    partial: Vec<u8>,
+}
+
+impl Decoder {
+    /// Calls `f` with every char the chunk completes, along with the number of
+    /// bytes encoding it.
+    pub fn decode(&mut self, mut chunk: &[u8], mut f: impl FnMut(char, usize)) {
+        while !self.partial.is_empty() {
+            // A char is at most 4 bytes long, so it's either complete or
+            // invalid once 4 bytes are known.
+            let take = chunk.len().min(4 - self.partial.len());
+            self.partial.extend_from_slice(&chunk[..take]);
+            chunk = &chunk[take..];
+            let Some((c, len)) = decode_prefix(&self.partial) else {
+                return;
+            };
+            f(c, len);
+            self.partial.drain(..len);
+        }
+
+        let mut chunks = chunk.utf8_chunks().peekable();
+        while let Some(utf8) = chunks.next() {
+            for c in utf8.valid().chars() {
+                f(c, c.len_utf8());
+            }
+            let invalid = utf8.invalid();
+            if chunks.peek().is_none() && decode_prefix(invalid).is_none() {
+                self.partial.extend_from_slice(invalid);
+            } else {
+                // The bytes after the first one are continuation bytes, which
+                // can't start a char either.
+                for _ in invalid {
+                    f(REPLACEMENT_CHARACTER, 1);
+                }
+            }
+        }
+    }
+
+    /// Ends the text, decoding the bytes of a char it cut short.
+    pub fn finish(&mut self, mut f: impl FnMut(char, usize)) {
+        for _ in self.partial.drain(..) {
+            f(REPLACEMENT_CHARACTER, 1);
+        }
+    }
+}
+
+// Decodes the first char of the bytes like `decode`, returning `None` if they're
+// empty or the start of a char cut short.
+fn decode_prefix(bytes: &[u8]) -> Option<(char, usize)> {
+    let len = match bytes.first()? {
+        0x00..=0x7F => 1,
+        0xC2..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF4 => 4,
+        _ => return Some((REPLACEMENT_CHARACTER, 1)),
+    };
+    match std::str::from_utf8(&bytes[..len.min(bytes.len())]) {
+        Ok(s) => s.chars().next().map(|c| (c, len)),
+        Err(err) if err.error_len().is_none() => None,
+        Err(_) => Some((REPLACEMENT_CHARACTER, 1)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_chunks(chunks: &[&[u8]]) -> Vec<(char, usize)> {
+        let mut decoder = Decoder::default();
+        let mut chars = vec![];
+        for chunk in chunks {
+            decoder.decode(chunk, |c, len| chars.push((c, len)));
+        }
+        decoder.finish(|c, len| chars.push((c, len)));
+        chars
+    }
+
+    #[test]
+    fn split_chars() {
+        let euro = "€".as_bytes();
+        let expected = [('a', 1), ('€', 3), ('b', 1)];
+        assert_eq!(decode_chunks(&[b"a", euro, b"b"]), expected);
+        assert_eq!(
+            decode_chunks(&[b"a", &euro[..1], &euro[1..], b"b"]),
+            expected
+        );
+        assert_eq!(
+            decode_chunks(&[b"a", &euro[..1], &euro[1..2], b"", &euro[2..], b"b"]),
+            expected
+        );
+        assert_eq!(decode_chunks(&[b"a\xe2", b"\x82\xacb"]), expected);
+    }
+
+    #[test]
+    fn invalid_bytes() {
+        let fffd = REPLACEMENT_CHARACTER;
+        assert_eq!(decode_chunks(&[b"a\xffb"]), [('a', 1), (fffd, 1), ('b', 1)]);
+        // An invalid sequence split between chunks decodes the same.
+        let expected = [(fffd, 1), (fffd, 1), ('b', 1)];
+        assert_eq!(decode_chunks(&[b"\xe2\x82b"]), expected);
+        assert_eq!(decode_chunks(&[b"\xe2\x82", b"b"]), expected);
+        assert_eq!(decode_chunks(&[b"\xe2", b"\x82b"]), expected);
+        // A char cut short by the end of the text.
+        assert_eq!(
+            decode_chunks(&[b"a\xe2\x82"]),
+            [('a', 1), (fffd, 1), (fffd, 1)]
+        );
+    }
+
+    #[test]
+    fn decode_both_ways() {
+        let texts: [&[u8]; 6] = [
+            "aé€😀".as_bytes(),
+            b"\xe2\x82\xac\xac",
+            b"\xf0\xe2\x82\xac",
+            b"\xe0\x80\x80\xed\xa0\x80",
+            b"\xc3",
+            b"",
+        ];
+        for text in texts {
+            let forwards: Vec<(usize, char)> = char_indices(text).collect();
+            let mut backwards: Vec<(usize, char)> = char_indices(text).rev().collect();
+            backwards.reverse();
+            assert_eq!(forwards, backwards, "{text:?}");
+
+            let lossy: String = forwards.iter().map(|&(_, c)| c).collect();
+            let chunks: String = decode_chunks(&[text]).iter().map(|&(c, _)| c).collect();
+            assert_eq!(lossy, chunks, "{text:?}");
+        }
+        let chars: Vec<char> = char_indices(b"\xe2\x82\xac\xac").map(|(_, c)| c).collect();
+        assert_eq!(chars, ['€', REPLACEMENT_CHARACTER]);
+    }
+
+    #[test]
+    fn sequences_match_encodings() {
+        let ranges = [
+            ('\0', char::MAX),
+            ('a', 'z'),
+            ('\u{80}', '\u{10000}'),
+            ('\u{7FF}', '\u{800}'),
+            ('\u{D7FF}', '\u{E000}'),
+            ('\u{1F600}', '\u{1F64F}'),
+        ];
+        assert_eq!(sequences('à', 'ÿ'), [vec![(0xC3, 0xC3), (0xA0, 0xBF)]]);
+        assert_eq!(sequences('\0', char::MAX).len(), 9);
+
+        let chars = (0..=char::MAX as u32)
+            .step_by(61)
+            .filter_map(char::from_u32);
+        for c in chars.chain(['\u{D7FF}', '\u{E000}', char::MAX]) {
+            let mut bytes = [0; 4];
+            let bytes = c.encode_utf8(&mut bytes).as_bytes();
+            for (start, end) in ranges {
+                let matching = sequences(start, end)
+                    .into_iter()
+                    .filter(|seq| {
+                        seq.len() == bytes.len()
+                            && seq.iter().zip(bytes).all(|(&(s, e), &b)| s <= b && b <= e)
+                    })
+                    .count();
+                let expected = usize::from(start <= c && c <= end);
+                assert_eq!(matching, expected, "{c:?} in {start:?}-{end:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,295 @@
+use std::{
+    char::REPLACEMENT_CHARACTER,
+    collections::{BTreeSet, HashMap},
+};
+
+use crate::automata::{
+    dense::{DenseDFA, StateID, DEAD},
+    utf16,
+};
+
+/// The transition out of a high surrogate on a unit that isn't a low surrogate.
+const INVALID: StateID = StateID::MAX;
+
+/// A low surrogate, which transitions like U+FFFD from the states between chars.
+const LONE_SURROGATE: u16 = 0xDC00;
+
+/// A DFA transitioning on the code units of UTF-16 encoded text, compiled from a
+/// dense DFA transitioning on chars.
+///
+/// Like the dense DFA maps chars to classes, code units are mapped to the classes of
+/// units that always transition alike, found from the sequences of unit ranges
+/// encoding each class of chars, see `utf16::sequences`. A char outside the Basic
+/// Multilingual Plane is read in two transitions, going through a state after its
+/// high surrogate. The states of the dense DFA keep their IDs, and the surrogate
+/// states come after them.
+///
+/// Like `utf16::char_indices`, a surrogate that isn't part of a pair matches as
+/// U+FFFD: a lone low surrogate transitions like U+FFFD, while a unit that doesn't
+/// complete a high surrogate leads to `INVALID`, the search then reading the high
+/// surrogate as U+FFFD and that unit again after it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Utf16DFA {
+    /// The first unit of each class, in order.
+    classes: Vec<u16>,
+    transitions: Vec<StateID>,
+    matches: Vec<bool>,
+    /// Whether each state matches at the end of the text, see `DenseDFA`.
+    end_matches: Vec<bool>,
+    /// For each surrogate state, the char state it started from.
+    partials: Vec<StateID>,
+    start: StateID,
+    /// The starting state for units after the start of the text.
+    inner_start: StateID,
+}
+
+impl Utf16DFA {
+    pub fn from(dense: &DenseDFA) -> Utf16DFA {
+        // A class of units can't span several kinds of units, nor a boundary of
+        // the ranges of any sequence.
+        let mut starts = BTreeSet::from([0, 0xD800, 0xDC00, 0xE000]);
+        let chars = dense.classes();
+        for class in 0..chars.len() {
+            for (start, end) in chars.ranges(class) {
+                for (first, last) in utf16::sequences(start, end).into_iter().flatten() {
+                    starts.insert(first);
+                    starts.extend(last.checked_add(1));
+                }
+            }
+        }
+
+        let states_len = dense.states_len();
+        let mut dfa = Utf16DFA {
+            classes: starts.into_iter().collect(),
+            transitions: vec![],
+            matches: (0..states_len)
+                .map(|state| dense.is_match_state(state as StateID))
+                .collect(),
+            end_matches: (0..states_len)
+                .map(|state| dense.is_end_match_state(state as StateID))
+                .collect(),
+            partials: vec![],
+            start: dense.start_state(),
+            inner_start: dense.inner_start_state(),
+        };
+        let stride = dfa.classes.len();
+        dfa.transitions = vec![DEAD; states_len * stride];
+
+        // The dead state already loops back to itself.
+        for state in 1..states_len as StateID {
+            let mut partials = HashMap::new();
+            for class in 0..stride {
+                let unit = dfa.classes[class];
+                let target = if utf16::is_high_surrogate(unit) {
+                    // The surrogate states are shared by the high surrogates leading
+                    // to the same states on every low surrogate.
+                    let targets: Vec<StateID> = (0..stride)
+                        .map(|class| match dfa.classes[class] {
+                            low if utf16::is_low_surrogate(low) => {
+                                dense.next_state(state, utf16::pair(unit, low))
+                            }
+                            _ => INVALID,
+                        })
+                        .collect();
+                    *partials.entry(targets).or_insert_with_key(|targets| {
+                        let partial = dfa.matches.len() as StateID;
+                        dfa.transitions.extend(targets);
+                        dfa.matches.push(false);
+                        dfa.end_matches.push(false);
+                        dfa.partials.push(state);
+                        partial
+                    })
+                } else {
+                    let c = char::from_u32(unit as u32).unwrap_or(REPLACEMENT_CHARACTER);
+                    dense.next_state(state, c)
+                };
+                dfa.transitions[state as usize * stride + class] = target;
+            }
+        }
+        dfa
+    }
+
+    pub fn start_state(&self) -> StateID {
+        self.start
+    }
+
+    /// The number of states, counting the surrogate states.
+    pub fn states_len(&self) -> usize {
+        self.matches.len()
+    }
+
+    /// The number of classes of code units, which is the number of transitions of
+    /// each state.
+    pub fn stride(&self) -> usize {
+        self.classes.len()
+    }
+
+    pub fn class(&self, unit: u16) -> usize {
+        self.classes.partition_point(|&start| start <= unit) - 1
+    }
+
+    pub fn next_state(&self, state: StateID, unit: u16) -> StateID {
+        self.transitions[state as usize * self.stride() + self.class(unit)]
+    }
+
+    pub fn is_match_state(&self, state: StateID) -> bool {
+        self.matches[state as usize]
+    }
+
+    /// Whether the state matches at the end of the text, see
+    /// `DenseDFA::is_end_match_state`.
+    pub fn is_end_match_state(&self, state: StateID) -> bool {
+        self.end_matches[state as usize]
+    }
+
+    // Tests whether this DFA accepts the units.
+    pub fn test(&self, units: &[u16]) -> bool {
+        let mut last_match = None;
+        self.for_each_match(units, 0, |at| {
+            last_match = Some(at);
+            true
+        });
+        last_match == Some(units.len())
+    }
+
+    /// Runs the DFA over `units` from the offset `start` until it dies, returning the
+    /// last position where it accepted, like `DenseDFA::find_end`.
+    pub fn find_end(&self, units: &[u16], start: usize) -> Option<usize> {
+        let mut last_match = None;
+        self.for_each_match(units, start, |at| {
+            last_match = Some(at);
+            true
+        });
+        last_match
+    }
+
+    /// Runs the DFA over `units` from the offset `start`, returning the first
+    /// position where it accepts, like `DenseDFA::find_earliest_end`.
+    pub fn find_earliest_end(&self, units: &[u16], start: usize) -> Option<usize> {
+        let mut first_match = None;
+        self.for_each_match(units, start, |at| {
+            first_match = Some(at);
+            false
+        });
+        first_match
+    }
+
+    /// Runs the DFA over the whole `units` until it dies, calling `f` with every
+    /// position where it accepts, like `DenseDFA::for_each_match_end`.
+    pub fn for_each_match_end(&self, units: &[u16], mut f: impl FnMut(usize)) {
+        self.for_each_match(units, 0, |at| {
+            f(at);
+            true
+        });
+    }
+
+    // Runs the DFA over the units from `start`, calling `f` with every position where
+    // the DFA accepts until it dies or `f` returns false.
+    fn for_each_match(&self, units: &[u16], start: usize, mut f: impl FnMut(usize) -> bool) {
+        let mut state = if start == 0 {
+            self.start
+        } else {
+            self.inner_start
+        };
+        for (i, &unit) in units[start..].iter().enumerate() {
+            let at = start + i;
+            if self.is_match_state(state) && !f(at) {
+                return;
+            }
+            let mut next = self.next_state(state, unit);
+            if next == INVALID {
+                state = self.lone_surrogate(state);
+                if state == DEAD || (self.is_match_state(state) && !f(at)) {
+                    return;
+                }
+                next = self.next_state(state, unit);
+            }
+            if next == DEAD {
+                return;
+            }
+            state = next;
+        }
+
+        if self.is_partial(state) {
+            state = self.lone_surrogate(state);
+        }
+        if self.is_end_match_state(state) {
+            f(units.len());
+        }
+    }
+
+    fn is_partial(&self, state: StateID) -> bool {
+        state as usize >= self.states_len() - self.partials.len()
+    }
+
+    // Reads the high surrogate of the surrogate state as U+FFFD, returning the char
+    // state reached.
+    fn lone_surrogate(&self, state: StateID) -> StateID {
+        let chars_len = self.states_len() - self.partials.len();
+        let origin = self.partials[state as usize - chars_len];
+        self.next_state(origin, LONE_SURROGATE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        automata::{dfa_table::DFATable, limits::SizeLimits},
+        match_kind::MatchKind,
+        regex,
+    };
+
+    fn search(pattern: &str) -> (DenseDFA, Utf16DFA) {
+        let table = regex::compile_nfa(pattern).unwrap().get_transition_table();
+        let table = DFATable::determinize_search(&table, MatchKind::All, &SizeLimits::default());
+        let dense = DenseDFA::from(&table.unwrap());
+        let wide = Utf16DFA::from(&dense);
+        (dense, wide)
+    }
+
+    #[test]
+    fn matches_dense_dfa() {
+        let patterns = ["[a-z]+", "é|😀+", ".", "[^a]x", "\u{FFFD}", "([😀-🙏]|ab)c"];
+        let texts: [Vec<u16>; 6] = [
+            "abc".encode_utf16().collect(),
+            "xé😀😀y🙏c".encode_utf16().collect(),
+            vec![0x61, 0xD83D, 0x78],
+            vec![0xDE00, 0xD83D, 0xD83D, 0xDE00, 0x63],
+            vec![0xD83D],
+            vec![],
+        ];
+
+        for pattern in patterns {
+            let (dense, wide) = search(pattern);
+            for units in &texts {
+                // The decoded text, with the offset in the units of each char.
+                let mut decoded = String::new();
+                let mut offsets = HashMap::new();
+                for (i, c) in utf16::char_indices(units) {
+                    offsets.insert(decoded.len(), i);
+                    decoded.push(c);
+                }
+                offsets.insert(decoded.len(), units.len());
+
+                let mut expected = vec![];
+                dense.for_each_match_end(&decoded, |end| expected.push(offsets[&end]));
+                let mut ends = vec![];
+                wide.for_each_match_end(units, |end| ends.push(end));
+                assert_eq!(ends, expected, "/{pattern}/ on {units:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_surrogate_pairs() {
+        let nfa = regex::compile_nfa("[😀-🙏]+x").unwrap();
+        let dense = DenseDFA::from(&DFATable::determinize(&nfa.get_transition_table()));
+        let wide = Utf16DFA::from(&dense);
+        let units: Vec<u16> = "😀🙏x".encode_utf16().collect();
+        assert!(wide.test(&units));
+        assert!(!wide.test(&units[1..]));
+        assert!(!wide.test(&[0xD83D, 0x78]));
+        assert!(!wide.test(&[0xD83D, 0xDE00, 0x78, 0xD83D]));
+    }
+}
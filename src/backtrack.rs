@@ -0,0 +1,640 @@
+use std::{collections::BTreeMap, ops::Range};
+
+use crate::{
+    ast::{AssertionKind, GroupKind, LookaroundKind, Repetition, Span, AST},
+    error::Error,
+    match_kind::MatchKind,
+};
+
+/// The number of steps a single search may take before the backtracker gives
+/// up, unless configured otherwise.
+pub const DEFAULT_STEP_LIMIT: usize = 1_000_000;
+
+type Result<T> = core::result::Result<T, Error>;
+
+/// A backtracking engine that executes the AST directly.
+///
+/// Unlike the automata, this engine can run any pattern the parser accepts,
+/// including backreferences. The price is a worst case exponential running
+/// time, which is why every search is bounded by a step budget: once a search
+/// has visited `step_limit` AST nodes it fails with
+/// `Error::StepLimitExceeded` instead of running forever.
+///
+/// Lookbehind assertions are matched by trying every possible start before
+/// the current position, so their expression must have a bounded length.
+#[derive(Debug, Clone)]
+pub struct Backtracker {
+    ast: AST,
+    captures_len: usize,
+    step_limit: usize,
+    match_kind: MatchKind,
+}
+
+impl Backtracker {
+    pub fn new(ast: AST) -> Result<Backtracker> {
+        check_lookbehinds(&ast)?;
+        let captures_len = ast.captures_len();
+        Ok(Backtracker {
+            ast,
+            captures_len,
+            step_limit: DEFAULT_STEP_LIMIT,
+            match_kind: MatchKind::LeftmostFirst,
+        })
+    }
+
+    /// Set the match semantics of the searches.
+    pub fn with_match_kind(mut self, kind: MatchKind) -> Backtracker {
+        self.match_kind = kind;
+        self
+    }
+
+    /// Set the maximum number of steps a single search may take.
+    pub fn with_step_limit(mut self, limit: usize) -> Backtracker {
+        self.step_limit = limit;
+        self
+    }
+
+    pub fn step_limit(&self) -> usize {
+        self.step_limit
+    }
+
+    /// The span of the whole pattern.
+    pub fn span(&self) -> &Span {
+        self.ast.span()
+    }
+
+    // Tests whether the whole string matches.
+    pub fn test(&self, text: &str) -> Result<bool> {
+        let mut search = Search::new(text, self.captures_len, self.step_limit);
+        search.run(&self.ast, 0, &mut |s, end| Ok(end == s.text.len()))
+    }
+
+    /// Finds the match in `text`, returning its capture slots.
+    /// Group `i` uses the slots `2 * i` and `2 * i + 1`, with group `0` being
+    /// the whole match.
+    ///
+    /// All the attempted starting positions share the step budget.
+    pub fn captures(&self, text: &str) -> Result<Option<Vec<Option<usize>>>> {
+        self.captures_at(text, 0)
+    }
+
+    /// Finds the match starting at or after the byte offset `start`, which
+    /// must be on a char boundary. The text before `start` is still seen by
+    /// anchors and lookbehinds.
+    pub fn captures_at(&self, text: &str, start: usize) -> Result<Option<Vec<Option<usize>>>> {
+        let kind = self.match_kind;
+        let mut search = Search::new(text, self.captures_len, self.step_limit);
+        let mut matched: Option<Vec<Option<usize>>> = None;
+        let starts = text[start..].char_indices().map(|(i, _)| start + i);
+        for start in starts.chain(Some(text.len())) {
+            // Searching for all matches, a match starting at or after the end
+            // of the earliest match found so far can't end before it.
+            if let Some(ref m) = matched {
+                if Some(start) >= m[1] {
+                    break;
+                }
+            }
+            // Only a leftmost-first search stops at the first match; the other
+            // kinds go on backtracking through every way to match at `start`.
+            search.run(&self.ast, start, &mut |s, end| {
+                let better = match matched {
+                    None => true,
+                    Some(ref m) => match kind {
+                        MatchKind::LeftmostFirst => false,
+                        MatchKind::LeftmostLongest => Some(end) > m[1],
+                        MatchKind::All => Some(end) < m[1],
+                    },
+                };
+                if better {
+                    let mut slots = s.slots.clone();
+                    slots[0] = Some(start);
+                    slots[1] = Some(end);
+                    matched = Some(slots);
+                }
+                Ok(kind == MatchKind::LeftmostFirst)
+            })?;
+            if matched.is_some() && kind != MatchKind::All {
+                break;
+            }
+        }
+        Ok(matched)
+    }
+
+    /// Returns an iterator over the start and end of a match for every position
+    /// where a match ends, overlapping matches included, like
+    /// `PikeVM::overlapping_matches`.
+    ///
+    /// Every way to match from each starting position is tried in turn, all of
+    /// them sharing the step budget. Once it's exceeded, the iterator returns
+    /// the error and stops.
+    pub fn overlapping_matches<'b, 't>(&'b self, text: &'t str) -> OverlappingMatches<'b, 't> {
+        OverlappingMatches {
+            backtracker: self,
+            search: Search::new(text, self.captures_len, self.step_limit),
+            start: 0,
+            starts_by_end: BTreeMap::new(),
+        }
+    }
+}
+
+/// The matches of `Backtracker::overlapping_matches`, found as the starting
+/// positions are tried.
+pub struct OverlappingMatches<'b, 't> {
+    backtracker: &'b Backtracker,
+    search: Search<'t>,
+    /// The next starting position to try, past the end of the text once they
+    /// were all tried.
+    start: usize,
+    /// The leftmost start of the matches found ending at each position.
+    starts_by_end: BTreeMap<usize, usize>,
+}
+
+impl Iterator for OverlappingMatches<'_, '_> {
+    type Item = Result<(usize, usize)>;
+
+    fn next(&mut self) -> Option<Result<(usize, usize)>> {
+        let text = self.search.text;
+        loop {
+            // A match starting at or after the next starting position can't end
+            // before it, so the matches ending before it are all known.
+            if let Some(entry) = self.starts_by_end.first_entry() {
+                if *entry.key() < self.start {
+                    let (end, start) = entry.remove_entry();
+                    return Some(Ok((start, end)));
+                }
+            }
+            if self.start > text.len() {
+                return None;
+            }
+
+            let start = self.start;
+            let starts_by_end = &mut self.starts_by_end;
+            let result = self
+                .search
+                .run(&self.backtracker.ast, start, &mut |_, end| {
+                    starts_by_end.entry(end).or_insert(start);
+                    Ok(false)
+                });
+            self.start = match text[start..].chars().next() {
+                Some(c) => start + c.len_utf8(),
+                None => text.len() + 1,
+            };
+            if let Err(err) = result {
+                self.start = text.len() + 1;
+                self.starts_by_end.clear();
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
+/// Fail with `Error::Unsupported` if any lookbehind in the AST can match an
+/// unbounded number of characters.
+fn check_lookbehinds(ast: &AST) -> Result<()> {
+    match ast {
+        AST::Group(group) => {
+            if let GroupKind::Lookaround(kind) = group.kind {
+                if kind.is_lookbehind() && group.ast.char_len_bounds().1.is_none() {
+                    return Err(Error::unsupported(
+                        &group.span,
+                        "lookbehind assertions must match a bounded number of characters",
+                    ));
+                }
+            }
+            check_lookbehinds(&group.ast)
+        }
+        AST::Repetition(rep) => check_lookbehinds(&rep.ast),
+        AST::Alternation(alt) => alt.asts.iter().try_for_each(check_lookbehinds),
+        AST::Concat(con) => con.asts.iter().try_for_each(check_lookbehinds),
+        AST::Empty(_)
+        | AST::Literal(_)
+        | AST::Dot(_)
+        | AST::Class(_)
+        | AST::Assertion(_)
+        | AST::Backreference(_) => Ok(()),
+    }
+}
+
+/// Returns true if both chars are equal when their case is ignored.
+fn eq_ignore_case(a: char, b: char) -> bool {
+    a == b || a.to_lowercase().eq(b.to_lowercase()) || a.to_uppercase().eq(b.to_uppercase())
+}
+
+/// The state of a single search. Capture positions are stored as pairs of
+/// slots, where group `i` uses the slots `2 * i` and `2 * i + 1`.
+struct Search<'t> {
+    text: &'t str,
+    slots: Vec<Option<usize>>,
+    steps: usize,
+    limit: usize,
+}
+
+/// The continuation called with the position where a sub-expression stopped
+/// matching. It returns whether the rest of the pattern matched from there.
+type Next<'n, 't> = &'n mut dyn FnMut(&mut Search<'t>, usize) -> Result<bool>;
+
+impl<'t> Search<'t> {
+    fn new(text: &'t str, captures_len: usize, limit: usize) -> Search<'t> {
+        Search {
+            text,
+            slots: vec![None; (captures_len + 1) * 2],
+            steps: 0,
+            limit,
+        }
+    }
+
+    fn step(&mut self) -> Result<()> {
+        self.steps += 1;
+        if self.steps > self.limit {
+            return Err(Error::StepLimitExceeded { limit: self.limit });
+        }
+        Ok(())
+    }
+
+    fn char_at(&self, at: usize) -> Option<char> {
+        self.text[at..].chars().next()
+    }
+
+    /// Match `ast` starting at `at`, calling `next` for every position where
+    /// it could stop, in priority order, until one of them succeeds.
+    fn run(&mut self, ast: &AST, at: usize, next: Next<'_, 't>) -> Result<bool> {
+        self.step()?;
+
+        match ast {
+            AST::Empty(_) => next(self, at),
+            AST::Literal(lit) => match self.char_at(at) {
+                Some(c) if c == lit.c => next(self, at + c.len_utf8()),
+                _ => Ok(false),
+            },
+            AST::Dot(_) => match self.char_at(at) {
+                Some(c) if c != '\n' => next(self, at + c.len_utf8()),
+                _ => Ok(false),
+            },
+            AST::Class(cls) => match self.char_at(at) {
+                Some(c) if cls.matches(c) => next(self, at + c.len_utf8()),
+                _ => Ok(false),
+            },
+            AST::Group(group) => match group.kind {
+                GroupKind::NonCapturing => self.run(&group.ast, at, next),
+                GroupKind::Lookaround(kind) => self.lookaround(kind, &group.ast, at, next),
+                GroupKind::CaptureIndex(index) | GroupKind::CaptureName { index, .. } => {
+                    let slot = index as usize * 2;
+                    self.run(&group.ast, at, &mut |s, end| {
+                        let old = (s.slots[slot], s.slots[slot + 1]);
+                        s.slots[slot] = Some(at);
+                        s.slots[slot + 1] = Some(end);
+                        if next(s, end)? {
+                            return Ok(true);
+                        }
+                        (s.slots[slot], s.slots[slot + 1]) = old;
+                        Ok(false)
+                    })
+                }
+            },
+            AST::Alternation(alt) => {
+                for ast in &alt.asts {
+                    if self.run(ast, at, next)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            AST::Concat(con) => self.concat(&con.asts, at, next),
+            AST::Repetition(rep) => self.repeat(rep, 0, at, next),
+            AST::Assertion(assertion) => {
+                let before = self.text[..at].chars().next_back();
+                let matched = match assertion.kind {
+                    AssertionKind::StartText => at == 0,
+                    AssertionKind::EndText => at == self.text.len(),
+                    AssertionKind::StartLine => matches!(before, None | Some('\n')),
+                    AssertionKind::EndLine => matches!(self.char_at(at), None | Some('\n')),
+                };
+                if matched {
+                    next(self, at)
+                } else {
+                    Ok(false)
+                }
+            }
+            AST::Backreference(br) => {
+                let slot = br.index as usize * 2;
+                match (self.slots[slot], self.slots[slot + 1]) {
+                    (Some(start), Some(end)) => {
+                        match self.backreference(start..end, at, br.case_insensitive) {
+                            Some(end) => next(self, end),
+                            None => Ok(false),
+                        }
+                    }
+                    // A group that didn't participate in the match can't be
+                    // referred to.
+                    _ => Ok(false),
+                }
+            }
+        }
+    }
+
+    /// Match the text captured in `captured` again at `at`, returning where
+    /// the repeated text ends.
+    fn backreference(
+        &self,
+        captured: Range<usize>,
+        at: usize,
+        case_insensitive: bool,
+    ) -> Option<usize> {
+        let text = &self.text[captured];
+        if !case_insensitive {
+            return self.text[at..].starts_with(text).then(|| at + text.len());
+        }
+
+        let mut end = at;
+        for c in text.chars() {
+            let other = self.char_at(end)?;
+            if !eq_ignore_case(c, other) {
+                return None;
+            }
+            end += other.len_utf8();
+        }
+        Some(end)
+    }
+
+    /// Match a lookaround assertion at `at`. The assertion itself never
+    /// consumes input, and once it succeeded the search doesn't backtrack into
+    /// it again.
+    fn lookaround(
+        &mut self,
+        kind: LookaroundKind,
+        ast: &AST,
+        at: usize,
+        next: Next<'_, 't>,
+    ) -> Result<bool> {
+        let slots = self.slots.clone();
+
+        let matched = if kind.is_lookbehind() {
+            let (min, max) = ast.char_len_bounds();
+            let max = max.expect("lookbehind length must be bounded");
+            let mut starts = self.text[..at].char_indices().rev().map(|(i, _)| i);
+            let mut found = false;
+            let mut start = Some(at);
+            for len in 0..=max {
+                let Some(from) = start else { break };
+                if len >= min && self.run(ast, from, &mut |_, end| Ok(end == at))? {
+                    found = true;
+                    break;
+                }
+                start = starts.next();
+            }
+            found
+        } else {
+            self.run(ast, at, &mut |_, _| Ok(true))?
+        };
+
+        // Captures set inside the assertion are only kept when it matched
+        // and the assertion is positive.
+        if !(matched && kind.is_positive()) {
+            self.slots.clone_from(&slots);
+        }
+        if matched == kind.is_positive() && next(self, at)? {
+            return Ok(true);
+        }
+        self.slots = slots;
+        Ok(false)
+    }
+
+    fn concat(&mut self, asts: &[AST], at: usize, next: Next<'_, 't>) -> Result<bool> {
+        match asts.split_first() {
+            None => next(self, at),
+            Some((first, rest)) => self.run(first, at, &mut |s, i| s.concat(rest, i, next)),
+        }
+    }
+
+    /// Match the remaining iterations of a repetition, `count` of which
+    /// already matched. Greedy repetitions try one more iteration before the
+    /// rest of the pattern, lazy ones the other way around.
+    fn repeat(
+        &mut self,
+        rep: &Repetition,
+        count: u32,
+        at: usize,
+        next: Next<'_, 't>,
+    ) -> Result<bool> {
+        let (min, max) = rep.op.kind.bounds();
+        if count < min {
+            return self.run(&rep.ast, at, &mut |s, i| s.repeat(rep, count + 1, i, next));
+        }
+
+        if !rep.greedy && next(self, at)? {
+            return Ok(true);
+        }
+
+        if max.is_none_or(|max| count < max) {
+            // An iteration that matches the empty string would loop forever
+            // without getting anywhere, so it stops the repetition. Like the
+            // automata, which drop a thread coming back to the loop where it
+            // started, an unbounded repetition fails there, while a bounded one
+            // goes on with the rest of the pattern.
+            let more = self.run(&rep.ast, at, &mut |s, i| match (i == at, max) {
+                (false, _) => s.repeat(rep, count + 1, i, next),
+                (true, Some(_)) => next(s, i),
+                (true, None) => Ok(false),
+            })?;
+            if more {
+                return Ok(true);
+            }
+        }
+
+        if rep.greedy {
+            next(self, at)
+        } else {
+            Ok(false)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ast::parser::{Parser, ParserBuilder},
+        automata::pikevm::PikeVM,
+        regex::compile_nfa,
+    };
+
+    fn backtracker(pattern: &str) -> Backtracker {
+        Backtracker::new(Parser::new().parse(pattern).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_literals_and_alternation() {
+        let re = backtracker("ab|cd");
+        assert_eq!(re.test("ab"), Ok(true));
+        assert_eq!(re.test("cd"), Ok(true));
+        assert_eq!(re.test("ad"), Ok(false));
+        assert_eq!(re.test("abcd"), Ok(false));
+    }
+
+    #[test]
+    fn test_repetitions() {
+        let re = backtracker("a{2,3}b*c?");
+        assert_eq!(re.test("aa"), Ok(true));
+        assert_eq!(re.test("aaabbbc"), Ok(true));
+        assert_eq!(re.test("a"), Ok(false));
+        assert_eq!(re.test("aaaa"), Ok(false));
+    }
+
+    #[test]
+    fn lazy_repetitions() {
+        let re = backtracker("a{2,}?b*?c??");
+        assert_eq!(re.test("aaabbc"), Ok(true));
+        assert_eq!(re.test("a"), Ok(false));
+
+        let re = backtracker(r#""(.*?)""#);
+        assert_eq!(
+            re.captures(r#"say "hi" and "bye""#),
+            Ok(Some(vec![Some(4), Some(8), Some(5), Some(7)]))
+        );
+    }
+
+    #[test]
+    fn captures_leftmost_first() {
+        let re = backtracker("(a|ab)(c|bcd)");
+        assert_eq!(
+            re.captures("xabcd"),
+            Ok(Some(vec![
+                Some(1),
+                Some(5),
+                Some(1),
+                Some(2),
+                Some(2),
+                Some(5)
+            ]))
+        );
+        assert_eq!(backtracker("b+").captures("aaa"), Ok(None));
+    }
+
+    #[test]
+    fn test_empty_loop_terminates() {
+        let re = backtracker("(a*)*b");
+        assert_eq!(re.test("aab"), Ok(true));
+        assert_eq!(re.test("aa"), Ok(false));
+    }
+
+    #[test]
+    fn empty_iterations_match_like_pikevm() {
+        let patterns = [
+            "(|a)?",
+            "(|a){1,2}",
+            "(|a){2,3}b",
+            "((b)*?)?",
+            "(|a)*",
+            "(a*)*",
+            "(a*)+b",
+            "(a|)+?c",
+            "((a?)?)*b",
+            "(a??){2}",
+        ];
+        let texts = ["", "a", "aa", "bac", "ab", "aac", "b"];
+        for pattern in patterns {
+            let re = backtracker(pattern);
+            let nfa = compile_nfa(pattern).unwrap();
+            let vm = PikeVM::new(&nfa, re.captures_len);
+            for text in texts {
+                let expected = vm.captures(text);
+                assert_eq!(re.captures(text), Ok(expected), "/{pattern}/ on {text:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_numeric_backreference() {
+        let re = backtracker(r#"(['"]).*\1"#);
+        assert_eq!(re.test(r#""hello""#), Ok(true));
+        assert_eq!(re.test("'it''s'"), Ok(true));
+        assert_eq!(re.test(r#""hello'"#), Ok(false));
+    }
+
+    #[test]
+    fn assertions() {
+        let re = backtracker("^a$");
+        assert_eq!(re.captures("xa"), Ok(None));
+        assert_eq!(re.captures("a"), Ok(Some(vec![Some(0), Some(1)])));
+
+        let ast = ParserBuilder::new()
+            .multi_line(true)
+            .build()
+            .parse("^b$")
+            .unwrap();
+        let re = Backtracker::new(ast).unwrap();
+        assert_eq!(re.captures("a\nb\nc"), Ok(Some(vec![Some(2), Some(3)])));
+        assert_eq!(re.captures("ab\nc"), Ok(None));
+    }
+
+    #[test]
+    fn case_insensitive_backreference() {
+        let ast = ParserBuilder::new()
+            .case_insensitive(true)
+            .build()
+            .parse(r"(a+)\1")
+            .unwrap();
+        let re = Backtracker::new(ast).unwrap();
+        assert_eq!(re.test("aAAa"), Ok(true));
+        assert_eq!(re.test("aAAb"), Ok(false));
+    }
+
+    #[test]
+    fn test_named_backreference() {
+        let re = backtracker(r"(?<word>\w+) \k<word>");
+        assert_eq!(re.test("hey hey"), Ok(true));
+        assert_eq!(re.test("hey you"), Ok(false));
+    }
+
+    #[test]
+    fn test_backreference_to_unmatched_group() {
+        let re = backtracker(r"(a)?b\1");
+        assert_eq!(re.test("aba"), Ok(true));
+        assert_eq!(re.test("b"), Ok(false));
+    }
+
+    #[test]
+    fn test_lookahead() {
+        let re = backtracker("(?=a.c)...(?!d)..");
+        assert_eq!(re.test("abcxy"), Ok(true));
+        assert_eq!(re.test("abdxy"), Ok(false));
+        assert_eq!(re.test("abcdy"), Ok(false));
+    }
+
+    #[test]
+    fn test_lookbehind() {
+        let re = backtracker("[a-z]+(?<=ab|xyz)1(?<!x1)[0-9]");
+        assert_eq!(re.test("cab12"), Ok(true));
+        assert_eq!(re.test("wxyz12"), Ok(true));
+        assert_eq!(re.test("cac12"), Ok(false));
+    }
+
+    #[test]
+    fn test_lookaround_captures() {
+        let re = backtracker(r"(?=(a+))\1b");
+        assert_eq!(re.test("aab"), Ok(true));
+        assert_eq!(re.test("ab"), Ok(true));
+        assert_eq!(re.test("b"), Ok(false));
+    }
+
+    #[test]
+    fn unbounded_lookbehind() {
+        let ast = Parser::new().parse("a(?<=b(c+))").unwrap();
+        match Backtracker::new(ast) {
+            Err(Error::Unsupported { span, .. }) => {
+                assert_eq!((span.start.offset, span.end.offset), (1, 11));
+            }
+            result => panic!("expected an unsupported error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_step_limit() {
+        let re = backtracker("(a|a)*b").with_step_limit(10_000);
+        assert_eq!(re.test("aaab"), Ok(true));
+        assert_eq!(
+            re.test("aaaaaaaaaaaaaaaaaaaaaaaaa"),
+            Err(Error::StepLimitExceeded { limit: 10_000 })
+        );
+    }
+}
@@ -0,0 +1,272 @@
+use crate::{automata::dfa::DFA, codegen::Tables};
+
+/// The C99 sources generated from a DFA, see `generate`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CSource {
+    /// The header declaring the function.
+    pub header: String,
+    /// The source defining the function, including the header.
+    pub source: String,
+}
+
+/// Generates the C99 sources of a function `bool name(const char *input, size_t len)`
+/// running the DFA, a header to include as `header_name` and its source file. The
+/// `name` must be a C identifier, distinct from those of the other functions of the
+/// program, as the function is global.
+///
+/// The function holds the same tables as the Rust function of `rust::generate`, and
+/// runs the DFA the same way over the chars of the UTF-8 encoded input, so for a DFA
+/// built by `codegen::search_dfa`, it tests whether the input contains a match. Like
+/// `utf8::char_indices`, a byte that isn't part of a valid UTF-8 sequence is read
+/// as U+FFFD on its own. The generated code only uses the standard library.
+pub fn generate(dfa: &DFA, header_name: &str, name: &str) -> CSource {
+    let tables = Tables::from(dfa.dense());
+    let state = format!("uint{}_t", tables.state_bits());
+    let class = format!("uint{}_t", tables.class_bits());
+
+    let guard: String = header_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let header = format!(
+        "\
+#ifndef {guard}
+#define {guard}
+
+#include <stdbool.h>
+#include <stddef.h>
+
+/* Tests whether the UTF-8 encoded input, of len bytes, contains a match. */
+bool {name}(const char *input, size_t len);
+
+#endif
+"
+    );
+
+    let hex = |&(start, _): &(char, usize)| format!("0x{:X}", start as u32);
+    let starts = rows(tables.ranges.iter().map(hex));
+    let classes = rows(tables.ranges.iter().map(|&(_, class)| class.to_string()));
+    let transitions = rows(tables.transitions.iter().map(|row| {
+        let row: Vec<String> = row.iter().map(|state| state.to_string()).collect();
+        format!("{{{}}}", row.join(", "))
+    }));
+    let matches = rows(tables.matches.iter().map(|is_match| is_match.to_string()));
+    let end_matches = rows(
+        tables
+            .end_matches
+            .iter()
+            .map(|is_match| is_match.to_string()),
+    );
+    let source = format!(
+        "\
+#include \"{header_name}\"
+
+#include <stdint.h>
+
+#define RANGES_LEN {ranges_len}
+
+/* The first code point of each range of chars in the same class, in order. */
+static const uint32_t RANGE_STARTS[RANGES_LEN] = {{
+{starts}}};
+static const {class} RANGE_CLASSES[RANGES_LEN] = {{
+{classes}}};
+/* The state reached from each state on each class. The dead state is 0. */
+static const {state} TRANSITIONS[{states_len}][{stride}] = {{
+{transitions}}};
+static const bool MATCHES[{states_len}] = {{
+{matches}}};
+/* Whether each state matches at the end of the input. */
+static const bool END_MATCHES[{states_len}] = {{
+{end_matches}}};
+
+/* Decodes the char at the start of the bytes into c, returning its length in
+ * bytes. A byte that isn't part of a valid UTF-8 sequence decodes to U+FFFD. */
+static size_t decode(const unsigned char *bytes, size_t len, uint32_t *c)
+{{
+    size_t n;
+    uint32_t min;
+    if (bytes[0] < 0x80) {{
+        *c = bytes[0];
+        return 1;
+    }} else if (bytes[0] >= 0xC2 && bytes[0] <= 0xDF) {{
+        n = 2;
+        min = 0x80;
+    }} else if (bytes[0] >= 0xE0 && bytes[0] <= 0xEF) {{
+        n = 3;
+        min = 0x800;
+    }} else if (bytes[0] >= 0xF0 && bytes[0] <= 0xF4) {{
+        n = 4;
+        min = 0x10000;
+    }} else {{
+        *c = 0xFFFD;
+        return 1;
+    }}
+    if (len < n) {{
+        *c = 0xFFFD;
+        return 1;
+    }}
+    *c = bytes[0] & (0x7F >> n);
+    for (size_t i = 1; i < n; i++) {{
+        if ((bytes[i] & 0xC0) != 0x80) {{
+            *c = 0xFFFD;
+            return 1;
+        }}
+        *c = (*c << 6) | (bytes[i] & 0x3F);
+    }}
+    if (*c < min || *c > 0x10FFFF || (*c >= 0xD800 && *c <= 0xDFFF)) {{
+        *c = 0xFFFD;
+        return 1;
+    }}
+    return n;
+}}
+
+/* Returns the class of the char, from the last range starting before it. */
+static size_t class_of(uint32_t c)
+{{
+    size_t low = 0, high = RANGES_LEN;
+    while (low < high) {{
+        size_t mid = low + (high - low) / 2;
+        if (RANGE_STARTS[mid] <= c) {{
+            low = mid + 1;
+        }} else {{
+            high = mid;
+        }}
+    }}
+    return RANGE_CLASSES[low - 1];
+}}
+
+bool {name}(const char *input, size_t len)
+{{
+    const unsigned char *bytes = (const unsigned char *)input;
+    size_t state = {start};
+    size_t at = 0;
+    while (at < len) {{
+        uint32_t c;
+        if (MATCHES[state]) {{
+            return true;
+        }}
+        at += decode(bytes + at, len - at, &c);
+        state = TRANSITIONS[state][class_of(c)];
+        if (state == 0) {{
+            return false;
+        }}
+    }}
+    return END_MATCHES[state];
+}}
+",
+        ranges_len = tables.ranges.len(),
+        stride = tables.transitions[0].len(),
+        states_len = tables.matches.len(),
+        start = tables.start,
+    );
+
+    CSource { header, source }
+}
+
+// Lays out the items of an array initializer, one per line.
+fn rows(items: impl Iterator<Item = String>) -> String {
+    items.map(|item| format!("    {item},\n")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{automata::limits::SizeLimits, codegen::search_dfa, regex::Regex};
+    use std::{env, fs, io, process::Command};
+
+    /// A program printing whether each of its arguments contains a match.
+    const MAIN: &str = r#"#include <stdio.h>
+#include <string.h>
+
+#include "is_match.h"
+
+int main(int argc, char **argv)
+{
+    for (int i = 1; i < argc; i++) {
+        putchar(contains_match(argv[i], strlen(argv[i])) ? '1' : '0');
+    }
+    return 0;
+}
+"#;
+
+    #[test]
+    #[cfg(unix)]
+    fn generated_code_matches_like_regex() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let pattern = "[a-z]+@example\\.com|é|😀+|a\u{FFFD}b|^ab|c$";
+        let dfa = search_dfa(pattern, &SizeLimits::default()).unwrap();
+        let CSource { header, source } = generate(&dfa, "is_match.h", "contains_match");
+
+        let dir = env::temp_dir().join(format!("codegen-c-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("is_match.h"), header).unwrap();
+        fs::write(dir.join("is_match.c"), source).unwrap();
+        fs::write(dir.join("main.c"), MAIN).unwrap();
+        let status = Command::new("cc")
+            .current_dir(&dir)
+            .args(["-std=c99", "-Wall", "-Werror", "-o", "is_match"])
+            .args(["is_match.c", "main.c"])
+            .status();
+        // Without a C toolchain, there's nothing to check the code with.
+        let status = match status {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                eprintln!("skipping the C code check, as there's no C compiler `cc`");
+                fs::remove_dir_all(&dir).unwrap();
+                return;
+            }
+            status => status.unwrap(),
+        };
+        assert!(status.success());
+
+        let texts: [&[u8]; 12] = [
+            b"-",
+            b"bob@example.com",
+            b"bob@example.org",
+            "x😀y".as_bytes(),
+            "té".as_bytes(),
+            b"abx",
+            b"xab",
+            b"xc",
+            b"cx",
+            b"a\xffb",
+            b"a\xe2\x82b",
+            b"\xc3\xa9",
+        ];
+        let output = Command::new(dir.join("is_match"))
+            .args(texts.map(OsStr::from_bytes))
+            .output()
+            .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let regex = Regex::new(pattern).unwrap();
+        let is_match = |text: &[u8]| regex.is_match_bytes(text).unwrap();
+        let expected: String = texts
+            .iter()
+            .map(|text| if is_match(text) { '1' } else { '0' })
+            .collect();
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+    }
+
+    #[test]
+    fn generate_sources() {
+        let dfa = search_dfa("ab", &SizeLimits::default()).unwrap();
+        let CSource { header, source } = generate(&dfa, "match-ab.h", "matches_ab");
+
+        assert!(header.starts_with("#ifndef MATCH_AB_H\n#define MATCH_AB_H\n"));
+        assert!(header.contains("bool matches_ab(const char *input, size_t len);\n"));
+        assert!(source.contains("bool matches_ab(const char *input, size_t len)\n{\n"));
+        assert!(source.starts_with("#include \"match-ab.h\"\n"));
+        assert!(source.contains("RANGE_STARTS[RANGES_LEN] = {\n    0x0,\n    0x61,\n"));
+        let transitions = "static const uint8_t TRANSITIONS[4][3] = {\n    {0, 0, 0},\n";
+        assert!(source.contains(transitions), "{source}");
+        assert!(source.contains("static const bool MATCHES[4] = {\n    false,\n"));
+        assert!(source.contains("static const bool END_MATCHES[4] = {\n    false,\n"));
+    }
+}
@@ -0,0 +1,147 @@
+/// Tests whether the input contains a match.
+pub fn is_match(input: &str) -> bool {
+    // The first char of each range of chars in the same class, in order.
+    static RANGES: [(char, u8); 24] = [
+        ('\u{0}', 0),
+        ('\u{2e}', 1),
+        ('\u{2f}', 0),
+        ('\u{40}', 2),
+        ('\u{41}', 0),
+        ('\u{61}', 3),
+        ('\u{62}', 4),
+        ('\u{63}', 5),
+        ('\u{64}', 6),
+        ('\u{65}', 7),
+        ('\u{66}', 6),
+        ('\u{6c}', 8),
+        ('\u{6d}', 9),
+        ('\u{6e}', 6),
+        ('\u{6f}', 10),
+        ('\u{70}', 11),
+        ('\u{71}', 6),
+        ('\u{78}', 12),
+        ('\u{79}', 6),
+        ('\u{7b}', 0),
+        ('\u{e9}', 13),
+        ('\u{ea}', 0),
+        ('\u{1f600}', 14),
+        ('\u{1f601}', 0),
+    ];
+    // The state reached from each state on each class. The dead state is 0.
+    static TRANSITIONS: [[u8; 15]; 32] = [
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [2, 2, 2, 28, 27, 29, 27, 27, 27, 27, 27, 27, 27, 25, 26],
+        [2, 2, 2, 27, 27, 29, 27, 27, 27, 27, 27, 27, 27, 25, 26],
+        [0, 0, 0, 0, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0],
+        [2, 2, 2, 27, 27, 29, 27, 6, 27, 27, 27, 27, 27, 25, 26],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 7, 0, 0],
+        [2, 2, 4, 27, 27, 29, 27, 27, 27, 27, 27, 27, 8, 25, 26],
+        [0, 0, 0, 9, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [2, 2, 4, 10, 27, 29, 27, 27, 27, 27, 27, 27, 27, 25, 26],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 11, 0, 0, 0, 0, 0],
+        [2, 2, 4, 27, 27, 29, 27, 27, 27, 12, 27, 27, 27, 25, 26],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 13, 0, 0, 0],
+        [2, 2, 4, 27, 27, 29, 27, 27, 27, 27, 27, 14, 27, 25, 26],
+        [0, 0, 0, 0, 0, 0, 0, 0, 15, 0, 0, 0, 0, 0, 0],
+        [2, 2, 4, 27, 27, 29, 27, 27, 16, 27, 27, 27, 27, 25, 26],
+        [0, 0, 0, 0, 0, 0, 0, 17, 0, 0, 0, 0, 0, 0, 0],
+        [2, 2, 4, 27, 27, 29, 27, 18, 27, 27, 27, 27, 27, 25, 26],
+        [0, 19, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [2, 20, 4, 27, 27, 29, 27, 27, 27, 27, 27, 27, 27, 25, 26],
+        [0, 0, 0, 0, 0, 21, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [2, 2, 2, 27, 27, 22, 27, 27, 27, 27, 27, 27, 27, 25, 26],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 23, 0, 0, 0, 0],
+        [2, 2, 4, 27, 27, 29, 27, 27, 27, 27, 24, 27, 27, 25, 26],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 25, 0, 0, 0, 0, 0],
+        [2, 2, 4, 27, 27, 29, 27, 27, 27, 25, 27, 27, 27, 25, 26],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 26],
+        [2, 2, 4, 27, 27, 29, 27, 27, 27, 27, 27, 27, 27, 25, 26],
+        [2, 2, 4, 27, 31, 29, 27, 27, 27, 27, 27, 27, 27, 25, 26],
+        [2, 2, 4, 27, 27, 29, 27, 27, 27, 27, 27, 27, 27, 25, 26],
+        [0, 0, 3, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 0, 0],
+        [0, 0, 3, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 0, 0],
+    ];
+    static MATCHES: [bool; 32] = [
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        true,
+        true,
+        false,
+        false,
+        false,
+        false,
+        true,
+    ];
+    // Whether each state matches at the end of the input.
+    static END_MATCHES: [bool; 32] = [
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        true,
+        false,
+        false,
+        true,
+        true,
+        false,
+        false,
+        true,
+        false,
+        true,
+    ];
+
+    let mut state = 1;
+    for c in input.chars() {
+        if MATCHES[state] {
+            return true;
+        }
+        let range = RANGES.partition_point(|&(start, _)| start <= c) - 1;
+        state = TRANSITIONS[state][RANGES[range].1 as usize] as usize;
+        if state == 0 {
+            return false;
+        }
+    }
+    END_MATCHES[state]
+}
@@ -0,0 +1,146 @@
+pub mod c;
+pub mod rust;
+
+use crate::{
+    automata::{
+        dense::{DenseDFA, StateID},
+        dfa::DFA,
+        limits::SizeLimits,
+    },
+    error::Error,
+    match_kind::MatchKind,
+    regex,
+};
+
+/// Compiles the pattern into the minimized DFA searching for its matches, which the
+/// generated code runs to test whether a text contains a match.
+pub fn search_dfa(pattern: &str, limits: &SizeLimits) -> Result<DFA, Error> {
+    let nfa = regex::compile_nfa_with_limits(pattern, limits)?;
+    let mut dfa = DFA::search(&nfa, MatchKind::LeftmostFirst, limits)?;
+    dfa.minimize();
+    Ok(dfa)
+}
+
+/// The tables of a dense DFA, as the generated code holds them.
+///
+/// The generated code has no `CharClasses`, so the class of a char is found by a
+/// binary search over the first char of every range of chars, which are sorted.
+/// The dead state keeps the ID 0.
+struct Tables {
+    /// The first char of each range of chars, in order, with the class of the range.
+    ranges: Vec<(char, usize)>,
+    /// The transitions of each state, one per class.
+    transitions: Vec<Vec<StateID>>,
+    matches: Vec<bool>,
+    /// Whether each state matches at the end of the text, e.g. before a `$`.
+    end_matches: Vec<bool>,
+    start: StateID,
+}
+
+impl Tables {
+    fn from(dense: &DenseDFA) -> Tables {
+        let classes = dense.classes();
+        let mut ranges: Vec<(char, usize)> = (0..classes.len())
+            .flat_map(|class| {
+                let ranges = classes.ranges(class).into_iter();
+                ranges.map(move |(start, _)| (start, class))
+            })
+            .collect();
+        ranges.sort_unstable();
+
+        let states = 0..dense.states_len() as StateID;
+        Tables {
+            ranges,
+            transitions: states
+                .clone()
+                .map(|state| {
+                    let classes = (0..classes.len()).map(|class| classes.representative(class));
+                    classes.map(|c| dense.next_state(state, c)).collect()
+                })
+                .collect(),
+            matches: states
+                .clone()
+                .map(|state| dense.is_match_state(state))
+                .collect(),
+            end_matches: states
+                .map(|state| dense.is_end_match_state(state))
+                .collect(),
+            start: dense.start_state(),
+        }
+    }
+
+    /// The number of bits of the smallest unsigned integers holding every state ID.
+    fn state_bits(&self) -> u32 {
+        bits(self.matches.len() - 1)
+    }
+
+    /// The number of bits of the smallest unsigned integers holding every class.
+    fn class_bits(&self) -> u32 {
+        bits(self.transitions[0].len() - 1)
+    }
+
+    // Runs the tables like the generated code does.
+    #[cfg(test)]
+    fn is_match(&self, text: &str) -> bool {
+        let mut state = self.start as usize;
+        for c in text.chars() {
+            if self.matches[state] {
+                return true;
+            }
+            let range = self.ranges.partition_point(|&(start, _)| start <= c) - 1;
+            state = self.transitions[state][self.ranges[range].1] as usize;
+            if state == 0 {
+                return false;
+            }
+        }
+        self.end_matches[state]
+    }
+}
+
+// The number of bits of the smallest unsigned integers holding the value.
+fn bits(max: usize) -> u32 {
+    match max {
+        max if max <= u8::MAX as usize => 8,
+        max if max <= u16::MAX as usize => 16,
+        _ => 32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regex::Regex;
+
+    #[test]
+    fn tables_match_like_regex() {
+        let patterns = [
+            "[a-z]+@example\\.com",
+            "é|😀+",
+            "a.c",
+            "[^a]x",
+            "",
+            "(ab)*c",
+            "^ab|c$",
+            "^$",
+        ];
+        let texts = [
+            "",
+            "bob@example.com",
+            "x😀y",
+            "abc",
+            "a\nc",
+            "zx",
+            "ababc",
+            "ax",
+        ];
+        for pattern in patterns {
+            let dfa = search_dfa(pattern, &SizeLimits::default()).unwrap();
+            let tables = Tables::from(dfa.dense());
+            let regex = Regex::new(pattern).unwrap();
+            for text in texts {
+                let expected = regex.is_match_bytes(text.as_bytes()).unwrap();
+                assert_eq!(tables.is_match(text), expected, "/{pattern}/ on {text:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use crate::{automata::dfa::DFA, codegen::Tables};
+
+/// Generates the source of a standalone Rust function `name(input: &str) -> bool`
+/// running the DFA, for instance from a `build.rs` script, so that the pattern costs
+/// nothing to compile at run time. The `name` must be a Rust identifier.
+///
+/// The function holds the tables of the DFA in statics, and runs it from the start
+/// of the input like `DenseDFA::find_earliest_end`, so for a DFA built by
+/// `codegen::search_dfa`, it tests whether the input contains a match, like
+/// `Regex::is_match_bytes`. The generated code only uses the standard library.
+pub fn generate(dfa: &DFA, name: &str) -> String {
+    let tables = Tables::from(dfa.dense());
+    let state = format!("u{}", tables.state_bits());
+    let class = format!("u{}", tables.class_bits());
+
+    let ranges: String = tables
+        .ranges
+        .iter()
+        .map(|&(start, class)| format!("        ('\\u{{{:x}}}', {class}),\n", start as u32))
+        .collect();
+    let transitions: String = tables
+        .transitions
+        .iter()
+        .map(|row| format!("        {row:?},\n"))
+        .collect();
+    let matches: String = tables
+        .matches
+        .iter()
+        .map(|is_match| format!("        {is_match},\n"))
+        .collect();
+    let end_matches: String = tables
+        .end_matches
+        .iter()
+        .map(|is_match| format!("        {is_match},\n"))
+        .collect();
+
+    format!(
+        "\
+/// Tests whether the input contains a match.
+pub fn {name}(input: &str) -> bool {{
+    // The first char of each range of chars in the same class, in order.
+    static RANGES: [(char, {class}); {ranges_len}] = [
+{ranges}    ];
+    // The state reached from each state on each class. The dead state is 0.
+    static TRANSITIONS: [[{state}; {stride}]; {states_len}] = [
+{transitions}    ];
+    static MATCHES: [bool; {states_len}] = [
+{matches}    ];
+    // Whether each state matches at the end of the input.
+    static END_MATCHES: [bool; {states_len}] = [
+{end_matches}    ];
+
+    let mut state = {start};
+    for c in input.chars() {{
+        if MATCHES[state] {{
+            return true;
+        }}
+        let range = RANGES.partition_point(|&(start, _)| start <= c) - 1;
+        state = TRANSITIONS[state][RANGES[range].1 as usize] as usize;
+        if state == 0 {{
+            return false;
+        }}
+    }}
+    END_MATCHES[state]
+}}
+",
+        ranges_len = tables.ranges.len(),
+        stride = tables.transitions[0].len(),
+        states_len = tables.matches.len(),
+        start = tables.start,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{automata::limits::SizeLimits, codegen::search_dfa, regex::Regex};
+
+    /// The pattern the checked in fixture is generated from.
+    const FIXTURE_PATTERN: &str = "[a-z]+@example\\.com|é|😀+|^ab|c$";
+
+    /// The code generated for `FIXTURE_PATTERN`, compiled along with the tests.
+    mod fixture {
+        include!("fixtures/is_match.rs");
+    }
+
+    #[test]
+    fn fixture_is_generated() {
+        let dfa = search_dfa(FIXTURE_PATTERN, &SizeLimits::default()).unwrap();
+        let code = generate(&dfa, "is_match");
+        assert_eq!(code, include_str!("fixtures/is_match.rs"), "{code}");
+    }
+
+    #[test]
+    fn generated_code_matches_like_regex() {
+        let regex = Regex::new(FIXTURE_PATTERN).unwrap();
+        let texts = [
+            "",
+            "bob@example.com",
+            "bob@example.org",
+            "x😀y",
+            "té",
+            "abx",
+            "xab",
+            "xc",
+            "cx",
+            "c\n",
+        ];
+        for text in texts {
+            let expected = regex.find(text).is_some();
+            assert_eq!(fixture::is_match(text), expected, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn generate_tables() {
+        let dfa = search_dfa("ab", &SizeLimits::default()).unwrap();
+        let code = generate(&dfa, "matches_ab");
+
+        assert!(code.starts_with("/// Tests whether the input contains a match.\n"));
+        assert!(code.contains("pub fn matches_ab(input: &str) -> bool {\n"));
+        let ranges = "[(char, u8); 4] = [\n        ('\\u{0}', 0),\n        ('\\u{61}', 1),\n";
+        assert!(code.contains(ranges), "{code}");
+        assert!(code.contains("static TRANSITIONS: [[u8; 3]; 4] = [\n        [0, 0, 0],\n"));
+        assert!(code.contains("static MATCHES: [bool; 4] = ["));
+        assert!(code.contains("static END_MATCHES: [bool; 4] = ["));
+    }
+}
@@ -5,18 +5,71 @@ use crate::ast;
 pub enum Error {
     /// A syntax error.
     Syntax(String),
+    /// The pattern is valid, but uses a feature that the selected engine
+    /// can't execute. The span points at the offending part of the pattern.
+    Unsupported { span: ast::Span, reason: String },
+    /// The backtracking engine gave up after running out of its step budget.
+    StepLimitExceeded { limit: usize },
+    /// Compiling the pattern was stopped as the automata grew over one of
+    /// their size limits.
+    CompiledTooBig { limit: usize, kind: LimitKind },
+}
+
+/// The size limit of the compiled automata that was exceeded.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LimitKind {
+    /// The number of NFA states.
+    NfaStates,
+    /// The number of DFA states.
+    DfaStates,
+    /// The memory used by the transitions of the DFA, in bytes.
+    TransitionMemory,
+    /// The number of NFA states held by the states of the DFA in total.
+    Determinization,
 }
 
 impl Error {
     pub(crate) fn from_ast_parse_error(err: ast::Error) -> Error {
         Error::Syntax(err.to_string())
     }
+
+    pub(crate) fn unsupported(span: &ast::Span, reason: &str) -> Error {
+        Error::Unsupported {
+            span: span.clone(),
+            reason: reason.to_string(),
+        }
+    }
 }
 
 impl core::fmt::Display for Error {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match *self {
             Error::Syntax(ref x) => x.fmt(f),
+            Error::Unsupported {
+                ref span,
+                ref reason,
+            } => write!(
+                f,
+                "unsupported feature at {}..{}: {}",
+                span.start.offset, span.end.offset, reason
+            ),
+            Error::StepLimitExceeded { limit } => {
+                write!(f, "backtracking step limit of {} exceeded", limit)
+            }
+            Error::CompiledTooBig { limit, kind } => {
+                write!(f, "compiled regex exceeds the limit of {} {}", limit, kind)
+            }
+        }
+    }
+}
+
+impl core::fmt::Display for LimitKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            LimitKind::NfaStates => write!(f, "NFA states"),
+            LimitKind::DfaStates => write!(f, "DFA states"),
+            LimitKind::TransitionMemory => write!(f, "bytes of DFA transitions"),
+            LimitKind::Determinization => write!(f, "NFA states held by DFA states"),
         }
     }
 }
@@ -48,11 +101,10 @@ impl<'e> From<&'e ast::Error> for Formatter<'e, ast::ErrorKind> {
 
 impl<'e, E: core::fmt::Display> core::fmt::Display for Formatter<'e, E> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        // let _spans = Spans::from_formatter(self);
-        // writeln!(f, "regex parse error:")?;
-        // let notated = Spans::from_formatter(self).notate();
-        // write!(f, "{}", notated)?;
-        // write!(f, "error: {}", self.err)?;
+        writeln!(f, "regex parse error:")?;
+        let notated = Spans::from_formatter(self).notate();
+        write!(f, "{}", notated)?;
+        write!(f, "error: {}", self.err)?;
         Ok(())
     }
 }
@@ -61,118 +113,157 @@ impl<'e, E: core::fmt::Display> core::fmt::Display for Formatter<'e, E> {
 /// it convenient to notate the regex pattern. ("Notate" means "point out
 /// exactly where the error occurred in the regex pattern.")
 ///
-/// Technically, we can only ever have two spans given our current error
-/// structure. However, after toiling with a specific algorithm for handling
-/// two spans, it became obvious that an algorithm to handle an arbitrary
-/// number of spans was actually much simpler.
+/// Positions only carry a byte offset, so lines and columns are recomputed
+/// here from the pattern itself.
 struct Spans<'p> {
     /// The original regex pattern string.
     pattern: &'p str,
-    // /// The total width that should be used for line numbers. The width is
-    // /// used for left padding the line numbers for alignment.
-    // ///
-    // /// A value of `0` means line numbers should not be displayed. That is,
-    // /// the pattern is itself only one line.
-    // line_number_width: usize,
-    // /// All error spans that occur on a single line. This sequence always has
-    // /// length equivalent to the number of lines in `pattern`, where the index
-    // /// of the sequence represents a line number, starting at `0`. The spans
-    // /// in each line are sorted in ascending order.
-    // by_line: Vec<Vec<ast::Span>>,
-    // /// All error spans that occur over one or more lines. That is, the start
-    // /// and end position of the span have different line numbers. The spans are
-    // /// sorted in ascending order.
-    // multi_line: Vec<ast::Span>,
+    /// The total width that should be used for line numbers. The width is
+    /// used for left padding the line numbers for alignment.
+    ///
+    /// A value of `0` means line numbers should not be displayed. That is,
+    /// the pattern is itself only one line.
+    line_number_width: usize,
+    /// The error span, as a zero-based line number and the (start, end)
+    /// character columns on that line.
+    line: usize,
+    columns: (usize, usize),
 }
 
 impl<'p> Spans<'p> {
     /// Build a sequence of spans from a formatter.
     fn from_formatter<'e, E: core::fmt::Display>(fmter: &'p Formatter<'e, E>) -> Spans<'p> {
-        // let mut line_count = fmter.pattern.lines().count();
-        // // If the pattern ends with a `\n` literal, then our line count is
-        // // off by one, since a span can occur immediately after the last `\n`,
-        // // which is consider to be an additional line.
-        // if fmter.pattern.ends_with('\n') {
-        //     line_count += 1;
-        // }
-        // let line_number_width = if line_count <= 1 {
-        //     0
-        // } else {
-        //     line_count.to_string().len()
-        // };
-        let spans = Spans {
-            pattern: &fmter.pattern,
-            // line_number_width,
-            // by_line: vec![vec![]; line_count],
-            // multi_line: vec![],
+        let mut line_count = fmter.pattern.lines().count();
+        // If the pattern ends with a `\n` literal, then our line count is
+        // off by one, since a span can occur immediately after the last `\n`,
+        // which is consider to be an additional line.
+        if fmter.pattern.ends_with('\n') {
+            line_count += 1;
+        }
+        let line_number_width = if line_count <= 1 {
+            0
+        } else {
+            line_count.to_string().len()
         };
-        // spans.add(fmter.span.clone());
-        // if let Some(span) = fmter.aux_span {
-        //     spans.add(span.clone());
-        // }
-        spans
+
+        let start = fmter.span.start.offset.min(fmter.pattern.len());
+        let end = fmter.span.end.offset.min(fmter.pattern.len()).max(start);
+        let before = &fmter.pattern[..start];
+        let line = before.matches('\n').count();
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let start_column = fmter.pattern[line_start..start].chars().count();
+        // Spans covering several lines are only notated on their first line.
+        let line_end = fmter.pattern[start..]
+            .find('\n')
+            .map_or(fmter.pattern.len(), |i| start + i);
+        let end_column = start_column + fmter.pattern[start..end.min(line_end)].chars().count();
+
+        Spans {
+            pattern: fmter.pattern,
+            line_number_width,
+            line,
+            columns: (start_column, end_column),
+        }
+    }
+
+    /// Notate the pattern string with carents (`^`) pointing at each span
+    /// location.
+    fn notate(&self) -> String {
+        let mut notated = String::new();
+        for (i, line) in self.pattern.lines().enumerate() {
+            if self.line_number_width > 0 {
+                notated.push_str(&self.left_pad_line_number(i + 1));
+                notated.push_str(": ");
+            } else {
+                notated.push_str("    ");
+            }
+            notated.push_str(line);
+            notated.push('\n');
+            if i == self.line {
+                notated.push_str(&self.notate_line());
+                notated.push('\n');
+            }
+        }
+        if self.pattern.is_empty() || self.line == self.pattern.lines().count() {
+            notated.push_str(&self.notate_line());
+            notated.push('\n');
+        }
+        notated
     }
 
-    // /// Add the given span to this sequence, putting it in the right place.
-    // fn add(&mut self, span: ast::Span) {
-    //     // This is grossly inefficient since we sort after each add, but right
-    //     // now, we only ever add two spans at most.
-    //     // if span.is_one_line() {
-    //     let i = span.start.line - 1; // because lines are 1-indexed
-    //     self.by_line[i].push(span);
-    //     self.by_line[i].sort();
-    //     // } else {
-    //     //     self.multi_line.push(span);
-    //     //     self.multi_line.sort();
-    //     // }
-    // }
-    //
-    // /// Notate the pattern string with carents (`^`) pointing at each span
-    // /// location. This only applies to spans that occur within a single line.
-    // fn notate(&self) -> String {
-    //     let mut notated = String::new();
-    //     for (i, line) in self.pattern.lines().enumerate() {
-    //         if self.line_number_width > 0 {
-    //             notated.push_str(&self.left_pad_line_number(i + 1));
-    //             notated.push_str(": ");
-    //         } else {
-    //             notated.push_str("    ");
-    //         }
-    //         notated.push_str(line);
-    //         notated.push('\n');
-    //         if let Some(notes) = self.notate_line(i) {
-    //             notated.push_str(&notes);
-    //             notated.push('\n');
-    //         }
-    //     }
-    //     notated
-    // }
-    //
-    // /// Return notes for the line indexed at `i` (zero-based). If there are no
-    // /// spans for the given line, then `None` is returned. Otherwise, an
-    // /// appropriately space padded string with correctly positioned `^` is
-    // /// returned, accounting for line numbers.
-    // fn notate_line(&self, i: usize) -> Option<String> {
-    //     let spans = &self.by_line[i];
-    //     if spans.is_empty() {
-    //         return None;
-    //     }
-    //     let mut notes = String::new();
-    //     for _ in 0..self.line_number_padding() {
-    //         notes.push(' ');
-    //     }
-    //     let mut pos = 0;
-    //     for span in spans {
-    //         for _ in pos..(span.start.column - 1) {
-    //             notes.push(' ');
-    //             pos += 1;
-    //         }
-    //         let note_len = span.end.column.saturating_sub(span.start.column);
-    //         for _ in 0..core::cmp::max(1, note_len) {
-    //             notes.push('^');
-    //             pos += 1;
-    //         }
-    //     }
-    //     Some(notes)
-    // }
+    /// Return the notes for the line containing the span: an appropriately
+    /// space padded string with correctly positioned `^`.
+    fn notate_line(&self) -> String {
+        let mut notes = String::new();
+        for _ in 0..self.line_number_padding() {
+            notes.push(' ');
+        }
+        let (start, end) = self.columns;
+        for _ in 0..start {
+            notes.push(' ');
+        }
+        for _ in 0..core::cmp::max(1, end - start) {
+            notes.push('^');
+        }
+        notes
+    }
+
+    /// Left pad the given line number with spaces such that it is aligned with
+    /// other line numbers.
+    fn left_pad_line_number(&self, n: usize) -> String {
+        let n = n.to_string();
+        let pad = self.line_number_width.checked_sub(n.len()).unwrap();
+        let mut result: String = core::iter::repeat_n(' ', pad).collect();
+        result.push_str(&n);
+        result
+    }
+
+    /// Return the line number padding beginning at the start of each line of
+    /// the pattern.
+    ///
+    /// If the pattern is only one line, then this returns a fixed padding
+    /// for visual indentation.
+    fn line_number_padding(&self) -> usize {
+        if self.line_number_width == 0 {
+            4
+        } else {
+            2 + self.line_number_width
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parser::Parser;
+
+    #[test]
+    fn notate_parse_error() {
+        let err = Parser::new().parse("a(bc").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "regex parse error:\n    a(bc\n     ^\nerror: unclosed group"
+        );
+    }
+
+    #[test]
+    fn notate_parse_error_span() {
+        let err = Parser::new().parse(r"(a)\2").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "regex parse error:\n    (a)\\2\n       ^^\nerror: backreference to undefined group 2"
+        );
+    }
+
+    #[test]
+    fn compiled_too_big() {
+        let err = Error::CompiledTooBig {
+            limit: 10_000,
+            kind: LimitKind::DfaStates,
+        };
+        assert_eq!(
+            err.to_string(),
+            "compiled regex exceeds the limit of 10000 DFA states"
+        );
+    }
 }
@@ -0,0 +1,698 @@
+pub mod spec;
+
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    iter::FusedIterator,
+};
+
+use crate::{
+    automata::{
+        dfa::DFA, dfa_table::DFATable, limits::SizeLimits, nfa_table::NFATable, state::Edges,
+    },
+    error::Error,
+    regex,
+};
+
+/// The start condition a lexer begins in.
+pub const INITIAL: &str = "INITIAL";
+
+/// A builder of a `Lexer` from an ordered list of rules.
+///
+/// Each rule matches a pattern, producing tokens of its kind, or nothing for the
+/// skip rules, e.g. of whitespace and comments. When several rules match the
+/// longest token, the one added first wins, so keywords go before the rule of
+/// identifiers.
+///
+/// Like in flex, the lexer is always in a start condition, `INITIAL` at first, and
+/// only the rules active in it match. A rule is active in the conditions it lists,
+/// or in `INITIAL` and the inclusive conditions when it lists none. Matching a rule
+/// can change the condition, e.g. to read the inside of a string literal or of a
+/// comment with other rules, see `Rule`.
+#[derive(Clone, Debug)]
+pub struct LexerBuilder<K> {
+    rules: Vec<Rule<K>>,
+    /// The declared conditions, with whether they're inclusive.
+    conditions: Vec<(String, bool)>,
+    limits: SizeLimits,
+}
+
+impl<K: Clone> Default for LexerBuilder<K> {
+    fn default() -> Self {
+        LexerBuilder::new()
+    }
+}
+
+impl<K: Clone> LexerBuilder<K> {
+    pub fn new() -> LexerBuilder<K> {
+        LexerBuilder {
+            rules: vec![],
+            conditions: vec![(INITIAL.to_owned(), true)],
+            limits: SizeLimits::default(),
+        }
+    }
+
+    /// Adds a rule producing tokens of the kind.
+    pub fn rule(&mut self, kind: K, pattern: &str) -> &mut LexerBuilder<K> {
+        self.add(Rule::token(kind, pattern))
+    }
+
+    /// Adds a rule whose matches are skipped instead of producing tokens.
+    pub fn skip(&mut self, pattern: &str) -> &mut LexerBuilder<K> {
+        self.add(Rule::skip(pattern))
+    }
+
+    /// Adds a rule, with the lowest priority so far.
+    pub fn add(&mut self, rule: Rule<K>) -> &mut LexerBuilder<K> {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Declares an exclusive start condition, where only the rules listing it are
+    /// active, like `%x` in flex.
+    pub fn condition(&mut self, name: &str) -> &mut LexerBuilder<K> {
+        self.conditions.push((name.to_owned(), false));
+        self
+    }
+
+    /// Declares an inclusive start condition, where the rules listing no condition
+    /// are active too, like `%s` in flex.
+    pub fn inclusive_condition(&mut self, name: &str) -> &mut LexerBuilder<K> {
+        self.conditions.push((name.to_owned(), true));
+        self
+    }
+
+    /// Set the limits on the size of the automata.
+    pub fn size_limits(&mut self, limits: SizeLimits) -> &mut LexerBuilder<K> {
+        self.limits = limits;
+        self
+    }
+
+    /// Compiles the rules into a DFA for each start condition, failing with
+    /// `Error::CompiledTooBig` if one of them exceeds the size limits. The patterns
+    /// can only use the features automata can express, so backreferences,
+    /// lookarounds and multi-line anchors fail with `Error::Unsupported`, and a rule
+    /// naming an undeclared condition fails with `Error::Syntax`.
+    pub fn build(&self) -> Result<Lexer<K>, Error> {
+        let mut ids: HashMap<&str, usize> = HashMap::new();
+        for (name, _) in &self.conditions {
+            let id = ids.len();
+            ids.entry(name).or_insert(id);
+        }
+        let id = |name: &String| {
+            ids.get(name.as_str())
+                .copied()
+                .ok_or_else(|| Error::Syntax(format!("undeclared start condition {name}")))
+        };
+
+        // The rules active in each condition, in order.
+        let mut active = vec![vec![]; ids.len()];
+        for (r, rule) in self.rules.iter().enumerate() {
+            let conditions: Vec<usize> = if rule.conditions.is_empty() {
+                let inclusive = self.conditions.iter().filter(|(_, inclusive)| *inclusive);
+                inclusive.map(|(name, _)| ids[name.as_str()]).collect()
+            } else {
+                rule.conditions
+                    .iter()
+                    .map(id)
+                    .collect::<Result<_, Error>>()?
+            };
+            for c in conditions {
+                if active[c].last() != Some(&r) {
+                    active[c].push(r);
+                }
+            }
+        }
+
+        // Each rule is compiled once, and the conditions where the same rules are
+        // active share their DFA.
+        let tables = self
+            .rules
+            .iter()
+            .map(|rule| {
+                let nfa = regex::compile_nfa_with_limits(&rule.pattern, &self.limits)?;
+                Ok(nfa.get_transition_table())
+            })
+            .collect::<Result<Vec<NFATable>, Error>>()?;
+        let mut dfas = vec![];
+        let mut shared = HashMap::new();
+        let mut condition_dfas = vec![];
+        for rules in active {
+            let dfa = match shared.entry(rules) {
+                Entry::Occupied(entry) => *entry.get(),
+                Entry::Vacant(entry) => {
+                    let rules = entry.key().clone();
+                    let dfa = rules_dfa(rules.iter().map(|&r| &tables[r]), &self.limits)?;
+                    dfas.push(RulesDFA { dfa, rules });
+                    *entry.insert(dfas.len() - 1)
+                }
+            };
+            condition_dfas.push(dfa);
+        }
+
+        let mut conditions = vec![String::new(); ids.len()];
+        for (&name, &id) in &ids {
+            conditions[id] = name.to_owned();
+        }
+        let rules = self
+            .rules
+            .iter()
+            .map(|rule| Ok((rule.kind.clone(), rule.action.map(id)?)))
+            .collect::<Result<_, Error>>()?;
+        Ok(Lexer {
+            rules,
+            conditions,
+            dfas,
+            condition_dfas,
+        })
+    }
+}
+
+// Builds the anchored DFA matching any of the patterns, whose accepting states are
+// tagged with the first pattern matching there.
+fn rules_dfa<'t>(
+    tables: impl IntoIterator<Item = &'t NFATable>,
+    limits: &SizeLimits,
+) -> Result<DFA, Error> {
+    let union = NFATable::union(tables);
+    let mut table = DFATable::determinize_with_limits(&union, limits)?;
+
+    // Only the rule of the highest priority matching in a state can produce a token
+    // there, and the states of different rules must not be merged.
+    for patterns in table.patterns.values_mut() {
+        patterns.split_off(&(patterns.first().unwrap() + 1));
+    }
+    let mut dfa = DFA::from_table(table);
+    dfa.minimize();
+    Ok(dfa)
+}
+
+/// A rule of a lexer, matching a pattern to produce tokens of its kind, or nothing
+/// for a skip rule.
+#[derive(Clone, Debug)]
+pub struct Rule<K> {
+    /// The kind of the tokens, `None` for a skip rule.
+    kind: Option<K>,
+    pattern: String,
+    /// The start conditions the rule is active in, none for the inclusive ones.
+    conditions: Vec<String>,
+    action: Action<String>,
+}
+
+impl<K> Rule<K> {
+    pub fn token(kind: K, pattern: &str) -> Rule<K> {
+        Rule {
+            kind: Some(kind),
+            pattern: pattern.to_owned(),
+            conditions: vec![],
+            action: Action::Stay,
+        }
+    }
+
+    pub fn skip(pattern: &str) -> Rule<K> {
+        Rule {
+            kind: None,
+            pattern: pattern.to_owned(),
+            conditions: vec![],
+            action: Action::Stay,
+        }
+    }
+
+    /// Makes the rule active only in the start conditions.
+    pub fn within<I, S>(mut self, conditions: I) -> Rule<K>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let conditions = conditions.into_iter();
+        self.conditions = conditions.map(|c| c.as_ref().to_owned()).collect();
+        self
+    }
+
+    /// After a match, enters the start condition, until a rule pops it.
+    pub fn push(mut self, condition: &str) -> Rule<K> {
+        self.action = Action::Push(condition.to_owned());
+        self
+    }
+
+    /// After a match, goes back to the start condition the last push left. Popping
+    /// the condition the lexer began in leaves it there.
+    pub fn pop(mut self) -> Rule<K> {
+        self.action = Action::Pop;
+        self
+    }
+
+    /// After a match, replaces the current start condition, like `BEGIN` in flex.
+    pub fn switch(mut self, condition: &str) -> Rule<K> {
+        self.action = Action::Switch(condition.to_owned());
+        self
+    }
+}
+
+/// How matching a rule changes the start condition, named by the builder and
+/// identified by its index in the lexer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Action<C> {
+    Stay,
+    Push(C),
+    Pop,
+    Switch(C),
+}
+
+impl<C> Action<C> {
+    fn map<D>(&self, f: impl FnOnce(&C) -> Result<D, Error>) -> Result<Action<D>, Error> {
+        Ok(match self {
+            Action::Stay => Action::Stay,
+            Action::Push(c) => Action::Push(f(c)?),
+            Action::Pop => Action::Pop,
+            Action::Switch(c) => Action::Switch(f(c)?),
+        })
+    }
+}
+
+/// A tokenizer splitting text into the tokens of its rules, see `LexerBuilder`.
+///
+/// The rules active in each start condition are compiled into a DFA matching any
+/// of them from the start of a token, whose accepting states are tagged with the
+/// rule of the highest priority matching there. Tokens are read with maximal munch:
+/// from the end of the last token, the DFA of the current condition runs until it
+/// dies, and the token ends at the last position where it accepted. A rule never
+/// matches the empty string.
+#[derive(Clone, Debug)]
+pub struct Lexer<K> {
+    /// The kind of the tokens of each rule, `None` for the skip rules, and how it
+    /// changes the start condition.
+    rules: Vec<(Option<K>, Action<usize>)>,
+    /// The names of the start conditions, `INITIAL` first.
+    conditions: Vec<String>,
+    dfas: Vec<RulesDFA>,
+    /// The index in `dfas` of the DFA of each start condition.
+    condition_dfas: Vec<usize>,
+}
+
+/// The DFA of the rules active in a start condition.
+#[derive(Clone, Debug)]
+struct RulesDFA {
+    dfa: DFA,
+    /// The rule of each pattern of the DFA.
+    rules: Vec<usize>,
+}
+
+impl<K: Clone> Lexer<K> {
+    /// Compiles the rules, in order of priority, without any skip rule.
+    pub fn new<I, S>(rules: I) -> Result<Lexer<K>, Error>
+    where
+        I: IntoIterator<Item = (K, S)>,
+        S: AsRef<str>,
+    {
+        let mut builder = LexerBuilder::new();
+        for (kind, pattern) in rules {
+            builder.rule(kind, pattern.as_ref());
+        }
+        builder.build()
+    }
+
+    /// The names of the start conditions, `INITIAL` first.
+    pub fn conditions(&self) -> &[String] {
+        &self.conditions
+    }
+
+    /// Returns an iterator over the tokens of the text, starting in the `INITIAL`
+    /// condition and ending after the first error.
+    pub fn tokens<'l, 't>(&'l self, text: &'t str) -> Tokens<'l, 't, K> {
+        Tokens {
+            lexer: self,
+            text,
+            at: 0,
+            stack: vec![0],
+            failed: false,
+        }
+    }
+
+    /// Splits the whole text into tokens, failing on the first text no active rule
+    /// matches.
+    pub fn tokenize<'t>(&self, text: &'t str) -> Result<Vec<Token<'t, K>>, LexError> {
+        self.tokens(text).collect()
+    }
+
+    // Finds the longest non-empty match from `start` of a rule active in the
+    // condition, returning where it ends and the rule matching there.
+    fn longest_match(&self, condition: usize, text: &str, start: usize) -> Option<(usize, usize)> {
+        let RulesDFA { dfa, rules } = &self.dfas[self.condition_dfas[condition]];
+        let dense = dfa.dense();
+        let mut state = dense.start_state_at(Edges::from_offset(start));
+        let mut last_match = None;
+        for (i, c) in text[start..].char_indices() {
+            state = dense.next_state(state, c);
+            if dense.is_dead_state(state) {
+                return last_match;
+            }
+            if let Some(&pattern) = dense.match_patterns(state).first() {
+                last_match = Some((start + i + c.len_utf8(), rules[pattern]));
+            }
+        }
+        // A rule ending with `$` only matches at the end of the text.
+        match dense.end_match_patterns(state).first() {
+            Some(&pattern) if start < text.len() => Some((text.len(), rules[pattern])),
+            _ => last_match,
+        }
+    }
+}
+
+/// A token, the text matched by a rule.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token<'t, K> {
+    kind: K,
+    text: &'t str,
+    start: usize,
+    end: usize,
+}
+
+impl<'t, K> Token<'t, K> {
+    pub fn kind(&self) -> &K {
+        &self.kind
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    pub fn as_str(&self) -> &'t str {
+        &self.text[self.start..self.end]
+    }
+}
+
+/// An iterator over the tokens of a text, returned by `Lexer::tokens`.
+#[derive(Debug)]
+pub struct Tokens<'l, 't, K> {
+    lexer: &'l Lexer<K>,
+    text: &'t str,
+    at: usize,
+    /// The pushed start conditions, the current one last.
+    stack: Vec<usize>,
+    failed: bool,
+}
+
+impl<K> Tokens<'_, '_, K> {
+    /// The name of the current start condition.
+    pub fn condition(&self) -> &str {
+        &self.lexer.conditions[*self.stack.last().unwrap()]
+    }
+}
+
+impl<'t, K: Clone> Iterator for Tokens<'_, 't, K> {
+    type Item = Result<Token<'t, K>, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.at < self.text.len() && !self.failed {
+            let condition = *self.stack.last().unwrap();
+            let Some((end, rule)) = self.lexer.longest_match(condition, self.text, self.at) else {
+                self.failed = true;
+                return Some(Err(LexError::new(self.text, self.at)));
+            };
+            let start = std::mem::replace(&mut self.at, end);
+            let (kind, action) = &self.lexer.rules[rule];
+            match *action {
+                Action::Stay => {}
+                Action::Push(condition) => self.stack.push(condition),
+                Action::Pop if self.stack.len() > 1 => {
+                    self.stack.pop();
+                }
+                Action::Pop => {}
+                Action::Switch(condition) => *self.stack.last_mut().unwrap() = condition,
+            }
+            if let Some(kind) = kind {
+                return Some(Ok(Token {
+                    kind: kind.clone(),
+                    text: self.text,
+                    start,
+                    end,
+                }));
+            }
+        }
+        None
+    }
+}
+
+impl<K: Clone> FusedIterator for Tokens<'_, '_, K> {}
+
+/// An error telling where the text matches no rule of a lexer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LexError {
+    /// The offset in bytes of the unrecognized text.
+    pub offset: usize,
+    /// The line of the offset, starting from 1.
+    pub line: usize,
+    /// The column of the offset in chars, starting from 1.
+    pub column: usize,
+}
+
+impl LexError {
+    fn new(text: &str, offset: usize) -> LexError {
+        let before = &text[..offset];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        LexError {
+            offset,
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+impl core::fmt::Display for LexError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "unrecognized input at line {}, column {}",
+            self.line, self.column
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    enum Kind {
+        If,
+        Ident,
+        Int,
+        Op,
+        Quote,
+        Text,
+        Interpolation,
+        Close,
+    }
+
+    fn lexer() -> Lexer<Kind> {
+        LexerBuilder::new()
+            .rule(Kind::If, "if")
+            .rule(Kind::Ident, "[a-zA-Zé_][a-zA-Zé_0-9]*")
+            .rule(Kind::Int, "[0-9]+")
+            .rule(Kind::Op, r"=|==|\+|<")
+            .skip(r"[ \t\n]+")
+            .skip(r"#[^\n]*")
+            .build()
+            .unwrap()
+    }
+
+    fn tokens(lexer: &Lexer<Kind>, text: &str) -> Vec<(Kind, String)> {
+        let tokens = lexer.tokenize(text).unwrap();
+        tokens
+            .iter()
+            .map(|token| (*token.kind(), token.as_str().to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn tokenize() {
+        let lexer = lexer();
+        let expected = [
+            (Kind::If, "if"),
+            (Kind::Ident, "iffy"),
+            (Kind::Op, "=="),
+            (Kind::Int, "42"),
+            (Kind::Ident, "x"),
+            (Kind::Op, "="),
+            (Kind::Ident, "été"),
+            (Kind::Op, "+"),
+            (Kind::Int, "1"),
+        ];
+        let expected: Vec<(Kind, String)> = expected
+            .iter()
+            .map(|&(kind, text)| (kind, text.to_owned()))
+            .collect();
+        let text = "if iffy==42 # compare\n\tx = été+1";
+        assert_eq!(tokens(&lexer, text), expected);
+
+        let tokens = lexer.tokenize("  if1 <").unwrap();
+        let spans: Vec<(usize, usize)> = tokens.iter().map(|t| (t.start(), t.end())).collect();
+        assert_eq!(spans, [(2, 5), (6, 7)]);
+        assert!(lexer.tokenize("").unwrap().is_empty());
+        assert!(lexer.tokenize(" # only a comment").unwrap().is_empty());
+    }
+
+    #[test]
+    fn priority() {
+        // The first rule matching the longest token wins, whatever its length.
+        let lexer = Lexer::new([(Kind::Ident, "[a-z]+"), (Kind::If, "if")]).unwrap();
+        assert_eq!(tokens(&lexer, "if"), [(Kind::Ident, "if".to_owned())]);
+        // Yet a longer token wins over the priority.
+        let lexer = Lexer::new([(Kind::Op, "a"), (Kind::Ident, "a+")]).unwrap();
+        assert_eq!(tokens(&lexer, "a"), [(Kind::Op, "a".to_owned())]);
+        assert_eq!(tokens(&lexer, "aaa"), [(Kind::Ident, "aaa".to_owned())]);
+    }
+
+    #[test]
+    fn anchors() {
+        let lexer = Lexer::new([
+            (Kind::If, "^[a-z]+"),
+            (Kind::Ident, "[a-z]+"),
+            (Kind::Int, "[0-9]+$"),
+            (Kind::Op, "[0-9]"),
+        ])
+        .unwrap();
+        let expected = [
+            (Kind::If, "ab"),
+            (Kind::Op, "1"),
+            (Kind::Ident, "c"),
+            (Kind::Int, "23"),
+        ];
+        let expected: Vec<(Kind, String)> = expected
+            .iter()
+            .map(|&(kind, text)| (kind, text.to_owned()))
+            .collect();
+        assert_eq!(tokens(&lexer, "ab1c23"), expected);
+    }
+
+    #[test]
+    fn errors() {
+        let lexer = lexer();
+        let err = lexer.tokenize("x = 1\n  été ? 2").unwrap_err();
+        assert_eq!(
+            err,
+            LexError {
+                offset: 14,
+                line: 2,
+                column: 7
+            }
+        );
+        assert_eq!(err.to_string(), "unrecognized input at line 2, column 7");
+
+        // The tokens before the error are still read, and none after it.
+        let results: Vec<_> = lexer.tokens("x ? y").collect();
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok() && results[1].is_err());
+
+        // Empty matches don't make progress, so they aren't tokens.
+        let lexer = Lexer::new([(Kind::Int, "[0-9]*")]).unwrap();
+        assert_eq!(lexer.tokenize("12a").unwrap_err().offset, 2);
+    }
+
+    #[test]
+    fn unsupported_rule() {
+        let err = Lexer::new([(Kind::Ident, "[a-z]+"), (Kind::If, r"(a)\1")]).unwrap_err();
+        assert!(matches!(err, Error::Unsupported { .. }), "{err:?}");
+    }
+
+    #[test]
+    fn nested_comments() {
+        let lexer = LexerBuilder::new()
+            .condition("COMMENT")
+            .rule(Kind::Ident, "[a-z]+")
+            .skip(" +")
+            .add(Rule::skip(r"/\*").push("COMMENT"))
+            .add(Rule::skip(r"/\*").within(["COMMENT"]).push("COMMENT"))
+            .add(Rule::skip(r"\*/").within(["COMMENT"]).pop())
+            .add(Rule::skip(r"[^*/]+|\*|/").within(["COMMENT"]))
+            .build()
+            .unwrap();
+        let text = "a /* x /* y * z */ b */ c";
+        let expected = [(Kind::Ident, "a".to_owned()), (Kind::Ident, "c".to_owned())];
+        assert_eq!(tokens(&lexer, text), expected);
+
+        // The identifier rule isn't active in comments.
+        let mut tokens = lexer.tokens("a /* /* */ b");
+        assert!(tokens.next().unwrap().is_ok());
+        assert!(tokens.next().is_none());
+        assert_eq!(tokens.condition(), "COMMENT");
+        assert_eq!(lexer.tokenize("*/").unwrap_err().offset, 0);
+    }
+
+    #[test]
+    fn string_interpolation() {
+        let lexer = LexerBuilder::new()
+            .condition("STRING")
+            .rule(Kind::Ident, "[a-z]+")
+            .rule(Kind::Op, r"\+")
+            .skip(" +")
+            .add(Rule::token(Kind::Quote, "\"").push("STRING"))
+            .add(Rule::token(Kind::Close, r"\}").pop())
+            .add(Rule::token(Kind::Text, r#"([^$"]|\$[^{"])+"#).within(["STRING"]))
+            .add(
+                Rule::token(Kind::Interpolation, r"\$\{")
+                    .within(["STRING"])
+                    .push(INITIAL),
+            )
+            .add(Rule::token(Kind::Quote, "\"").within(["STRING"]).pop())
+            .build()
+            .unwrap();
+        let kinds: Vec<Kind> = tokens(&lexer, r#""a ${x + "b${y}"}c""#)
+            .into_iter()
+            .map(|(kind, _)| kind)
+            .collect();
+        let expected = [
+            Kind::Quote,
+            Kind::Text,
+            Kind::Interpolation,
+            Kind::Ident,
+            Kind::Op,
+            Kind::Quote,
+            Kind::Text,
+            Kind::Interpolation,
+            Kind::Ident,
+            Kind::Close,
+            Kind::Quote,
+            Kind::Close,
+            Kind::Text,
+            Kind::Quote,
+        ];
+        assert_eq!(kinds, expected);
+        assert_eq!(lexer.conditions(), [INITIAL, "STRING"]);
+    }
+
+    #[test]
+    fn switch_conditions() {
+        // `RAW` is inclusive, so the rules without conditions are active there too,
+        // after its own.
+        let lexer = LexerBuilder::new()
+            .inclusive_condition("RAW")
+            .add(Rule::token(Kind::Text, "[^\n]+").within(["RAW"]))
+            .add(Rule::skip("\n").within(["RAW"]).switch(INITIAL))
+            .rule(Kind::Int, "[0-9]+")
+            .add(Rule::token(Kind::Op, "<<").switch("RAW"))
+            .skip("[ \n]+")
+            .build()
+            .unwrap();
+        let expected = [
+            (Kind::Int, "1".to_owned()),
+            (Kind::Op, "<<".to_owned()),
+            (Kind::Text, " 2 << 3".to_owned()),
+            (Kind::Int, "4".to_owned()),
+        ];
+        assert_eq!(tokens(&lexer, "1 << 2 << 3\n4"), expected);
+
+        let err = LexerBuilder::<Kind>::new()
+            .add(Rule::skip("a").within(["NOPE"]))
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            err,
+            Error::Syntax("undeclared start condition NOPE".to_owned())
+        );
+    }
+}
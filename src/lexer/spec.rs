@@ -0,0 +1,609 @@
+use std::{collections::HashMap, iter::Peekable, str::Chars};
+
+use crate::{
+    error::Error,
+    lexer::{Lexer, LexerBuilder, Rule, INITIAL},
+};
+
+/// A lexer specification in the layout of lex and flex `.l` files:
+///
+/// ```text
+/// definitions
+/// %%
+/// rules
+/// %%
+/// user code
+/// ```
+///
+/// The definitions section names patterns, e.g. `DIGIT [0-9]`, and declares the
+/// start conditions with `%x` and `%s`. Each rule of the rules section is a pattern,
+/// where `{DIGIT}` expands a definition, optionally prefixed with the conditions it
+/// is active in, e.g. `<STRING>`, and followed by its action. The actions and the
+/// user code are C code, kept as opaque strings, while `%{ %}` blocks, indented
+/// lines and options are ignored.
+///
+/// The patterns are translated into the syntax of this crate. Trailing context,
+/// e.g. `a/b`, isn't supported, and neither are the line anchors: a `^` starting a
+/// pattern or a `$` ending it is an error, while elsewhere they're literal chars
+/// like in lex. `<<EOF>>` rules are left out.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Spec {
+    /// The declared start conditions, with whether they're inclusive.
+    conditions: Vec<(String, bool)>,
+    rules: Vec<SpecRule>,
+}
+
+/// A rule of a `Spec`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpecRule {
+    /// The start conditions the rule is active in, `*` for all of them, or none for
+    /// `INITIAL` and the inclusive ones.
+    pub conditions: Vec<String>,
+    /// The pattern in lex syntax.
+    pub pattern: String,
+    /// The pattern in the syntax of this crate, with the definitions expanded.
+    pub regex: String,
+    /// The code run on a match.
+    pub action: String,
+    /// The line of the rule in the specification, starting from 1.
+    pub line: usize,
+}
+
+/// The sections of a specification, separated by `%%` lines.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Definitions,
+    Rules,
+    UserCode,
+}
+
+impl Spec {
+    /// Parses a specification, failing with `Error::Syntax` on a malformed line.
+    pub fn parse(source: &str) -> Result<Spec, Error> {
+        let mut spec = Spec {
+            conditions: vec![],
+            rules: vec![],
+        };
+        let mut definitions: HashMap<String, String> = HashMap::new();
+        // The conditions of the enclosing `<SC>{ ... }` scopes.
+        let mut scopes: Vec<Vec<String>> = vec![];
+        let mut section = Section::Definitions;
+
+        let mut lines = source.lines().enumerate().map(|(i, line)| (i + 1, line));
+        while let Some((number, line)) = lines.next() {
+            let error = |message: &str| Error::Syntax(format!("line {number}: {message}"));
+            if line.trim_end() == "%%" {
+                section = match section {
+                    Section::Definitions => Section::Rules,
+                    _ => Section::UserCode,
+                };
+                continue;
+            }
+            if line.starts_with("%{") {
+                lines.find(|(_, line)| line.starts_with("%}"));
+                continue;
+            }
+            if line.trim().is_empty() || line.starts_with(char::is_whitespace) {
+                continue;
+            }
+
+            match section {
+                Section::Definitions => {
+                    if line.starts_with("/*") {
+                        if !line.contains("*/") {
+                            lines.find(|(_, line)| line.contains("*/"));
+                        }
+                        continue;
+                    }
+                    let mut words = line.split_whitespace();
+                    match words.next().unwrap() {
+                        "%x" | "%X" => spec.declare(words, false),
+                        "%s" | "%S" => spec.declare(words, true),
+                        option if option.starts_with('%') => {}
+                        _ => {
+                            let (name, pattern) = definition(line)
+                                .ok_or_else(|| error("expected a name followed by a pattern"))?;
+                            let regex = translate(pattern, &definitions)
+                                .map_err(|message| error(&message))?;
+                            definitions.insert(name.to_owned(), regex);
+                        }
+                    }
+                }
+                Section::Rules => {
+                    if line.trim_end() == "}" && !scopes.is_empty() {
+                        scopes.pop();
+                        continue;
+                    }
+                    let (conditions, rest) =
+                        start_conditions(line).map_err(|message| error(&message))?;
+                    if rest.trim() == "{" {
+                        scopes.push(conditions);
+                        continue;
+                    }
+
+                    let pattern = rule_pattern(rest);
+                    let mut action = rest[pattern.len()..].trim().to_owned();
+                    // A block of code can go on over the following lines.
+                    let mut depth = brace_depth(&action);
+                    while depth > 0 {
+                        let Some((_, line)) = lines.next() else {
+                            return Err(error("unclosed action"));
+                        };
+                        action.push('\n');
+                        action.push_str(line);
+                        depth += brace_depth(line);
+                    }
+                    if pattern == "<<EOF>>" {
+                        continue;
+                    }
+
+                    let mut all_conditions: Vec<String> = scopes.concat();
+                    all_conditions.extend(conditions);
+                    spec.rules.push(SpecRule {
+                        conditions: all_conditions,
+                        pattern: pattern.to_owned(),
+                        regex: translate(pattern, &definitions)
+                            .map_err(|message| error(&message))?,
+                        action,
+                        line: number,
+                    });
+                }
+                Section::UserCode => break,
+            }
+        }
+
+        // The action `|` is the action of the next rule.
+        for i in (0..spec.rules.len().saturating_sub(1)).rev() {
+            if spec.rules[i].action == "|" {
+                spec.rules[i].action = spec.rules[i + 1].action.clone();
+            }
+        }
+        Ok(spec)
+    }
+
+    /// The declared start conditions, with whether they're inclusive, without
+    /// `INITIAL`.
+    pub fn conditions(&self) -> &[(String, bool)] {
+        &self.conditions
+    }
+
+    /// The rules, in order of priority.
+    pub fn rules(&self) -> &[SpecRule] {
+        &self.rules
+    }
+
+    /// Compiles the rules into a lexer, whose tokens are of the kind of the index
+    /// of their rule.
+    ///
+    /// The rules whose action has no `return` word are skip rules, as the scanner goes
+    /// on with the next match after running it. Otherwise, the actions are only
+    /// searched for the calls changing the start condition: `BEGIN(SC)` switches to
+    /// `SC`, `yy_push_state(SC)` pushes it and `yy_pop_state()` pops it.
+    pub fn lexer(&self) -> Result<Lexer<usize>, Error> {
+        let mut builder = LexerBuilder::new();
+        for (name, inclusive) in &self.conditions {
+            if *inclusive {
+                builder.inclusive_condition(name);
+            } else {
+                builder.condition(name);
+            }
+        }
+
+        let all: Vec<&str> = [INITIAL]
+            .into_iter()
+            .chain(self.conditions.iter().map(|(name, _)| name.as_str()))
+            .collect();
+        for (i, spec_rule) in self.rules.iter().enumerate() {
+            let mut rule = if contains_word(&spec_rule.action, "return") {
+                Rule::token(i, &spec_rule.regex)
+            } else {
+                Rule::skip(&spec_rule.regex)
+            };
+            rule = if spec_rule.conditions.iter().any(|c| c == "*") {
+                rule.within(&all)
+            } else {
+                rule.within(&spec_rule.conditions)
+            };
+            let action = &spec_rule.action;
+            if let Some(condition) = call_argument(action, "BEGIN") {
+                rule = rule.switch(condition);
+            } else if let Some(condition) = call_argument(action, "yy_push_state") {
+                rule = rule.push(condition);
+            } else if contains_word(action, "yy_pop_state") {
+                rule = rule.pop();
+            }
+            builder.add(rule);
+        }
+        builder.build()
+    }
+
+    fn declare<'l>(&mut self, names: impl Iterator<Item = &'l str>, inclusive: bool) {
+        let names = names.map(|name| (name.to_owned(), inclusive));
+        self.conditions.extend(names);
+    }
+}
+
+// Splits a definition line into its name and its pattern.
+fn definition(line: &str) -> Option<(&str, &str)> {
+    let end = line
+        .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+        .unwrap_or(line.len());
+    let (name, pattern) = line.split_at(end);
+    let pattern = pattern.trim();
+    let valid = name.starts_with(|c: char| c.is_alphabetic() || c == '_');
+    (valid && !pattern.is_empty() && line[end..].starts_with(char::is_whitespace))
+        .then_some((name, pattern))
+}
+
+// Splits the `<SC1,SC2>` prefix of a rule off its line.
+fn start_conditions(line: &str) -> Result<(Vec<String>, &str), String> {
+    if !line.starts_with('<') || line.starts_with("<<EOF>>") {
+        return Ok((vec![], line));
+    }
+    let end = line
+        .find('>')
+        .ok_or_else(|| "unclosed start conditions".to_owned())?;
+    let conditions = line[1..end]
+        .split(',')
+        .map(|name| name.trim().to_owned())
+        .collect();
+    Ok((conditions, &line[end + 1..]))
+}
+
+// Returns the pattern starting a rule, which ends at the first whitespace outside
+// of a string or a bracketed class.
+fn rule_pattern(rest: &str) -> &str {
+    let mut chars = rest.char_indices();
+    let (mut quoted, mut bracketed) = (false, false);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '"' if !bracketed => quoted = !quoted,
+            '[' if !quoted => bracketed = true,
+            ']' if bracketed => bracketed = false,
+            c if c.is_whitespace() && !quoted && !bracketed => return &rest[..i],
+            _ => {}
+        }
+    }
+    rest
+}
+
+// The number of braces the code opens minus the ones it closes, outside of
+// strings and char literals.
+fn brace_depth(code: &str) -> isize {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut chars = code.chars();
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\\', Some(_)) => {
+                chars.next();
+            }
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            ('{', None) => depth += 1,
+            ('}', None) => depth -= 1,
+            _ => {}
+        }
+    }
+    depth
+}
+
+// Returns the argument of the first call of the function in the code, e.g. `SC`
+// for `BEGIN(SC)` or `BEGIN SC`, where the condition 0 is `INITIAL`.
+fn call_argument<'a>(code: &'a str, function: &str) -> Option<&'a str> {
+    let at = word_indices(code, function).next()?;
+    let rest = &code[at + function.len()..];
+    let rest = rest.trim_start().trim_start_matches('(').trim_start();
+    let end = rest
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(rest.len());
+    match &rest[..end] {
+        "" => None,
+        "0" => Some(INITIAL),
+        condition => Some(condition),
+    }
+}
+
+// Whether the word is in the code on its own, not as a part of an identifier.
+fn contains_word(code: &str, word: &str) -> bool {
+    word_indices(code, word).next().is_some()
+}
+
+// Returns the offsets of the word in the code where it's on its own, not a part of
+// an identifier.
+fn word_indices<'a>(code: &'a str, word: &'a str) -> impl Iterator<Item = usize> + 'a {
+    let is_identifier = |c: char| c.is_alphanumeric() || c == '_';
+    code.match_indices(word).map(|(i, _)| i).filter(move |&i| {
+        let before = code[..i].chars().next_back();
+        let after = code[i + word.len()..].chars().next();
+        !before.is_some_and(is_identifier) && !after.is_some_and(is_identifier)
+    })
+}
+
+// Translates a lex pattern into the syntax of this crate, expanding the
+// definitions between braces.
+fn translate(pattern: &str, definitions: &HashMap<String, String>) -> Result<String, String> {
+    let mut regex = String::new();
+    let mut chars = pattern.chars().peekable();
+    let mut first = true;
+    while let Some(c) = chars.next() {
+        match c {
+            // The lex anchors match at the start and the end of a line, while the
+            // ones of this crate match at the start and the end of the text.
+            '^' if first => return Err("start of line anchors are not supported".to_owned()),
+            '$' if chars.peek().is_none() => {
+                return Err("end of line anchors are not supported".to_owned())
+            }
+            '"' => loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => push_literal(&mut regex, escape(&mut chars)?),
+                    Some(c) => push_literal(&mut regex, c),
+                    None => return Err("unclosed string".to_owned()),
+                }
+            },
+            '[' => translate_class(&mut regex, &mut chars)?,
+            '{' if chars.peek().is_some_and(|&c| c.is_alphabetic() || c == '_') => {
+                let name: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                let expansion = definitions
+                    .get(&name)
+                    .ok_or_else(|| format!("undefined definition {name}"))?;
+                regex.push('(');
+                regex.push_str(expansion);
+                regex.push(')');
+            }
+            '{' => {
+                regex.push('{');
+                regex.extend(chars.by_ref().take_while(|&c| c != '}'));
+                regex.push('}');
+            }
+            '\\' => push_literal(&mut regex, escape(&mut chars)?),
+            '/' => return Err("trailing context is not supported".to_owned()),
+            '.' | '*' | '+' | '?' | '|' | '(' | ')' => regex.push(c),
+            c => push_literal(&mut regex, c),
+        }
+        first = false;
+    }
+    Ok(regex)
+}
+
+// Translates a bracketed class, after its opening `[`.
+fn translate_class(regex: &mut String, chars: &mut Peekable<Chars>) -> Result<(), String> {
+    regex.push('[');
+    if chars.next_if_eq(&'^').is_some() {
+        regex.push('^');
+    }
+    let mut first = true;
+    loop {
+        match chars.next() {
+            Some(']') if !first => break,
+            Some('[') if chars.next_if_eq(&':').is_some() => {
+                let name: String = chars.by_ref().take_while(|&c| c != ':').collect();
+                if chars.next() != Some(']') {
+                    return Err("unclosed POSIX class".to_owned());
+                }
+                regex.push_str(posix_class(&name)?);
+            }
+            Some('\\') => push_literal(regex, escape(chars)?),
+            Some('-') if !first && chars.peek() != Some(&']') => regex.push('-'),
+            Some(c) => push_literal(regex, c),
+            None => return Err("unclosed class".to_owned()),
+        }
+        first = false;
+    }
+    regex.push(']');
+    Ok(())
+}
+
+// Returns the ranges of the POSIX class, to put in a bracketed class.
+fn posix_class(name: &str) -> Result<&'static str, String> {
+    Ok(match name {
+        "alpha" => "a-zA-Z",
+        "digit" => "0-9",
+        "alnum" => "a-zA-Z0-9",
+        "upper" => "A-Z",
+        "lower" => "a-z",
+        "xdigit" => "0-9a-fA-F",
+        "space" => r"\s",
+        "blank" => r" \t",
+        "punct" => r"!-\/:-@\[-`\{-~",
+        _ => return Err(format!("unsupported POSIX class [:{name}:]")),
+    })
+}
+
+// Reads the escape sequence after a backslash, returning the char it stands for.
+fn escape(chars: &mut Peekable<Chars>) -> Result<char, String> {
+    let c = chars
+        .next()
+        .ok_or_else(|| "trailing backslash".to_owned())?;
+    let (radix, max_len) = match c {
+        'n' => return Ok('\n'),
+        't' => return Ok('\t'),
+        'r' => return Ok('\r'),
+        'f' => return Ok('\x0C'),
+        'v' => return Ok('\x0B'),
+        'a' => return Ok('\x07'),
+        'b' => return Ok('\x08'),
+        'x' => (16, 2),
+        '0'..='7' => (8, 3),
+        c => return Ok(c),
+    };
+    let mut digits = String::new();
+    if c != 'x' {
+        digits.push(c);
+    }
+    while digits.len() < max_len {
+        match chars.next_if(|c| c.is_digit(radix)) {
+            Some(c) => digits.push(c),
+            None => break,
+        }
+    }
+    u32::from_str_radix(&digits, radix)
+        .ok()
+        .and_then(char::from_u32)
+        .ok_or_else(|| format!("invalid escape sequence \\{c}{digits}"))
+}
+
+// Appends the char, escaped if it's a meta character.
+fn push_literal(regex: &mut String, c: char) {
+    if "\\.+*?()|[]{}^$-/".contains(c) {
+        regex.push('\\');
+    }
+    regex.push(c);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPEC: &str = r#"
+/* A calculator with strings. */
+%{
+#include "tokens.h"
+%}
+DIGIT   [0-9]
+ID      [a-z_][a-z0-9_]*
+%option noyywrap
+%x STRING COMMENT
+
+%%
+if|else             return KEYWORD;
+{ID}                return ID;
+{DIGIT}+("."{DIGIT}+)?  return NUMBER;
+"+"|"-"|"*"|"/"     return OP;
+[ \t\n]+            ;
+"/*"                yy_push_state(COMMENT);
+<COMMENT>{
+"*/"                yy_pop_state();
+[^*]+|"*"           ;
+}
+\"                  { BEGIN(STRING);
+                      return QUOTE; }
+<STRING>[^"\\]+     |
+<STRING>\\.         return TEXT;
+<STRING>\"          BEGIN(INITIAL); return QUOTE;
+<<EOF>>             return END;
+%%
+int main() { return yylex(); }
+"#;
+
+    #[test]
+    fn parse() {
+        let spec = Spec::parse(SPEC).unwrap();
+        let conditions = [("STRING".to_owned(), false), ("COMMENT".to_owned(), false)];
+        assert_eq!(spec.conditions(), conditions);
+
+        let rules: Vec<(&str, &str)> = spec
+            .rules()
+            .iter()
+            .map(|rule| (rule.regex.as_str(), rule.action.as_str()))
+            .collect();
+        assert_eq!(
+            rules,
+            [
+                ("if|else", "return KEYWORD;"),
+                ("([a-z_][a-z0-9_]*)", "return ID;"),
+                (r"([0-9])+(\.([0-9])+)?", "return NUMBER;"),
+                (r"\+|\-|\*|\/", "return OP;"),
+                ("[ \t\n]+", ";"),
+                (r"\/\*", "yy_push_state(COMMENT);"),
+                (r"\*\/", "yy_pop_state();"),
+                (r"[^\*]+|\*", ";"),
+                (
+                    "\"",
+                    "{ BEGIN(STRING);\n                      return QUOTE; }"
+                ),
+                (r#"[^"\\]+"#, "return TEXT;"),
+                (r"\\.", "return TEXT;"),
+                ("\"", "BEGIN(INITIAL); return QUOTE;"),
+            ]
+        );
+        assert_eq!(spec.rules()[6].conditions, ["COMMENT"]);
+        assert_eq!(spec.rules()[9].conditions, ["STRING"]);
+        assert_eq!(spec.rules()[1].line, 13);
+        assert_eq!(spec.rules()[1].pattern, "{ID}");
+    }
+
+    #[test]
+    fn lexer() {
+        let lexer = Spec::parse(SPEC).unwrap().lexer().unwrap();
+        let text = r#"if x1 /* a * b */ + 2.5 "say \"hi\"" else"#;
+        let rules: Vec<(usize, &str)> = lexer
+            .tokenize(text)
+            .unwrap()
+            .iter()
+            .map(|token| (*token.kind(), token.as_str()))
+            .collect();
+        assert_eq!(
+            rules,
+            [
+                (0, "if"),
+                (1, "x1"),
+                (3, "+"),
+                (2, "2.5"),
+                (8, "\""),
+                (9, "say "),
+                (10, "\\\""),
+                (9, "hi"),
+                (10, "\\\""),
+                (11, "\""),
+                (0, "else"),
+            ]
+        );
+    }
+
+    #[test]
+    fn translate_patterns() {
+        let definitions = HashMap::from([("D".to_owned(), "[0-9]".to_owned())]);
+        let translate = |pattern| translate(pattern, &definitions);
+        assert_eq!(translate("{D}{2,3}"), Ok("([0-9]){2,3}".to_owned()));
+        assert_eq!(translate(r#""a.b"\.c"#), Ok(r"a\.b\.c".to_owned()));
+        assert_eq!(translate(r"[]a-c\]-]"), Ok(r"[\]a-c\]\-]".to_owned()));
+        assert_eq!(translate("[[:digit:]_]"), Ok("[0-9_]".to_owned()));
+        assert_eq!(translate(r"\x41\101\n"), Ok("AA\n".to_owned()));
+        assert_eq!(translate("{E}"), Err("undefined definition E".to_owned()));
+        let err = Err("trailing context is not supported".to_owned());
+        assert_eq!(translate("a/b"), err);
+        assert_eq!(translate("a^b$c"), Ok(r"a\^b\$c".to_owned()));
+        let err = Err("start of line anchors are not supported".to_owned());
+        assert_eq!(translate("^ab"), err);
+        let err = Err("end of line anchors are not supported".to_owned());
+        assert_eq!(translate("ab$"), err);
+    }
+
+    #[test]
+    fn return_words() {
+        assert!(contains_word("return X;", "return"));
+        assert!(contains_word("{ n++; return(X); }", "return"));
+        assert!(!contains_word("returned = 1;", "return"));
+        assert!(!contains_word("noreturn();", "return"));
+        assert!(!contains_word("no_return();", "return"));
+    }
+
+    #[test]
+    fn call_arguments() {
+        assert_eq!(call_argument("BEGIN(STRING);", "BEGIN"), Some("STRING"));
+        assert_eq!(call_argument("BEGIN 0;", "BEGIN"), Some(INITIAL));
+        assert_eq!(call_argument("{ MY_BEGIN(X); return T; }", "BEGIN"), None);
+        let action = "MY_BEGIN(X); BEGIN(Y);";
+        assert_eq!(call_argument(action, "BEGIN"), Some("Y"));
+        let action = "yy_push_state_of(X);";
+        assert_eq!(call_argument(action, "yy_push_state"), None);
+    }
+
+    #[test]
+    fn errors() {
+        let err = Spec::parse("%%\n\"abc  return STR;\n").unwrap_err();
+        assert_eq!(err, Error::Syntax("line 2: unclosed string".to_owned()));
+        let err = Spec::parse("%%\nx  { return X;\n").unwrap_err();
+        assert_eq!(err, Error::Syntax("line 2: unclosed action".to_owned()));
+        let err = Spec::parse("%%\nx  ;\n^y  return Y;\n").unwrap_err();
+        let message = "line 3: start of line anchors are not supported";
+        assert_eq!(err, Error::Syntax(message.to_owned()));
+        let err = Spec::parse("%%\n<S>x  return X;\n").unwrap().lexer();
+        assert!(matches!(err, Err(Error::Syntax(_))), "{err:?}");
+    }
+}
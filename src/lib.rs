@@ -0,0 +1,9 @@
+pub mod ast;
+pub mod automata;
+pub mod backtrack;
+pub mod codegen;
+pub mod error;
+pub mod lexer;
+pub mod match_kind;
+pub mod regex;
+pub mod regex_set;
@@ -1,23 +1,27 @@
-mod ast;
-mod automata;
-mod error;
-mod regex;
-
-use automata::{dfa::DFA, nfa_table::NFATable, state};
-use std::{cmp::Ordering, collections::HashSet};
-
-use clap::{Parser, Subcommand};
+use rust_automata_regexp_machine::{
+    automata::{dfa::DFA, limits::SizeLimits, nfa_table::NFATable, state},
+    codegen,
+    lexer::{spec::Spec, Lexer},
+    regex::{self, Regex},
+};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, HashSet},
+    fs,
+    path::PathBuf,
+    time::Instant,
+};
+
+use clap::{error::ErrorKind, CommandFactory, Parser, Subcommand, ValueEnum};
 use term_table::{row::Row, table_cell::*, Table, TableStyle};
 
-use crate::regex::Regex;
-
 /// Automata RegExp machine
 #[derive(Parser, Debug)]
 #[clap(version)]
 struct Args {
-    /// RegExp
+    /// RegExp, required by the commands testing or showing a regex
     #[arg(short, long)]
-    regexp: String,
+    regexp: Option<String>,
 
     #[command(subcommand)]
     cmd: Commands,
@@ -40,22 +44,89 @@ enum Commands {
         /// Simplify notations
         #[arg(short, long)]
         simplify_notations: bool,
+
+        /// Algorithm used to minimize the DFA
+        #[arg(short, long, value_enum, default_value_t = Minimization::Hopcroft)]
+        minimization: Minimization,
+    },
+
+    /// Tokenize a file with the rules of a lex specification
+    Lex {
+        /// The `.l` specification
+        #[arg(short, long)]
+        spec: PathBuf,
+
+        /// The file to tokenize
+        input: PathBuf,
+    },
+
+    /// Generate the source of a function testing whether a text contains a match
+    Codegen {
+        /// Language of the generated code
+        #[arg(short, long, value_enum, default_value_t = Language::Rust)]
+        language: Language,
+
+        /// Path of the generated file, without its extension. The code is printed
+        /// when omitted, which only works for Rust
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Name of the generated function
+        #[arg(short, long, default_value = "is_match", value_parser = identifier)]
+        name: String,
     },
 }
 
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Language {
+    /// A Rust function `name(input: &str) -> bool`
+    Rust,
+    /// A C99 function `bool name(const char *input, size_t len)`, in a `.c` file
+    /// with its `.h` header
+    C,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Minimization {
+    /// Hopcroft's partition refinement
+    Hopcroft,
+    /// Brzozowski's double reversal
+    Brzozowski,
+}
+
 fn main() {
-    println!("Regex automata course!");
     let args = Args::parse();
+    // The generated code is printed alone, to be redirected into a source file.
+    if !matches!(args.cmd, Commands::Codegen { .. }) {
+        println!("Regex automata course!");
+    }
 
-    let r = Regex::new(&args.regexp).unwrap();
+    let regexp = || {
+        args.regexp.as_deref().unwrap_or_else(|| {
+            let message = "the command requires a regex, given with --regexp";
+            Args::command()
+                .error(ErrorKind::MissingRequiredArgument, message)
+                .exit()
+        })
+    };
 
     match args.cmd {
         Commands::Test { input } => {
-            assert_eq!(r.test(&input), true);
+            let r = Regex::new(regexp()).unwrap();
+            assert!(r.test(&input));
         }
-        Commands::Table { simplify_notations } => {
-            let nfa = r.nfa.clone();
-            let mut dfa = r.dfa.clone();
+        Commands::Table {
+            simplify_notations,
+            minimization,
+        } => {
+            let nfa = match regex::compile_nfa(regexp()) {
+                Ok(nfa) => nfa,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                }
+            };
+            let mut dfa = DFA::from(&nfa);
             // println!(":::: final NFA={:#?}", re);
             let nfa_table = nfa.get_transition_table();
             // println!(":::: nfa table ={:#?}", nfa_table);
@@ -65,30 +136,172 @@ fn main() {
                 dfa.simplify_notations();
             }
 
-            println!("");
+            println!();
             println!("> - starting");
             println!("✓ - accepting");
-            println!("");
+            println!();
 
             println!("NFA: Transition table:");
-            println!("");
+            println!();
             print_nfa_table(&nfa_table);
 
+            println!("DFA: Char classes:");
+            println!();
+            print_char_classes(&dfa);
+
             println!("DFA: Original transition table:");
-            println!("");
+            println!();
             print_dfa_table(&dfa);
 
-            dfa.minimize();
+            let started = Instant::now();
+            match minimization {
+                Minimization::Hopcroft => dfa.minimize(),
+                Minimization::Brzozowski => dfa.minimize_brzozowski(),
+            };
+            let elapsed = started.elapsed();
             if simplify_notations {
                 dfa.simplify_notations();
             }
-            println!("DFA: Minimized transition table");
-            println!("");
+            println!(
+                "DFA: Minimized transition table ({:?}, took {:?})",
+                minimization, elapsed
+            );
+            println!();
             print_dfa_table(&dfa);
         }
+        Commands::Lex { spec, input } => {
+            let (spec, input) = match (fs::read_to_string(spec), fs::read_to_string(input)) {
+                (Ok(spec), Ok(input)) => (spec, input),
+                (Err(err), _) | (_, Err(err)) => {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                }
+            };
+            let spec = match Spec::parse(&spec) {
+                Ok(spec) => spec,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                }
+            };
+            match spec.lexer() {
+                Ok(lexer) => print_tokens(&spec, &lexer, &input),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Commands::Codegen {
+            language,
+            ref output,
+            ref name,
+        } => {
+            let dfa = match codegen::search_dfa(regexp(), &SizeLimits::default()) {
+                Ok(dfa) => dfa,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                }
+            };
+            let files = match language {
+                Language::Rust => {
+                    let code = codegen::rust::generate(&dfa, name);
+                    let code = format!("// Generated from the pattern {:?}.\n{code}", regexp());
+                    vec![("rs", code)]
+                }
+                Language::C => {
+                    let Some(output) = output else {
+                        let message = "C code is generated into files, given with --output";
+                        Args::command()
+                            .error(ErrorKind::MissingRequiredArgument, message)
+                            .exit()
+                    };
+                    let header_name = output.with_extension("h");
+                    let header_name = header_name.file_name().unwrap().to_string_lossy();
+                    let sources = codegen::c::generate(&dfa, &header_name, name);
+                    // A line comment, as the pattern could end a block comment.
+                    let comment = format!("// Generated from the pattern {:?}.\n", regexp());
+                    vec![
+                        ("h", comment.clone() + &sources.header),
+                        ("c", comment + &sources.source),
+                    ]
+                }
+            };
+            for (extension, code) in files {
+                let Some(output) = output else {
+                    print!("{}", code);
+                    continue;
+                };
+                if let Err(err) = fs::write(output.with_extension(extension), code) {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                }
+            }
+        }
     };
 }
 
+// Parses the name of a generated function, which must be an identifier in both
+// Rust and C.
+fn identifier(name: &str) -> Result<String, String> {
+    let mut chars = name.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if valid {
+        Ok(name.to_owned())
+    } else {
+        Err("expected an ASCII identifier".to_owned())
+    }
+}
+
+fn print_tokens(spec: &Spec, lexer: &Lexer<usize>, input: &str) {
+    let mut table = Table::builder().style(TableStyle::thin()).build();
+    table.add_row(Row::new(vec![
+        TableCell::new("position"),
+        TableCell::new("text"),
+        TableCell::new("rule"),
+        TableCell::new("action"),
+    ]));
+
+    // The line and column of each token, counted from the previous one.
+    let (mut line, mut column, mut at) = (1, 1, 0);
+    let mut error = None;
+    for token in lexer.tokens(input) {
+        let token = match token {
+            Ok(token) => token,
+            Err(err) => {
+                error = Some(err);
+                break;
+            }
+        };
+        for c in input[at..token.start()].chars() {
+            if c == '\n' {
+                (line, column) = (line + 1, 1);
+            } else {
+                column += 1;
+            }
+        }
+        at = token.start();
+
+        let rule = &spec.rules()[*token.kind()];
+        table.add_row(Row::new(vec![
+            TableCell::new(format!("{line}:{column}")),
+            TableCell::new(format!("{:?}", token.as_str())),
+            TableCell::new(&rule.pattern),
+            TableCell::new(&rule.action),
+        ]));
+    }
+
+    println!("{}", table.render());
+    if let Some(err) = error {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
+
 fn print_nfa_table(nfa_table: &NFATable) {
     let mut table_states = nfa_table.table.keys().collect::<Vec<_>>();
     table_states.sort();
@@ -96,8 +309,8 @@ fn print_nfa_table(nfa_table: &NFATable) {
     let mut map_table_transitions = HashSet::new();
     let mut table_transitions = vec![];
 
-    for (_, transitions) in &nfa_table.table {
-        for (t, _) in transitions {
+    for transitions in nfa_table.table.values() {
+        for t in transitions.keys() {
             if !map_table_transitions.contains(t) {
                 map_table_transitions.insert(t.to_owned());
                 table_transitions.push(t);
@@ -132,7 +345,7 @@ fn print_nfa_table(nfa_table: &NFATable) {
     table.add_row(header);
 
     for state in table_states {
-        let transitions = nfa_table.table.get(&state).unwrap();
+        let transitions = nfa_table.table.get(state).unwrap();
         let mut row = Row::empty();
         let mut label = state.to_string();
         if nfa_table.starting_state == *state {
@@ -161,16 +374,36 @@ fn print_nfa_table(nfa_table: &NFATable) {
     println!("{}", table.render());
 }
 
+fn print_char_classes(dfa: &DFA) {
+    let classes = dfa.classes();
+
+    let mut table = Table::builder().style(TableStyle::thin()).build();
+    table.add_row(Row::new(vec![
+        TableCell::new("class"),
+        TableCell::new("chars"),
+    ]));
+    for class in 0..classes.len() {
+        table.add_row(Row::new(vec![
+            TableCell::new(class),
+            TableCell::new(classes.label(class)),
+        ]));
+    }
+
+    println!("{}", table.render());
+}
+
 fn print_dfa_table(dfa: &DFA) {
     let dfa_table = dfa.get_transition_table();
-    let table_states = dfa_table.keys().collect::<Vec<_>>();
-
-    let first_state_row = dfa_table.get(table_states[0]).unwrap();
-    let table_transitions = first_state_row.keys().collect::<Vec<_>>();
+    // Rows only hold the transitions a state has, so the columns are gathered
+    // from all of them.
+    let table_transitions = dfa_table
+        .values()
+        .flat_map(|transitions| transitions.keys())
+        .collect::<BTreeSet<_>>();
 
     let mut header = Row::empty();
     header.add_cell(TableCell::new(""));
-    for t in table_transitions {
+    for t in &table_transitions {
         header.add_cell(TableCell::new(t.to_string()));
     }
 
@@ -178,17 +411,18 @@ fn print_dfa_table(dfa: &DFA) {
 
     table.add_row(header);
 
-    for (state, transitions) in dfa_table {
+    for (state, transitions) in &dfa_table {
         let mut row = Row::empty();
         let mut label = state.to_string();
         if dfa.get_starting_state() == *state {
             label = format!("> {label}");
-        } else if dfa.get_accepting_states().contains(&state) {
+        } else if dfa.get_accepting_states().contains(state) {
             label = format!("✓ {label}");
         }
         row.add_cell(TableCell::new(label));
 
-        for (_transition, transition_states) in transitions {
+        for transition in &table_transitions {
+            let transition_states = transitions.get(*transition).map_or("", |s| s.as_str());
             row.add_cell(TableCell::new(transition_states.to_string()));
         }
         table.add_row(row);
@@ -0,0 +1,21 @@
+/// The semantics of a search, deciding which match it finds when several of
+/// them start or end at different positions.
+///
+/// Matching a whole string with `test` is the same under every kind.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MatchKind {
+    /// Among the matches starting at the leftmost position, the one preferred
+    /// by the pattern wins, like in Perl: earlier alternatives before later
+    /// ones, and more repetitions before fewer ones, unless the repetition is
+    /// lazy. So `a|ab` finds `a` in `ab`.
+    #[default]
+    LeftmostFirst,
+    /// Among the matches starting at the leftmost position, the longest one
+    /// wins, like in POSIX. So `a|ab` finds `ab` in `ab`, and lazy
+    /// repetitions match like greedy ones.
+    LeftmostLongest,
+    /// Every match is reported. A search stops at the first position where a
+    /// match ends, and finds the leftmost start of the matches ending there.
+    /// So `a|ab` finds `a` in `ab`, and `abc|b` finds `b` in `abc`.
+    All,
+}
@@ -1,57 +1,1249 @@
+use std::{
+    cell::OnceCell,
+    collections::VecDeque,
+    io::{self, Read},
+};
+
 use crate::{
-    ast::{parser::Parser, AST},
-    automata::{dfa::DFA, nfa::NFA},
+    ast::{
+        parser::{Parser, ParserBuilder},
+        AssertionKind, GroupKind, Repetition, AST,
+    },
+    automata::{
+        bytes::ByteDFA,
+        dense,
+        dfa::DFA,
+        lazy::{self, LazyDFA, DEFAULT_CACHE_CAPACITY},
+        limits::SizeLimits,
+        nfa::NFA,
+        pikevm::{self, Latin1, PikeVM, Slots},
+        state::{Edges, Look},
+        utf16,
+        wide::Utf16DFA,
+    },
+    backtrack::{self, Backtracker, DEFAULT_STEP_LIMIT},
     error::Error,
+    match_kind::MatchKind,
 };
 
 pub struct Regex {
-    pub nfa: NFA,
-    pub dfa: DFA,
+    engine: Engine,
+    captures_len: usize,
+    match_kind: MatchKind,
+}
+
+/// The engine executing a compiled pattern.
+enum Engine {
+    /// The pattern is compiled into DFAs, fully built before any search.
+    /// Capture groups are found by simulating the NFA.
+    Dfa {
+        nfa: NFA,
+        dfas: Box<Dfas<DFA>>,
+        encoded: Box<EncodedDfas>,
+    },
+    /// The pattern is compiled into DFAs whose states are built as searches
+    /// reach them. Capture groups are found by simulating the NFA.
+    LazyDfa { nfa: NFA, dfas: Box<Dfas<LazyDFA>> },
+    /// The pattern is compiled into an NFA, which is simulated for every search.
+    Nfa(NFA),
+    /// The pattern uses features the automata can't express, e.g.
+    /// backreferences, so it is executed by the backtracker.
+    Backtrack(Backtracker),
+}
+
+/// The DFAs executing a pattern. A DFA only tells where a match ends, so a
+/// match is found in two passes: the forward DFA finds the end of the match,
+/// then the reverse DFA runs backwards from there to find where it starts,
+/// which is the leftmost start of a match with that end.
+struct Dfas<D> {
+    /// Matches whole strings.
+    test: D,
+    /// Searches for the end of the match, following the match kind.
+    forward: D,
+    /// Searches for the end of every match, overlapping ones included.
+    overlapping: D,
+    /// Matches the reversed strings.
+    reverse: D,
+}
+
+/// The forward DFA of the `Dfa` engine compiled for the encodings of text other
+/// than `&str`, each the first time text in that encoding is searched. Finding
+/// where a match starts decodes the text for the reverse DFA instead, as it only
+/// reads the match.
+#[derive(Default)]
+struct EncodedDfas {
+    utf8: OnceCell<ByteDFA>,
+    utf16: OnceCell<Utf16DFA>,
+    latin1: OnceCell<ByteDFA>,
+}
+
+impl EncodedDfas {
+    fn utf8(&self, forward: &DFA) -> &ByteDFA {
+        self.utf8.get_or_init(|| ByteDFA::from(forward.dense()))
+    }
+
+    fn utf16(&self, forward: &DFA) -> &Utf16DFA {
+        self.utf16.get_or_init(|| Utf16DFA::from(forward.dense()))
+    }
+
+    fn latin1(&self, forward: &DFA) -> &ByteDFA {
+        self.latin1.get_or_init(|| ByteDFA::latin1(forward.dense()))
+    }
+}
+
+/// The engines a `RegexBuilder` can compile a pattern for.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EngineKind {
+    /// Use the lazy DFA when the automata can express the pattern, and the
+    /// backtracker otherwise, e.g. for backreferences, lookarounds or
+    /// multi-line anchors.
+    #[default]
+    Auto,
+    /// Build the whole DFA up front. Its size can be exponential in the size
+    /// of the pattern, which `RegexBuilder::size_limits` bounds.
+    Dfa,
+    /// Build the states of the DFA as searches reach them.
+    LazyDfa,
+    /// Simulate the NFA, never building any DFA state.
+    Nfa,
+    /// Execute the pattern by backtracking, which supports every feature at
+    /// the price of a worst case exponential running time.
+    Backtrack,
+}
+
+/// A builder configuring how a pattern is compiled into a `Regex`.
+///
+/// The automata engines fail with `Error::Unsupported` on patterns they
+/// can't express, unless the engine is picked by `EngineKind::Auto`.
+#[derive(Clone, Debug)]
+pub struct RegexBuilder {
+    pattern: String,
+    parser: ParserBuilder,
+    limits: SizeLimits,
+    minimize: bool,
+    engine: EngineKind,
+    step_limit: usize,
+    cache_capacity: usize,
+    match_kind: MatchKind,
+}
+
+impl RegexBuilder {
+    pub fn new(pattern: &str) -> RegexBuilder {
+        RegexBuilder {
+            pattern: pattern.to_owned(),
+            parser: ParserBuilder::new(),
+            limits: SizeLimits::default(),
+            minimize: false,
+            engine: EngineKind::Auto,
+            step_limit: DEFAULT_STEP_LIMIT,
+            cache_capacity: DEFAULT_CACHE_CAPACITY,
+            match_kind: MatchKind::LeftmostFirst,
+        }
+    }
+
+    /// Compiles the pattern, failing with `Error::CompiledTooBig` if its
+    /// automata exceed the size limits.
+    pub fn build(&self) -> Result<Regex, Error> {
+        let ast = self
+            .parser
+            .build()
+            .parse(&self.pattern)
+            .map_err(Error::from_ast_parse_error)?;
+        let captures_len = ast.captures_len();
+
+        let engine = match self.engine {
+            EngineKind::Auto => match ast_to_nfa(&ast, &self.limits) {
+                Ok(nfa) => self.lazy_dfa(nfa),
+                Err(Error::Unsupported { .. }) => self.backtracker(ast)?,
+                Err(err) => return Err(err),
+            },
+            EngineKind::Dfa => {
+                let nfa = ast_to_nfa(&ast, &self.limits)?;
+                let mut dfas = Dfas {
+                    test: DFA::from_with_limits(&nfa, &self.limits)?,
+                    forward: DFA::search(&nfa, self.match_kind, &self.limits)?,
+                    overlapping: DFA::search(&nfa, MatchKind::All, &self.limits)?,
+                    reverse: DFA::reversed(&nfa, &self.limits)?,
+                };
+                if self.minimize {
+                    dfas.test.minimize();
+                    dfas.forward.minimize();
+                    dfas.overlapping.minimize();
+                    dfas.reverse.minimize();
+                }
+                Engine::Dfa {
+                    nfa,
+                    dfas: Box::new(dfas),
+                    encoded: Box::default(),
+                }
+            }
+            EngineKind::LazyDfa => self.lazy_dfa(ast_to_nfa(&ast, &self.limits)?),
+            EngineKind::Nfa => Engine::Nfa(ast_to_nfa(&ast, &self.limits)?),
+            EngineKind::Backtrack => self.backtracker(ast)?,
+        };
+
+        Ok(Regex {
+            engine,
+            captures_len,
+            match_kind: self.match_kind,
+        })
+    }
+
+    fn lazy_dfa(&self, nfa: NFA) -> Engine {
+        // The states of the DFAs are only built as the input needs them, so a
+        // pattern with an exponentially large DFA can't hang here.
+        let dfas = Dfas {
+            test: LazyDFA::new(&nfa).with_cache_capacity(self.cache_capacity),
+            forward: LazyDFA::search(&nfa, self.match_kind)
+                .with_cache_capacity(self.cache_capacity),
+            overlapping: LazyDFA::search(&nfa, MatchKind::All)
+                .with_cache_capacity(self.cache_capacity),
+            reverse: LazyDFA::reverse(&nfa).with_cache_capacity(self.cache_capacity),
+        };
+        Engine::LazyDfa {
+            nfa,
+            dfas: Box::new(dfas),
+        }
+    }
+
+    fn backtracker(&self, ast: AST) -> Result<Engine, Error> {
+        let bt = Backtracker::new(ast)?
+            .with_step_limit(self.step_limit)
+            .with_match_kind(self.match_kind);
+        Ok(Engine::Backtrack(bt))
+    }
+
+    /// Match letters regardless of their case.
+    pub fn case_insensitive(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.parser.case_insensitive(yes);
+        self
+    }
+
+    /// Make `^` and `$` match at the start and end of every line.
+    pub fn multi_line(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.parser.multi_line(yes);
+        self
+    }
+
+    /// Make `.` match `\n` too.
+    pub fn dot_all(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.parser.dot_all(yes);
+        self
+    }
+
+    /// Ignore whitespace in the pattern, and allow `#` comments.
+    pub fn verbose(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.parser.ignore_whitespace(yes);
+        self
+    }
+
+    /// Set the maximum depth of nested groups and repetitions.
+    pub fn nest_limit(&mut self, limit: u32) -> &mut RegexBuilder {
+        self.parser.nest_limit(limit);
+        self
+    }
+
+    /// Set the limits on the size of the compiled automata.
+    pub fn size_limits(&mut self, limits: SizeLimits) -> &mut RegexBuilder {
+        self.limits = limits;
+        self
+    }
+
+    /// Minimize the DFA of `EngineKind::Dfa` once it's built.
+    pub fn minimize(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.minimize = yes;
+        self
+    }
+
+    /// Set the engine executing the pattern, `EngineKind::Auto` by default.
+    pub fn engine(&mut self, engine: EngineKind) -> &mut RegexBuilder {
+        self.engine = engine;
+        self
+    }
+
+    /// Set the step budget of each backtracking search.
+    pub fn step_limit(&mut self, limit: usize) -> &mut RegexBuilder {
+        self.step_limit = limit;
+        self
+    }
+
+    /// Set the memory the lazy DFA may use to cache its states, in bytes.
+    pub fn cache_capacity(&mut self, capacity: usize) -> &mut RegexBuilder {
+        self.cache_capacity = capacity;
+        self
+    }
+
+    /// Set which match the searches report when several overlap. This has no
+    /// effect on `Regex::test`, which only matches whole strings.
+    pub fn match_kind(&mut self, kind: MatchKind) -> &mut RegexBuilder {
+        self.match_kind = kind;
+        self
+    }
 }
 
 impl Regex {
     pub fn new(input: &str) -> Result<Regex, Error> {
-        let ast = Parser::new()
-            .parse(input)
-            .map_err(Error::from_ast_parse_error)?;
-        let nfa = ast_to_nfa(&ast);
-        let dfa = nfa_to_dfa(&nfa);
-        // dfa.minimize();
-        Ok(Regex { nfa, dfa })
+        RegexBuilder::new(input).build()
+    }
+
+    /// Compiles the pattern, failing with `Error::CompiledTooBig` if its NFA
+    /// has more states than the limits allow.
+    pub fn with_size_limits(input: &str, limits: &SizeLimits) -> Result<Regex, Error> {
+        RegexBuilder::new(input).size_limits(*limits).build()
+    }
+
+    /// Set the step budget of the backtracking engine. This has no effect on
+    /// patterns executed by the DFA, which always run in linear time.
+    pub fn with_step_limit(mut self, limit: usize) -> Regex {
+        if let Engine::Backtrack(bt) = self.engine {
+            self.engine = Engine::Backtrack(bt.with_step_limit(limit));
+        }
+        self
     }
 
+    /// Tests whether the whole string matches.
+    ///
+    /// A backtracker exceeding its step budget is taken as no match, which
+    /// `try_test` tells apart.
     pub fn test(&self, text: &str) -> bool {
-        self.dfa.test(text)
+        or_no_match(self.try_test(text))
+    }
+
+    /// Tests whether the whole string matches, failing if the backtracker
+    /// exceeds its step budget.
+    pub fn try_test(&self, text: &str) -> Result<bool, Error> {
+        match self.engine {
+            Engine::Dfa { ref dfas, .. } => Ok(dfas.test.test(text)),
+            Engine::LazyDfa { ref dfas, .. } => Ok(dfas.test.test(text)),
+            Engine::Nfa(ref nfa) => Ok(self.pikevm(nfa).test(text)),
+            Engine::Backtrack(ref bt) => bt.test(text),
+        }
+    }
+
+    /// Tests whether the text read from `reader` contains a match anywhere,
+    /// unlike `test` which matches the whole text.
+    ///
+    /// The automata engines match the text as it's read, in chunks, and stop
+    /// reading at the first match. Invalid UTF-8 is read as U+FFFD. The
+    /// backtracker needs the whole text as a `&str`, e.g. for backreferences,
+    /// so it fails with `io::ErrorKind::Unsupported` without reading anything,
+    /// like `is_match_bytes` fails with `Error::Unsupported`.
+    pub fn is_match_read(&self, reader: impl Read) -> io::Result<bool> {
+        // Whatever the match kind, the forward DFA first accepts at the end of
+        // the match ending first.
+        match self.engine {
+            Engine::Dfa { ref dfas, .. } => {
+                let mut stream = dfas.forward.stream();
+                let found = read_chunks(reader, |chunk| !stream.feed(chunk).is_empty())?;
+                Ok(found || !stream.end().is_empty())
+            }
+            Engine::LazyDfa { ref dfas, .. } => {
+                let mut stream = dfas.forward.stream();
+                let found = read_chunks(reader, |chunk| !stream.feed(chunk).is_empty())?;
+                Ok(found || !stream.end().is_empty())
+            }
+            Engine::Nfa(ref nfa) => {
+                let mut stream = self.pikevm(nfa).stream();
+                let found = read_chunks(reader, |chunk| stream.feed(chunk))?;
+                Ok(found || stream.end())
+            }
+            Engine::Backtrack(ref bt) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                only_str(bt).to_string(),
+            )),
+        }
+    }
+
+    /// Tests whether the bytes contain a match anywhere, like `is_match_read`.
+    ///
+    /// The bytes are UTF-8 encoded text, but needn't be valid: a byte that isn't
+    /// part of the encoding of a char matches as U+FFFD on its own, like
+    /// `utf8::decode` reads it.
+    ///
+    /// The bytes are never copied. Only the `Dfa` engine compiles an automaton
+    /// reading bytes, the first time bytes are searched. The lazy DFA and the
+    /// NFA decode each char as they read it instead. The backtracker only
+    /// searches `&str`, so it fails with `Error::Unsupported`.
+    pub fn is_match_bytes(&self, bytes: &[u8]) -> Result<bool, Error> {
+        match self.engine {
+            Engine::Dfa {
+                ref dfas,
+                ref encoded,
+                ..
+            } => {
+                let dfa = encoded.utf8(&dfas.forward);
+                Ok(dfa.find_earliest_end(bytes, 0).is_some())
+            }
+            Engine::LazyDfa { ref dfas, .. } => {
+                Ok(dfas.forward.find_earliest_end_bytes(bytes, 0).is_some())
+            }
+            Engine::Nfa(ref nfa) => {
+                let mut stream = self.pikevm(nfa).stream();
+                Ok(stream.feed(bytes) || stream.end())
+            }
+            Engine::Backtrack(ref bt) => Err(only_str(bt)),
+        }
+    }
+
+    /// Returns the first match in the bytes, like `find`. The bytes needn't be
+    /// valid UTF-8, see `is_match_bytes`, and the offsets of the match are offsets
+    /// in the bytes. Where the match starts is found by decoding its chars
+    /// backwards. Fails with `Error::Unsupported` for the backtracker.
+    pub fn find_bytes<'t>(&self, bytes: &'t [u8]) -> Result<Option<BytesMatch<'t>>, Error> {
+        let span = match self.engine {
+            Engine::Dfa {
+                ref dfas,
+                ref encoded,
+                ..
+            } => {
+                let dfa = encoded.utf8(&dfas.forward);
+                let end = match self.match_kind {
+                    MatchKind::All => dfa.find_earliest_end(bytes, 0),
+                    _ => dfa.find_end(bytes, 0),
+                };
+                end.and_then(|end| Some((dfas.reverse.rfind_start_bytes(bytes, 0, end)?, end)))
+            }
+            Engine::LazyDfa { ref dfas, .. } => {
+                let end = match self.match_kind {
+                    MatchKind::All => dfas.forward.find_earliest_end_bytes(bytes, 0),
+                    _ => dfas.forward.find_end_bytes(bytes, 0),
+                };
+                end.and_then(|end| Some((dfas.reverse.rfind_start_bytes(bytes, 0, end)?, end)))
+            }
+            Engine::Nfa(ref nfa) => self.pikevm(nfa).captures(bytes).and_then(span_of),
+            Engine::Backtrack(ref bt) => return Err(only_str(bt)),
+        };
+        Ok(span.map(|(start, end)| BytesMatch { bytes, start, end }))
+    }
+
+    /// Tests whether the UTF-16 encoded units contain a match anywhere, like
+    /// `is_match_bytes`. A surrogate that isn't part of a pair matches as U+FFFD
+    /// on its own.
+    ///
+    /// The units are never copied. Only the `Dfa` engine compiles an automaton
+    /// reading units, the first time units are searched. The lazy DFA and the
+    /// NFA decode each char as they read it instead. The backtracker fails with
+    /// `Error::Unsupported`.
+    pub fn is_match_utf16(&self, units: &[u16]) -> Result<bool, Error> {
+        match self.engine {
+            Engine::Dfa {
+                ref dfas,
+                ref encoded,
+                ..
+            } => {
+                let dfa = encoded.utf16(&dfas.forward);
+                Ok(dfa.find_earliest_end(units, 0).is_some())
+            }
+            Engine::LazyDfa { ref dfas, .. } => {
+                let chars = utf16::char_indices(units);
+                let end = dfas
+                    .forward
+                    .find_match(chars, Edges::WHOLE, units.len(), true);
+                Ok(end.is_some())
+            }
+            Engine::Nfa(ref nfa) => Ok(self.pikevm(nfa).captures(units).is_some()),
+            Engine::Backtrack(ref bt) => Err(only_str(bt)),
+        }
+    }
+
+    /// Returns the first match in the UTF-16 encoded units, like `find`. The
+    /// offsets of the match are offsets in code units. Fails with
+    /// `Error::Unsupported` for the backtracker.
+    pub fn find_utf16<'t>(&self, units: &'t [u16]) -> Result<Option<Utf16Match<'t>>, Error> {
+        // The reverse DFA reads the chars backwards, each with the offset after it.
+        let rev_chars = |end: usize| {
+            let chars = utf16::char_indices(&units[..end]).rev();
+            chars.map(|(i, c)| (i + c.len_utf16(), c))
+        };
+        let backwards = |end: usize| Edges::backwards(0, end, units.len());
+        let earliest = self.match_kind == MatchKind::All;
+        let span = match self.engine {
+            Engine::Dfa {
+                ref dfas,
+                ref encoded,
+                ..
+            } => {
+                let dfa = encoded.utf16(&dfas.forward);
+                let end = match self.match_kind {
+                    MatchKind::All => dfa.find_earliest_end(units, 0),
+                    _ => dfa.find_end(units, 0),
+                };
+                let reverse = dfas.reverse.dense();
+                end.and_then(|end| {
+                    let start = reverse.find_match(rev_chars(end), backwards(end), 0, false)?;
+                    Some((start, end))
+                })
+            }
+            Engine::LazyDfa { ref dfas, .. } => {
+                let chars = utf16::char_indices(units);
+                let end = dfas
+                    .forward
+                    .find_match(chars, Edges::WHOLE, units.len(), earliest);
+                let reverse = &dfas.reverse;
+                end.and_then(|end| {
+                    let start = reverse.find_match(rev_chars(end), backwards(end), 0, false)?;
+                    Some((start, end))
+                })
+            }
+            Engine::Nfa(ref nfa) => self.pikevm(nfa).captures(units).and_then(span_of),
+            Engine::Backtrack(ref bt) => return Err(only_str(bt)),
+        };
+        Ok(span.map(|(start, end)| Utf16Match { units, start, end }))
+    }
+
+    /// Tests whether the Latin-1 encoded bytes contain a match anywhere. Each byte
+    /// is the char with the same code point, from U+0000 to U+00FF.
+    ///
+    /// The bytes are never copied, like with `is_match_utf16`, and the backtracker
+    /// fails with `Error::Unsupported`.
+    pub fn is_match_latin1(&self, bytes: &[u8]) -> Result<bool, Error> {
+        match self.engine {
+            Engine::Dfa {
+                ref dfas,
+                ref encoded,
+                ..
+            } => {
+                let dfa = encoded.latin1(&dfas.forward);
+                Ok(dfa.find_earliest_end(bytes, 0).is_some())
+            }
+            Engine::LazyDfa { ref dfas, .. } => {
+                let chars = latin1_char_indices(bytes);
+                let end = dfas
+                    .forward
+                    .find_match(chars, Edges::WHOLE, bytes.len(), true);
+                Ok(end.is_some())
+            }
+            Engine::Nfa(ref nfa) => Ok(self.pikevm(nfa).captures(&Latin1(bytes)).is_some()),
+            Engine::Backtrack(ref bt) => Err(only_str(bt)),
+        }
+    }
+
+    /// Returns the first match in the Latin-1 encoded bytes, like `find`. Fails
+    /// with `Error::Unsupported` for the backtracker.
+    pub fn find_latin1<'t>(&self, bytes: &'t [u8]) -> Result<Option<BytesMatch<'t>>, Error> {
+        // The reverse DFA reads the chars backwards, each with the offset after it.
+        let rev_chars = |end: usize| {
+            let chars = latin1_char_indices(&bytes[..end]).rev();
+            chars.map(|(i, c)| (i + 1, c))
+        };
+        let backwards = |end: usize| Edges::backwards(0, end, bytes.len());
+        let earliest = self.match_kind == MatchKind::All;
+        let span = match self.engine {
+            Engine::Dfa {
+                ref dfas,
+                ref encoded,
+                ..
+            } => {
+                let dfa = encoded.latin1(&dfas.forward);
+                let end = match self.match_kind {
+                    MatchKind::All => dfa.find_earliest_end(bytes, 0),
+                    _ => dfa.find_end(bytes, 0),
+                };
+                let reverse = dfas.reverse.dense();
+                end.and_then(|end| {
+                    let start = reverse.find_match(rev_chars(end), backwards(end), 0, false)?;
+                    Some((start, end))
+                })
+            }
+            Engine::LazyDfa { ref dfas, .. } => {
+                let chars = latin1_char_indices(bytes);
+                let end = dfas
+                    .forward
+                    .find_match(chars, Edges::WHOLE, bytes.len(), earliest);
+                let reverse = &dfas.reverse;
+                end.and_then(|end| {
+                    let start = reverse.find_match(rev_chars(end), backwards(end), 0, false)?;
+                    Some((start, end))
+                })
+            }
+            Engine::Nfa(ref nfa) => {
+                let slots = self.pikevm(nfa).captures(&Latin1(bytes));
+                slots.and_then(span_of)
+            }
+            Engine::Backtrack(ref bt) => return Err(only_str(bt)),
+        };
+        Ok(span.map(|(start, end)| BytesMatch { bytes, start, end }))
+    }
+
+    /// Returns the first match in the string, which is the leftmost-first one
+    /// unless the builder set another `MatchKind`.
+    ///
+    /// A backtracker exceeding its step budget is taken as no match, which
+    /// `try_find` tells apart.
+    pub fn find<'t>(&self, text: &'t str) -> Option<Match<'t>> {
+        self.find_at(text, 0)
+    }
+
+    /// Returns the first match in the string, failing if the backtracker
+    /// exceeds its step budget.
+    pub fn try_find<'t>(&self, text: &'t str) -> Result<Option<Match<'t>>, Error> {
+        self.try_find_at(text, 0)
+    }
+
+    /// Returns the first match starting at or after the byte offset `start`.
+    ///
+    /// Unlike searching `&text[start..]`, the text before `start` is still
+    /// seen by anchors and lookbehinds, so `^` doesn't match at `start`
+    /// unless it's the start of the text. A backtracker exceeding its step
+    /// budget is taken as no match, which `try_find_at` tells apart.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `start` is past the end of the text or not on a char boundary.
+    pub fn find_at<'t>(&self, text: &'t str, start: usize) -> Option<Match<'t>> {
+        or_no_match(self.try_find_at(text, start))
+    }
+
+    /// Returns the first match starting at or after the byte offset `start`,
+    /// failing if the backtracker exceeds its step budget.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `start` is past the end of the text or not on a char boundary.
+    pub fn try_find_at<'t>(&self, text: &'t str, start: usize) -> Result<Option<Match<'t>>, Error> {
+        assert!(
+            text.is_char_boundary(start),
+            "byte offset {start} isn't a char boundary of the text"
+        );
+        // The reverse DFA stops at the start of the search, so it can't find a
+        // match starting before it.
+        let span = match self.engine {
+            Engine::Dfa { ref dfas, .. } => {
+                let end = match self.match_kind {
+                    MatchKind::All => dfas.forward.find_earliest_end(text, start),
+                    _ => dfas.forward.find_end(text, start),
+                };
+                end.and_then(|end| Some((dfas.reverse.rfind_start(text, start, end)?, end)))
+            }
+            Engine::LazyDfa { ref dfas, .. } => {
+                let end = match self.match_kind {
+                    MatchKind::All => dfas.forward.find_earliest_end(text, start),
+                    _ => dfas.forward.find_end(text, start),
+                };
+                end.and_then(|end| Some((dfas.reverse.rfind_start(text, start, end)?, end)))
+            }
+            Engine::Nfa(_) | Engine::Backtrack(_) => {
+                let caps = self.try_captures_at(text, start)?;
+                return Ok(caps.and_then(|caps| caps.get(0)));
+            }
+        };
+        Ok(span.map(|(start, end)| Match { text, start, end }))
+    }
+
+    /// Returns an iterator over the successive non-overlapping matches in the
+    /// string.
+    ///
+    /// After an empty match, the next search starts one char further, so the
+    /// iterator never stops inside a UTF-8 encoded char. An empty match right
+    /// where the previous match ended is skipped, e.g. `a*` finds `0..2` and
+    /// `3..3` in `"aab"`.
+    ///
+    /// The iterator stops once the backtracker exceeds its step budget, like
+    /// `find` finds no match then.
+    pub fn find_iter<'r, 't>(&'r self, text: &'t str) -> Matches<'r, 't> {
+        Matches {
+            regex: self,
+            text,
+            at: 0,
+            last_end: None,
+        }
+    }
+
+    /// Returns an iterator over every match in the string, overlapping ones
+    /// included: for every position where a match ends, in order, the match
+    /// with the leftmost start among those ending there. So `aa` finds `0..2`,
+    /// `1..3` and `2..4` in `"aaaa"`. The match kind plays no part here.
+    ///
+    /// The matches are found as the iterator advances: the DFA engines run a
+    /// DFA searching for every match end over the text, then the reverse DFA
+    /// from each end.
+    ///
+    /// The iterator stops once the backtracker exceeds its step budget, which
+    /// `try_find_overlapping_iter` tells apart.
+    pub fn find_overlapping_iter<'r, 't>(&'r self, text: &'t str) -> OverlappingMatches<'r, 't> {
+        OverlappingMatches(self.try_find_overlapping_iter(text))
+    }
+
+    /// Returns an iterator over every match in the string, overlapping ones
+    /// included, like `find_overlapping_iter`. Once the backtracker exceeds
+    /// its step budget, the iterator returns the error and stops.
+    pub fn try_find_overlapping_iter<'r, 't>(
+        &'r self,
+        text: &'t str,
+    ) -> TryOverlappingMatches<'r, 't> {
+        let search = match self.engine {
+            Engine::Dfa { ref dfas, .. } => OverlappingSearch::Dfa {
+                ends: DfaEnds::new(dfas.overlapping.stream()),
+                reverse: &dfas.reverse,
+            },
+            Engine::LazyDfa { ref dfas, .. } => OverlappingSearch::LazyDfa {
+                ends: DfaEnds::new(dfas.overlapping.stream()),
+                reverse: &dfas.reverse,
+            },
+            Engine::Nfa(ref nfa) => {
+                OverlappingSearch::Nfa(self.pikevm(nfa).overlapping_matches(text))
+            }
+            Engine::Backtrack(ref bt) => OverlappingSearch::Backtrack(bt.overlapping_matches(text)),
+        };
+        TryOverlappingMatches { text, search }
+    }
+
+    /// Returns the first match in the string along with the positions of each
+    /// capture group.
+    ///
+    /// With the default `MatchKind::LeftmostFirst`, among the matches starting
+    /// at the leftmost position, the one preferred by the pattern wins: earlier
+    /// alternatives before later ones, and more repetitions before fewer ones,
+    /// unless the repetition is lazy. So `".*?"` stops at the first closing
+    /// quote, while `".*"` runs up to the last one.
+    ///
+    /// A backtracker exceeding its step budget is taken as no match, which
+    /// `try_captures` tells apart.
+    pub fn captures<'t>(&self, text: &'t str) -> Option<Captures<'t>> {
+        self.captures_at(text, 0)
+    }
+
+    /// Returns the first match in the string along with the positions of each
+    /// capture group, failing if the backtracker exceeds its step budget.
+    pub fn try_captures<'t>(&self, text: &'t str) -> Result<Option<Captures<'t>>, Error> {
+        self.try_captures_at(text, 0)
+    }
+
+    /// Returns the first match starting at or after the byte offset `start`
+    /// along with the positions of each capture group. A backtracker exceeding
+    /// its step budget is taken as no match, which `try_captures_at` tells
+    /// apart.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `start` is past the end of the text or not on a char boundary.
+    pub fn captures_at<'t>(&self, text: &'t str, start: usize) -> Option<Captures<'t>> {
+        or_no_match(self.try_captures_at(text, start))
+    }
+
+    /// Returns the first match starting at or after the byte offset `start`
+    /// along with the positions of each capture group, failing if the
+    /// backtracker exceeds its step budget.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `start` is past the end of the text or not on a char boundary.
+    pub fn try_captures_at<'t>(
+        &self,
+        text: &'t str,
+        start: usize,
+    ) -> Result<Option<Captures<'t>>, Error> {
+        assert!(
+            text.is_char_boundary(start),
+            "byte offset {start} isn't a char boundary of the text"
+        );
+        let slots = match self.engine {
+            Engine::Dfa { ref nfa, .. }
+            | Engine::LazyDfa { ref nfa, .. }
+            | Engine::Nfa(ref nfa) => self.pikevm(nfa).captures_at(text, start),
+            Engine::Backtrack(ref bt) => bt.captures_at(text, start)?,
+        };
+        Ok(slots.map(|slots| Captures { text, slots }))
+    }
+
+    fn pikevm<'n>(&self, nfa: &'n NFA) -> PikeVM<'n> {
+        PikeVM::new(nfa, self.captures_len).with_match_kind(self.match_kind)
     }
 }
 
-/// Translates AST to NFA.
-fn ast_to_nfa(ast: &AST) -> NFA {
-    // This uses recursion to walk all the nested nodes, which isn't ideal if we were planing
-    // to support large trees, and the original Rust implementation uses a visitor implementation instead.
-    // But to keep this simple we use recursion for this implementation.
-    match ast {
-        AST::Literal(lit) => NFA::char(lit.c),
-        AST::Concat(con) => NFA::concat(con.asts.iter().map(|a| ast_to_nfa(&a)).collect()),
-        AST::Alternation(alt) => NFA::or(alt.asts.iter().map(|a| ast_to_nfa(&a)).collect()),
-        AST::Empty(_) => NFA::empty(),
+/// The error of the backtracker for a text that isn't a `&str`, e.g. bytes or a
+/// text read in chunks.
+fn only_str(bt: &Backtracker) -> Error {
+    let reason = "the backtracker only searches `&str`, use another engine for other texts";
+    Error::unsupported(bt.span(), reason)
+}
+
+/// The span of the match of the capture slots.
+fn span_of(slots: Slots) -> Option<(usize, usize)> {
+    Some((slots[0]?, slots[1]?))
+}
+
+/// Unwraps the result of a search, taking a backtracker exceeding its step
+/// budget as no match, which the `try_` methods return instead.
+fn or_no_match<T: Default>(result: Result<T, Error>) -> T {
+    result.unwrap_or_default()
+}
+
+/// Returns an iterator over the chars of the Latin-1 encoded bytes, along with
+/// their offsets.
+fn latin1_char_indices(bytes: &[u8]) -> impl DoubleEndedIterator<Item = (usize, char)> + '_ {
+    bytes.iter().enumerate().map(|(i, &b)| (i, char::from(b)))
+}
+
+/// Feeds the text read from `reader` to `feed` in chunks, until `feed` returns
+/// true or the text ends. Returns whether `feed` returned true.
+fn read_chunks(mut reader: impl Read, mut feed: impl FnMut(&[u8]) -> bool) -> io::Result<bool> {
+    let mut buf = [0; 8 * 1024];
+    loop {
+        let len = match reader.read(&mut buf) {
+            Ok(0) => return Ok(false),
+            Ok(len) => len,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        if feed(&buf[..len]) {
+            return Ok(true);
+        }
+    }
+}
+
+/// An iterator over the successive non-overlapping matches in a string,
+/// returned by `Regex::find_iter`.
+pub struct Matches<'r, 't> {
+    regex: &'r Regex,
+    text: &'t str,
+    /// The offset where the next search starts, past the end of the text once
+    /// the iteration is over.
+    at: usize,
+    /// The end of the previous match.
+    last_end: Option<usize>,
+}
+
+impl<'r, 't> Iterator for Matches<'r, 't> {
+    type Item = Match<'t>;
+
+    fn next(&mut self) -> Option<Match<'t>> {
+        loop {
+            if self.at > self.text.len() {
+                return None;
+            }
+            let m = self.regex.find_at(self.text, self.at)?;
+            if m.start < m.end {
+                self.at = m.end;
+            } else {
+                // Move past the char after an empty match, or the search
+                // would find the same match again.
+                self.at = match self.text[m.end..].chars().next() {
+                    Some(c) => m.end + c.len_utf8(),
+                    None => self.text.len() + 1,
+                };
+                // An empty match right where the previous match ended is
+                // already covered by it, e.g. `a*` on `"ab"` only finds `"a"`
+                // before the `b`.
+                if self.last_end == Some(m.end) {
+                    continue;
+                }
+            }
+            self.last_end = Some(m.end);
+            return Some(m);
+        }
+    }
+}
+
+/// An iterator over every match in a string, overlapping ones included,
+/// returned by `Regex::find_overlapping_iter`.
+pub struct OverlappingMatches<'r, 't>(TryOverlappingMatches<'r, 't>);
+
+impl<'r, 't> Iterator for OverlappingMatches<'r, 't> {
+    type Item = Match<'t>;
+
+    fn next(&mut self) -> Option<Match<'t>> {
+        self.0.next()?.ok()
+    }
+}
+
+/// An iterator over every match in a string, overlapping ones included, or
+/// the error of a backtracker exceeding its step budget, returned by
+/// `Regex::try_find_overlapping_iter`.
+pub struct TryOverlappingMatches<'r, 't> {
+    text: &'t str,
+    search: OverlappingSearch<'r, 't>,
+}
+
+/// The search of each engine for overlapping matches.
+enum OverlappingSearch<'r, 't> {
+    Dfa {
+        ends: DfaEnds<dense::Stream<'r>>,
+        reverse: &'r DFA,
+    },
+    LazyDfa {
+        ends: DfaEnds<lazy::Stream<'r>>,
+        reverse: &'r LazyDFA,
+    },
+    Nfa(pikevm::OverlappingMatches<'r, 't>),
+    Backtrack(backtrack::OverlappingMatches<'r, 't>),
+}
+
+impl<'r, 't> Iterator for TryOverlappingMatches<'r, 't> {
+    type Item = Result<Match<'t>, Error>;
+
+    fn next(&mut self) -> Option<Result<Match<'t>, Error>> {
+        let text = self.text;
+        let (start, end) = match self.search {
+            OverlappingSearch::Dfa {
+                ref mut ends,
+                reverse,
+            } => {
+                let end = ends.next_end(text)?;
+                (reverse.rfind_start(text, 0, end)?, end)
+            }
+            OverlappingSearch::LazyDfa {
+                ref mut ends,
+                reverse,
+            } => {
+                let end = ends.next_end(text)?;
+                (reverse.rfind_start(text, 0, end)?, end)
+            }
+            OverlappingSearch::Nfa(ref mut matches) => matches.next()?,
+            OverlappingSearch::Backtrack(ref mut matches) => match matches.next()? {
+                Ok(span) => span,
+                Err(err) => return Some(Err(err)),
+            },
+        };
+        Some(Ok(Match { text, start, end }))
+    }
+}
+
+/// The ends of the matches of a DFA searching for all of them, fed the text
+/// one char at a time.
+struct DfaEnds<S> {
+    stream: S,
+    /// The position of the stream in the text, past its end once ended.
+    at: usize,
+    /// The ends the stream revealed that weren't returned yet.
+    ends: VecDeque<usize>,
+}
+
+impl<S: ChunkStream> DfaEnds<S> {
+    fn new(stream: S) -> DfaEnds<S> {
+        DfaEnds {
+            stream,
+            at: 0,
+            ends: VecDeque::new(),
+        }
+    }
+
+    fn next_end(&mut self, text: &str) -> Option<usize> {
+        loop {
+            if let Some(end) = self.ends.pop_front() {
+                return Some(end);
+            }
+            if self.at > text.len() {
+                return None;
+            }
+            match text[self.at..].chars().next() {
+                Some(c) => {
+                    let next = self.at + c.len_utf8();
+                    let ends = self.stream.feed(&text.as_bytes()[self.at..next]);
+                    self.ends.extend(ends);
+                    self.at = if self.stream.is_dead() {
+                        text.len() + 1
+                    } else {
+                        next
+                    };
+                }
+                None => {
+                    self.ends.extend(self.stream.end());
+                    self.at = text.len() + 1;
+                }
+            }
+        }
+    }
+}
+
+/// The streams of the dense and lazy DFAs, see `dense::Stream`.
+trait ChunkStream {
+    fn feed(&mut self, chunk: &[u8]) -> &[usize];
+    fn end(&mut self) -> &[usize];
+    fn is_dead(&self) -> bool;
+}
+
+impl ChunkStream for dense::Stream<'_> {
+    fn feed(&mut self, chunk: &[u8]) -> &[usize] {
+        dense::Stream::feed(self, chunk)
+    }
+
+    fn end(&mut self) -> &[usize] {
+        dense::Stream::end(self)
+    }
+
+    fn is_dead(&self) -> bool {
+        dense::Stream::is_dead(self)
+    }
+}
+
+impl ChunkStream for lazy::Stream<'_> {
+    fn feed(&mut self, chunk: &[u8]) -> &[usize] {
+        lazy::Stream::feed(self, chunk)
+    }
+
+    fn end(&mut self) -> &[usize] {
+        lazy::Stream::end(self)
+    }
+
+    fn is_dead(&self) -> bool {
+        lazy::Stream::is_dead(self)
+    }
+}
+
+/// A single match in a string, given by its byte offsets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Match<'t> {
+    text: &'t str,
+    start: usize,
+    end: usize,
+}
+
+impl<'t> Match<'t> {
+    /// The byte offset where the match starts.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// The byte offset right after the end of the match.
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// The matched substring.
+    pub fn as_str(&self) -> &'t str {
+        &self.text[self.start..self.end]
+    }
+}
+
+/// A single match in bytes, given by its offsets, returned by
+/// `Regex::find_bytes` and `Regex::find_latin1`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BytesMatch<'t> {
+    bytes: &'t [u8],
+    start: usize,
+    end: usize,
+}
+
+impl<'t> BytesMatch<'t> {
+    /// The offset where the match starts.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// The offset right after the end of the match.
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// The matched bytes.
+    pub fn as_bytes(&self) -> &'t [u8] {
+        &self.bytes[self.start..self.end]
+    }
+}
+
+/// A single match in UTF-16 encoded units, given by its offsets in code units,
+/// returned by `Regex::find_utf16`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Utf16Match<'t> {
+    units: &'t [u16],
+    start: usize,
+    end: usize,
+}
+
+impl<'t> Utf16Match<'t> {
+    /// The offset where the match starts.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// The offset right after the end of the match.
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// The matched units.
+    pub fn as_units(&self) -> &'t [u16] {
+        &self.units[self.start..self.end]
+    }
+}
+
+/// The capture groups of a single match. Group `0` is the whole match.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Captures<'t> {
+    text: &'t str,
+    slots: Vec<Option<usize>>,
+}
+
+impl<'t> Captures<'t> {
+    /// Returns the match of group `i`, or `None` if the group didn't
+    /// participate in the match.
+    pub fn get(&self, i: usize) -> Option<Match<'t>> {
+        match (self.slots.get(i * 2), self.slots.get(i * 2 + 1)) {
+            (Some(&Some(start)), Some(&Some(end))) => Some(Match {
+                text: self.text,
+                start,
+                end,
+            }),
+            _ => None,
+        }
     }
 }
 
-/// Translates NFA to DFA.
-fn nfa_to_dfa(nfa: &NFA) -> DFA {
-    DFA::from(nfa)
+/// Compiles the pattern into an NFA.
+///
+/// This fails with `Error::Unsupported` for patterns using features that
+/// can't be expressed by finite automata, and with `Error::CompiledTooBig`
+/// if the NFA exceeds the default size limits.
+pub fn compile_nfa(input: &str) -> Result<NFA, Error> {
+    compile_nfa_with_limits(input, &SizeLimits::default())
+}
+
+/// Compiles the pattern into an NFA like `compile_nfa`, within the size limits.
+pub(crate) fn compile_nfa_with_limits(input: &str, limits: &SizeLimits) -> Result<NFA, Error> {
+    ast_to_nfa(&parse(input)?, limits)
+}
+
+fn parse(input: &str) -> Result<AST, Error> {
+    Parser::new()
+        .parse(input)
+        .map_err(Error::from_ast_parse_error)
+}
+
+/// Translates AST to NFA.
+fn ast_to_nfa(ast: &AST, limits: &SizeLimits) -> Result<NFA, Error> {
+    Compiler { limits, states: 0 }.ast_to_nfa(ast)
+}
+
+/// Translates AST to NFA, counting the states it builds. The count is
+/// checked before building each fragment, so a pattern repeating a large
+/// fragment many times fails before all of its copies are built.
+struct Compiler<'l> {
+    limits: &'l SizeLimits,
+    states: usize,
+}
+
+impl<'l> Compiler<'l> {
+    fn ast_to_nfa(&mut self, ast: &AST) -> Result<NFA, Error> {
+        // This uses recursion to walk all the nested nodes, which isn't ideal if we were planing
+        // to support large trees, and the original Rust implementation uses a visitor implementation instead.
+        // But to keep this simple we use recursion for this implementation.
+        match ast {
+            AST::Literal(lit) => {
+                self.add_states(2)?;
+                Ok(NFA::char(lit.c))
+            }
+            AST::Concat(con) => Ok(NFA::concat(
+                con.asts
+                    .iter()
+                    .map(|ast| self.ast_to_nfa(ast))
+                    .collect::<Result<Vec<_>, _>>()?,
+            )),
+            AST::Alternation(alt) => {
+                // Each union adds a fork and a merge state.
+                self.add_states(2 * (alt.asts.len() - 1))?;
+                Ok(NFA::or(
+                    alt.asts
+                        .iter()
+                        .map(|ast| self.ast_to_nfa(ast))
+                        .collect::<Result<Vec<_>, _>>()?,
+                ))
+            }
+            AST::Empty(_) => self.empty(),
+            AST::Group(group) => match group.kind {
+                GroupKind::Lookaround(_) => Err(Error::unsupported(
+                    &group.span,
+                    "lookaround assertions are not supported by the automata",
+                )),
+                GroupKind::NonCapturing => self.ast_to_nfa(&group.ast),
+                GroupKind::CaptureIndex(index) | GroupKind::CaptureName { index, .. } => {
+                    self.add_states(4)?;
+                    Ok(NFA::capture(self.ast_to_nfa(&group.ast)?, index as usize))
+                }
+            },
+            AST::Repetition(rep) => self.repetition_to_nfa(rep),
+            AST::Dot(_) => {
+                self.add_states(2)?;
+                Ok(NFA::class(&[('\0', '\u{9}'), ('\u{B}', char::MAX)]))
+            }
+            AST::Class(cls) => {
+                self.add_states(2)?;
+                let ranges: Vec<(char, char)> =
+                    cls.to_ranges().iter().map(|r| (r.start, r.end)).collect();
+                Ok(NFA::class(&ranges))
+            }
+            AST::Assertion(assertion) => match assertion.kind {
+                AssertionKind::StartText => self.look(Look::Start),
+                AssertionKind::EndText => self.look(Look::End),
+                AssertionKind::StartLine | AssertionKind::EndLine => Err(Error::unsupported(
+                    &assertion.span,
+                    "multi-line anchors are not supported by the automata",
+                )),
+            },
+            AST::Backreference(br) => Err(Error::unsupported(
+                &br.span,
+                "backreferences need to remember what a group matched, \
+                 which no finite automaton can do",
+            )),
+        }
+    }
+
+    /// Translates a repetition to NFA by unrolling its bounded part into copies
+    /// of the repeated fragment. The optional copies are ordered so that a lazy
+    /// repetition prefers skipping them.
+    fn repetition_to_nfa(&mut self, rep: &Repetition) -> Result<NFA, Error> {
+        let (min, max) = rep.op.kind.bounds();
+        let ast = &rep.ast;
+
+        let mut fragments = vec![];
+        for _ in 0..min {
+            fragments.push(self.ast_to_nfa(ast)?);
+        }
+        match max {
+            None => {
+                self.add_states(2)?;
+                let fragment = self.ast_to_nfa(ast)?;
+                fragments.push(if rep.greedy {
+                    NFA::rep(fragment)
+                } else {
+                    NFA::rep_lazy(fragment)
+                });
+            }
+            Some(max) => {
+                for _ in min..max {
+                    self.add_states(2)?;
+                    let mut branches = vec![self.ast_to_nfa(ast)?, self.empty()?];
+                    if !rep.greedy {
+                        branches.reverse();
+                    }
+                    fragments.push(NFA::or(branches));
+                }
+            }
+        }
+
+        Ok(match fragments.len() {
+            0 => self.empty()?,
+            1 => fragments.pop().unwrap(),
+            _ => NFA::concat(fragments),
+        })
+    }
+
+    fn empty(&mut self) -> Result<NFA, Error> {
+        self.add_states(2)?;
+        Ok(NFA::empty())
+    }
+
+    fn look(&mut self, look: Look) -> Result<NFA, Error> {
+        self.add_states(2)?;
+        Ok(NFA::look(look))
+    }
+
+    fn add_states(&mut self, states: usize) -> Result<(), Error> {
+        self.states += states;
+        self.limits.check_nfa_states(self.states)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{ast::AST, error::Error};
+    use crate::{
+        ast::AST,
+        error::{Error, LimitKind},
+    };
 
     fn parse_ast(input: &str) -> Result<AST, Error> {
-        Parser::new()
-            .parse(input)
-            .map_err(Error::from_ast_parse_error)
+        parse(input)
     }
 
     #[test]
@@ -59,7 +1251,7 @@ mod tests {
         let ast = parse_ast("").unwrap();
         dbg!(&ast);
 
-        let nfa = ast_to_nfa(&ast);
+        let nfa = ast_to_nfa(&ast, &SizeLimits::default()).unwrap();
         dbg!(&nfa);
 
         assert_eq!(nfa, NFA::empty());
@@ -70,7 +1262,7 @@ mod tests {
         let ast = parse_ast("a").unwrap();
         dbg!(&ast);
 
-        let nfa = ast_to_nfa(&ast);
+        let nfa = ast_to_nfa(&ast, &SizeLimits::default()).unwrap();
         dbg!(&nfa);
 
         assert_eq!(nfa, NFA::char('a'));
@@ -81,7 +1273,7 @@ mod tests {
         let ast = parse_ast("ab").unwrap();
         dbg!(&ast);
 
-        let nfa = ast_to_nfa(&ast);
+        let nfa = ast_to_nfa(&ast, &SizeLimits::default()).unwrap();
         dbg!(&nfa);
 
         assert_eq!(nfa, NFA::concat(vec![NFA::char('a'), NFA::char('b')]));
@@ -92,7 +1284,7 @@ mod tests {
         let ast = parse_ast("ab|c").unwrap();
         dbg!(&ast);
 
-        let nfa = ast_to_nfa(&ast);
+        let nfa = ast_to_nfa(&ast, &SizeLimits::default()).unwrap();
         dbg!(&nfa);
 
         assert_eq!(
@@ -106,12 +1298,12 @@ mod tests {
     //
     // #[test]
     // fn test_empty() {
-    //     assert_eq!(Regex::new("").unwrap().test(""), true);
+    //     assert!(Regex::new("").unwrap().test(""));
     // }
 
     #[test]
     fn test_single_char() {
-        assert_eq!(Regex::new("a").unwrap().test("a"), true);
+        assert!(Regex::new("a").unwrap().test("a"));
     }
 
     #[test]
@@ -119,4 +1311,646 @@ mod tests {
 
     #[test]
     fn test_multiple_chars_with_alternation() {}
+
+    #[test]
+    fn test_optional_and_repetition() {
+        let re = Regex::new("ab?c*").unwrap();
+        assert!(re.test("a"));
+        assert!(re.test("abcc"));
+        assert!(!re.test("abb"));
+    }
+
+    #[test]
+    fn test_classes() {
+        let re = Regex::new(r"[a-z_][a-z0-9_]*\.[^.\n]+").unwrap();
+        assert!(matches!(re.engine, Engine::LazyDfa { .. }));
+        assert!(re.test("main.rs"));
+        assert!(re.test("x_1.τ"));
+        assert!(!re.test("1x.rs"));
+        assert!(!re.test("main."));
+        assert!(!re.test("main.r.s"));
+
+        let re = Regex::new("a.c").unwrap();
+        assert!(re.test("abc"));
+        assert!(re.test("a€c"));
+        assert!(!re.test("a\nc"));
+    }
+
+    #[test]
+    fn test_exponential_dfa() {
+        let re = Regex::new("(a|b)*a(a|b){20}").unwrap();
+        assert!(re.test(&format!("{}a{}", "b".repeat(50), "ab".repeat(10))));
+        assert!(!re.test(&"ab".repeat(50)));
+    }
+
+    #[test]
+    fn compiler_counts_nfa_states() {
+        for pattern in ["a", "ab|c|", "(a|b)*?c", "x(y)[a-z]{2,4}", "(ab){3,}."] {
+            let ast = parse_ast(pattern).unwrap();
+            let mut compiler = Compiler {
+                limits: &SizeLimits::default(),
+                states: 0,
+            };
+            let nfa = compiler.ast_to_nfa(&ast).unwrap();
+            assert_eq!(
+                compiler.states,
+                nfa.get_transition_table().table.len(),
+                "/{pattern}/"
+            );
+        }
+    }
+
+    #[test]
+    fn new_compiled_too_big() {
+        let err = Regex::new("((a{100}){100}){100}").err();
+        assert_eq!(
+            err,
+            Some(Error::CompiledTooBig {
+                limit: 100_000,
+                kind: LimitKind::NfaStates
+            })
+        );
+
+        let limits = SizeLimits {
+            nfa_states: 10,
+            ..SizeLimits::default()
+        };
+        assert!(Regex::with_size_limits("abcde", &limits).is_ok());
+        assert!(Regex::with_size_limits("abcdef", &limits).is_err());
+    }
+
+    #[test]
+    fn find_leftmost_first() {
+        let re = Regex::new("b+|ab").unwrap();
+        let m = re.find("xxabbb").unwrap();
+        assert_eq!((m.start(), m.end(), m.as_str()), (2, 4, "ab"));
+        assert_eq!(Regex::new("a|b").unwrap().find("xyz"), None);
+    }
+
+    #[test]
+    fn find_lazy_quoted_string() {
+        let text = r#"a "b" c "d" e"#;
+        let lazy = Regex::new(r#""(x|b|d| )*?""#).unwrap();
+        assert_eq!(lazy.find(text).unwrap().as_str(), r#""b""#);
+        let greedy = Regex::new(r#""(x|b|d| |c|")*""#).unwrap();
+        assert_eq!(greedy.find(text).unwrap().as_str(), r#""b" c "d""#);
+    }
+
+    #[test]
+    fn captures_lazy_repetitions() {
+        let re = Regex::new("(a+?)(a*)").unwrap();
+        let caps = re.captures("aaa").unwrap();
+        assert_eq!(caps.get(1).unwrap().as_str(), "a");
+        assert_eq!(caps.get(2).unwrap().as_str(), "aa");
+
+        let re = Regex::new("(a{1,3}?)(b)?").unwrap();
+        let caps = re.captures("aaa").unwrap();
+        assert_eq!(caps.get(0).unwrap().as_str(), "a");
+        assert_eq!(caps.get(2), None);
+        assert_eq!(caps.get(3), None);
+    }
+
+    #[test]
+    fn captures_backtracker() {
+        let re = Regex::new(r#"(['"])(.*?)\1"#).unwrap();
+        let caps = re.captures(r#"x = 'a' + 'b'"#).unwrap();
+        assert_eq!(caps.get(0).unwrap().as_str(), "'a'");
+        assert_eq!(caps.get(2).unwrap().as_str(), "a");
+    }
+
+    #[test]
+    fn ast_to_nfa_backreference_unsupported() {
+        let ast = parse_ast(r"(a)b\1").unwrap();
+
+        let err = ast_to_nfa(&ast, &SizeLimits::default()).unwrap_err();
+        match err {
+            Error::Unsupported { span, .. } => {
+                assert_eq!((span.start.offset, span.end.offset), (4, 6));
+            }
+            err => panic!("expected an unsupported error, got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_backreference() {
+        let re = Regex::new(r#"(['"]).*\1"#).unwrap();
+        assert!(compile_nfa(r#"(['"]).*\1"#).is_err());
+        assert!(re.test(r#"'quoted'"#));
+        assert!(!re.test(r#"'quoted""#));
+    }
+
+    #[test]
+    fn test_backreference_step_limit() {
+        let re = Regex::new(r"(a|aa)*\1b").unwrap().with_step_limit(1_000);
+        assert_eq!(re.try_test("aab"), Ok(true));
+        assert_eq!(
+            re.try_test("aaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"),
+            Err(Error::StepLimitExceeded { limit: 1_000 })
+        );
+        assert_eq!(
+            re.try_find("aaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"),
+            Err(Error::StepLimitExceeded { limit: 1_000 })
+        );
+        assert!(re.try_captures("xaab").unwrap().is_some());
+    }
+
+    #[test]
+    fn step_limit_is_no_match() {
+        let re = Regex::new(r"(a|aa)*\1b").unwrap().with_step_limit(1_000);
+        let text = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+        assert!(!re.test(text));
+        assert_eq!(re.find(text), None);
+        assert!(re.captures(text).is_none());
+        assert_eq!(re.find_iter(text).count(), 0);
+        assert_eq!(re.find_overlapping_iter(text).count(), 0);
+
+        // The matches before the step budget runs out are still found.
+        let re = Regex::new(r"b|(a|aa)*\1c").unwrap().with_step_limit(1_000);
+        let ends: Vec<usize> = re
+            .find_overlapping_iter("bxaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa")
+            .map(|m| m.end())
+            .collect();
+        assert_eq!(ends, [1]);
+    }
+
+    #[test]
+    fn test_lookaround() {
+        let re = Regex::new(r"[\w.]+(?<=\.rs)(?!x)").unwrap();
+        assert!(re.test("main.rs"));
+        assert!(!re.test("main.py"));
+    }
+
+    #[test]
+    fn new_unbounded_lookbehind() {
+        match Regex::new("(?<!a*)b") {
+            Err(Error::Unsupported { span, .. }) => {
+                assert_eq!((span.start.offset, span.end.offset), (0, 7));
+            }
+            result => panic!("expected an unsupported error, got {:?}", result.is_ok()),
+        }
+    }
+
+    #[test]
+    fn new_undefined_backreference() {
+        assert!(matches!(Regex::new(r"(a)\2"), Err(Error::Syntax(_))));
+        assert!(matches!(Regex::new(r"(?<a>x)\k<b>"), Err(Error::Syntax(_))));
+    }
+
+    #[test]
+    fn builder_engines() {
+        let engines = [
+            EngineKind::Auto,
+            EngineKind::Dfa,
+            EngineKind::LazyDfa,
+            EngineKind::Nfa,
+            EngineKind::Backtrack,
+        ];
+        for engine in engines {
+            for minimize in [false, true] {
+                let re = RegexBuilder::new("(a|b)*abb")
+                    .engine(engine)
+                    .minimize(minimize)
+                    .build()
+                    .unwrap();
+                assert!(re.test("babb"), "{engine:?}");
+                assert!(!re.test("babba"), "{engine:?}");
+                let m = re.find("xxabbx").unwrap();
+                assert_eq!((m.start(), m.end()), (2, 5), "{engine:?}");
+            }
+        }
+
+        let re = RegexBuilder::new("a")
+            .engine(EngineKind::Dfa)
+            .build()
+            .unwrap();
+        assert!(matches!(re.engine, Engine::Dfa { .. }));
+        let re = RegexBuilder::new("a")
+            .engine(EngineKind::Nfa)
+            .build()
+            .unwrap();
+        assert!(matches!(re.engine, Engine::Nfa(_)));
+        let re = RegexBuilder::new(r"(a)\1").build().unwrap();
+        assert!(matches!(re.engine, Engine::Backtrack(_)));
+    }
+
+    #[test]
+    fn builder_unsupported_engine() {
+        for engine in [EngineKind::Dfa, EngineKind::LazyDfa, EngineKind::Nfa] {
+            let result = RegexBuilder::new(r"(a)\1").engine(engine).build();
+            assert!(
+                matches!(result, Err(Error::Unsupported { .. })),
+                "{engine:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn anchors_match_like_backtracker() {
+        let patterns = ["^abc$", "a$|b", "^a|b", "^$", "(^|x)a+($|y)", "^a*$|b+"];
+        let texts = ["", "abc", "xabc", "abcx", "ba", "ab", "bbaa", "xaay", "aaa"];
+        let engines = [
+            EngineKind::Dfa,
+            EngineKind::LazyDfa,
+            EngineKind::Nfa,
+            EngineKind::Auto,
+        ];
+        for pattern in patterns {
+            let backtracker = RegexBuilder::new(pattern)
+                .engine(EngineKind::Backtrack)
+                .build()
+                .unwrap();
+            for engine in engines {
+                for minimize in [false, true] {
+                    let re = RegexBuilder::new(pattern)
+                        .engine(engine)
+                        .minimize(minimize)
+                        .build()
+                        .unwrap();
+                    for text in texts {
+                        let ranges = |re: &Regex| -> Vec<(usize, usize)> {
+                            re.find_iter(text).map(|m| (m.start(), m.end())).collect()
+                        };
+                        let bytes = re.find_bytes(text.as_bytes()).unwrap().map(|m| m.end());
+                        let at = format!("/{pattern}/ on {text:?} with {engine:?}");
+                        assert_eq!(re.test(text), backtracker.test(text), "{at}");
+                        assert_eq!(ranges(&re), ranges(&backtracker), "{at}");
+                        assert_eq!(bytes, backtracker.find(text).map(|m| m.end()), "{at}");
+                    }
+                }
+            }
+        }
+
+        // Text anchors no longer need the backtracker.
+        let re = RegexBuilder::new("^ab$").build().unwrap();
+        assert!(matches!(re.engine, Engine::LazyDfa { .. }));
+        let result = RegexBuilder::new("^ab")
+            .multi_line(true)
+            .engine(EngineKind::Dfa)
+            .build();
+        assert!(matches!(result, Err(Error::Unsupported { .. })));
+    }
+
+    #[test]
+    fn builder_dfa_size_limits() {
+        let limits = SizeLimits {
+            dfa_states: 100,
+            ..SizeLimits::default()
+        };
+        let result = RegexBuilder::new("(a|b)*a(a|b){10}")
+            .engine(EngineKind::Dfa)
+            .size_limits(limits)
+            .build();
+        assert!(matches!(
+            result,
+            Err(Error::CompiledTooBig {
+                kind: LimitKind::DfaStates,
+                ..
+            })
+        ));
+
+        // Few DFA states, each holding hundreds of NFA states.
+        let limits = SizeLimits {
+            determinization: 10_000,
+            ..SizeLimits::default()
+        };
+        let result = RegexBuilder::new("[a-z]{10}{10}")
+            .engine(EngineKind::Dfa)
+            .size_limits(limits)
+            .build();
+        assert!(matches!(
+            result,
+            Err(Error::CompiledTooBig {
+                kind: LimitKind::Determinization,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn builder_flags() {
+        let re = RegexBuilder::new("hello [a-c]+")
+            .case_insensitive(true)
+            .build()
+            .unwrap();
+        assert!(re.test("HeLLo aBc"));
+        assert!(!re.test("HeLLo abd"));
+
+        let re = RegexBuilder::new("a.b").dot_all(true).build().unwrap();
+        assert!(re.test("a\nb"));
+
+        let re = RegexBuilder::new("^b+$").multi_line(true).build().unwrap();
+        let m = re.find("a\nbb\nc").unwrap();
+        assert_eq!((m.start(), m.end()), (2, 4));
+        let re = RegexBuilder::new("^b+$").build().unwrap();
+        assert_eq!(re.find("a\nbb\nc"), None);
+
+        let re = RegexBuilder::new("a b* # some bs\n c")
+            .verbose(true)
+            .build()
+            .unwrap();
+        assert!(re.test("abbc"));
+        assert!(!re.test("a bc"));
+
+        let result = RegexBuilder::new("((a))").nest_limit(1).build();
+        assert!(matches!(result, Err(Error::Syntax(_))));
+    }
+
+    #[test]
+    fn find_at_offset() {
+        let re = Regex::new("ab").unwrap();
+        let m = re.find_at("abxab", 1).unwrap();
+        assert_eq!((m.start(), m.end()), (3, 5));
+        assert_eq!(re.find_at("abxab", 4), None);
+        assert_eq!(re.find_at("abxab", 5), None);
+
+        // The text before the offset is still seen by anchors.
+        let re = Regex::new("^b").unwrap();
+        assert_eq!(re.find_at("ab", 1), None);
+        let re = Regex::new("(?<=a)b").unwrap();
+        assert_eq!(re.find_at("ab", 1).map(|m| m.start()), Some(1));
+    }
+
+    #[test]
+    #[should_panic]
+    fn find_at_inside_char() {
+        Regex::new("a").unwrap().find_at("éa", 1);
+    }
+
+    #[test]
+    fn find_iter_matches() {
+        let spans = |pattern: &str, text: &str| -> Vec<(usize, usize)> {
+            let re = Regex::new(pattern).unwrap();
+            re.find_iter(text).map(|m| (m.start(), m.end())).collect()
+        };
+        assert_eq!(spans("[0-9]+", "a1b22c333"), [(1, 2), (3, 5), (6, 9)]);
+        assert_eq!(spans("x", "abc"), []);
+        assert_eq!(spans("a*", "aab"), [(0, 2), (3, 3)]);
+        assert_eq!(spans("a*", ""), [(0, 0)]);
+        assert_eq!(spans("", "é€"), [(0, 0), (2, 2), (5, 5)]);
+        assert_eq!(spans("a|", "éa"), [(0, 0), (2, 3)]);
+
+        let re = Regex::new(r"(\w)\1").unwrap();
+        let found: Vec<&str> = re.find_iter("aabccdd").map(|m| m.as_str()).collect();
+        assert_eq!(found, ["aa", "cc", "dd"]);
+    }
+
+    #[test]
+    fn is_match_read_chunks() {
+        // Reads one byte at a time.
+        struct Bytes<'a>(&'a [u8]);
+
+        impl io::Read for Bytes<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let len = buf.len().min(self.0.len()).min(1);
+                buf[..len].copy_from_slice(&self.0[..len]);
+                self.0 = &self.0[len..];
+                Ok(len)
+            }
+        }
+
+        let engines = [EngineKind::Dfa, EngineKind::LazyDfa, EngineKind::Nfa];
+        for engine in engines {
+            let re = RegexBuilder::new("é+x|[0-9]{3}")
+                .engine(engine)
+                .build()
+                .unwrap();
+            let is_match = |text: &[u8]| re.is_match_read(Bytes(text)).unwrap();
+            assert!(is_match("abéééx".as_bytes()), "{engine:?}");
+            assert!(is_match(b"ab\xff123"), "{engine:?}");
+            assert!(!is_match("abéé".as_bytes()), "{engine:?}");
+            assert!(!is_match(b"12\xff3"), "{engine:?}");
+            assert!(re.is_match_read("----123".as_bytes()).unwrap());
+
+            let re = RegexBuilder::new("^ab|c$").engine(engine).build().unwrap();
+            let is_match = |text: &[u8]| re.is_match_read(Bytes(text)).unwrap();
+            assert!(is_match(b"abx"), "{engine:?}");
+            assert!(is_match(b"xc"), "{engine:?}");
+            assert!(!is_match(b"xab"), "{engine:?}");
+            assert!(!is_match(b"cx"), "{engine:?}");
+            assert!(!is_match(b""), "{engine:?}");
+        }
+
+        let re = RegexBuilder::new("é+x")
+            .engine(EngineKind::Backtrack)
+            .build()
+            .unwrap();
+        let err = re.is_match_read("éx".as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn find_bytes_matches() {
+        let engines = [EngineKind::Dfa, EngineKind::LazyDfa, EngineKind::Nfa];
+        for engine in engines {
+            let re = RegexBuilder::new("é+x|[0-9]{3}|a\u{FFFD}+b")
+                .engine(engine)
+                .build()
+                .unwrap();
+            let find = |bytes| re.find_bytes(bytes).unwrap().map(|m| (m.start(), m.end()));
+            assert_eq!(find("--ééx".as_bytes()), Some((2, 7)), "{engine:?}");
+            assert_eq!(find(b"\xc3\xa9x\xa9x123"), Some((0, 3)), "{engine:?}");
+            assert_eq!(find(b"\xff\xff123"), Some((2, 5)), "{engine:?}");
+            // Each byte of an invalid sequence matches as U+FFFD on its own.
+            assert_eq!(find(b"xa\xe2\x82b"), Some((1, 5)), "{engine:?}");
+            assert_eq!(find(b"\xf0\x9f12\xf0"), None, "{engine:?}");
+            assert_eq!(find(b""), None, "{engine:?}");
+            let m = re.find_bytes(b"\xe912345").unwrap().unwrap();
+            assert_eq!(m.as_bytes(), b"123");
+
+            assert_eq!(re.is_match_bytes(b"\xc3\xc3\xa9x"), Ok(true), "{engine:?}");
+            assert_eq!(re.is_match_bytes(b"\xc3x\xa9x12"), Ok(false), "{engine:?}");
+        }
+    }
+
+    #[test]
+    fn other_texts_unsupported_by_backtracker() {
+        // The backreference needs the backtracker.
+        let re = Regex::new(r"(a)\1").unwrap();
+        let unsupported = |err| matches!(err, Some(Error::Unsupported { .. }));
+        assert!(unsupported(re.is_match_bytes(b"aa").err()));
+        assert!(unsupported(re.find_bytes(b"aa").err()));
+        assert!(unsupported(re.is_match_utf16(&[0x61, 0x61]).err()));
+        assert!(unsupported(re.find_utf16(&[0x61, 0x61]).err()));
+        assert!(unsupported(re.is_match_latin1(b"aa").err()));
+        assert!(unsupported(re.find_latin1(b"aa").err()));
+        let err = re.is_match_read(&b"aa"[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn find_utf16_matches() {
+        let engines = [EngineKind::Dfa, EngineKind::LazyDfa, EngineKind::Nfa];
+        for engine in engines {
+            let re = RegexBuilder::new("[😀-🙏]+x|[0-9]{3}|a\u{FFFD}b")
+                .engine(engine)
+                .build()
+                .unwrap();
+            let find = |units: &[u16]| re.find_utf16(units).unwrap().map(|m| (m.start(), m.end()));
+            let units: Vec<u16> = "--😀🙏x".encode_utf16().collect();
+            assert_eq!(find(&units), Some((2, 7)), "{engine:?}");
+            assert_eq!(find(&units[3..]), Some((1, 4)), "{engine:?}");
+            // A lone surrogate matches as U+FFFD.
+            assert_eq!(find(&[0x61, 0xD83D, 0x62]), Some((0, 3)), "{engine:?}");
+            assert_eq!(find(&[0x61, 0xDE00, 0x62]), Some((0, 3)), "{engine:?}");
+            assert_eq!(find(&[0x61, 0xD83D, 0xDE00, 0x62]), None, "{engine:?}");
+            assert_eq!(find(&[]), None, "{engine:?}");
+            let units: Vec<u16> = "é12345".encode_utf16().collect();
+            let m = re.find_utf16(&units).unwrap().unwrap();
+            assert_eq!(m.as_units(), &units[1..4]);
+
+            let is_match = |units: &[u16]| re.is_match_utf16(units).unwrap();
+            assert!(is_match(&[0xD83D, 0xD83D, 0xDE00, 0x78]), "{engine:?}");
+            assert!(!is_match(&[0xD83D, 0x78, 0x31, 0x32]), "{engine:?}");
+        }
+    }
+
+    #[test]
+    fn find_latin1_matches() {
+        let engines = [EngineKind::Dfa, EngineKind::LazyDfa, EngineKind::Nfa];
+        for engine in engines {
+            let re = RegexBuilder::new("[à-ÿ]+x|[0-9]{3}|€")
+                .engine(engine)
+                .build()
+                .unwrap();
+            let find = |bytes| re.find_latin1(bytes).unwrap().map(|m| (m.start(), m.end()));
+            assert_eq!(find(b"--\xe0\xffx"), Some((2, 5)), "{engine:?}");
+            assert_eq!(find(b"\xdfx12345"), Some((2, 5)), "{engine:?}");
+            // Latin-1 has no euro sign, and UTF-8 encoded chars read as several.
+            assert_eq!(find("€é".as_bytes()), None, "{engine:?}");
+            assert_eq!(find(b""), None, "{engine:?}");
+            let m = re.find_latin1(b"\xe9x").unwrap().unwrap();
+            assert_eq!(m.as_bytes(), b"\xe9x");
+
+            assert_eq!(re.is_match_latin1(b"\xff\xffx"), Ok(true), "{engine:?}");
+            assert_eq!(re.is_match_latin1("àx".as_bytes()), Ok(false), "{engine:?}");
+        }
+    }
+
+    #[test]
+    fn find_overlapping_matches() {
+        let engines = [
+            EngineKind::Dfa,
+            EngineKind::LazyDfa,
+            EngineKind::Nfa,
+            EngineKind::Backtrack,
+        ];
+        let spans = |pattern: &str, text: &str, engine| -> Vec<(usize, usize)> {
+            let re = RegexBuilder::new(pattern).engine(engine).build().unwrap();
+            re.find_overlapping_iter(text)
+                .map(|m| (m.start(), m.end()))
+                .collect()
+        };
+        for engine in engines {
+            assert_eq!(spans("aa", "aaaa", engine), [(0, 2), (1, 3), (2, 4)]);
+            assert_eq!(spans("abc|b", "abc", engine), [(1, 2), (0, 3)]);
+            assert_eq!(spans("a+", "baa", engine), [(1, 2), (1, 3)]);
+            assert_eq!(spans("é?", "aé", engine), [(0, 0), (1, 1), (1, 3)]);
+            assert_eq!(spans("x", "abc", engine), []);
+            assert_eq!(spans("^a|b$|a", "aab", engine), [(0, 1), (1, 2), (2, 3)]);
+            assert_eq!(spans("^$", "", engine), [(0, 0)]);
+        }
+
+        let re = Regex::new(r"(\w)\1").unwrap();
+        let found: Vec<&str> = re
+            .find_overlapping_iter("aaab")
+            .map(|m| m.as_str())
+            .collect();
+        assert_eq!(found, ["aa", "aa"]);
+
+        // The matches before the step budget runs out are still found.
+        let re = Regex::new(r"b|(a|aa)*\1c").unwrap().with_step_limit(1_000);
+        let mut matches = re.try_find_overlapping_iter("bxaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa");
+        assert_eq!(matches.next().map(|m| m.map(|m| m.end())), Some(Ok(1)));
+        assert_eq!(
+            matches.next(),
+            Some(Err(Error::StepLimitExceeded { limit: 1_000 }))
+        );
+        assert_eq!(matches.next(), None);
+    }
+
+    #[test]
+    fn find_dfa_matches_nfa() {
+        let patterns = [
+            "b+|ab",
+            "abcd|c",
+            "a*",
+            "(a|ab)(c|bcd)",
+            r#""(.*?)""#,
+            "a{2,3}?",
+            "[0-9]+(px|em)?",
+            "x*|é",
+            "",
+        ];
+        let texts = [
+            "xxabbb",
+            "abcd",
+            "baab",
+            r#"say "hi" and "bye""#,
+            "12em 3",
+            "éxé",
+            "",
+        ];
+
+        let kinds = [
+            MatchKind::LeftmostFirst,
+            MatchKind::LeftmostLongest,
+            MatchKind::All,
+        ];
+        let engines = [EngineKind::Dfa, EngineKind::LazyDfa, EngineKind::Backtrack];
+
+        for pattern in patterns {
+            for kind in kinds {
+                let nfa = RegexBuilder::new(pattern)
+                    .engine(EngineKind::Nfa)
+                    .match_kind(kind)
+                    .build()
+                    .unwrap();
+                for engine in engines {
+                    let re = RegexBuilder::new(pattern)
+                        .engine(engine)
+                        .match_kind(kind)
+                        .build()
+                        .unwrap();
+                    for text in texts {
+                        for (start, _) in text.char_indices().chain([(text.len(), ' ')]) {
+                            assert_eq!(
+                                re.find_at(text, start),
+                                nfa.find_at(text, start),
+                                "/{pattern}/ on {text:?} from {start} with {engine:?} {kind:?}"
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn match_kinds() {
+        let find = |pattern: &str, text: &str, kind: MatchKind| {
+            let re = RegexBuilder::new(pattern).match_kind(kind).build().unwrap();
+            re.find(text).map(|m| m.as_str().to_owned())
+        };
+
+        let (first, longest, all) = (
+            MatchKind::LeftmostFirst,
+            MatchKind::LeftmostLongest,
+            MatchKind::All,
+        );
+        assert_eq!(find("a|ab", "ab", first).as_deref(), Some("a"));
+        assert_eq!(find("a|ab", "ab", longest).as_deref(), Some("ab"));
+        assert_eq!(find("a|ab", "ab", all).as_deref(), Some("a"));
+        assert_eq!(find("abc|b", "abc", first).as_deref(), Some("abc"));
+        assert_eq!(find("abc|b", "abc", all).as_deref(), Some("b"));
+        assert_eq!(find("a+?", "aaa", longest).as_deref(), Some("aaa"));
+
+        // The leftmost-longest capture groups come from the longest match.
+        let re = RegexBuilder::new("(a|ab)(c|bcd)")
+            .match_kind(MatchKind::LeftmostLongest)
+            .build()
+            .unwrap();
+        let caps = re.captures("abcd").unwrap();
+        assert_eq!(caps.get(0).unwrap().as_str(), "abcd");
+        assert_eq!(caps.get(1).unwrap().as_str(), "a");
+    }
 }
@@ -0,0 +1,198 @@
+use crate::{
+    automata::{lazy::LazyDFA, limits::SizeLimits, nfa_table::NFATable},
+    error::Error,
+    regex,
+};
+
+/// A set of patterns, telling which of them match a text in a single pass over it.
+///
+/// The NFAs of the patterns are combined into one, whose accepting states belong to
+/// the pattern they end, and determinized into a single DFA searching for all the
+/// matches of every pattern. Each of its accepting states carries the set of patterns
+/// matching there, so running it over the text once finds every pattern matching
+/// anywhere in it, however many patterns the set has.
+///
+/// The states of a DFA combining many patterns are large, and there can be many of
+/// them, so like the default engine of `Regex`, the DFA is lazy: a search only builds
+/// the states the text reaches. The patterns can only use the features automata can
+/// express, so backreferences, lookarounds and multi-line anchors fail with
+/// `Error::Unsupported`.
+#[derive(Debug)]
+pub struct RegexSet {
+    patterns: Vec<String>,
+    dfa: LazyDFA,
+}
+
+impl RegexSet {
+    pub fn new<I, S>(patterns: I) -> Result<RegexSet, Error>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        RegexSet::with_size_limits(patterns, &SizeLimits::default())
+    }
+
+    /// Compiles the patterns, failing with `Error::CompiledTooBig` if the NFA of any
+    /// of them exceeds the size limits.
+    pub fn with_size_limits<I, S>(patterns: I, limits: &SizeLimits) -> Result<RegexSet, Error>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let patterns: Vec<String> = patterns
+            .into_iter()
+            .map(|pattern| pattern.as_ref().to_owned())
+            .collect();
+        let tables = patterns
+            .iter()
+            .map(|pattern| {
+                let nfa = regex::compile_nfa_with_limits(pattern, limits)?;
+                Ok(nfa.get_transition_table())
+            })
+            .collect::<Result<Vec<NFATable>, Error>>()?;
+        Ok(RegexSet {
+            patterns,
+            dfa: LazyDFA::search_patterns(&tables),
+        })
+    }
+
+    /// The patterns of the set, in the order they were given. The ID of a pattern
+    /// is its index here.
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    /// The number of patterns in the set.
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Tests whether any pattern matches anywhere in the text, stopping at the first
+    /// match.
+    pub fn is_match(&self, text: &str) -> bool {
+        let mut found = false;
+        self.for_each_pattern(text, |_| {
+            found = true;
+            false
+        });
+        found
+    }
+
+    /// Returns which patterns match anywhere in the text. The search stops once all
+    /// the patterns matched.
+    pub fn matches(&self, text: &str) -> SetMatches {
+        let mut matched = vec![false; self.len()];
+        let mut left = self.len();
+        self.for_each_pattern(text, |pattern| {
+            if !matched[pattern] {
+                matched[pattern] = true;
+                left -= 1;
+            }
+            left > 0
+        });
+        SetMatches { matched }
+    }
+
+    // Runs the DFA over the text, calling `f` with the ID of every pattern matching
+    // where it accepts until the text ends or `f` returns false.
+    fn for_each_pattern(&self, text: &str, mut f: impl FnMut(usize) -> bool) {
+        if self.is_empty() {
+            return;
+        }
+        self.dfa
+            .for_each_match_patterns(text, |patterns| patterns.iter().all(|&p| f(p)));
+    }
+}
+
+/// The patterns of a `RegexSet` matching a text, returned by `RegexSet::matches`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SetMatches {
+    matched: Vec<bool>,
+}
+
+impl SetMatches {
+    /// Whether any pattern matched.
+    pub fn matched_any(&self) -> bool {
+        self.matched.contains(&true)
+    }
+
+    /// Whether the pattern with the ID matched.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the set has no pattern with that ID.
+    pub fn matched(&self, pattern: usize) -> bool {
+        self.matched[pattern]
+    }
+
+    /// Returns an iterator over the IDs of the patterns that matched, in order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.matched
+            .iter()
+            .enumerate()
+            .filter_map(|(pattern, &matched)| matched.then_some(pattern))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regex::Regex;
+
+    #[test]
+    fn matches() {
+        let set = RegexSet::new([
+            r"ERROR",
+            r"WARN(ING)?",
+            r"user=[a-z]+",
+            r"[0-9]{3}ms",
+            r"id=[0-9]+",
+        ])
+        .unwrap();
+        let matched = |text| set.matches(text).iter().collect::<Vec<usize>>();
+
+        assert_eq!(matched("ERROR user=bob took 250ms"), [0, 2, 3]);
+        assert_eq!(matched("WARNING: slow"), [1]);
+        assert_eq!(matched("user=é"), []);
+        assert_eq!(matched("id=7 id=x"), [4]);
+        assert_eq!(matched(""), []);
+        assert!(set.matches("WARN").matched(1));
+        assert!(!set.matches("WARN").matched(0));
+        assert!(!set.matches("nothing").matched_any());
+    }
+
+    #[test]
+    fn matches_like_regexes() {
+        let patterns = [
+            "a+b", "b+", "ab|ba", "x*", "[^a]c", "😀|é", "^a", "b$", "^$",
+        ];
+        let texts = ["", "ab", "bba", "aaac", "xé", "cc😀"];
+        let set = RegexSet::new(patterns).unwrap();
+        for text in texts {
+            let expected: Vec<usize> = (0..patterns.len())
+                .filter(|&i| Regex::new(patterns[i]).unwrap().find(text).is_some())
+                .collect();
+            let matches = set.matches(text);
+            assert_eq!(matches.iter().collect::<Vec<usize>>(), expected, "{text:?}");
+            assert_eq!(set.is_match(text), !expected.is_empty(), "{text:?}");
+        }
+    }
+
+    #[test]
+    fn empty_set() {
+        let set = RegexSet::new(Vec::<&str>::new()).unwrap();
+        assert!(set.is_empty());
+        assert!(!set.is_match("text"));
+        assert!(!set.matches("").matched_any());
+    }
+
+    #[test]
+    fn unsupported_pattern() {
+        let err = RegexSet::new(["a", r"(a)\1"]).unwrap_err();
+        assert!(matches!(err, Error::Unsupported { .. }), "{err:?}");
+    }
+}